@@ -296,6 +296,7 @@ async fn migrate_data(
             backed_up: session.backed_up,
             history_visibility: None,
             algorithm: RustEventEncryptionAlgorithm::MegolmV1AesSha2,
+            received_at: None,
         };
 
         let session = matrix_sdk_crypto::olm::InboundGroupSession::from_pickle(pickle)?;
@@ -485,6 +485,7 @@ impl OlmMachine {
             &key_counts,
             unused_fallback_keys.as_deref(),
         ))?;
+        let events: Vec<_> = events.into_iter().map(|e| e.event).collect();
 
         Ok(serde_json::to_string(&events)?)
     }
@@ -691,7 +692,8 @@ impl OlmMachine {
         let event_json: Event<'_> = serde_json::from_str(decrypted.event.json().get())?;
 
         Ok(match &encryption_info.algorithm_info {
-            AlgorithmInfo::MegolmV1AesSha2 { curve25519_key, sender_claimed_keys } => {
+            AlgorithmInfo::MegolmV1AesSha2 { curve25519_key, sender_claimed_keys }
+            | AlgorithmInfo::OlmV1Curve25519AesSha2 { curve25519_key, sender_claimed_keys } => {
                 DecryptedEvent {
                     clear_event: serde_json::to_string(&event_json)?,
                     sender_curve25519_key: curve25519_key.to_owned(),
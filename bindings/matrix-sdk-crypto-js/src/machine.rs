@@ -245,15 +245,17 @@ impl OlmMachine {
         let me = self.inner.clone();
 
         Ok(future_to_promise(async move {
-            Ok(serde_json::to_string(
-                &me.receive_sync_changes(
+            let events = me
+                .receive_sync_changes(
                     to_device_events,
                     &changed_devices,
                     &one_time_key_counts,
                     unused_fallback_keys.as_deref(),
                 )
-                .await?,
-            )?)
+                .await?;
+            let events: Vec<_> = events.into_iter().map(|e| e.event).collect();
+
+            Ok(serde_json::to_string(&events)?)
         }))
     }
 
@@ -164,7 +164,10 @@ impl DecryptedRoomEvent {
     #[wasm_bindgen(getter, js_name = "senderCurve25519Key")]
     pub fn sender_curve25519_key(&self) -> Option<JsString> {
         Some(match &self.encryption_info.as_ref()?.algorithm_info {
-            AlgorithmInfo::MegolmV1AesSha2 { curve25519_key, .. } => curve25519_key.clone().into(),
+            AlgorithmInfo::MegolmV1AesSha2 { curve25519_key, .. }
+            | AlgorithmInfo::OlmV1Curve25519AesSha2 { curve25519_key, .. } => {
+                curve25519_key.clone().into()
+            }
         })
     }
 
@@ -173,7 +176,8 @@ impl DecryptedRoomEvent {
     #[wasm_bindgen(getter, js_name = "senderClaimedEd25519Key")]
     pub fn sender_claimed_ed25519_key(&self) -> Option<JsString> {
         match &self.encryption_info.as_ref()?.algorithm_info {
-            AlgorithmInfo::MegolmV1AesSha2 { sender_claimed_keys, .. } => {
+            AlgorithmInfo::MegolmV1AesSha2 { sender_claimed_keys, .. }
+            | AlgorithmInfo::OlmV1Curve25519AesSha2 { sender_claimed_keys, .. } => {
                 sender_claimed_keys.get(&ruma::DeviceKeyAlgorithm::Ed25519).cloned().map(Into::into)
             }
         }
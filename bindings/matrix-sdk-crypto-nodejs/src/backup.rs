@@ -0,0 +1,261 @@
+//! Types to back up room keys to the server.
+
+use napi_derive::*;
+
+use crate::{into_err, requests};
+
+/// The private part of the backup key, the one used for recovery.
+#[napi]
+pub struct BackupRecoveryKey {
+    pub(crate) inner: matrix_sdk_crypto::store::RecoveryKey,
+}
+
+#[napi]
+impl BackupRecoveryKey {
+    /// Create a new random `BackupRecoveryKey`.
+    #[napi(factory)]
+    pub fn create_random_key() -> napi::Result<BackupRecoveryKey> {
+        Ok(Self { inner: matrix_sdk_crypto::store::RecoveryKey::new().map_err(into_err)? })
+    }
+
+    /// Try to create a `BackupRecoveryKey` from a base 64 encoded string.
+    #[napi(factory, strict)]
+    pub fn from_base64(key: String) -> napi::Result<BackupRecoveryKey> {
+        Ok(Self {
+            inner: matrix_sdk_crypto::store::RecoveryKey::from_base64(&key).map_err(into_err)?,
+        })
+    }
+
+    /// Try to create a `BackupRecoveryKey` from a base 58 encoded string.
+    #[napi(factory, strict)]
+    pub fn from_base58(key: String) -> napi::Result<BackupRecoveryKey> {
+        Ok(Self {
+            inner: matrix_sdk_crypto::store::RecoveryKey::from_base58(&key).map_err(into_err)?,
+        })
+    }
+
+    /// Convert the recovery key to a base 58 encoded string.
+    #[napi]
+    pub fn to_base58(&self) -> String {
+        self.inner.to_base58()
+    }
+
+    /// Convert the recovery key to a base 64 encoded string.
+    #[napi]
+    pub fn to_base64(&self) -> String {
+        self.inner.to_base64()
+    }
+
+    /// Get the public part of the backup key.
+    #[napi(getter)]
+    pub fn megolm_v1_public_key(&self) -> MegolmV1BackupKey {
+        self.inner.megolm_v1_public_key().into()
+    }
+
+    /// Try to decrypt a message that was encrypted using the public
+    /// part of the backup key.
+    #[napi(strict)]
+    pub fn decrypt_v1(
+        &self,
+        ephemeral_key: String,
+        mac: String,
+        ciphertext: String,
+    ) -> napi::Result<String> {
+        self.inner.decrypt_v1(mac, ephemeral_key, ciphertext).map_err(into_err)
+    }
+}
+
+impl BackupRecoveryKey {
+    fn to_owned_key(&self) -> matrix_sdk_crypto::store::RecoveryKey {
+        matrix_sdk_crypto::store::RecoveryKey::from_bytes(self.inner.as_bytes())
+    }
+}
+
+/// The public part of the backup key.
+#[napi]
+pub struct MegolmV1BackupKey {
+    inner: matrix_sdk_crypto::backups::MegolmV1BackupKey,
+}
+
+impl From<matrix_sdk_crypto::backups::MegolmV1BackupKey> for MegolmV1BackupKey {
+    fn from(inner: matrix_sdk_crypto::backups::MegolmV1BackupKey) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl MegolmV1BackupKey {
+    /// The actual base64 encoded public key.
+    #[napi(getter)]
+    pub fn public_key_base64(&self) -> String {
+        self.inner.to_base64()
+    }
+
+    /// The full name of the backup algorithm this backup key supports.
+    #[napi(getter)]
+    pub fn algorithm(&self) -> String {
+        self.inner.backup_algorithm().to_owned()
+    }
+}
+
+/// The number of room keys we have and how many of them are backed
+/// up.
+#[napi]
+pub struct RoomKeyCounts {
+    /// The total number of room keys.
+    #[napi(readonly)]
+    pub total: i64,
+
+    /// The number of backed up room keys.
+    #[napi(readonly)]
+    pub backed_up: i64,
+}
+
+/// The backup keys stored in the crypto store.
+#[napi]
+pub struct BackupKeys {
+    inner: matrix_sdk_crypto::store::BackupKeys,
+}
+
+#[napi]
+impl BackupKeys {
+    /// The recovery key, the one used to decrypt backed up room keys.
+    #[napi(getter)]
+    pub fn recovery_key(&self) -> Option<BackupRecoveryKey> {
+        self.inner.recovery_key.as_ref().map(|key| BackupRecoveryKey {
+            inner: matrix_sdk_crypto::store::RecoveryKey::from_bytes(key.as_bytes()),
+        })
+    }
+
+    /// The version that we are using for backups.
+    #[napi(getter)]
+    pub fn backup_version(&self) -> Option<String> {
+        self.inner.backup_version.clone()
+    }
+}
+
+/// A state machine that handles backing up room keys.
+///
+/// The state machine can be activated using the `enable_backup_v1`
+/// method. After the state machine has been enabled a request that
+/// will upload encrypted room keys can be generated using the `backup`
+/// method.
+#[napi]
+pub struct BackupMachine {
+    inner: matrix_sdk_crypto::backups::BackupMachine,
+}
+
+impl From<matrix_sdk_crypto::backups::BackupMachine> for BackupMachine {
+    fn from(inner: matrix_sdk_crypto::backups::BackupMachine) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl BackupMachine {
+    /// Are we able to back up room keys to the server?
+    #[napi]
+    pub async fn enabled(&self) -> bool {
+        self.inner.enabled().await
+    }
+
+    /// Activate the given backup key to be used to encrypt and backup
+    /// room keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `public_key_base64`, the base64 encoded public part of the backup key.
+    /// * `version`, the version of the backup, as returned by the server when
+    ///   the backup was created.
+    #[napi(strict)]
+    pub async fn enable_backup_v1(
+        &self,
+        public_key_base64: String,
+        version: String,
+    ) -> napi::Result<()> {
+        let key = matrix_sdk_crypto::backups::MegolmV1BackupKey::from_base64(&public_key_base64)
+            .map_err(into_err)?;
+        key.set_version(version);
+
+        self.inner.enable_backup_v1(key).await.map_err(into_err)
+    }
+
+    /// Disable and reset our backup state.
+    ///
+    /// This will remove any pending backup request, remove the backup
+    /// key and reset the backup state of each room key we have.
+    #[napi]
+    pub async fn disable_backup(&self) -> napi::Result<()> {
+        self.inner.disable_backup().await.map_err(into_err)
+    }
+
+    /// Check if the given backup has been verified by us or by another
+    /// of our devices that we trust.
+    ///
+    /// `backup_info` is the JSON-encoded content of the backup as returned
+    /// by the `/room_keys/version` endpoint.
+    #[napi(strict)]
+    pub async fn verify_backup(&self, backup_info: String) -> napi::Result<bool> {
+        let backup_info = serde_json::from_str(&backup_info).map_err(into_err)?;
+
+        Ok(self.inner.verify_backup(backup_info, false).await.map_err(into_err)?.trusted())
+    }
+
+    /// Get the number of backed up room keys and the total number of
+    /// room keys.
+    #[napi]
+    pub async fn room_key_counts(&self) -> napi::Result<RoomKeyCounts> {
+        let counts = self.inner.room_key_counts().await.map_err(into_err)?;
+
+        Ok(RoomKeyCounts {
+            total: counts.total.try_into().map_err(into_err)?,
+            backed_up: counts.backed_up.try_into().map_err(into_err)?,
+        })
+    }
+
+    /// Store the recovery key and the backup version in the crypto
+    /// store.
+    ///
+    /// This is useful if the client wants to support gossiping of the
+    /// backup key.
+    #[napi(strict)]
+    pub async fn save_recovery_key(
+        &self,
+        recovery_key: Option<&BackupRecoveryKey>,
+        version: Option<String>,
+    ) -> napi::Result<()> {
+        let recovery_key = recovery_key.map(BackupRecoveryKey::to_owned_key);
+
+        self.inner.save_recovery_key(recovery_key, version).await.map_err(into_err)
+    }
+
+    /// Get the backup keys we have saved in our crypto store.
+    #[napi]
+    pub async fn get_backup_keys(&self) -> napi::Result<BackupKeys> {
+        Ok(BackupKeys { inner: self.inner.get_backup_keys().await.map_err(into_err)? })
+    }
+
+    /// Encrypt a batch of room keys and return a request that needs to
+    /// be sent out to backup the room keys.
+    ///
+    /// Returns `null` if there are no room keys to back up. The
+    /// response of the request needs to be passed to
+    /// `OlmMachine.mark_request_as_sent`.
+    #[napi]
+    pub async fn backup(&self) -> napi::Result<Option<requests::KeysBackupRequest>> {
+        let Some(request) = self.inner.backup().await.map_err(into_err)? else {
+            return Ok(None);
+        };
+
+        match request.request() {
+            matrix_sdk_crypto::OutgoingRequests::KeysBackup(keys_backup_request) => {
+                Ok(Some(requests::KeysBackupRequest::try_from((
+                    request.request_id().to_string(),
+                    keys_backup_request,
+                ))?))
+            }
+
+            _ => Err(napi::Error::from_reason("The backup request has an unexpected type")),
+        }
+    }
+}
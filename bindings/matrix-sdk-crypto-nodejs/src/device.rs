@@ -0,0 +1,217 @@
+//! Types for a `Device`.
+
+use napi::bindgen_prelude::ToNapiValue;
+use napi_derive::*;
+
+use crate::{
+    identifiers::{DeviceId, UserId},
+    into_err, types, verification, vodozemac,
+};
+
+/// A device represents a E2EE capable client of an user.
+#[napi]
+pub struct Device {
+    pub(crate) inner: matrix_sdk_crypto::Device,
+}
+
+impl From<matrix_sdk_crypto::Device> for Device {
+    fn from(inner: matrix_sdk_crypto::Device) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl Device {
+    /// Request an interactive verification with this device.
+    ///
+    /// `methods` represents the verification methods that we should
+    /// advertise as supported by us; if not set, the default methods
+    /// are used.
+    #[napi(strict)]
+    pub async fn request_verification(
+        &self,
+        methods: Option<Vec<verification::VerificationMethod>>,
+    ) -> verification::RequestedVerification {
+        let (request, outgoing_request) = match verification::into_ruma_methods(methods) {
+            Some(methods) => self.inner.request_verification_with_methods(methods).await,
+            None => self.inner.request_verification().await,
+        };
+
+        verification::RequestedVerification { request, outgoing_request }
+    }
+
+    /// Is this device considered to be verified.
+    ///
+    /// This method returns true if either the `is_locally_trusted`
+    /// method returns `true` or if the `is_cross_signing_trusted`
+    /// method returns `true`.
+    #[napi]
+    pub fn is_verified(&self) -> bool {
+        self.inner.is_verified()
+    }
+
+    /// Is this device considered to be verified using cross signing.
+    #[napi]
+    pub fn is_cross_signing_trusted(&self) -> bool {
+        self.inner.is_cross_signing_trusted()
+    }
+
+    /// Set the local trust state of the device to the given state.
+    ///
+    /// This won’t affect any cross signing trust state, this only
+    /// sets a flag marking to have the given trust state.
+    ///
+    /// `trust_state` represents the new trust state that should be
+    /// set for the device.
+    #[napi(strict)]
+    pub async fn set_local_trust(&self, local_state: LocalTrust) -> napi::Result<()> {
+        self.inner.set_local_trust(local_state.into()).await.map_err(into_err)
+    }
+
+    /// The user ID of the device owner.
+    #[napi(getter)]
+    pub fn user_id(&self) -> UserId {
+        self.inner.user_id().to_owned().into()
+    }
+
+    /// The unique ID of the device.
+    #[napi(getter)]
+    pub fn device_id(&self) -> DeviceId {
+        self.inner.device_id().to_owned().into()
+    }
+
+    /// Get the human readable name of the device.
+    #[napi(getter)]
+    pub fn display_name(&self) -> Option<String> {
+        self.inner.display_name().map(ToOwned::to_owned)
+    }
+
+    /// Get the Curve25519 key of the given device.
+    #[napi(getter)]
+    pub fn curve25519_key(&self) -> Option<vodozemac::Curve25519PublicKey> {
+        self.inner.curve25519_key().map(Into::into)
+    }
+
+    /// Get the Ed25519 key of the given device.
+    #[napi(getter)]
+    pub fn ed25519_key(&self) -> Option<vodozemac::Ed25519PublicKey> {
+        self.inner.ed25519_key().map(Into::into)
+    }
+
+    /// Get a map containing all the device signatures.
+    #[napi(getter)]
+    pub fn signatures(&self) -> types::Signatures {
+        self.inner.signatures().clone().into()
+    }
+
+    /// Get the trust state of the device.
+    #[napi(getter)]
+    pub fn local_trust_state(&self) -> LocalTrust {
+        self.inner.local_trust_state().into()
+    }
+
+    /// Is the device locally marked as trusted?
+    #[napi]
+    pub fn is_locally_trusted(&self) -> bool {
+        self.inner.is_locally_trusted()
+    }
+
+    /// Is the device locally marked as blacklisted?
+    ///
+    /// Blacklisted devices won’t receive any group sessions.
+    #[napi]
+    pub fn is_blacklisted(&self) -> bool {
+        self.inner.is_blacklisted()
+    }
+
+    /// Is the device deleted?
+    #[napi]
+    pub fn is_deleted(&self) -> bool {
+        self.inner.is_deleted()
+    }
+}
+
+/// The local trust state of a device.
+#[napi]
+pub enum LocalTrust {
+    /// The device has been verified and is trusted.
+    Verified,
+
+    /// The device been blacklisted from communicating.
+    BlackListed,
+
+    /// The trust state of the device is being ignored.
+    Ignored,
+
+    /// The trust state is unset.
+    Unset,
+}
+
+impl From<matrix_sdk_crypto::LocalTrust> for LocalTrust {
+    fn from(value: matrix_sdk_crypto::LocalTrust) -> Self {
+        use matrix_sdk_crypto::LocalTrust::*;
+
+        match value {
+            Verified => Self::Verified,
+            BlackListed => Self::BlackListed,
+            Ignored => Self::Ignored,
+            Unset => Self::Unset,
+        }
+    }
+}
+
+impl From<LocalTrust> for matrix_sdk_crypto::LocalTrust {
+    fn from(value: LocalTrust) -> Self {
+        use LocalTrust::*;
+
+        match value {
+            Verified => Self::Verified,
+            BlackListed => Self::BlackListed,
+            Ignored => Self::Ignored,
+            Unset => Self::Unset,
+        }
+    }
+}
+
+/// A read only view over all devices belonging to a user.
+#[napi]
+pub struct UserDevices {
+    pub(crate) inner: matrix_sdk_crypto::UserDevices,
+}
+
+impl From<matrix_sdk_crypto::UserDevices> for UserDevices {
+    fn from(inner: matrix_sdk_crypto::UserDevices) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl UserDevices {
+    /// Get the specific device with the given device ID.
+    #[napi(strict)]
+    pub fn get(&self, device_id: &DeviceId) -> Option<Device> {
+        self.inner.get(&device_id.inner).map(Into::into)
+    }
+
+    /// Returns true if there is at least one devices of this user
+    /// that is considered to be verified, false otherwise.
+    ///
+    /// This won't consider your own device as verified, as your own
+    /// device is always implicitly verified.
+    #[napi]
+    pub fn is_any_verified(&self) -> bool {
+        self.inner.is_any_verified()
+    }
+
+    /// Array over all the device IDs of the user devices.
+    #[napi]
+    pub fn keys(&self) -> Vec<DeviceId> {
+        self.inner.keys().map(ToOwned::to_owned).map(DeviceId::from).collect()
+    }
+
+    /// Array over all the devices of the user devices.
+    #[napi]
+    pub fn devices(&self) -> Vec<Device> {
+        self.inner.devices().map(Device::from).collect()
+    }
+}
@@ -237,6 +237,52 @@ impl RoomId {
     }
 }
 
+/// A Matrix [event ID].
+///
+/// An `EventId` is generated randomly or converted from a string
+/// slice, and can be converted back into a string as needed.
+///
+/// [event ID]: https://spec.matrix.org/v1.2/appendices/#room-ids-and-event-ids
+#[napi]
+#[derive(Debug, Clone)]
+pub struct EventId {
+    pub(crate) inner: ruma::OwnedEventId,
+}
+
+impl From<ruma::OwnedEventId> for EventId {
+    fn from(inner: ruma::OwnedEventId) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl EventId {
+    /// Parse/validate and create a new `EventId`.
+    #[napi(constructor, strict)]
+    pub fn new(id: String) -> napi::Result<Self> {
+        Ok(Self::from(ruma::EventId::parse(id).map_err(into_err)?))
+    }
+
+    /// Returns the event's localpart.
+    #[napi(getter)]
+    pub fn localpart(&self) -> String {
+        self.inner.localpart().to_owned()
+    }
+
+    /// Returns the server name of the event ID.
+    #[napi(getter)]
+    pub fn server_name(&self) -> Option<ServerName> {
+        Some(ServerName { inner: self.inner.server_name()?.to_owned() })
+    }
+
+    /// Return the event ID as a string.
+    #[napi]
+    #[allow(clippy::inherent_to_string)]
+    pub fn to_string(&self) -> String {
+        self.inner.as_str().to_owned()
+    }
+}
+
 /// A Matrix-spec compliant [server name].
 ///
 /// It consists of a host and an optional port (separated by a colon if
@@ -0,0 +1,153 @@
+//! User identities.
+
+use napi::bindgen_prelude::Either;
+use napi_derive::*;
+
+use crate::{identifiers, into_err, requests, verification};
+
+/// Either an `OwnUserIdentity` or a `UserIdentity`.
+pub type UserIdentities = Either<OwnUserIdentity, UserIdentity>;
+
+pub(crate) fn user_identities(inner: matrix_sdk_crypto::UserIdentities) -> UserIdentities {
+    use matrix_sdk_crypto::UserIdentities::*;
+
+    match inner {
+        Own(own) => Either::A(OwnUserIdentity { inner: own }),
+        Other(other) => Either::B(UserIdentity { inner: other }),
+    }
+}
+
+/// Struct representing a cross signing identity of a user.
+///
+/// This is the user identity of a user that is our own.
+#[napi]
+pub struct OwnUserIdentity {
+    inner: matrix_sdk_crypto::OwnUserIdentity,
+}
+
+#[napi]
+impl OwnUserIdentity {
+    /// Mark our user identity as verified.
+    ///
+    /// This will mark the identity locally as verified and sign it with our own
+    /// device.
+    ///
+    /// Returns a signature upload request that needs to be sent out.
+    #[napi]
+    pub async fn verify(&self) -> napi::Result<requests::SignatureUploadRequest> {
+        let request = self.inner.verify().await.map_err(into_err)?;
+
+        requests::SignatureUploadRequest::try_from((
+            ruma::TransactionId::new().to_string(),
+            &request,
+        ))
+    }
+
+    /// Send a verification request to our other devices.
+    ///
+    /// `methods` represents the verification methods that we should
+    /// advertise as supported by us; if not set, the default methods
+    /// are used.
+    #[napi(strict)]
+    pub async fn request_verification(
+        &self,
+        methods: Option<Vec<verification::VerificationMethod>>,
+    ) -> napi::Result<verification::RequestedVerification> {
+        let (request, outgoing_request) = match verification::into_ruma_methods(methods) {
+            Some(methods) => self.inner.request_verification_with_methods(methods).await,
+            None => self.inner.request_verification().await,
+        }
+        .map_err(into_err)?;
+
+        Ok(verification::RequestedVerification { request, outgoing_request })
+    }
+
+    /// Does our user identity trust our own device, i.e. have we signed our own
+    /// device keys with our self-signing key?
+    #[napi]
+    pub async fn trusts_our_own_device(&self) -> napi::Result<bool> {
+        self.inner.trusts_our_own_device().await.map_err(into_err)
+    }
+}
+
+/// Struct representing a cross signing identity of a user.
+///
+/// This is the user identity of a user that isn't our own. Other users will
+/// only contain a master key and a self signing key, meaning that only device
+/// signatures can be checked with this identity.
+///
+/// This struct wraps a read-only version of the struct and allows verifications
+/// to be requested to verify our own device with the user identity.
+#[napi]
+pub struct UserIdentity {
+    inner: matrix_sdk_crypto::UserIdentity,
+}
+
+#[napi]
+impl UserIdentity {
+    /// Is this user identity verified?
+    #[napi]
+    pub fn is_verified(&self) -> bool {
+        self.inner.is_verified()
+    }
+
+    /// Manually verify this user.
+    ///
+    /// This method will attempt to sign the user identity using our private
+    /// cross signing key.
+    ///
+    /// This method fails if we don't have the private part of our user-signing
+    /// key.
+    ///
+    /// Returns a request that needs to be sent out for the user to be marked as
+    /// verified.
+    #[napi]
+    pub async fn verify(&self) -> napi::Result<requests::SignatureUploadRequest> {
+        let request = self.inner.verify().await.map_err(into_err)?;
+
+        requests::SignatureUploadRequest::try_from((
+            ruma::TransactionId::new().to_string(),
+            &request,
+        ))
+    }
+
+    /// Create a `VerificationRequest` object after the verification
+    /// request content has been sent out.
+    #[napi(strict)]
+    pub async fn request_verification(
+        &self,
+        room_id: &identifiers::RoomId,
+        request_event_id: &identifiers::EventId,
+        methods: Option<Vec<verification::VerificationMethod>>,
+    ) -> verification::VerificationRequest {
+        self.inner
+            .request_verification(
+                &room_id.inner,
+                &request_event_id.inner,
+                verification::into_ruma_methods(methods),
+            )
+            .await
+            .into()
+    }
+
+    /// Send a verification request to the given user.
+    ///
+    /// The returned content, encoded as JSON, needs to be sent out into a DM
+    /// room with the given user.
+    ///
+    /// After the content has been sent out a `VerificationRequest` can be
+    /// started with the `request_verification` method.
+    #[napi(strict)]
+    pub async fn verification_request_content(
+        &self,
+        methods: Option<Vec<verification::VerificationMethod>>,
+    ) -> napi::Result<String> {
+        serde_json::to_string(
+            &self
+                .inner
+                .verification_request_content(verification::into_ruma_methods(methods))
+                .await,
+        )
+        .map_err(into_err)
+    }
+}
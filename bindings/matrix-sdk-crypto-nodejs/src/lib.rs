@@ -17,18 +17,23 @@
 //#![warn(missing_docs, missing_debug_implementations)]
 
 pub mod attachment;
+pub mod backup;
+pub mod device;
 pub mod encryption;
 mod errors;
 pub mod events;
 pub mod identifiers;
+pub mod identities;
 pub mod machine;
 pub mod olm;
 pub mod requests;
 pub mod responses;
+pub mod store;
 pub mod sync_events;
 #[cfg(feature = "tracing")]
 pub mod tracing;
 pub mod types;
+pub mod verification;
 pub mod vodozemac;
 
 use crate::errors::into_err;
@@ -7,15 +7,15 @@ use std::{
     sync::Arc,
 };
 
-use napi::bindgen_prelude::{within_runtime_if_available, Either7, ToNapiValue};
+use napi::bindgen_prelude::{within_runtime_if_available, Either, Either7, ToNapiValue};
 use napi_derive::*;
-use ruma::{serde::Raw, DeviceKeyAlgorithm, OwnedTransactionId, UInt};
+use ruma::{serde::Raw, DeviceKeyAlgorithm, OwnedTransactionId, TransactionId, UInt};
 use serde_json::{value::RawValue, Value as JsonValue};
 use zeroize::Zeroize;
 
 use crate::{
-    encryption, identifiers, into_err, olm, requests, responses, responses::response_from_string,
-    sync_events, types, vodozemac,
+    backup, device, encryption, identifiers, identities, into_err, olm, requests, responses,
+    responses::response_from_string, store, sync_events, types, verification, vodozemac,
 };
 
 /// The value used by the `OlmMachine` JS class.
@@ -192,6 +192,28 @@ impl OlmMachine {
         self.inner.identity_keys().into()
     }
 
+    /// Get the display name of our own device.
+    #[napi]
+    pub async fn display_name(&self) -> napi::Result<Option<String>> {
+        self.inner.display_name().await.map_err(into_err)
+    }
+
+    /// Get the list of users whose devices we are currently tracking.
+    ///
+    /// A user can be marked for tracking using the `update_tracked_users`
+    /// method.
+    #[napi]
+    pub async fn tracked_users(&self) -> napi::Result<Vec<identifiers::UserId>> {
+        Ok(self
+            .inner
+            .tracked_users()
+            .await
+            .map_err(into_err)?
+            .into_iter()
+            .map(identifiers::UserId::from)
+            .collect())
+    }
+
     /// Handle a to-device and one-time key counts from a sync response.
     ///
     /// This will decrypt and handle to-device events returning the
@@ -228,19 +250,19 @@ impl OlmMachine {
                 .collect::<Vec<_>>(),
         );
 
-        serde_json::to_string(
-            &self
-                .inner
-                .receive_sync_changes(
-                    to_device_events,
-                    &changed_devices,
-                    &one_time_key_counts,
-                    unused_fallback_keys.as_deref(),
-                )
-                .await
-                .map_err(into_err)?,
-        )
-        .map_err(into_err)
+        let events = self
+            .inner
+            .receive_sync_changes(
+                to_device_events,
+                &changed_devices,
+                &one_time_key_counts,
+                unused_fallback_keys.as_deref(),
+            )
+            .await
+            .map_err(into_err)?;
+        let events: Vec<_> = events.into_iter().map(|e| e.event).collect();
+
+        serde_json::to_string(&events).map_err(into_err)
     }
 
     /// Get the outgoing requests that need to be sent out.
@@ -478,6 +500,267 @@ impl OlmMachine {
         self.inner.sign(message.as_str()).await.into()
     }
 
+    /// Export all the private cross signing keys we have.
+    ///
+    /// The export will contain the seed for the ed25519 keys as a
+    /// unpadded base64 encoded string.
+    ///
+    /// This method returns `null` if we don’t have any private cross
+    /// signing keys.
+    #[napi]
+    pub async fn export_cross_signing_keys(&self) -> Option<store::CrossSigningKeyExport> {
+        self.inner.export_cross_signing_keys().await.map(Into::into)
+    }
+
+    /// Import our private cross signing keys.
+    ///
+    /// The export needs to contain the seed for the ed25519 keys as
+    /// an unpadded base64 encoded string.
+    #[napi(strict)]
+    pub async fn import_cross_signing_keys(
+        &self,
+        export: &store::CrossSigningKeyExport,
+    ) -> napi::Result<olm::CrossSigningStatus> {
+        let export = matrix_sdk_crypto::store::CrossSigningKeyExport {
+            master_key: export.inner.master_key.clone(),
+            self_signing_key: export.inner.self_signing_key.clone(),
+            user_signing_key: export.inner.user_signing_key.clone(),
+        };
+
+        Ok(self.inner.import_cross_signing_keys(export).await.map_err(into_err)?.into())
+    }
+
+    /// Create a new cross signing identity and get the upload request
+    /// to push the new public keys to the server.
+    ///
+    /// **Warning**: This will delete any existing cross signing keys
+    /// that might exist on the server and thus will reset the trust
+    /// between all the devices.
+    ///
+    /// Uploading these keys will require user interactive auth.
+    #[napi(strict)]
+    pub async fn bootstrap_cross_signing(
+        &self,
+        reset: bool,
+    ) -> napi::Result<requests::CrossSigningBootstrapRequests> {
+        let (upload_signing_keys_request, upload_signatures_request) =
+            self.inner.bootstrap_cross_signing(reset).await.map_err(into_err)?;
+
+        Ok(requests::CrossSigningBootstrapRequests {
+            upload_signing_keys_request: requests::SigningKeysUploadRequest::try_from((
+                TransactionId::new().to_string(),
+                &upload_signing_keys_request,
+            ))?,
+            upload_signatures_request: requests::SignatureUploadRequest::try_from((
+                TransactionId::new().to_string(),
+                &upload_signatures_request,
+            ))?,
+        })
+    }
+
+    /// Get the cross signing user identity of a user.
+    ///
+    /// Returns either an `OwnUserIdentity`, a `UserIdentity`, or
+    /// `null` if the identity of the user isn't known.
+    #[napi(strict)]
+    pub async fn get_identity(
+        &self,
+        user_id: &identifiers::UserId,
+    ) -> napi::Result<Option<Either<identities::OwnUserIdentity, identities::UserIdentity>>> {
+        Ok(self
+            .inner
+            .get_identity(&user_id.inner, None)
+            .await
+            .map_err(into_err)?
+            .map(identities::user_identities))
+    }
+
+    /// Invalidate the currently active outbound group session for the
+    /// given room.
+    ///
+    /// Returns true if a session was invalidated, false if there was
+    /// no session to invalidate.
+    #[napi(strict)]
+    pub async fn invalidate_group_session(
+        &self,
+        room_id: &identifiers::RoomId,
+    ) -> napi::Result<bool> {
+        self.inner.invalidate_group_session(&room_id.inner).await.map_err(into_err)
+    }
+
+    /// Get a map holding all the devices of a user.
+    ///
+    /// `user_id` represents the unique ID of the user that the
+    /// devices belong to.
+    #[napi(strict)]
+    pub async fn get_user_devices(
+        &self,
+        user_id: &identifiers::UserId,
+    ) -> napi::Result<device::UserDevices> {
+        Ok(self.inner.get_user_devices(&user_id.inner, None).await.map_err(into_err)?.into())
+    }
+
+    /// Get a specific device of a user if one is found and the crypto
+    /// store didn't throw an error.
+    ///
+    /// `user_id` represents the unique ID of the user that the
+    /// identity belongs to. `device_id` represents the unique ID of
+    /// the device.
+    #[napi(strict)]
+    pub async fn get_device(
+        &self,
+        user_id: &identifiers::UserId,
+        device_id: &identifiers::DeviceId,
+    ) -> napi::Result<Option<device::Device>> {
+        Ok(self
+            .inner
+            .get_device(&user_id.inner, &device_id.inner, None)
+            .await
+            .map_err(into_err)?
+            .map(Into::into))
+    }
+
+    /// Get a verification request object with the given flow ID.
+    #[napi(strict)]
+    pub fn get_verification_request(
+        &self,
+        user_id: &identifiers::UserId,
+        flow_id: String,
+    ) -> Option<verification::VerificationRequest> {
+        self.inner.get_verification_request(&user_id.inner, flow_id).map(Into::into)
+    }
+
+    /// Get all the verification requests of a given user.
+    #[napi(strict)]
+    pub fn get_verification_requests(
+        &self,
+        user_id: &identifiers::UserId,
+    ) -> Vec<verification::VerificationRequest> {
+        self.inner.get_verification_requests(&user_id.inner).into_iter().map(Into::into).collect()
+    }
+
+    /// Receive a verification event.
+    ///
+    /// This method can be used to pass verification events that are
+    /// happening in rooms to the `OlmMachine`. The event should be in
+    /// the decrypted form, encoded as JSON.
+    #[napi(strict)]
+    pub async fn receive_verification_event(
+        &self,
+        event: String,
+        room_id: &identifiers::RoomId,
+    ) -> napi::Result<()> {
+        let event: ruma::events::AnySyncMessageLikeEvent =
+            serde_json::from_str(event.as_str()).map_err(into_err)?;
+        let event = event.into_full_event(room_id.inner.clone());
+
+        self.inner.receive_verification_event(&event).await.map_err(into_err)
+    }
+
+    /// Export the room keys, encoded as JSON.
+    ///
+    /// If `room_id` is set, only the room keys of this room are
+    /// exported, otherwise all the known room keys are exported.
+    #[napi(strict)]
+    pub async fn export_room_keys(
+        &self,
+        room_id: Option<&identifiers::RoomId>,
+    ) -> napi::Result<String> {
+        let room_id = room_id.map(|room_id| room_id.inner.clone());
+
+        serde_json::to_string(
+            &self
+                .inner
+                .export_room_keys(|session| {
+                    room_id.as_deref().map_or(true, |room_id| session.room_id() == room_id)
+                })
+                .await
+                .map_err(into_err)?,
+        )
+        .map_err(into_err)
+    }
+
+    /// Import the given room keys into our store.
+    ///
+    /// `exported_room_keys` is a JSON-encoded list of previously
+    /// exported keys that should be imported into our store. If we
+    /// already have a better version of a key, the key will _not_ be
+    /// imported.
+    ///
+    /// Returns a JSON-encoded object containing `imported_count`,
+    /// `total_count` and `keys`.
+    #[napi(strict)]
+    pub async fn import_room_keys(&self, exported_room_keys: String) -> napi::Result<String> {
+        let exported_room_keys: Vec<matrix_sdk_crypto::olm::ExportedRoomKey> =
+            serde_json::from_str(exported_room_keys.as_str()).map_err(into_err)?;
+
+        let matrix_sdk_crypto::RoomKeyImportResult { imported_count, total_count, keys } = self
+            .inner
+            .import_room_keys(exported_room_keys, false, |_, _| {})
+            .await
+            .map_err(into_err)?;
+
+        serde_json::to_string(&serde_json::json!({
+            "imported_count": imported_count,
+            "total_count": total_count,
+            "keys": keys,
+        }))
+        .map_err(into_err)
+    }
+
+    /// Encrypt the list of exported room keys using the given
+    /// passphrase.
+    ///
+    /// `exported_room_keys` is a JSON-encoded list of sessions that
+    /// should be encrypted (it's generally returned by
+    /// `export_room_keys`). `passphrase` is the passphrase that will be
+    /// used to encrypt the exported room keys. And `rounds` is the
+    /// number of rounds that should be used for the key derivation
+    /// when the passphrase gets turned into an AES key. More rounds are
+    /// increasingly computationnally intensive and as such help against
+    /// brute-force attacks. Should be at least `10_000`, while values in
+    /// the `100_000` ranges should be preferred.
+    #[napi(strict)]
+    pub fn encrypt_exported_room_keys(
+        exported_room_keys: String,
+        passphrase: String,
+        rounds: u32,
+    ) -> napi::Result<String> {
+        let exported_room_keys: Vec<matrix_sdk_crypto::olm::ExportedRoomKey> =
+            serde_json::from_str(exported_room_keys.as_str()).map_err(into_err)?;
+
+        matrix_sdk_crypto::encrypt_room_key_export(&exported_room_keys, &passphrase, rounds)
+            .map_err(into_err)
+    }
+
+    /// Try to decrypt a list of exported room keys.
+    ///
+    /// `encrypted_exported_room_keys` is the result from
+    /// `encrypt_exported_room_keys`. `passphrase` is the passphrase
+    /// that was used when calling `encrypt_exported_room_keys`.
+    ///
+    /// Returns the JSON-encoded list of exported room keys.
+    #[napi(strict)]
+    pub fn decrypt_exported_room_keys(
+        encrypted_exported_room_keys: String,
+        passphrase: String,
+    ) -> napi::Result<String> {
+        serde_json::to_string(
+            &matrix_sdk_crypto::decrypt_room_key_export(
+                encrypted_exported_room_keys.as_bytes(),
+                &passphrase,
+            )
+            .map_err(into_err)?,
+        )
+        .map_err(into_err)
+    }
+
+    /// Get the backup machine, to back up room keys to the server.
+    #[napi(getter)]
+    pub fn backup_machine(&self) -> backup::BackupMachine {
+        self.inner.backup_machine().clone().into()
+    }
+
     /// Shut down the `OlmMachine`.
     ///
     /// The `OlmMachine` cannot be used after this method has been called,
@@ -495,3 +778,53 @@ impl OlmMachine {
         self.inner = OlmMachineInner::Closed;
     }
 }
+
+#[cfg(feature = "qrcode")]
+#[napi]
+impl OlmMachine {
+    /// Get a verification object for the given user ID with the given
+    /// flow ID (a to-device request ID if the verification has been
+    /// requested by a to-device request, or a room event ID if the
+    /// verification has been requested by a room event).
+    ///
+    /// It returns either a `Sas` or a `Qr` object, or `null`.
+    #[napi(strict)]
+    pub fn get_verification(
+        &self,
+        user_id: &identifiers::UserId,
+        flow_id: String,
+    ) -> napi::Result<Option<Either<verification::Sas, verification::Qr>>> {
+        use matrix_sdk_crypto::Verification::*;
+
+        self.inner
+            .get_verification(&user_id.inner, flow_id.as_str())
+            .map(|verification| match verification {
+                SasV1(sas) => Ok(Either::A(sas.into())),
+                QrV1(qr) => Ok(Either::B(qr.into())),
+                _ => Err(napi::Error::from_reason("Unknown verification type")),
+            })
+            .transpose()
+    }
+}
+
+#[cfg(not(feature = "qrcode"))]
+#[napi]
+impl OlmMachine {
+    /// Get a verification object for the given user ID with the given
+    /// flow ID (a to-device request ID if the verification has been
+    /// requested by a to-device request, or a room event ID if the
+    /// verification has been requested by a room event).
+    ///
+    /// It returns a `Sas` object, or `null`.
+    #[napi(strict)]
+    pub fn get_verification(
+        &self,
+        user_id: &identifiers::UserId,
+        flow_id: String,
+    ) -> Option<verification::Sas> {
+        self.inner
+            .get_verification(&user_id.inner, flow_id.as_str())
+            .and_then(matrix_sdk_crypto::Verification::sas_v1)
+            .map(Into::into)
+    }
+}
@@ -5,6 +5,7 @@ use std::time::Duration;
 use matrix_sdk_crypto::requests::{
     KeysBackupRequest as RumaKeysBackupRequest, KeysQueryRequest as RumaKeysQueryRequest,
     RoomMessageRequest as RumaRoomMessageRequest, ToDeviceRequest as RumaToDeviceRequest,
+    UploadSigningKeysRequest as RumaUploadSigningKeysRequest,
 };
 use napi::bindgen_prelude::{Either7, ToNapiValue};
 use napi_derive::*;
@@ -153,6 +154,7 @@ impl ToDeviceRequest {
 ///
 /// [specification]: https://spec.matrix.org/unstable/client-server-api/#post_matrixclientv3keyssignaturesupload
 #[napi]
+#[derive(Clone)]
 pub struct SignatureUploadRequest {
     /// The request ID.
     #[napi(readonly)]
@@ -240,6 +242,50 @@ impl KeysBackupRequest {
     }
 }
 
+/// Request that will publish a cross signing identity.
+///
+/// This uploads the public cross signing key triplet.
+#[napi]
+#[derive(Clone)]
+pub struct SigningKeysUploadRequest {
+    /// The request ID.
+    #[napi(readonly)]
+    pub id: String,
+
+    /// A JSON-encoded string containing the rest of the payload: `master_key`,
+    /// `self_signing_key`, `user_signing_key`.
+    ///
+    /// It represents the body of the HTTP request.
+    #[napi(readonly)]
+    pub body: String,
+}
+
+/// The requests to send out after bootstrapping a new cross signing
+/// identity, see `OlmMachine.bootstrap_cross_signing`.
+#[napi]
+pub struct CrossSigningBootstrapRequests {
+    pub(crate) upload_signing_keys_request: SigningKeysUploadRequest,
+    pub(crate) upload_signatures_request: SignatureUploadRequest,
+}
+
+#[napi]
+impl CrossSigningBootstrapRequests {
+    /// The request that publishes the public cross signing keys.
+    ///
+    /// Uploading these keys will require user interactive auth.
+    #[napi(getter)]
+    pub fn upload_signing_keys_request(&self) -> SigningKeysUploadRequest {
+        self.upload_signing_keys_request.clone()
+    }
+
+    /// The request that uploads the signature of our own device made
+    /// with the new self signing key.
+    #[napi(getter)]
+    pub fn upload_signatures_request(&self) -> SignatureUploadRequest {
+        self.upload_signatures_request.clone()
+    }
+}
+
 macro_rules! request {
     (
         $destination_request:ident from $source_request:ident
@@ -324,6 +370,7 @@ request!(ToDeviceRequest from RumaToDeviceRequest extracts event_type: string, t
 request!(SignatureUploadRequest from RumaSignatureUploadRequest groups signed_keys);
 request!(RoomMessageRequest from RumaRoomMessageRequest extracts room_id: string, txn_id: string, event_type: event_type, content: json);
 request!(KeysBackupRequest from RumaKeysBackupRequest groups rooms);
+request!(SigningKeysUploadRequest from RumaUploadSigningKeysRequest groups master_key, self_signing_key, user_signing_key);
 
 pub type OutgoingRequests = Either7<
     KeysUploadRequest,
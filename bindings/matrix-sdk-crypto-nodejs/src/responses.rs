@@ -160,7 +160,10 @@ impl DecryptedRoomEvent {
     #[napi(getter)]
     pub fn sender_curve25519_key(&self) -> Option<String> {
         Some(match &self.encryption_info.as_ref()?.algorithm_info {
-            AlgorithmInfo::MegolmV1AesSha2 { curve25519_key, .. } => curve25519_key.clone(),
+            AlgorithmInfo::MegolmV1AesSha2 { curve25519_key, .. }
+            | AlgorithmInfo::OlmV1Curve25519AesSha2 { curve25519_key, .. } => {
+                curve25519_key.clone()
+            }
         })
     }
 
@@ -169,7 +172,8 @@ impl DecryptedRoomEvent {
     #[napi(getter)]
     pub fn sender_claimed_ed25519_key(&self) -> Option<String> {
         match &self.encryption_info.as_ref()?.algorithm_info {
-            AlgorithmInfo::MegolmV1AesSha2 { sender_claimed_keys, .. } => {
+            AlgorithmInfo::MegolmV1AesSha2 { sender_claimed_keys, .. }
+            | AlgorithmInfo::OlmV1Curve25519AesSha2 { sender_claimed_keys, .. } => {
                 sender_claimed_keys.get(&ruma::DeviceKeyAlgorithm::Ed25519).cloned()
             }
         }
@@ -0,0 +1,54 @@
+//! Store types.
+
+use napi_derive::*;
+
+/// A struct containing private cross signing keys that can be backed
+/// up or uploaded to the secret store.
+#[napi]
+pub struct CrossSigningKeyExport {
+    pub(crate) inner: matrix_sdk_crypto::store::CrossSigningKeyExport,
+}
+
+impl From<matrix_sdk_crypto::store::CrossSigningKeyExport> for CrossSigningKeyExport {
+    fn from(inner: matrix_sdk_crypto::store::CrossSigningKeyExport) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl CrossSigningKeyExport {
+    /// Create a new `CrossSigningKeyExport` from the seeds of the
+    /// private cross signing keys, encoded as unpadded base64.
+    #[napi(constructor, strict)]
+    pub fn new(
+        master_key: Option<String>,
+        self_signing_key: Option<String>,
+        user_signing_key: Option<String>,
+    ) -> Self {
+        Self {
+            inner: matrix_sdk_crypto::store::CrossSigningKeyExport {
+                master_key,
+                self_signing_key,
+                user_signing_key,
+            },
+        }
+    }
+
+    /// The seed of the master key encoded as unpadded base64.
+    #[napi(getter)]
+    pub fn master_key(&self) -> Option<String> {
+        self.inner.master_key.clone()
+    }
+
+    /// The seed of the self signing key encoded as unpadded base64.
+    #[napi(getter)]
+    pub fn self_signing_key(&self) -> Option<String> {
+        self.inner.self_signing_key.clone()
+    }
+
+    /// The seed of the user signing key encoded as unpadded base64.
+    #[napi(getter)]
+    pub fn user_signing_key(&self) -> Option<String> {
+        self.inner.user_signing_key.clone()
+    }
+}
@@ -0,0 +1,774 @@
+//! Different verification types.
+
+use napi::bindgen_prelude::{Either, ToNapiValue};
+use napi_derive::*;
+use ruma::events::key::verification::VerificationMethod as RumaVerificationMethod;
+
+use crate::{
+    identifiers::{DeviceId, RoomId, UserId},
+    into_err, requests,
+};
+
+/// List of available verification methods.
+#[napi]
+pub enum VerificationMethod {
+    /// The `m.sas.v1` verification method.
+    ///
+    /// SAS means Short Authentication String.
+    SasV1,
+
+    /// The `m.qr_code.scan.v1` verification method.
+    QrCodeScanV1,
+
+    /// The `m.qr_code.show.v1` verification method.
+    QrCodeShowV1,
+
+    /// The `m.reciprocate.v1` verification method.
+    ReciprocateV1,
+}
+
+impl From<VerificationMethod> for RumaVerificationMethod {
+    fn from(value: VerificationMethod) -> Self {
+        use VerificationMethod::*;
+
+        match value {
+            SasV1 => Self::SasV1,
+            QrCodeScanV1 => Self::QrCodeScanV1,
+            QrCodeShowV1 => Self::QrCodeShowV1,
+            ReciprocateV1 => Self::ReciprocateV1,
+        }
+    }
+}
+
+impl TryFrom<RumaVerificationMethod> for VerificationMethod {
+    type Error = napi::Error;
+
+    fn try_from(value: RumaVerificationMethod) -> Result<Self, Self::Error> {
+        use RumaVerificationMethod::*;
+
+        Ok(match value {
+            SasV1 => Self::SasV1,
+            QrCodeScanV1 => Self::QrCodeScanV1,
+            QrCodeShowV1 => Self::QrCodeShowV1,
+            ReciprocateV1 => Self::ReciprocateV1,
+            _ => {
+                return Err(napi::Error::from_reason(format!(
+                    "Unknown verification method (received `{value:?}`)"
+                )))
+            }
+        })
+    }
+}
+
+pub(crate) fn into_ruma_methods(
+    methods: Option<Vec<VerificationMethod>>,
+) -> Option<Vec<RumaVerificationMethod>> {
+    methods.map(|methods| methods.into_iter().map(Into::into).collect())
+}
+
+/// An outgoing verification request, either a `ToDeviceRequest` or a
+/// `RoomMessageRequest`.
+pub type OutgoingVerificationRequest =
+    Either<requests::ToDeviceRequest, requests::RoomMessageRequest>;
+
+pub(crate) fn outgoing_verification_request(
+    request: matrix_sdk_crypto::OutgoingVerificationRequest,
+) -> napi::Result<OutgoingVerificationRequest> {
+    use matrix_sdk_crypto::OutgoingVerificationRequest::*;
+
+    let request_id = request.request_id().to_string();
+
+    Ok(match request {
+        ToDevice(request) => {
+            Either::A(requests::ToDeviceRequest::try_from((request_id, &request))?)
+        }
+
+        InRoom(request) => {
+            Either::B(requests::RoomMessageRequest::try_from((request_id, &request))?)
+        }
+    })
+}
+
+/// Short Authentication String (SAS) verification.
+#[napi]
+pub struct Sas {
+    pub(crate) inner: matrix_sdk_crypto::Sas,
+}
+
+impl From<matrix_sdk_crypto::Sas> for Sas {
+    fn from(inner: matrix_sdk_crypto::Sas) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl Sas {
+    /// Get our own user ID.
+    #[napi(getter)]
+    pub fn user_id(&self) -> UserId {
+        self.inner.user_id().to_owned().into()
+    }
+
+    /// Get our own device ID.
+    #[napi(getter)]
+    pub fn device_id(&self) -> DeviceId {
+        self.inner.device_id().to_owned().into()
+    }
+
+    /// Get the user id of the other side.
+    #[napi(getter)]
+    pub fn other_user_id(&self) -> UserId {
+        self.inner.other_user_id().to_owned().into()
+    }
+
+    /// Get the device ID of the other side.
+    #[napi(getter)]
+    pub fn other_device_id(&self) -> DeviceId {
+        self.inner.other_device_id().to_owned().into()
+    }
+
+    /// Get the unique ID that identifies this SAS verification flow,
+    /// be either a to-device request ID or a room event ID.
+    #[napi(getter)]
+    pub fn flow_id(&self) -> String {
+        self.inner.flow_id().as_str().to_owned()
+    }
+
+    /// Get the room ID if the verification is happening inside a
+    /// room.
+    #[napi(getter)]
+    pub fn room_id(&self) -> Option<RoomId> {
+        self.inner.room_id().map(ToOwned::to_owned).map(Into::into)
+    }
+
+    /// Does this verification flow support displaying emoji for the
+    /// short authentication string.
+    #[napi]
+    pub fn supports_emoji(&self) -> bool {
+        self.inner.supports_emoji()
+    }
+
+    /// Did this verification flow start from a verification request.
+    #[napi]
+    pub fn started_from_request(&self) -> bool {
+        self.inner.started_from_request()
+    }
+
+    /// Is this a verification that is veryfying one of our own
+    /// devices.
+    #[napi]
+    pub fn is_self_verification(&self) -> bool {
+        self.inner.is_self_verification()
+    }
+
+    /// Have we confirmed that the short auth string matches.
+    #[napi]
+    pub fn have_we_confirmed(&self) -> bool {
+        self.inner.have_we_confirmed()
+    }
+
+    /// Has the verification been accepted by both parties.
+    #[napi]
+    pub fn has_been_accepted(&self) -> bool {
+        self.inner.has_been_accepted()
+    }
+
+    /// Get info about the cancellation if the verification flow has
+    /// been cancelled.
+    #[napi(getter)]
+    pub fn cancel_info(&self) -> Option<CancelInfo> {
+        self.inner.cancel_info().map(Into::into)
+    }
+
+    /// Did we initiate the verification flow.
+    #[napi]
+    pub fn we_started(&self) -> bool {
+        self.inner.we_started()
+    }
+
+    /// Accept the SAS verification.
+    ///
+    /// This does nothing if the verification was already accepted,
+    /// otherwise it returns an `AcceptEventContent` that needs to be
+    /// sent out.
+    #[napi]
+    pub fn accept(&self) -> napi::Result<Option<OutgoingVerificationRequest>> {
+        self.inner.accept().map(outgoing_verification_request).transpose()
+    }
+
+    /// Confirm the SAS verification.
+    ///
+    /// This confirms that the short auth strings match on both sides.
+    ///
+    /// Does nothing if we’re not in a state where we can confirm the
+    /// short auth string, otherwise returns a `ConfirmedSas` holding the
+    /// requests that need to be sent to the server.
+    #[napi]
+    pub async fn confirm(&self) -> napi::Result<ConfirmedSas> {
+        let (outgoing_verification_requests, signature_upload_request) =
+            self.inner.confirm().await.map_err(into_err)?;
+
+        Ok(ConfirmedSas {
+            outgoing_requests: outgoing_verification_requests,
+            signature_upload_request,
+        })
+    }
+
+    /// Cancel the verification.
+    #[napi]
+    pub fn cancel(&self) -> napi::Result<Option<OutgoingVerificationRequest>> {
+        self.inner.cancel().map(outgoing_verification_request).transpose()
+    }
+
+    /// Has the SAS verification flow timed out.
+    #[napi]
+    pub fn timed_out(&self) -> bool {
+        self.inner.timed_out()
+    }
+
+    /// Are we in a state where we can show the short auth string.
+    #[napi]
+    pub fn can_be_presented(&self) -> bool {
+        self.inner.can_be_presented()
+    }
+
+    /// Is the SAS flow done.
+    #[napi]
+    pub fn is_done(&self) -> bool {
+        self.inner.is_done()
+    }
+
+    /// Is the SAS flow canceled.
+    #[napi]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+
+    /// Get the emoji version of the short auth string.
+    ///
+    /// Returns `null` if we can't yet present the short auth string,
+    /// otherwise seven `Emoji` containing the emoji and description.
+    #[napi]
+    pub fn emoji(&self) -> Option<Vec<Emoji>> {
+        Some(self.inner.emoji()?.into_iter().map(Into::into).collect())
+    }
+
+    /// Get the index of the emoji representing the short auth string
+    ///
+    /// Returns `null` if we can’t yet present the short auth
+    /// string, otherwise seven `u8` numbers in the range from 0 to 63
+    /// inclusive which can be converted to an emoji using [the
+    /// relevant specification
+    /// entry](https://spec.matrix.org/unstable/client-server-api/#sas-method-emoji).
+    #[napi]
+    pub fn emoji_index(&self) -> Option<Vec<u32>> {
+        Some(self.inner.emoji_index()?.into_iter().map(Into::into).collect())
+    }
+
+    /// Get the decimal version of the short auth string.
+    ///
+    /// Returns `null` if we can’t yet present the short auth string,
+    /// otherwise an array containing three 4-digit integers that
+    /// represent the short auth string.
+    #[napi]
+    pub fn decimals(&self) -> Option<Vec<u32>> {
+        let (first, second, third) = self.inner.decimals()?;
+
+        Some(vec![first.into(), second.into(), third.into()])
+    }
+}
+
+/// The requests to send out after a SAS verification has been
+/// confirmed, see `Sas.confirm`.
+#[napi]
+pub struct ConfirmedSas {
+    outgoing_requests: Vec<matrix_sdk_crypto::OutgoingVerificationRequest>,
+    signature_upload_request: Option<ruma::api::client::keys::upload_signatures::v3::Request>,
+}
+
+#[napi]
+impl ConfirmedSas {
+    /// The verification requests that need to be sent out, either
+    /// `ToDeviceRequest`s or `RoomMessageRequest`s.
+    #[napi(getter)]
+    pub fn outgoing_requests(&self) -> napi::Result<Vec<OutgoingVerificationRequest>> {
+        self.outgoing_requests.iter().cloned().map(outgoing_verification_request).collect()
+    }
+
+    /// The request that uploads the signatures of the newly verified
+    /// device or identity, if any.
+    #[napi(getter)]
+    pub fn signature_upload_request(
+        &self,
+    ) -> napi::Result<Option<requests::SignatureUploadRequest>> {
+        self.signature_upload_request
+            .as_ref()
+            .map(|request| {
+                requests::SignatureUploadRequest::try_from((
+                    ruma::TransactionId::new().to_string(),
+                    request,
+                ))
+            })
+            .transpose()
+    }
+}
+
+/// QR code based verification.
+#[cfg(feature = "qrcode")]
+#[napi]
+pub struct Qr {
+    pub(crate) inner: matrix_sdk_crypto::QrVerification,
+}
+
+#[cfg(feature = "qrcode")]
+impl From<matrix_sdk_crypto::QrVerification> for Qr {
+    fn from(inner: matrix_sdk_crypto::QrVerification) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg(feature = "qrcode")]
+#[napi]
+impl Qr {
+    /// Has the QR verification been scanned by the other side.
+    ///
+    /// When the verification object is in this state it’s required
+    /// that the user confirms that the other side has scanned the QR
+    /// code.
+    #[napi]
+    pub fn has_been_scanned(&self) -> bool {
+        self.inner.has_been_scanned()
+    }
+
+    /// Has the scanning of the QR code been confirmed by us?
+    #[napi]
+    pub fn has_been_confirmed(&self) -> bool {
+        self.inner.has_been_confirmed()
+    }
+
+    /// Get our own user ID.
+    #[napi(getter)]
+    pub fn user_id(&self) -> UserId {
+        self.inner.user_id().to_owned().into()
+    }
+
+    /// Get the user id of the other user that is participating in
+    /// this verification flow.
+    #[napi(getter)]
+    pub fn other_user_id(&self) -> UserId {
+        self.inner.other_user_id().to_owned().into()
+    }
+
+    /// Get the device ID of the other side.
+    #[napi(getter)]
+    pub fn other_device_id(&self) -> DeviceId {
+        self.inner.other_device_id().to_owned().into()
+    }
+
+    /// Did we initiate the verification request
+    #[napi]
+    pub fn we_started(&self) -> bool {
+        self.inner.we_started()
+    }
+
+    /// Get info about the cancellation if the verification flow has
+    /// been cancelled.
+    #[napi(getter)]
+    pub fn cancel_info(&self) -> Option<CancelInfo> {
+        self.inner.cancel_info().map(Into::into)
+    }
+
+    /// Has the verification flow completed?
+    #[napi]
+    pub fn is_done(&self) -> bool {
+        self.inner.is_done()
+    }
+
+    /// Has the verification flow been cancelled?
+    #[napi]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+
+    /// Is this a verification that is veryfying one of our own devices?
+    #[napi]
+    pub fn is_self_verification(&self) -> bool {
+        self.inner.is_self_verification()
+    }
+
+    /// Have we successfully scanned the QR code and are able to send
+    /// a reciprocation event.
+    #[napi]
+    pub fn reciprocated(&self) -> bool {
+        self.inner.reciprocated()
+    }
+
+    /// Get the unique ID that identifies this QR code verification
+    /// flow, be either a to-device request ID or a room event ID.
+    #[napi(getter)]
+    pub fn flow_id(&self) -> String {
+        self.inner.flow_id().as_str().to_owned()
+    }
+
+    /// Get the room id if the verification is happening inside a
+    /// room.
+    #[napi(getter)]
+    pub fn room_id(&self) -> Option<RoomId> {
+        self.inner.room_id().map(ToOwned::to_owned).map(Into::into)
+    }
+
+    /// Generate a QR code object that is representing this
+    /// verification flow, encoded as bytes.
+    ///
+    /// The bytes can be rendered into a QR code by the caller.
+    #[napi]
+    pub fn to_bytes(&self) -> napi::Result<napi::bindgen_prelude::Buffer> {
+        Ok(self.inner.to_bytes().map_err(into_err)?.into())
+    }
+
+    /// Notify the other side that we have successfully scanned the QR
+    /// code and that the QR verification flow can start.
+    ///
+    /// This will return some `OutgoingVerificationRequest` if the
+    /// QR code has been successfully scanned, otherwise it will
+    /// return `null`.
+    #[napi]
+    pub fn reciprocate(&self) -> napi::Result<Option<OutgoingVerificationRequest>> {
+        self.inner.reciprocate().map(outgoing_verification_request).transpose()
+    }
+
+    /// Confirm that the other side has scanned our QR code.
+    ///
+    /// It returns some `OutgoingVerificationRequest` if both sides have
+    /// confirmed, `null` otherwise.
+    #[napi]
+    pub fn confirm_scanning(&self) -> napi::Result<Option<OutgoingVerificationRequest>> {
+        self.inner.confirm_scanning().map(outgoing_verification_request).transpose()
+    }
+
+    /// Cancel the verification flow.
+    #[napi]
+    pub fn cancel(&self) -> napi::Result<Option<OutgoingVerificationRequest>> {
+        self.inner.cancel().map(outgoing_verification_request).transpose()
+    }
+}
+
+/// Information about the cancellation of a verification request or
+/// verification flow.
+#[napi]
+pub struct CancelInfo {
+    inner: matrix_sdk_crypto::CancelInfo,
+}
+
+impl From<matrix_sdk_crypto::CancelInfo> for CancelInfo {
+    fn from(inner: matrix_sdk_crypto::CancelInfo) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl CancelInfo {
+    /// Get the human readable reason of the cancellation.
+    #[napi(getter)]
+    pub fn reason(&self) -> String {
+        self.inner.reason().to_owned()
+    }
+
+    /// Get the `code` (e.g. `m.user`) that was used to cancel the
+    /// verification.
+    #[napi(getter)]
+    pub fn cancel_code(&self) -> String {
+        self.inner.cancel_code().as_str().to_owned()
+    }
+
+    /// Was the verification cancelled by us?
+    #[napi]
+    pub fn cancelled_by_us(&self) -> bool {
+        self.inner.cancelled_by_us()
+    }
+}
+
+/// An emoji that is used for interactive verification using a short
+/// auth string.
+///
+/// This will contain a single emoji and description from the list of
+/// emojis from [the specification].
+///
+/// [the specification]: https://spec.matrix.org/unstable/client-server-api/#sas-method-emoji
+#[napi]
+pub struct Emoji {
+    inner: matrix_sdk_crypto::Emoji,
+}
+
+impl From<matrix_sdk_crypto::Emoji> for Emoji {
+    fn from(inner: matrix_sdk_crypto::Emoji) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl Emoji {
+    /// The emoji symbol that represents a part of the short auth
+    /// string, for example: 🐶
+    #[napi(getter)]
+    pub fn symbol(&self) -> String {
+        self.inner.symbol.to_owned()
+    }
+
+    /// The description of the emoji, for example ‘Dog’.
+    #[napi(getter)]
+    pub fn description(&self) -> String {
+        self.inner.description.to_owned()
+    }
+}
+
+/// An object controlling key verification requests.
+///
+/// Interactive verification flows usually start with a verification
+/// request, this object lets you send and reply to such a
+/// verification request.
+///
+/// After the initial handshake the verification flow transitions into
+/// one of the verification methods.
+#[napi]
+pub struct VerificationRequest {
+    pub(crate) inner: matrix_sdk_crypto::VerificationRequest,
+}
+
+impl From<matrix_sdk_crypto::VerificationRequest> for VerificationRequest {
+    fn from(inner: matrix_sdk_crypto::VerificationRequest) -> Self {
+        Self { inner }
+    }
+}
+
+#[napi]
+impl VerificationRequest {
+    /// Our own user id.
+    #[napi(getter)]
+    pub fn own_user_id(&self) -> UserId {
+        self.inner.own_user_id().to_owned().into()
+    }
+
+    /// The ID of the other user that is participating in this
+    /// verification request.
+    #[napi(getter)]
+    pub fn other_user_id(&self) -> UserId {
+        self.inner.other_user().to_owned().into()
+    }
+
+    /// The ID of the other device that is participating in this
+    /// verification.
+    #[napi(getter)]
+    pub fn other_device_id(&self) -> Option<DeviceId> {
+        self.inner.other_device_id().map(Into::into)
+    }
+
+    /// Get the room ID if the verification is happening inside a
+    /// room.
+    #[napi(getter)]
+    pub fn room_id(&self) -> Option<RoomId> {
+        self.inner.room_id().map(ToOwned::to_owned).map(Into::into)
+    }
+
+    /// Get info about the cancellation if the verification request
+    /// has been cancelled.
+    #[napi(getter)]
+    pub fn cancel_info(&self) -> Option<CancelInfo> {
+        self.inner.cancel_info().map(Into::into)
+    }
+
+    /// Has the verification request been answered by another device.
+    #[napi]
+    pub fn is_passive(&self) -> bool {
+        self.inner.is_passive()
+    }
+
+    /// Is the verification request ready to start a verification flow.
+    #[napi]
+    pub fn is_ready(&self) -> bool {
+        self.inner.is_ready()
+    }
+
+    /// Has the verification flow timed out.
+    #[napi]
+    pub fn timed_out(&self) -> bool {
+        self.inner.timed_out()
+    }
+
+    /// Get the supported verification methods of the other side.
+    ///
+    /// Will be present only if the other side requested the
+    /// verification or if we’re in the ready state.
+    #[napi(getter)]
+    pub fn their_supported_methods(&self) -> napi::Result<Option<Vec<VerificationMethod>>> {
+        self.inner
+            .their_supported_methods()
+            .map(|methods| methods.into_iter().map(TryInto::try_into).collect())
+            .transpose()
+    }
+
+    /// Get our own supported verification methods that we advertised.
+    ///
+    /// Will be present only we requested the verification or if we’re
+    /// in the ready state.
+    #[napi(getter)]
+    pub fn our_supported_methods(&self) -> napi::Result<Option<Vec<VerificationMethod>>> {
+        self.inner
+            .our_supported_methods()
+            .map(|methods| methods.into_iter().map(TryInto::try_into).collect())
+            .transpose()
+    }
+
+    /// Get the unique ID of this verification request.
+    #[napi(getter)]
+    pub fn flow_id(&self) -> String {
+        self.inner.flow_id().as_str().to_owned()
+    }
+
+    /// Is this a verification that is veryfying one of our own
+    /// devices.
+    #[napi]
+    pub fn is_self_verification(&self) -> bool {
+        self.inner.is_self_verification()
+    }
+
+    /// Did we initiate the verification request.
+    #[napi]
+    pub fn we_started(&self) -> bool {
+        self.inner.we_started()
+    }
+
+    /// Has the verification flow that was started with this request
+    /// finished.
+    #[napi]
+    pub fn is_done(&self) -> bool {
+        self.inner.is_done()
+    }
+
+    /// Has the verification flow that was started with this request
+    /// been cancelled.
+    #[napi]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+
+    /// Accept the verification request signaling that our client
+    /// supports the given verification methods.
+    ///
+    /// `methods` represents the methods that we should advertise as
+    /// supported by us.
+    ///
+    /// It returns either a `ToDeviceRequest`, a `RoomMessageRequest`
+    /// or `null`.
+    #[napi(strict)]
+    pub fn accept_with_methods(
+        &self,
+        methods: Vec<VerificationMethod>,
+    ) -> napi::Result<Option<OutgoingVerificationRequest>> {
+        self.inner
+            .accept_with_methods(methods.into_iter().map(Into::into).collect())
+            .map(outgoing_verification_request)
+            .transpose()
+    }
+
+    /// Accept the verification request.
+    ///
+    /// This method will accept the request and signal that it
+    /// supports the `m.sas.v1`, the `m.qr_code.show.v1`, and
+    /// `m.reciprocate.v1` method.
+    ///
+    /// `m.qr_code.show.v1` will only be signaled if the `qrcode`
+    /// feature is enabled. If it's enabled and QR code scanning should
+    /// be supported or QR code showing shouldn't be supported the
+    /// `accept_with_methods` method should be used instead.
+    ///
+    /// It returns either a `ToDeviceRequest`, a `RoomMessageRequest`
+    /// or `null`.
+    #[napi]
+    pub fn accept(&self) -> napi::Result<Option<OutgoingVerificationRequest>> {
+        self.inner.accept().map(outgoing_verification_request).transpose()
+    }
+
+    /// Cancel the verification request.
+    ///
+    /// It returns either a `ToDeviceRequest`, a `RoomMessageRequest`
+    /// or `null`.
+    #[napi]
+    pub fn cancel(&self) -> napi::Result<Option<OutgoingVerificationRequest>> {
+        self.inner.cancel().map(outgoing_verification_request).transpose()
+    }
+
+    /// Transition from this verification request into a SAS
+    /// verification flow.
+    ///
+    /// Returns `null` if the verification request isn't ready to
+    /// start a SAS verification flow.
+    #[napi]
+    pub async fn start_sas(&self) -> napi::Result<Option<StartedSas>> {
+        Ok(self
+            .inner
+            .start_sas()
+            .await
+            .map_err(into_err)?
+            .map(|(sas, outgoing_request)| StartedSas { sas, outgoing_request }))
+    }
+}
+
+#[cfg(feature = "qrcode")]
+#[napi]
+impl VerificationRequest {
+    /// Generate a QR code that can be used by another client to start
+    /// a QR code based verification.
+    #[napi]
+    pub async fn generate_qr_code(&self) -> napi::Result<Option<Qr>> {
+        Ok(self.inner.generate_qr_code().await.map_err(into_err)?.map(Into::into))
+    }
+}
+
+/// A verification request that has just been created, along with
+/// the request that needs to be sent out to notify the other side.
+#[napi]
+pub struct RequestedVerification {
+    pub(crate) request: matrix_sdk_crypto::VerificationRequest,
+    pub(crate) outgoing_request: matrix_sdk_crypto::OutgoingVerificationRequest,
+}
+
+#[napi]
+impl RequestedVerification {
+    /// The newly created verification request.
+    #[napi(getter)]
+    pub fn request(&self) -> VerificationRequest {
+        self.request.clone().into()
+    }
+
+    /// The request that needs to be sent out, either a
+    /// `ToDeviceRequest` or a `RoomMessageRequest`.
+    #[napi(getter)]
+    pub fn outgoing_request(&self) -> napi::Result<OutgoingVerificationRequest> {
+        outgoing_verification_request(self.outgoing_request.clone())
+    }
+}
+
+/// A SAS verification flow that has just been started, along with
+/// the request that needs to be sent out to notify the other side.
+#[napi]
+pub struct StartedSas {
+    pub(crate) sas: matrix_sdk_crypto::Sas,
+    pub(crate) outgoing_request: matrix_sdk_crypto::OutgoingVerificationRequest,
+}
+
+#[napi]
+impl StartedSas {
+    /// The newly started SAS verification flow.
+    #[napi(getter)]
+    pub fn sas(&self) -> Sas {
+        self.sas.clone().into()
+    }
+
+    /// The request that needs to be sent out, either a
+    /// `ToDeviceRequest` or a `RoomMessageRequest`.
+    #[napi(getter)]
+    pub fn outgoing_request(&self) -> napi::Result<OutgoingVerificationRequest> {
+        outgoing_verification_request(self.outgoing_request.clone())
+    }
+}
@@ -9,6 +9,12 @@ pub struct Ed25519PublicKey {
     inner: vodozemac::Ed25519PublicKey,
 }
 
+impl From<vodozemac::Ed25519PublicKey> for Ed25519PublicKey {
+    fn from(inner: vodozemac::Ed25519PublicKey) -> Self {
+        Self { inner }
+    }
+}
+
 #[napi]
 impl Ed25519PublicKey {
     /// The number of bytes an Ed25519 public key has.
@@ -65,6 +71,12 @@ pub struct Curve25519PublicKey {
     inner: vodozemac::Curve25519PublicKey,
 }
 
+impl From<vodozemac::Curve25519PublicKey> for Curve25519PublicKey {
+    fn from(inner: vodozemac::Curve25519PublicKey) -> Self {
+        Self { inner }
+    }
+}
+
 #[napi]
 impl Curve25519PublicKey {
     /// The number of bytes a Curve25519 public key has.
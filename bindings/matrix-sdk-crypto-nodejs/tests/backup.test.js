@@ -0,0 +1,72 @@
+const { OlmMachine, UserId, DeviceId, BackupMachine, BackupRecoveryKey, MegolmV1BackupKey } = require("../");
+
+describe(BackupRecoveryKey.name, () => {
+    test("can create a random key", () => {
+        const key = BackupRecoveryKey.createRandomKey();
+
+        expect(key).toBeInstanceOf(BackupRecoveryKey);
+        expect(key.toBase64()).toMatch(/^[A-Za-z0-9+/]+$/);
+    });
+
+    test("can be encoded and decoded", () => {
+        const key = BackupRecoveryKey.createRandomKey();
+
+        expect(BackupRecoveryKey.fromBase64(key.toBase64()).toBase64()).toStrictEqual(key.toBase64());
+        expect(BackupRecoveryKey.fromBase58(key.toBase58()).toBase58()).toStrictEqual(key.toBase58());
+    });
+
+    test("cannot decode an invalid key", () => {
+        expect(() => BackupRecoveryKey.fromBase58("foo")).toThrow();
+    });
+
+    test("has a public key", () => {
+        const publicKey = BackupRecoveryKey.createRandomKey().megolmV1PublicKey;
+
+        expect(publicKey).toBeInstanceOf(MegolmV1BackupKey);
+        expect(publicKey.algorithm).toStrictEqual("m.megolm_backup.v1.curve25519-aes-sha2");
+        expect(publicKey.publicKeyBase64).toMatch(/^[A-Za-z0-9+/]+$/);
+    });
+});
+
+describe(BackupMachine.name, () => {
+    function machine() {
+        return OlmMachine.initialize(new UserId("@alice:example.org"), new DeviceId("foobar"));
+    }
+
+    test("is disabled by default", async () => {
+        const backupMachine = (await machine()).backupMachine;
+
+        expect(backupMachine).toBeInstanceOf(BackupMachine);
+        expect(await backupMachine.enabled()).toStrictEqual(false);
+        expect(await backupMachine.backup()).toBeNull();
+    });
+
+    test("can be enabled and disabled", async () => {
+        const backupMachine = (await machine()).backupMachine;
+        const publicKey = BackupRecoveryKey.createRandomKey().megolmV1PublicKey;
+
+        await backupMachine.enableBackupV1(publicKey.publicKeyBase64, "1");
+        expect(await backupMachine.enabled()).toStrictEqual(true);
+
+        await backupMachine.disableBackup();
+        expect(await backupMachine.enabled()).toStrictEqual(false);
+    });
+
+    test("can count room keys", async () => {
+        const counts = await (await machine()).backupMachine.roomKeyCounts();
+
+        expect(counts.total).toStrictEqual(0);
+        expect(counts.backedUp).toStrictEqual(0);
+    });
+
+    test("can save and read the recovery key", async () => {
+        const backupMachine = (await machine()).backupMachine;
+        const key = BackupRecoveryKey.createRandomKey();
+
+        await backupMachine.saveRecoveryKey(key, "1");
+
+        const backupKeys = await backupMachine.getBackupKeys();
+        expect(backupKeys.recoveryKey.toBase64()).toStrictEqual(key.toBase64());
+        expect(backupKeys.backupVersion).toStrictEqual("1");
+    });
+});
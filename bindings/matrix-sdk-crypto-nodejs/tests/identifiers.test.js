@@ -5,6 +5,7 @@ const {
     DeviceKeyAlgorithm,
     DeviceKeyAlgorithmName,
     RoomId,
+    EventId,
     ServerName,
 } = require("../");
 
@@ -118,6 +119,42 @@ describe(RoomId.name, () => {
     });
 });
 
+describe(EventId.name, () => {
+    test("cannot be invalid", () => {
+        expect(() => {
+            new EventId("foo");
+        }).toThrow();
+    });
+
+    describe("Versions 1 & 2", () => {
+        const event = new EventId("$h29iv0s8:foo.org");
+
+        test("localpart is present", () => {
+            expect(event.localpart).toStrictEqual("h29iv0s8");
+        });
+
+        test("server name is present", () => {
+            expect(event.serverName).toBeInstanceOf(ServerName);
+        });
+
+        test("can read the event ID as string", () => {
+            expect(event.toString()).toStrictEqual("$h29iv0s8:foo.org");
+        });
+    });
+
+    describe("Version 3", () => {
+        const event = new EventId("$acR1l0raoZnm60CBwAVgqbZqoO/mYU81xysh1u7XcJk");
+
+        test("localpart is present", () => {
+            expect(event.localpart).toStrictEqual("acR1l0raoZnm60CBwAVgqbZqoO/mYU81xysh1u7XcJk");
+        });
+
+        test("server name is absent", () => {
+            expect(event.serverName).toBeNull();
+        });
+    });
+});
+
 describe(ServerName.name, () => {
     test("cannot be invalid", () => {
         expect(() => {
@@ -15,6 +15,13 @@ const {
     CrossSigningStatus,
     MaybeSignature,
     StoreType,
+    CrossSigningBootstrapRequests,
+    CrossSigningKeyExport,
+    SigningKeysUploadRequest,
+    SignatureUploadRequest,
+    OwnUserIdentity,
+    UserDevices,
+    Device,
 } = require("../");
 const path = require("path");
 const os = require("os");
@@ -468,4 +475,94 @@ describe(OlmMachine.name, () => {
             expect(signatures.getSignature(user, new DeviceKeyId("world:foobar"))).toBeNull();
         }
     });
+
+    test("can read the display name", async () => {
+        expect(await (await machine()).displayName()).toBeNull();
+    });
+
+    test("can read tracked users", async () => {
+        const m = await machine();
+        await m.updateTrackedUsers([user]);
+
+        const trackedUsers = await m.trackedUsers();
+
+        expect(trackedUsers).toHaveLength(1);
+        expect(trackedUsers[0].toString()).toStrictEqual(user.toString());
+    });
+
+    test("can read own devices", async () => {
+        const m = await machine();
+        const userDevices = await m.getUserDevices(user);
+
+        expect(userDevices).toBeInstanceOf(UserDevices);
+        expect(userDevices.keys().map((deviceId) => deviceId.toString())).toStrictEqual([device.toString()]);
+
+        const ownDevice = await m.getDevice(user, device);
+
+        expect(ownDevice).toBeInstanceOf(Device);
+        expect(ownDevice.isLocallyTrusted()).toStrictEqual(true);
+        expect(await m.getDevice(user, new DeviceId("unknown"))).toBeNull();
+    });
+
+    test("can bootstrap, export and import cross-signing keys", async () => {
+        const m = await machine();
+
+        expect(await m.exportCrossSigningKeys()).toBeNull();
+
+        const requests = await m.bootstrapCrossSigning(true);
+
+        expect(requests).toBeInstanceOf(CrossSigningBootstrapRequests);
+        expect(requests.uploadSigningKeysRequest).toBeInstanceOf(SigningKeysUploadRequest);
+        expect(JSON.parse(requests.uploadSigningKeysRequest.body).master_key).toBeDefined();
+        expect(requests.uploadSignaturesRequest).toBeInstanceOf(SignatureUploadRequest);
+
+        const crossSigningStatus = await m.crossSigningStatus();
+        expect(crossSigningStatus.hasMaster).toStrictEqual(true);
+        expect(crossSigningStatus.hasSelfSigning).toStrictEqual(true);
+        expect(crossSigningStatus.hasUserSigning).toStrictEqual(true);
+
+        expect(await m.getIdentity(user)).toBeInstanceOf(OwnUserIdentity);
+
+        const exported = await m.exportCrossSigningKeys();
+        expect(exported).toBeInstanceOf(CrossSigningKeyExport);
+        expect(exported.masterKey).toBeDefined();
+        expect(exported.selfSigningKey).toBeDefined();
+        expect(exported.userSigningKey).toBeDefined();
+
+        const other = await machine(user, new DeviceId("other"));
+        const importedStatus = await other.importCrossSigningKeys(
+            new CrossSigningKeyExport(exported.masterKey, exported.selfSigningKey, exported.userSigningKey),
+        );
+        expect(importedStatus).toBeInstanceOf(CrossSigningStatus);
+    });
+
+    test("can export, encrypt, decrypt and import room keys", async () => {
+        const m = await machine();
+        await m.shareRoomKey(room, [], new EncryptionSettings());
+
+        const exported = await m.exportRoomKeys(room);
+        expect(JSON.parse(exported)).toHaveLength(1);
+        expect(JSON.parse(await m.exportRoomKeys(new RoomId("!other:matrix.org")))).toHaveLength(0);
+
+        const encrypted = OlmMachine.encryptExportedRoomKeys(exported, "passphrase", 10000);
+        const decrypted = OlmMachine.decryptExportedRoomKeys(encrypted, "passphrase");
+        expect(JSON.parse(decrypted)).toStrictEqual(JSON.parse(exported));
+
+        const other = await machine(new UserId("@bob:example.org"), new DeviceId("BOB"));
+        const result = JSON.parse(await other.importRoomKeys(decrypted));
+
+        expect(result.imported_count).toStrictEqual(1);
+        expect(result.total_count).toStrictEqual(1);
+        expect(result.keys[room.toString()]).toBeDefined();
+    });
+
+    test("can invalidate a group session", async () => {
+        const m = await machine();
+
+        expect(await m.invalidateGroupSession(room)).toStrictEqual(false);
+
+        await m.shareRoomKey(room, [], new EncryptionSettings());
+
+        expect(await m.invalidateGroupSession(room)).toStrictEqual(true);
+    });
 });
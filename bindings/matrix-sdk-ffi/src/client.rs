@@ -23,7 +23,8 @@ use tokio::sync::broadcast;
 use tracing::{debug, warn};
 
 use super::{
-    room::Room, session_verification::SessionVerificationController, ClientState, RUNTIME,
+    encryption::Encryption, room::Room, session_verification::SessionVerificationController,
+    ClientState, RUNTIME,
 };
 
 impl std::ops::Deref for Client {
@@ -333,6 +334,11 @@ impl Client {
         })
     }
 
+    /// Get the encryption manager of the client.
+    pub fn encryption(&self) -> Arc<Encryption> {
+        Arc::new(Encryption::new(self.client.clone()))
+    }
+
     /// Log out the current user
     pub fn logout(&self) -> anyhow::Result<()> {
         RUNTIME.block_on(async move {
@@ -0,0 +1,186 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::{bail, Context};
+use futures_util::StreamExt;
+pub use matrix_sdk::encryption::backups::BackupState;
+use matrix_sdk::{
+    encryption::RoomKeyImportResult as MatrixRoomKeyImportResult,
+    ruma::api::client::uiaa::{AuthData, Password, UserIdentifier},
+    Client as MatrixClient,
+};
+
+use super::RUNTIME;
+use crate::StoppableSpawn;
+
+pub trait BackupStateListener: Sync + Send {
+    fn on_update(&self, state: BackupState);
+}
+
+pub trait BackupRestoreProgressListener: Sync + Send {
+    fn on_progress(&self, imported_count: u64, total_count: u64);
+}
+
+pub trait UiaaPasswordProvider: Sync + Send {
+    /// Get the password of the user to answer the user interactive auth
+    /// session with the given ID, `None` aborts the operation.
+    fn password(&self, session: Option<String>) -> Option<String>;
+}
+
+pub struct RoomKeyImportResult {
+    /// The number of room keys that were imported.
+    pub imported_count: u64,
+    /// The total number of room keys that were found.
+    pub total_count: u64,
+}
+
+impl From<MatrixRoomKeyImportResult> for RoomKeyImportResult {
+    fn from(result: MatrixRoomKeyImportResult) -> Self {
+        Self {
+            imported_count: result.imported_count as u64,
+            total_count: result.total_count as u64,
+        }
+    }
+}
+
+/// The encryption manager of a [`Client`](crate::Client), handling key
+/// backups, key exports and cross-signing.
+#[derive(Clone)]
+pub struct Encryption {
+    client: MatrixClient,
+}
+
+impl Encryption {
+    pub(crate) fn new(client: MatrixClient) -> Self {
+        Self { client }
+    }
+
+    pub fn backup_state(&self) -> BackupState {
+        self.client.encryption().backups().state()
+    }
+
+    /// Call the listener with the current backup state and every time it
+    /// changes.
+    pub fn backup_state_listener(
+        &self,
+        listener: Box<dyn BackupStateListener>,
+    ) -> Arc<StoppableSpawn> {
+        let mut state_stream = self.client.encryption().backups().state_stream();
+
+        Arc::new(
+            RUNTIME
+                .spawn(async move {
+                    while let Some(state) = state_stream.next().await {
+                        listener.on_update(state);
+                    }
+                })
+                .into(),
+        )
+    }
+
+    /// Create a new key backup and start uploading room keys to it.
+    ///
+    /// Returns the base58 encoded recovery key of the backup.
+    pub fn enable_backups(&self) -> anyhow::Result<String> {
+        RUNTIME.block_on(async move {
+            let recovery_key = self.client.encryption().backups().enable().await?;
+            Ok(recovery_key.to_base58())
+        })
+    }
+
+    /// Enable the key backup we used before, if it's still the current one on
+    /// the homeserver.
+    ///
+    /// This should be called once after restoring a session.
+    pub fn resume_backups(&self) -> anyhow::Result<bool> {
+        RUNTIME.block_on(async move { Ok(self.client.encryption().backups().resume().await?) })
+    }
+
+    /// Stop uploading room keys and delete the key backup from the homeserver.
+    pub fn disable_backups(&self) -> anyhow::Result<()> {
+        RUNTIME.block_on(async move { Ok(self.client.encryption().backups().disable().await?) })
+    }
+
+    /// Download all the room keys from the key backup and start uploading
+    /// room keys to it.
+    pub fn restore_backup(
+        &self,
+        recovery_key: String,
+        progress_listener: Option<Box<dyn BackupRestoreProgressListener>>,
+    ) -> anyhow::Result<RoomKeyImportResult> {
+        RUNTIME.block_on(async move {
+            let result = self
+                .client
+                .encryption()
+                .backups()
+                .restore(&recovery_key, |imported_count, total_count| {
+                    if let Some(listener) = &progress_listener {
+                        listener.on_progress(imported_count as u64, total_count as u64);
+                    }
+                })
+                .await?;
+
+            Ok(result.into())
+        })
+    }
+
+    /// Export all our room keys to the file at the given path, encrypted with
+    /// the given passphrase.
+    pub fn export_room_keys(&self, path: String, passphrase: String) -> anyhow::Result<()> {
+        RUNTIME.block_on(async move {
+            self.client
+                .encryption()
+                .export_room_keys(PathBuf::from(path), &passphrase, |_| true)
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Import the room keys from the file at the given path, decrypting it
+    /// with the given passphrase.
+    pub fn import_room_keys(
+        &self,
+        path: String,
+        passphrase: String,
+    ) -> anyhow::Result<RoomKeyImportResult> {
+        RUNTIME.block_on(async move {
+            let result =
+                self.client.encryption().import_room_keys(PathBuf::from(path), &passphrase).await?;
+            Ok(result.into())
+        })
+    }
+
+    /// Create and upload a cross-signing identity, if we don't have one yet.
+    ///
+    /// If the homeserver requires user interactive auth, the password of the
+    /// user is requested from the `auth_provider` and the upload is retried.
+    pub fn bootstrap_cross_signing(
+        &self,
+        auth_provider: Option<Box<dyn UiaaPasswordProvider>>,
+    ) -> anyhow::Result<()> {
+        RUNTIME.block_on(async move {
+            let encryption = self.client.encryption();
+
+            let Err(error) = encryption.bootstrap_cross_signing(None).await else {
+                return Ok(());
+            };
+
+            let session = error.as_uiaa_response().map(|uiaa| uiaa.session.clone());
+            let (Some(session), Some(auth_provider)) = (session, auth_provider) else {
+                return Err(error.into());
+            };
+
+            let Some(password) = auth_provider.password(session.clone()) else {
+                bail!("User interactive auth was aborted");
+            };
+
+            let user_id = self.client.user_id().context("Missing user ID")?;
+            let mut password =
+                Password::new(UserIdentifier::UserIdOrLocalpart(user_id.to_string()), password);
+            password.session = session;
+
+            encryption.bootstrap_cross_signing(Some(AuthData::Password(password))).await?;
+
+            Ok(())
+        })
+    }
+}
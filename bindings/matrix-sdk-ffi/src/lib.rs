@@ -28,6 +28,7 @@ mod platform;
 pub mod authentication_service;
 pub mod client;
 pub mod client_builder;
+pub mod encryption;
 mod helpers;
 pub mod room;
 pub mod session_verification;
@@ -50,8 +51,8 @@ pub use matrix_sdk::{
 };
 
 pub use self::{
-    authentication_service::*, client::*, room::*, session_verification::*, sliding_sync::*,
-    timeline::*,
+    authentication_service::*, client::*, encryption::*, room::*, session_verification::*,
+    sliding_sync::*, timeline::*,
 };
 
 #[derive(Default, Debug)]
@@ -85,6 +86,7 @@ mod uniffi_types {
         },
         client::Client,
         client_builder::ClientBuilder,
+        encryption::Encryption,
         room::{Membership, MembershipState, Room, RoomMember},
         session_verification::{SessionVerificationController, SessionVerificationEmoji},
         sliding_sync::{
@@ -176,9 +176,8 @@ impl SlidingSyncRoom {
     ) -> anyhow::Result<SlidingSyncSubscribeResult> {
         let (items, mut stoppable_spawn) = self.add_timeline_listener_inner(listener)?;
         let room_id = self.inner.room_id().clone();
-        self.runner.subscribe(room_id.clone(), settings.map(Into::into));
-        let runner = self.runner.clone();
-        stoppable_spawn.set_callback(Box::new(move || runner.unsubscribe(room_id)));
+        let subscription = self.runner.subscribe_with_handle(room_id, settings.map(Into::into));
+        stoppable_spawn.set_callback(Box::new(move || drop(subscription)));
         Ok(SlidingSyncSubscribeResult { items, task_handle: Arc::new(stoppable_spawn) })
     }
 
@@ -650,6 +649,18 @@ impl SlidingSync {
         Ok(())
     }
 
+    /// Subscribe to the room until the returned handle is cancelled or
+    /// dropped, other subscribers of the same room are kept subscribed.
+    pub fn subscribe_with_handle(
+        &self,
+        room_id: String,
+        settings: Option<RoomSubscription>,
+    ) -> anyhow::Result<Arc<StoppableSpawn>> {
+        let subscription =
+            self.inner.subscribe_with_handle(room_id.try_into()?, settings.map(Into::into));
+        Ok(Arc::new(StoppableSpawn::with_callback(Box::new(move || drop(subscription)))))
+    }
+
     pub fn unsubscribe(&self, room_id: String) -> anyhow::Result<()> {
         self.inner.unsubscribe(room_id.try_into()?);
         Ok(())
@@ -221,6 +221,9 @@ pub enum EventSendState {
     /// The local event has been sent to the server, but unsuccessfully: The
     /// sending has failed.
     SendingFailed { error: String },
+    /// Sending the local event failed, it will be retried after the given
+    /// delay, in milliseconds.
+    Retrying { error: String, delay_ms: u64 },
     /// The local event has been sent successfully to the server.
     Sent { event_id: String },
 }
@@ -232,6 +235,9 @@ impl From<&matrix_sdk::room::timeline::EventSendState> for EventSendState {
         match value {
             NotSentYet => Self::NotSendYet,
             SendingFailed { error } => Self::SendingFailed { error: error.to_string() },
+            Retrying { error, delay } => {
+                Self::Retrying { error: error.to_string(), delay_ms: delay.as_millis() as u64 }
+            }
             Sent { event_id } => Self::Sent { event_id: event_id.to_string() },
         }
     }
@@ -0,0 +1,296 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Backfilling of historical messages ([MSC2716]).
+//!
+//! [MSC2716]: https://github.com/matrix-org/matrix-spec-proposals/pull/2716
+
+use ruma::{
+    events::{AnyStateEvent, AnyTimelineEvent, MessageLikeEventContent},
+    serde::Raw,
+    MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, UserId,
+};
+use serde_json::{json, Value as JsonValue};
+use tracing::debug;
+
+use crate::{AppService, Localpart, Result};
+
+/// The default number of events sent in one batch.
+const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// A historical message to import into a room with a [`Backfill`].
+#[derive(Debug, Clone)]
+pub struct HistoricalEvent {
+    sender_localpart: Localpart,
+    origin_server_ts: MilliSecondsSinceUnixEpoch,
+    event_type: String,
+    content: JsonValue,
+}
+
+impl HistoricalEvent {
+    /// Create a new historical message.
+    ///
+    /// # Arguments
+    ///
+    /// * `sender_localpart` - The localpart of the appservice user that sent
+    ///   the message.
+    /// * `origin_server_ts` - The time the message was originally sent at.
+    /// * `content` - The content of the message.
+    pub fn new(
+        sender_localpart: impl Into<Localpart>,
+        origin_server_ts: MilliSecondsSinceUnixEpoch,
+        content: impl MessageLikeEventContent,
+    ) -> Result<Self> {
+        Ok(Self {
+            sender_localpart: sender_localpart.into(),
+            origin_server_ts,
+            event_type: content.event_type().to_string(),
+            content: serde_json::to_value(&content)?,
+        })
+    }
+}
+
+/// The result of a [`Backfill`].
+#[derive(Debug, Clone, Default)]
+pub struct BackfillResponse {
+    /// The IDs of the imported messages, in chronological order.
+    pub event_ids: Vec<OwnedEventId>,
+    /// The ID of the batch to use to import messages that are older than the
+    /// imported ones, see [`Backfill::batch_id()`].
+    pub next_batch_id: Option<String>,
+}
+
+/// Import historical messages into a room, with [MSC2716] batch sending.
+///
+/// The messages are sent in batches, from the newest to the oldest one, each
+/// batch being inserted before the previous one. The membership of the
+/// senders is added to the state at the start of every batch.
+///
+/// The batches are sent by the `sender_localpart` user by default, which
+/// needs to be allowed to send historical messages in the room, see
+/// [`Backfill::sender()`].
+///
+/// [MSC2716]: https://github.com/matrix-org/matrix-spec-proposals/pull/2716
+#[derive(Debug)]
+pub struct Backfill<'a> {
+    appservice: &'a AppService,
+    room_id: OwnedRoomId,
+    prev_event_id: OwnedEventId,
+    batch_id: Option<String>,
+    batch_size: usize,
+    sender_localpart: Option<&'a str>,
+}
+
+impl<'a> Backfill<'a> {
+    pub(crate) fn new(
+        appservice: &'a AppService,
+        room_id: OwnedRoomId,
+        prev_event_id: OwnedEventId,
+    ) -> Self {
+        Self {
+            appservice,
+            room_id,
+            prev_event_id,
+            batch_id: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            sender_localpart: None,
+        }
+    }
+
+    /// Continue a previous backfill, inserting the messages before the batch
+    /// with the given ID.
+    pub fn batch_id(mut self, batch_id: impl Into<String>) -> Self {
+        self.batch_id = Some(batch_id.into());
+        self
+    }
+
+    /// Set the maximum number of messages sent in one batch.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Send the batches as the appservice user with the given localpart,
+    /// instead of the `sender_localpart` user.
+    pub fn sender(mut self, localpart: &'a str) -> Self {
+        self.sender_localpart = Some(localpart);
+        self
+    }
+
+    /// Import the given messages, in chronological order.
+    pub async fn send(
+        self,
+        events: impl IntoIterator<Item = HistoricalEvent>,
+    ) -> Result<BackfillResponse> {
+        let events: Vec<_> = events.into_iter().collect();
+        let client = self.appservice.user(self.sender_localpart).await?;
+
+        let mut batch_id = self.batch_id;
+        let mut batches_event_ids = Vec::new();
+
+        // Every batch is inserted before the previous one, so the newest
+        // messages need to be sent first.
+        for batch in events.rchunks(self.batch_size) {
+            let user_ids = batch
+                .iter()
+                .map(|event| {
+                    UserId::parse_with_server_name(
+                        event.sender_localpart.as_str(),
+                        &self.appservice.server_name,
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            // The senders need to be members of the room at the start of the
+            // batch.
+            let mut senders: Vec<&UserId> = Vec::new();
+            for user_id in &user_ids {
+                if !senders.contains(&&**user_id) {
+                    senders.push(user_id);
+                }
+            }
+
+            let state_events_at_start = senders
+                .iter()
+                .map(|user_id| member_event(user_id, batch[0].origin_server_ts))
+                .collect::<Result<_>>()?;
+            let events = batch
+                .iter()
+                .zip(&user_ids)
+                .map(|(event, user_id)| timeline_event(event, user_id))
+                .collect::<Result<_>>()?;
+
+            let mut request = batch_send::Request::new(
+                self.room_id.clone(),
+                self.prev_event_id.clone(),
+                state_events_at_start,
+                events,
+            );
+            request.batch_id = batch_id.take();
+            request.ts = batch.last().map(|event| event.origin_server_ts);
+
+            let response = client.send(request, None).await?;
+            debug!(
+                room_id = ?self.room_id,
+                next_batch_id = %response.next_batch_id,
+                "Sent a batch of historical messages"
+            );
+
+            batches_event_ids.push(response.event_ids);
+            batch_id = Some(response.next_batch_id);
+        }
+
+        Ok(BackfillResponse {
+            event_ids: batches_event_ids.into_iter().rev().flatten().collect(),
+            next_batch_id: batch_id,
+        })
+    }
+}
+
+/// Create the join event of the given user, for the state at the start of a
+/// batch.
+fn member_event(
+    user_id: &UserId,
+    origin_server_ts: MilliSecondsSinceUnixEpoch,
+) -> Result<Raw<AnyStateEvent>> {
+    Ok(Raw::new(&json!({
+        "type": "m.room.member",
+        "sender": user_id,
+        "state_key": user_id,
+        "origin_server_ts": origin_server_ts,
+        "content": { "membership": "join" },
+    }))?
+    .cast())
+}
+
+/// Create the JSON of a historical message.
+fn timeline_event(event: &HistoricalEvent, sender: &UserId) -> Result<Raw<AnyTimelineEvent>> {
+    Ok(Raw::new(&json!({
+        "type": event.event_type,
+        "sender": sender,
+        "origin_server_ts": event.origin_server_ts,
+        "content": event.content,
+    }))?
+    .cast())
+}
+
+/// The [MSC2716] batch send endpoint.
+///
+/// [MSC2716]: https://github.com/matrix-org/matrix-spec-proposals/pull/2716
+mod batch_send {
+    use ruma::{
+        api::{request, response, Metadata},
+        events::{AnyStateEvent, AnyTimelineEvent},
+        metadata,
+        serde::Raw,
+        MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId,
+    };
+
+    const METADATA: Metadata = metadata! {
+        method: POST,
+        rate_limited: false,
+        authentication: AccessToken,
+        history: {
+            unstable => "/_matrix/client/unstable/org.matrix.msc2716/rooms/:room_id/batch_send",
+        }
+    };
+
+    #[request]
+    pub struct Request {
+        /// The room to import the messages into.
+        #[ruma_api(path)]
+        pub room_id: OwnedRoomId,
+
+        /// The event the messages are inserted after.
+        #[ruma_api(query)]
+        pub prev_event_id: OwnedEventId,
+
+        /// The batch the messages are inserted before.
+        #[ruma_api(query)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub batch_id: Option<String>,
+
+        /// The timestamp of the events created by the homeserver for the
+        /// batch.
+        #[ruma_api(query)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ts: Option<MilliSecondsSinceUnixEpoch>,
+
+        /// The state of the room at the start of the batch.
+        pub state_events_at_start: Vec<Raw<AnyStateEvent>>,
+
+        /// The messages to import, in chronological order.
+        pub events: Vec<Raw<AnyTimelineEvent>>,
+    }
+
+    #[response]
+    pub struct Response {
+        /// The IDs of the imported messages.
+        pub event_ids: Vec<OwnedEventId>,
+
+        /// The ID of the batch to use to import older messages.
+        pub next_batch_id: String,
+    }
+
+    impl Request {
+        pub fn new(
+            room_id: OwnedRoomId,
+            prev_event_id: OwnedEventId,
+            state_events_at_start: Vec<Raw<AnyStateEvent>>,
+            events: Vec<Raw<AnyTimelineEvent>>,
+        ) -> Self {
+            Self { room_id, prev_event_id, batch_id: None, ts: None, state_events_at_start, events }
+        }
+    }
+}
@@ -68,6 +68,18 @@ pub enum Error {
     #[error("regex error: {0}")]
     Regex(#[from] regex::Error),
 
+    #[error("invalid regex `{regex}` in the {kind} namespace of the registration: {source}")]
+    InvalidNamespaceRegex { kind: &'static str, regex: String, source: regex::Error },
+
+    #[error("the user namespaces of the registration don't cover the sender_localpart user {0}")]
+    SenderNotInNamespace(ruma::OwnedUserId),
+
+    #[error("the `{0}` field of the registration is empty")]
+    EmptyRegistrationField(&'static str),
+
+    #[error("evicting encrypted clients from the pool requires a persistent store per user")]
+    ClientPoolWithoutUserStores,
+
     #[error("serde yaml error: {0}")]
     SerdeYaml(#[from] serde_yaml::Error),
 
@@ -79,6 +91,9 @@ pub enum Error {
 
     #[error("hyper error: {0}")]
     Hyper(#[from] hyper::Error),
+
+    #[error("transaction processing task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
 }
 
 impl Error {
@@ -17,8 +17,19 @@ use std::{future::Future, pin::Pin, sync::Arc};
 use matrix_sdk::locks::Mutex;
 
 use crate::{
-    ruma::api::appservice::query::{
-        query_room_alias::v1 as query_room, query_user_id::v1 as query_user,
+    ruma::{
+        api::appservice::{
+            ping::send_ping::v1 as ping,
+            query::{query_room_alias::v1 as query_room, query_user_id::v1 as query_user},
+            thirdparty::{
+                get_location_for_protocol::v1 as query_protocol_location,
+                get_location_for_room_alias::v1 as query_alias_location,
+                get_protocol::v1 as query_protocol,
+                get_user_for_protocol::v1 as query_protocol_user,
+                get_user_for_user_id::v1 as query_user_id_user,
+            },
+        },
+        thirdparty::{Location, Protocol, User},
     },
     AppService,
 };
@@ -31,19 +42,39 @@ pub(crate) type AppserviceFn<A, R> =
 pub struct EventHandler {
     pub users: Arc<Mutex<Option<AppserviceFn<query_user::Request, bool>>>>,
     pub rooms: Arc<Mutex<Option<AppserviceFn<query_room::Request, bool>>>>,
+    pub ping: Arc<Mutex<Option<AppserviceFn<ping::Request, ()>>>>,
+    pub protocols: Arc<Mutex<Option<AppserviceFn<query_protocol::Request, Option<Protocol>>>>>,
+    pub protocol_users: Arc<Mutex<Option<AppserviceFn<query_protocol_user::Request, Vec<User>>>>>,
+    pub user_id_users: Arc<Mutex<Option<AppserviceFn<query_user_id_user::Request, Vec<User>>>>>,
+    pub protocol_locations:
+        Arc<Mutex<Option<AppserviceFn<query_protocol_location::Request, Vec<Location>>>>>,
+    pub alias_locations:
+        Arc<Mutex<Option<AppserviceFn<query_alias_location::Request, Vec<Location>>>>>,
+}
+
+/// `Debug` helper showing whether a handler is registered.
+struct Registered<'a, T>(&'a Mutex<Option<T>>);
+
+impl<T> std::fmt::Debug for Registered<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.try_lock() {
+            Ok(lock) => std::fmt::Debug::fmt(&lock.is_some(), f),
+            Err(_) => f.write_str("<locked>"),
+        }
+    }
 }
 
 impl std::fmt::Debug for EventHandler {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut debug = f.debug_struct("EventHandler");
-        match self.users.try_lock() {
-            Ok(lock) => debug.field("users", &lock.is_some()),
-            Err(_) => debug.field("users", &format_args!("<locked>")),
-        };
-        match self.rooms.try_lock() {
-            Ok(lock) => debug.field("rooms", &lock.is_some()),
-            Err(_) => debug.field("rooms", &format_args!("<locked>")),
-        };
-        debug.finish()
+        f.debug_struct("EventHandler")
+            .field("users", &Registered(&self.users))
+            .field("rooms", &Registered(&self.rooms))
+            .field("ping", &Registered(&self.ping))
+            .field("protocols", &Registered(&self.protocols))
+            .field("protocol_users", &Registered(&self.protocol_users))
+            .field("user_id_users", &Registered(&self.user_id_users))
+            .field("protocol_locations", &Registered(&self.protocol_locations))
+            .field("alias_locations", &Registered(&self.alias_locations))
+            .finish()
     }
 }
@@ -22,13 +22,34 @@
 //! - [x] receive and validate requests from the homeserver correctly
 //! - [x] allow calling the homeserver with proper user identity assertion
 //! - [x] have consistent room state by leveraging matrix-sdk's state store
-//! - [ ] provide E2EE support by leveraging matrix-sdk's crypto store
+//! - [x] provide E2EE support by leveraging matrix-sdk's crypto store
 //!
 //! # Status
 //!
 //! The crate is in an experimental state. Follow
 //! [matrix-org/matrix-rust-sdk#228] for progress.
 //!
+//! # To-device events
+//!
+//! If the homeserver pushes to-device events ([MSC2409]), they're routed to
+//! the appservice user they're addressed to, so the usual event handlers are
+//! called for them.
+//!
+//! # Ephemeral events
+//!
+//! Ephemeral events ([MSC2409]) are routed the same way: typing notifications
+//! and receipts to the appservice users that are members of their room, and
+//! presence updates to every user receiving the transaction.
+//!
+//! # End-to-end encryption
+//!
+//! With the `e2e-encryption` feature, device list changes and one-time key
+//! counts ([MSC3202]) pushed by the homeserver are routed to the appservice
+//! user they're addressed to as well, so every user client keeps its own
+//! `OlmMachine` up to date. The homeserver needs to have these MSCs enabled
+//! for the appservice, and the users need a device of their own, see
+//! [`UserBuilder::login()`].
+//!
 //! # Registration
 //!
 //! The crate relies on the appservice registration being always in sync with
@@ -36,6 +57,11 @@
 //! for the access tokens and because membership states for appservice users are
 //! determined based on the registered namespaces.
 //!
+//! A new registration for the homeserver configuration can be generated with
+//! [`AppServiceRegistration::builder()`], and
+//! [`AppServiceRegistration::validate()`] checks a registration before the
+//! appservice is started.
+//!
 //! # Quickstart
 //!
 //! ```no_run
@@ -77,42 +103,61 @@
 //! [Application Service]: https://matrix.org/docs/spec/application_service/r0.1.2
 //! [matrix-org/matrix-rust-sdk#228]: https://github.com/matrix-org/matrix-rust-sdk/issues/228
 //! [examples directory]: https://github.com/matrix-org/matrix-rust-sdk/tree/main/crates/matrix-sdk-appservice/examples
+//! [MSC2409]: https://github.com/matrix-org/matrix-spec-proposals/pull/2409
+//! [MSC3202]: https://github.com/matrix-org/matrix-spec-proposals/pull/3202
 
-use std::{fmt::Debug, sync::Arc};
+use std::{collections::BTreeSet, fmt::Debug, sync::Arc, time::Duration};
 
 use axum::body::HttpBody;
-use dashmap::DashMap;
 pub use error::Error;
 use event_handler::AppserviceFn;
 pub use matrix_sdk;
 #[doc(no_inline)]
 pub use matrix_sdk::ruma;
-use matrix_sdk::{config::RequestConfig, reqwest::Url, Client, ClientBuilder};
+use matrix_sdk::{config::RequestConfig, reqwest::Url, Client, ClientBuilder, Session};
 use ruma::{
     api::{
         appservice::{
             event::push_events,
+            ping::send_ping::v1 as ping,
             query::{query_room_alias::v1 as query_room, query_user_id::v1 as query_user},
+            thirdparty::{
+                get_location_for_protocol::v1 as query_protocol_location,
+                get_location_for_room_alias::v1 as query_alias_location,
+                get_protocol::v1 as query_protocol,
+                get_user_for_protocol::v1 as query_protocol_user,
+                get_user_for_user_id::v1 as query_user_id_user,
+            },
         },
-        client::{account::register, sync::sync_events},
+        client::{account::register, appservice::request_ping, sync::sync_events},
     },
     assign,
-    events::{room::member::MembershipState, AnyStateEvent, AnyTimelineEvent},
-    DeviceId, OwnedRoomId, OwnedServerName,
+    events::{
+        presence::PresenceEvent, room::member::MembershipState, AnyStateEvent,
+        AnySyncEphemeralRoomEvent, AnyTimelineEvent,
+    },
+    serde::Raw,
+    thirdparty::{Location, Protocol, User},
+    DeviceId, EventId, OwnedRoomId, OwnedServerName, OwnedTransactionId, OwnedUserId, RoomId,
+    UserId,
 };
 use serde::Deserialize;
 use thiserror::Error;
 use tokio::task::JoinHandle;
 use tracing::{debug, info, warn};
 
+pub mod backfill;
 mod error;
 pub mod event_handler;
+pub mod pool;
 pub mod registration;
 pub mod user;
 mod webserver;
 
-pub use registration::AppServiceRegistration;
+pub use backfill::{Backfill, HistoricalEvent};
+pub use pool::ClientPool;
 use registration::NamespaceCache;
+pub use registration::{AppServiceRegistration, AppServiceRegistrationBuilder};
 pub use user::UserBuilder;
 pub use webserver::AppServiceRouter;
 
@@ -120,6 +165,8 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 const USER_KEY: &[u8] = b"appservice.users.";
 const USER_MEMBER: &[u8] = b"appservice.users.membership.";
+const USER_SESSION: &[u8] = b"appservice.sessions.";
+const ROOM_MEMBERS: &[u8] = b"appservice.rooms.members.";
 
 type Localpart = String;
 
@@ -130,9 +177,27 @@ pub struct AppService {
     server_name: OwnedServerName,
     registration: Arc<AppServiceRegistration>,
     namespaces: Arc<NamespaceCache>,
-    clients: Arc<DashMap<Localpart, Client>>,
+    clients: ClientPool,
     event_handler: event_handler::EventHandler,
     default_request_config: Option<RequestConfig>,
+    #[cfg(feature = "sled")]
+    user_sled_store: Option<UserSledStore>,
+}
+
+/// The sled store of each appservice user, see
+/// [`AppServiceBuilder::user_sled_store()`].
+#[cfg(feature = "sled")]
+#[derive(Clone)]
+struct UserSledStore {
+    path: std::path::PathBuf,
+    passphrase: Option<String>,
+}
+
+#[cfg(feature = "sled")]
+impl Debug for UserSledStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UserSledStore").field("path", &self.path).finish_non_exhaustive()
+    }
 }
 
 /// Builder for an AppService
@@ -143,6 +208,9 @@ pub struct AppServiceBuilder {
     registration: AppServiceRegistration,
     client_builder: Option<ClientBuilder>,
     default_request_config: Option<RequestConfig>,
+    client_pool_capacity: Option<usize>,
+    #[cfg(feature = "sled")]
+    user_sled_store: Option<UserSledStore>,
 }
 
 impl AppServiceBuilder {
@@ -168,6 +236,9 @@ impl AppServiceBuilder {
             registration,
             client_builder: None,
             default_request_config: None,
+            client_pool_capacity: None,
+            #[cfg(feature = "sled")]
+            user_sled_store: None,
         }
     }
 
@@ -183,6 +254,51 @@ impl AppServiceBuilder {
         self
     }
 
+    /// Set the maximum number of appservice user clients that are kept
+    /// active.
+    ///
+    /// When the limit is reached, the least recently used client is evicted
+    /// from the [`ClientPool`]. By default the number of clients is unbounded.
+    ///
+    /// Evicted clients are built again with [`AppService::user()`] from their
+    /// stored session when they're needed. The event handlers registered on
+    /// an evicted client are dropped with it and are not registered again on
+    /// the new client, so event handlers should be registered on the
+    /// `sender_localpart` user, which is never evicted.
+    ///
+    /// With the `e2e-encryption` feature, the encryption keys of an evicted
+    /// client must be loaded again when it's built, so each user needs a
+    /// persistent store, see [`user_sled_store()`][Self::user_sled_store].
+    /// Building the appservice fails otherwise.
+    pub fn client_pool_capacity(mut self, capacity: usize) -> Self {
+        self.client_pool_capacity = Some(capacity);
+        self
+    }
+
+    /// Give the client of each appservice user its own sled store.
+    ///
+    /// The store of a user is created in a subdirectory of `path` named after
+    /// its localpart. This replaces the store configuration of the client
+    /// builders.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The directory containing the stores of the users.
+    ///
+    /// * `passphrase` - The passphrase used to encrypt the stores.
+    #[cfg(feature = "sled")]
+    pub fn user_sled_store(
+        mut self,
+        path: impl AsRef<std::path::Path>,
+        passphrase: Option<&str>,
+    ) -> Self {
+        self.user_sled_store = Some(UserSledStore {
+            path: path.as_ref().to_owned(),
+            passphrase: passphrase.map(ToOwned::to_owned),
+        });
+        self
+    }
+
     /// Build the AppService.
     ///
     /// This will also construct an appservice [`user()`][AppService::user]
@@ -190,13 +306,34 @@ impl AppServiceBuilder {
     /// user can be used to register an event handler for all incoming
     /// events. Other appservice users only receive events if they're known to
     /// be a member of a room.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the registration is invalid, see
+    /// [`AppServiceRegistration::validate()`].
     pub async fn build(self) -> Result<AppService> {
+        self.registration.validate(&self.server_name)?;
+
+        // Evicted clients would create a new Olm account when they're built
+        // again if their encryption keys weren't persisted.
+        #[cfg(feature = "e2e-encryption")]
+        if self.client_pool_capacity.is_some() {
+            #[cfg(feature = "sled")]
+            let has_user_stores = self.user_sled_store.is_some();
+            #[cfg(not(feature = "sled"))]
+            let has_user_stores = false;
+
+            if !has_user_stores {
+                return Err(Error::ClientPoolWithoutUserStores);
+            }
+        }
+
         let homeserver_url = self.homeserver_url;
         let server_name = self.server_name;
         let registration = Arc::new(self.registration);
         let namespaces = Arc::new(NamespaceCache::from_registration(&registration)?);
-        let clients = Arc::new(DashMap::new());
         let sender_localpart = registration.sender_localpart.clone();
+        let clients = ClientPool::new(sender_localpart.clone(), self.client_pool_capacity);
         let event_handler = event_handler::EventHandler::default();
         let default_request_config = self.default_request_config;
 
@@ -208,6 +345,8 @@ impl AppServiceBuilder {
             clients,
             event_handler,
             default_request_config,
+            #[cfg(feature = "sled")]
+            user_sled_store: self.user_sled_store,
         };
         if let Some(client_builder) = self.client_builder {
             appservice
@@ -239,7 +378,9 @@ impl AppService {
     ///
     /// This method is a singleton that saves the client internally for re-use
     /// based on the `localpart`. The cached client can be retrieved by calling
-    /// this method again.
+    /// this method again. If the client was evicted from the [`ClientPool`],
+    /// a new one is built from the session stored for the user, without the
+    /// event handlers that were registered on the evicted client.
     ///
     /// Note that if you want to do actions like joining rooms with a
     /// user it needs to be registered first.
@@ -283,11 +424,41 @@ impl AppService {
         UserBuilder::new(self, localpart)
     }
 
-    /// Get the map containing all constructed appservice user clients.
-    pub fn users(&self) -> Arc<DashMap<Localpart, Client>> {
+    /// Get the pool containing the active appservice user clients.
+    pub fn users(&self) -> ClientPool {
         self.clients.clone()
     }
 
+    /// Get the client of the `sender_localpart` user, if it was built
+    /// already.
+    fn sender_client(&self) -> Option<Client> {
+        self.clients.get(&self.registration.sender_localpart)
+    }
+
+    /// Store the session of an appservice user, so its client can be built
+    /// again after it was evicted from the [`ClientPool`].
+    async fn store_session(&self, localpart: &str, session: &Session) -> Result<()> {
+        let Some(client) = self.sender_client() else { return Ok(()) };
+        client
+            .store()
+            .set_custom_value(
+                &[USER_SESSION, localpart.as_bytes()].concat(),
+                serde_json::to_vec(session)?,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Get the stored session of an appservice user.
+    async fn stored_session(&self, localpart: &str) -> Result<Option<Session>> {
+        let Some(client) = self.sender_client() else { return Ok(None) };
+        let key = [USER_SESSION, localpart.as_bytes()].concat();
+        match client.store().get_custom_value(&key).await? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
     /// Register a responder for queries about the existence of a user with a
     /// given mxid.
     ///
@@ -330,6 +501,159 @@ impl AppService {
         *self.event_handler.rooms.lock().await = Some(handler);
     }
 
+    /// Register a handler that is called when the homeserver pings the
+    /// appservice.
+    ///
+    /// The appservice always answers pings, the handler can be used to see
+    /// that the homeserver can reach it. See [MSC2659] and
+    /// [`ping_homeserver()`][Self::ping_homeserver].
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use matrix_sdk_appservice::AppService;
+    /// # fn run(appservice: AppService) {
+    /// appservice.register_ping_handler(Box::new(|appservice, req| {
+    ///     Box::pin(async move {
+    ///         println!("Got pinged in transaction {:?}", req.transaction_id);
+    ///     })
+    /// }));
+    /// # }
+    /// ```
+    ///
+    /// [MSC2659]: https://github.com/matrix-org/matrix-spec-proposals/pull/2659
+    pub async fn register_ping_handler(&self, handler: AppserviceFn<ping::Request, ()>) {
+        *self.event_handler.ping.lock().await = Some(handler);
+    }
+
+    /// Register a responder for queries about the metadata of a third party
+    /// protocol, if it's not known the responder should return `None`.
+    ///
+    /// See [GET /_matrix/app/v1/thirdparty/protocol/{protocol}](https://spec.matrix.org/v1.6/application-service-api/#get_matrixappv1thirdpartyprotocolprotocol).
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use matrix_sdk_appservice::AppService;
+    /// # fn run(appservice: AppService) {
+    /// appservice.register_protocol_query(Box::new(|appservice, req| {
+    ///     Box::pin(async move {
+    ///         println!("Got request for protocol {}", req.protocol);
+    ///         None
+    ///     })
+    /// }));
+    /// # }
+    /// ```
+    pub async fn register_protocol_query(
+        &self,
+        handler: AppserviceFn<query_protocol::Request, Option<Protocol>>,
+    ) {
+        *self.event_handler.protocols.lock().await = Some(handler);
+    }
+
+    /// Register a responder for queries about the third party users matching
+    /// the given fields of a protocol.
+    ///
+    /// See [GET /_matrix/app/v1/thirdparty/user/{protocol}](https://spec.matrix.org/v1.6/application-service-api/#get_matrixappv1thirdpartyuserprotocol).
+    pub async fn register_thirdparty_user_query(
+        &self,
+        handler: AppserviceFn<query_protocol_user::Request, Vec<User>>,
+    ) {
+        *self.event_handler.protocol_users.lock().await = Some(handler);
+    }
+
+    /// Register a responder for queries about the third party users of a
+    /// Matrix user ID.
+    ///
+    /// See [GET /_matrix/app/v1/thirdparty/user](https://spec.matrix.org/v1.6/application-service-api/#get_matrixappv1thirdpartyuser).
+    pub async fn register_thirdparty_user_id_query(
+        &self,
+        handler: AppserviceFn<query_user_id_user::Request, Vec<User>>,
+    ) {
+        *self.event_handler.user_id_users.lock().await = Some(handler);
+    }
+
+    /// Register a responder for queries about the third party locations
+    /// matching the given fields of a protocol.
+    ///
+    /// See [GET /_matrix/app/v1/thirdparty/location/{protocol}](https://spec.matrix.org/v1.6/application-service-api/#get_matrixappv1thirdpartylocationprotocol).
+    pub async fn register_thirdparty_location_query(
+        &self,
+        handler: AppserviceFn<query_protocol_location::Request, Vec<Location>>,
+    ) {
+        *self.event_handler.protocol_locations.lock().await = Some(handler);
+    }
+
+    /// Register a responder for queries about the third party locations of a
+    /// Matrix room alias.
+    ///
+    /// See [GET /_matrix/app/v1/thirdparty/location](https://spec.matrix.org/v1.6/application-service-api/#get_matrixappv1thirdpartylocation).
+    pub async fn register_thirdparty_alias_query(
+        &self,
+        handler: AppserviceFn<query_alias_location::Request, Vec<Location>>,
+    ) {
+        *self.event_handler.alias_locations.lock().await = Some(handler);
+    }
+
+    /// Import historical messages into a room, after the given event.
+    ///
+    /// See [`Backfill`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use matrix_sdk_appservice::{AppService, HistoricalEvent};
+    /// # async fn run(appservice: AppService) -> matrix_sdk_appservice::Result<()> {
+    /// use matrix_sdk_appservice::ruma::{
+    ///     event_id, events::room::message::RoomMessageEventContent, room_id,
+    ///     MilliSecondsSinceUnixEpoch, UInt,
+    /// };
+    ///
+    /// let messages = [
+    ///     ("_bridge_alice", 1_600_000_000_000_u64, "Hello"),
+    ///     ("_bridge_bob", 1_600_000_001_000, "Hi Alice"),
+    /// ];
+    /// let events = messages
+    ///     .into_iter()
+    ///     .map(|(sender, ts, body)| {
+    ///         let ts = MilliSecondsSinceUnixEpoch(UInt::new(ts).unwrap());
+    ///         HistoricalEvent::new(sender, ts, RoomMessageEventContent::text_plain(body))
+    ///     })
+    ///     .collect::<Result<Vec<_>, _>>()?;
+    ///
+    /// appservice
+    ///     .backfill(room_id!("!room:example.org"), event_id!("$oldest_event"))
+    ///     .send(events)
+    ///     .await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn backfill(&self, room_id: &RoomId, prev_event_id: &EventId) -> Backfill<'_> {
+        Backfill::new(self, room_id.to_owned(), prev_event_id.to_owned())
+    }
+
+    /// Ask the homeserver to ping the appservice, to check that it's reachable
+    /// and that the `hs_token` matches.
+    ///
+    /// Returns the duration of the ping request of the homeserver. See
+    /// [MSC2659].
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction_id` - An ID the homeserver forwards to the ping request,
+    ///   see [`register_ping_handler()`][Self::register_ping_handler].
+    ///
+    /// [MSC2659]: https://github.com/matrix-org/matrix-spec-proposals/pull/2659
+    pub async fn ping_homeserver(
+        &self,
+        transaction_id: Option<OwnedTransactionId>,
+    ) -> Result<Duration> {
+        let request = assign!(request_ping::v1::Request::new(self.registration.id.clone()), {
+            transaction_id,
+        });
+
+        let client = self.user(None).await?;
+        let response = client.send(request, None).await?;
+
+        Ok(response.duration)
+    }
+
     /// Register an appservice user by sending a [`register::v3::Request`] to
     /// the homeserver.
     ///
@@ -436,6 +760,15 @@ impl AppService {
                     event.membership().to_string().into_bytes(),
                 )
                 .await?;
+
+            let mut members = self.room_members(event.room_id()).await?;
+            let changed = match event.membership() {
+                MembershipState::Leave | MembershipState::Ban => members.remove(localpart),
+                _ => members.insert(localpart.to_owned()),
+            };
+            if changed {
+                self.set_room_members(event.room_id(), &members).await?;
+            }
         }
 
         /// Helper type for extracting the room id for an event
@@ -444,13 +777,17 @@ impl AppService {
             room_id: Option<OwnedRoomId>,
         }
 
-        // Spawn a task for each client that constructs and pushes a sync event
+        // Spawn a task for each client the transaction is relevant for that
+        // constructs and pushes a sync event
         let mut tasks: Vec<JoinHandle<_>> = Vec::new();
+        let ephemeral = Arc::new(EphemeralEvents::from_transaction(&transaction));
+        let localparts = self.transaction_localparts(&transaction, &ephemeral).await?;
         let transaction = Arc::new(transaction);
-        for user_client in self.clients.iter() {
+        for localpart in localparts {
             let client = sender_localpart_client.clone();
-            let user_client = user_client.clone();
+            let user_client = self.user(Some(&localpart)).await?;
             let transaction = transaction.clone();
+            let ephemeral = ephemeral.clone();
             let sender_localpart = self.registration.sender_localpart.clone();
 
             let task = tokio::spawn(async move {
@@ -473,16 +810,8 @@ impl AppService {
                         warn!("Transaction contained event with no ID");
                         continue;
                     };
-                    let key = &[USER_MEMBER, room_id.as_bytes(), b".", user_localpart.as_bytes()]
-                        .concat();
-                    let membership = match client.store().get_custom_value(key).await? {
-                        Some(value) => String::from_utf8(value).ok().map(MembershipState::from),
-                        // Assume the `sender_localpart` user is in every known room
-                        None if user_localpart == sender_localpart => Some(MembershipState::Join),
-                        None => None,
-                    };
-
-                    match membership {
+                    let is_sender = user_localpart == sender_localpart;
+                    match user_membership(&client, &room_id, user_localpart, is_sender).await? {
                         Some(MembershipState::Join) => {
                             let room = response.rooms.join.entry(room_id).or_default();
                             room.timeline.events.push(raw_event.clone().cast())
@@ -501,17 +830,170 @@ impl AppService {
                         None => debug!("Assuming {user_localpart} is not in {room_id}"),
                     }
                 }
+
+                // Ephemeral events are only sent to the members of a room.
+                for (room_id, raw_event) in &ephemeral.rooms {
+                    let is_sender = user_localpart == sender_localpart;
+                    let membership =
+                        user_membership(&client, room_id, user_localpart, is_sender).await?;
+                    if membership == Some(MembershipState::Join) {
+                        let room = response.rooms.join.entry(room_id.clone()).or_default();
+                        room.ephemeral.events.push(raw_event.clone());
+                    }
+                }
+                response.presence.events = ephemeral.presence.clone();
+
+                if let Some(device_id) = user_client.device_id() {
+                    add_to_device_events(&mut response, &transaction, user_id, device_id);
+
+                    #[cfg(feature = "e2e-encryption")]
+                    add_encryption_data(&mut response, &transaction, user_id, device_id);
+                }
+
                 user_client.receive_transaction(&transaction.txn_id, response).await?;
                 Ok::<_, Error>(())
             });
 
             tasks.push(task);
         }
+
+        // Wait for all the clients to be done before reporting the first
+        // error, the homeserver will retry the whole transaction and clients
+        // that already processed it will skip it.
+        let mut result = Ok(());
         for task in tasks {
-            if let Err(e) = task.await {
-                warn!("Joining sync task failed: {e}");
+            let task_result = task.await.map_err(Error::from).and_then(|r| r);
+            if let Err(e) = task_result {
+                warn!("Processing the transaction failed: {e}");
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Get the localparts of the users whose client should receive the given
+    /// transaction.
+    ///
+    /// These are the known appservice users in the rooms of the events and
+    /// ephemeral events, the appservice users an event is about, the
+    /// recipients of to-device events and, with end-to-end encryption, the
+    /// recipients of the encryption data. Users that never had a client
+    /// are skipped, the `sender_localpart` user always receives the
+    /// transaction.
+    async fn transaction_localparts(
+        &self,
+        transaction: &push_events::v1::Request,
+        ephemeral: &EphemeralEvents,
+    ) -> Result<BTreeSet<Localpart>> {
+        /// Helper type for extracting the room and the users of an event
+        #[derive(Debug, Deserialize)]
+        struct EventUsers {
+            room_id: Option<OwnedRoomId>,
+            sender: Option<OwnedUserId>,
+            state_key: Option<String>,
+        }
+
+        let mut user_ids = BTreeSet::new();
+        let mut localparts = BTreeSet::new();
+
+        for raw_event in &transaction.events {
+            let Ok(event) = raw_event.deserialize_as::<EventUsers>() else {
+                continue;
+            };
+            if let Some(room_id) = event.room_id {
+                localparts.extend(self.room_members(&room_id).await?);
+            }
+            user_ids.extend(event.sender);
+            user_ids.extend(event.state_key.and_then(|key| UserId::parse(key).ok()));
+        }
+
+        for (room_id, _) in &ephemeral.rooms {
+            localparts.extend(self.room_members(room_id).await?);
+        }
+
+        user_ids.extend(
+            transaction
+                .to_device
+                .iter()
+                .filter_map(|e| e.deserialize_as::<ToDeviceRecipient>().ok())
+                .map(|r| r.to_user_id),
+        );
+
+        #[cfg(feature = "e2e-encryption")]
+        {
+            user_ids.extend(transaction.device_one_time_keys_count.keys().cloned());
+            user_ids.extend(transaction.device_unused_fallback_key_types.keys().cloned());
+
+            // Device list changes concern every user that has an active
+            // client.
+            if !transaction.device_lists.is_empty() {
+                localparts.extend(self.clients.localparts());
             }
         }
+
+        localparts.extend(
+            user_ids
+                .iter()
+                .filter(|user_id| user_id.server_name() == &*self.server_name)
+                .filter(|user_id| self.user_id_is_in_namespace(user_id))
+                .map(|user_id| user_id.localpart().to_owned()),
+        );
+
+        let mut relevant = BTreeSet::new();
+        for localpart in localparts {
+            if self.clients.contains(&localpart) || self.stored_session(&localpart).await?.is_some()
+            {
+                relevant.insert(localpart);
+            }
+        }
+        relevant.insert(self.registration.sender_localpart.clone());
+
+        Ok(relevant)
+    }
+
+    /// Get the localparts of the appservice users that are known to be
+    /// members of the given room.
+    ///
+    /// Rooms that were joined before the members of rooms were recorded don't
+    /// have an entry yet, it's created out of the active members known by the
+    /// `sender_localpart` client.
+    async fn room_members(&self, room_id: &RoomId) -> Result<BTreeSet<Localpart>> {
+        let client = self.user(None).await?;
+        let key = [ROOM_MEMBERS, room_id.as_bytes()].concat();
+        if let Some(value) = client.store().get_custom_value(&key).await? {
+            return Ok(serde_json::from_slice(&value)?);
+        }
+
+        let Some(room) = client.get_room(room_id) else {
+            return Ok(BTreeSet::new());
+        };
+
+        let members: BTreeSet<_> = room
+            .active_members_no_sync()
+            .await?
+            .iter()
+            .map(|member| member.user_id())
+            .filter(|user_id| user_id.server_name() == &*self.server_name)
+            .filter(|user_id| self.user_id_is_in_namespace(user_id))
+            .map(|user_id| user_id.localpart().to_owned())
+            .collect();
+        self.set_room_members(room_id, &members).await?;
+
+        Ok(members)
+    }
+
+    /// Set the localparts of the appservice users that are known to be
+    /// members of the given room.
+    async fn set_room_members(
+        &self,
+        room_id: &RoomId,
+        members: &BTreeSet<Localpart>,
+    ) -> Result<()> {
+        let client = self.user(None).await?;
+        let key = [ROOM_MEMBERS, room_id.as_bytes()].concat();
+        client.store().set_custom_value(&key, serde_json::to_vec(members)?).await?;
         Ok(())
     }
 
@@ -538,6 +1020,137 @@ impl AppService {
     }
 }
 
+/// Helper type for extracting the recipient of a to-device event
+#[derive(Debug, Deserialize)]
+struct ToDeviceRecipient {
+    to_user_id: OwnedUserId,
+    to_device_id: String,
+}
+
+/// Helper type for extracting the room and the type of an ephemeral event
+#[derive(Debug, Deserialize)]
+struct EphemeralEventInfo {
+    #[serde(rename = "type")]
+    event_type: String,
+    room_id: Option<OwnedRoomId>,
+}
+
+/// The ephemeral events of a transaction, sorted by where they go in a sync
+/// response.
+#[derive(Debug, Default)]
+struct EphemeralEvents {
+    /// The typing notifications and receipts, with their room.
+    rooms: Vec<(OwnedRoomId, Raw<AnySyncEphemeralRoomEvent>)>,
+    /// The presence updates.
+    presence: Vec<Raw<PresenceEvent>>,
+}
+
+impl EphemeralEvents {
+    fn from_transaction(transaction: &push_events::v1::Request) -> Self {
+        let mut ephemeral = Self::default();
+
+        for event in &transaction.ephemeral {
+            let raw_event = match serde_json::value::to_raw_value(event) {
+                Ok(raw_event) => raw_event,
+                Err(e) => {
+                    warn!("Transaction contained an invalid ephemeral event: {e}");
+                    continue;
+                }
+            };
+            let info = match serde_json::from_str::<EphemeralEventInfo>(raw_event.get()) {
+                Ok(info) => info,
+                Err(e) => {
+                    warn!("Transaction contained an invalid ephemeral event: {e}");
+                    continue;
+                }
+            };
+
+            if info.event_type == "m.presence" {
+                ephemeral.presence.push(Raw::from_json(raw_event));
+            } else if let Some(room_id) = info.room_id {
+                ephemeral.rooms.push((room_id, Raw::from_json(raw_event)));
+            } else {
+                warn!(
+                    event_type = %info.event_type,
+                    "Transaction contained ephemeral event with no room ID"
+                );
+            }
+        }
+
+        ephemeral
+    }
+}
+
+/// Get the membership of the appservice user with the given localpart in the
+/// given room, as it was recorded from the transactions.
+///
+/// The `sender_localpart` user is assumed to be in every known room.
+async fn user_membership(
+    client: &Client,
+    room_id: &RoomId,
+    localpart: &str,
+    is_sender: bool,
+) -> Result<Option<MembershipState>> {
+    let key = [USER_MEMBER, room_id.as_bytes(), b".", localpart.as_bytes()].concat();
+    Ok(match client.store().get_custom_value(&key).await? {
+        Some(value) => String::from_utf8(value).ok().map(MembershipState::from),
+        None if is_sender => Some(MembershipState::Join),
+        None => None,
+    })
+}
+
+/// Add the to-device events of a transaction that are addressed to the given
+/// device to its sync response.
+fn add_to_device_events(
+    response: &mut sync_events::v3::Response,
+    transaction: &push_events::v1::Request,
+    user_id: &UserId,
+    device_id: &DeviceId,
+) {
+    for raw_event in &transaction.to_device {
+        let recipient = match raw_event.deserialize_as::<ToDeviceRecipient>() {
+            Ok(recipient) => recipient,
+            Err(e) => {
+                warn!("Transaction contained to-device event with no recipient: {e}");
+                continue;
+            }
+        };
+
+        if &*recipient.to_user_id == user_id
+            && (recipient.to_device_id == "*" || recipient.to_device_id == device_id.as_str())
+        {
+            response.to_device.events.push(raw_event.clone());
+        }
+    }
+}
+
+/// Add the end-to-end encryption related data of a transaction that is meant
+/// for the given device to its sync response.
+///
+/// Device list changes concern every user, one-time key counts and unused
+/// fallback keys only the device they're addressed to.
+#[cfg(feature = "e2e-encryption")]
+fn add_encryption_data(
+    response: &mut sync_events::v3::Response,
+    transaction: &push_events::v1::Request,
+    user_id: &UserId,
+    device_id: &DeviceId,
+) {
+    response.device_lists = transaction.device_lists.clone();
+
+    if let Some(counts) =
+        transaction.device_one_time_keys_count.get(user_id).and_then(|d| d.get(device_id))
+    {
+        response.device_one_time_keys_count = counts.clone();
+    }
+
+    response.device_unused_fallback_key_types = transaction
+        .device_unused_fallback_key_types
+        .get(user_id)
+        .and_then(|d| d.get(device_id))
+        .cloned();
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -562,7 +1175,7 @@ mod tests {
     use serde_json::json;
     use tower::{Service, ServiceExt};
     use wiremock::{
-        matchers::{body_json, header, method, path},
+        matchers::{body_json, header, method, path, path_regex, query_param},
         Mock, MockServer, ResponseTemplate,
     };
 
@@ -719,6 +1332,77 @@ mod tests {
         Ok(())
     }
 
+    #[async_test]
+    async fn test_put_transaction_is_processed_when_retried_after_failing() -> Result<()> {
+        let uri = "/_matrix/app/v1/transactions/1?access_token=hs_token";
+
+        let transaction = json!({
+            "events": [{
+                "content": { "membership": "join" },
+                "event_id": "$151800140517rfvjc:localhost",
+                "origin_server_ts": 151800140,
+                "sender": "@_appservice_carol:localhost",
+                "state_key": "@_appservice_carol:localhost",
+                "type": "m.room.member",
+                "room_id": "!coolplace:localhost",
+            }],
+        })
+        .to_string();
+
+        let appservice = appservice(None, None).await?;
+        let sender = appservice.user(None).await?;
+
+        let calls = Arc::new(Mutex::new(0));
+        sender.add_event_handler({
+            let calls = calls.clone();
+            move |_ev: OriginalSyncRoomMemberEvent| {
+                *calls.lock().unwrap() += 1;
+                future::ready(())
+            }
+        });
+
+        // The stored session of a user the transaction is about can't be
+        // loaded, so the transaction fails.
+        let session_key = [USER_SESSION, b"_appservice_carol"].concat();
+        sender.store().set_custom_value(&session_key, b"not a session".to_vec()).await?;
+
+        let mut service = appservice.service();
+
+        let response = service
+            .call(
+                Request::builder()
+                    .method(Method::PUT)
+                    .uri(uri)
+                    .body(Body::from(transaction.clone()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 500);
+        assert_eq!(*calls.lock().unwrap(), 0);
+
+        // Once the problem is gone, the homeserver retries the transaction,
+        // which must not be skipped as already processed.
+        sender.store().remove_custom_value(&session_key).await?;
+
+        let response = service
+            .call(
+                Request::builder()
+                    .method(Method::PUT)
+                    .uri(uri)
+                    .body(Body::from(transaction))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(*calls.lock().unwrap(), 1);
+
+        Ok(())
+    }
+
     #[async_test]
     async fn test_get_user() -> Result<()> {
         let appservice = appservice(None, None).await?;
@@ -755,6 +1439,172 @@ mod tests {
         Ok(())
     }
 
+    #[async_test]
+    async fn test_ping() -> Result<()> {
+        let appservice = appservice(None, None).await?;
+
+        #[allow(clippy::mutex_atomic)]
+        let pinged = Arc::new(Mutex::new(false));
+        appservice
+            .register_ping_handler({
+                let pinged = pinged.clone();
+                Box::new(move |_, _| {
+                    *pinged.lock().unwrap() = true;
+                    Box::pin(future::ready(()))
+                })
+            })
+            .await;
+
+        let response = appservice
+            .service()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/_matrix/app/v1/ping?access_token=hs_token")
+                    .body(Body::from(r#"{"transaction_id":"mautrix-go_1683636478256400935_123"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert!(*pinged.lock().unwrap());
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_ping_homeserver() -> Result<()> {
+        let server = MockServer::start().await;
+        let appservice = appservice(Some(server.uri()), None).await?;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"/appservice/appservice/ping$"))
+            .and(body_json(json!({ "transaction_id": "txn" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "duration_ms": 123 })))
+            .mount(&server)
+            .await;
+
+        let duration = appservice.ping_homeserver(Some("txn".into())).await?;
+        assert_eq!(duration.as_millis(), 123);
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_backfill() -> Result<()> {
+        use ruma::{
+            event_id, events::room::message::RoomMessageEventContent, MilliSecondsSinceUnixEpoch,
+            UInt,
+        };
+
+        let server = MockServer::start().await;
+        let appservice = appservice(Some(server.uri()), None).await?;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/versions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "versions": ["v1.0"] })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/unstable/org.matrix.msc2716/rooms/.*/batch_send$"))
+            .and(query_param("prev_event_id", "$prev"))
+            .and(query_param("user_id", "@_appservice_alice:localhost"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "state_event_ids": [],
+                "event_ids": ["$imported"],
+                "next_batch_id": "next",
+                "insertion_event_id": "$insertion",
+                "batch_event_id": "$batch",
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let events = ["_appservice_alice", "_appservice_bob", "_appservice_bob"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, sender)| {
+                let ts = MilliSecondsSinceUnixEpoch(UInt::from(1_000_u32 + i as u32));
+                let content = RoomMessageEventContent::text_plain(format!("Message {i}"));
+                HistoricalEvent::new(sender, ts, content)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let response = appservice
+            .backfill(room_id!("!coolplace:localhost"), event_id!("$prev"))
+            .sender("_appservice_alice")
+            .batch_size(2)
+            .send(events)
+            .await?;
+
+        assert_eq!(response.event_ids.len(), 2);
+        assert_eq!(response.next_batch_id.as_deref(), Some("next"));
+
+        let requests = server.received_requests().await.unwrap();
+        let batch_sends: Vec<_> =
+            requests.iter().filter(|r| r.url.path().ends_with("/batch_send")).collect();
+        assert_eq!(batch_sends.len(), 2);
+
+        // The newest batch comes first, without a batch ID.
+        let first: serde_json::Value = batch_sends[0].body_json()?;
+        assert!(!batch_sends[0].url.query_pairs().any(|(key, _)| key == "batch_id"));
+        assert_eq!(first["events"][0]["content"]["body"], "Message 1");
+        assert_eq!(first["events"][1]["content"]["body"], "Message 2");
+        assert_eq!(first["events"][1]["origin_server_ts"], 1002);
+        assert_eq!(first["state_events_at_start"].as_array().unwrap().len(), 1);
+        assert_eq!(first["state_events_at_start"][0]["state_key"], "@_appservice_bob:localhost");
+
+        // The older batch is chained to the first one.
+        let second: serde_json::Value = batch_sends[1].body_json()?;
+        assert!(batch_sends[1]
+            .url
+            .query_pairs()
+            .any(|(key, value)| key == "batch_id" && value == "next"));
+        assert_eq!(second["events"][0]["content"]["body"], "Message 0");
+        assert_eq!(second["events"][0]["sender"], "@_appservice_alice:localhost");
+        assert_eq!(second["state_events_at_start"][0]["content"]["membership"], "join");
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_get_thirdparty_user() -> Result<()> {
+        use ruma::thirdparty::User;
+
+        let appservice = appservice(None, None).await?;
+        let uri = "/_matrix/app/v1/thirdparty/user?userid=%40_appservice_alice:localhost&access_token=hs_token";
+
+        let response = appservice
+            .service()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+
+        appservice
+            .register_thirdparty_user_id_query(Box::new(|_, req| {
+                Box::pin(async move {
+                    vec![User::new(req.user_id, "irc".to_owned(), Default::default())]
+                })
+            }))
+            .await;
+
+        let response = appservice
+            .service()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let users: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(users[0]["userid"], "@_appservice_alice:localhost");
+        assert_eq!(users[0]["protocol"], "irc");
+
+        Ok(())
+    }
+
     #[async_test]
     async fn test_invalid_access_token() -> Result<()> {
         let uri = "/_matrix/app/v1/transactions/1?access_token=invalid_token";
@@ -995,10 +1845,360 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(not(feature = "e2e-encryption"))]
+    #[async_test]
+    async fn test_client_pool_evicts_least_recently_used() -> Result<()> {
+        let registration = AppServiceRegistration::try_from_yaml_str(registration_string())?;
+        let appservice = AppServiceBuilder::new(
+            "http://localhost:1234".parse()?,
+            "localhost".parse()?,
+            registration,
+        )
+        .client_builder(Client::builder().server_versions([MatrixVersion::V1_0]))
+        .client_pool_capacity(2)
+        .build()
+        .await?;
+
+        let alice = appservice.user(Some("_appservice_alice")).await?;
+        let alice_device_id = alice.device_id().unwrap().to_owned();
+        appservice.user(Some("_appservice_bob")).await?;
+
+        let pool = appservice.users();
+        assert_eq!(pool.len(), 2);
+        assert!(pool.contains("_appservice"), "The sender client is never evicted");
+        assert!(!pool.contains("_appservice_alice"));
+        assert!(pool.contains("_appservice_bob"));
+
+        // Alice is restored from her stored session.
+        let alice = appservice.user(Some("_appservice_alice")).await?;
+        assert_eq!(alice.device_id().unwrap(), alice_device_id);
+        assert!(!pool.contains("_appservice_bob"));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "e2e-encryption")]
+    #[async_test]
+    async fn test_client_pool_requires_user_stores_with_encryption() -> Result<()> {
+        let registration = AppServiceRegistration::try_from_yaml_str(registration_string())?;
+        let result = AppServiceBuilder::new(
+            "http://localhost:1234".parse()?,
+            "localhost".parse()?,
+            registration,
+        )
+        .client_builder(Client::builder().server_versions([MatrixVersion::V1_0]))
+        .client_pool_capacity(2)
+        .build()
+        .await;
+
+        assert_matches::assert_matches!(result, Err(Error::ClientPoolWithoutUserStores));
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "e2e-encryption", feature = "sled"))]
+    #[async_test]
+    async fn test_client_pool_rebuilds_encrypted_clients_from_their_store() -> Result<()> {
+        let stores = tempfile::tempdir()?;
+        let registration = AppServiceRegistration::try_from_yaml_str(registration_string())?;
+        let appservice = AppServiceBuilder::new(
+            "http://localhost:1234".parse()?,
+            "localhost".parse()?,
+            registration,
+        )
+        .client_builder(Client::builder().server_versions([MatrixVersion::V1_0]))
+        .client_pool_capacity(2)
+        .user_sled_store(stores.path(), None)
+        .build()
+        .await?;
+
+        let alice = appservice.user(Some("_appservice_alice")).await?;
+        let alice_device_id = alice.device_id().unwrap().to_owned();
+        let alice_key = alice.encryption().ed25519_key().await.unwrap();
+        drop(alice);
+
+        appservice.user(Some("_appservice_bob")).await?;
+        assert!(!appservice.users().contains("_appservice_alice"));
+
+        // Alice's client is built again with the same Olm account.
+        let alice = appservice.user(Some("_appservice_alice")).await?;
+        assert_eq!(alice.device_id().unwrap(), alice_device_id);
+        assert_eq!(alice.encryption().ed25519_key().await.unwrap(), alice_key);
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_room_members_are_migrated_from_the_state_store() -> Result<()> {
+        let appservice = appservice(None, None).await?;
+        appservice.user(Some("_appservice_alice")).await?;
+
+        let room_id = room_id!("!coolplace:localhost");
+        let event = Raw::new(&json!({
+            "content": { "membership": "join" },
+            "event_id": "$151800140517rfvjc:localhost",
+            "origin_server_ts": 151800140,
+            "sender": "@_appservice_alice:localhost",
+            "state_key": "@_appservice_alice:localhost",
+            "type": "m.room.member",
+            "room_id": room_id,
+        }))?
+        .cast::<AnyTimelineEvent>();
+        let transaction = push_events::v1::Request::new("migration".into(), vec![event]);
+        appservice.receive_transaction(transaction).await?;
+
+        // Forget the members of the room, like for a room joined by an older
+        // version.
+        let sender = appservice.user(None).await?;
+        let store = sender.store();
+        let key = [ROOM_MEMBERS, room_id.as_bytes()].concat();
+        store.remove_custom_value(&key).await?;
+
+        let members = appservice.room_members(room_id).await?;
+        assert_eq!(members, BTreeSet::from(["_appservice_alice".to_owned()]));
+        assert!(store.get_custom_value(&key).await?.is_some(), "The members should be saved");
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_receive_transaction_skips_uninvolved_users() -> Result<()> {
+        let appservice = appservice(None, None).await?;
+
+        let alice = appservice.user(Some("_appservice_alice")).await?;
+        let bob = appservice.user(Some("_appservice_bob")).await?;
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        for client in [&alice, &bob] {
+            let calls = calls.clone();
+            let user_id = client.user_id().unwrap().to_owned();
+            client.add_event_handler(move |_ev: OriginalSyncRoomMemberEvent| {
+                calls.lock().unwrap().push(user_id.clone());
+                future::ready(())
+            });
+        }
+
+        let event = Raw::new(&json!({
+            "content": { "membership": "join" },
+            "event_id": "$151800140517rfvjc:localhost",
+            "origin_server_ts": 151800140,
+            "sender": "@_appservice_alice:localhost",
+            "state_key": "@_appservice_alice:localhost",
+            "type": "m.room.member",
+            "room_id": "!coolplace:localhost",
+        }))?
+        .cast::<AnyTimelineEvent>();
+        let transaction = push_events::v1::Request::new("routing".into(), vec![event]);
+        let ephemeral = EphemeralEvents::from_transaction(&transaction);
+        let localparts = appservice.transaction_localparts(&transaction, &ephemeral).await?;
+        assert!(!localparts.contains("_appservice_bob"));
+
+        appservice.receive_transaction(transaction).await?;
+
+        assert_eq!(*calls.lock().unwrap(), vec![alice.user_id().unwrap().to_owned()]);
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_receive_transaction_routes_ephemeral_events() -> Result<()> {
+        use ruma::events::{presence::PresenceEvent, typing::SyncTypingEvent};
+
+        let appservice = appservice(None, None).await?;
+
+        let alice = appservice.user(Some("_appservice_alice")).await?;
+        let bob = appservice.user(Some("_appservice_bob")).await?;
+
+        let typing = Arc::new(Mutex::new(Vec::new()));
+        let presence = Arc::new(Mutex::new(Vec::new()));
+        for client in [&alice, &bob] {
+            let user_id = client.user_id().unwrap().to_owned();
+            client.add_event_handler({
+                let typing = typing.clone();
+                let user_id = user_id.clone();
+                move |ev: SyncTypingEvent| {
+                    typing.lock().unwrap().push((user_id.clone(), ev.content.user_ids));
+                    future::ready(())
+                }
+            });
+            client.add_event_handler({
+                let presence = presence.clone();
+                move |ev: PresenceEvent| {
+                    presence.lock().unwrap().push((user_id.clone(), ev.sender));
+                    future::ready(())
+                }
+            });
+        }
+
+        let member_event = Raw::new(&json!({
+            "content": { "membership": "join" },
+            "event_id": "$151800140517rfvjc:localhost",
+            "origin_server_ts": 151800140,
+            "sender": "@_appservice_alice:localhost",
+            "state_key": "@_appservice_alice:localhost",
+            "type": "m.room.member",
+            "room_id": "!coolplace:localhost",
+        }))?
+        .cast::<AnyTimelineEvent>();
+        let mut transaction = push_events::v1::Request::new("ephemeral".into(), vec![member_event]);
+        transaction.ephemeral = serde_json::from_value(json!([
+            {
+                "content": { "user_ids": ["@carol:example.org"] },
+                "room_id": "!coolplace:localhost",
+                "type": "m.typing",
+            },
+            {
+                "content": { "presence": "online" },
+                "sender": "@carol:example.org",
+                "type": "m.presence",
+            },
+        ]))?;
+
+        appservice.receive_transaction(transaction).await?;
+
+        let carol = ruma::user_id!("@carol:example.org").to_owned();
+        let alice_id = alice.user_id().unwrap().to_owned();
+        assert_eq!(*typing.lock().unwrap(), vec![(alice_id.clone(), vec![carol.clone()])]);
+        assert!(presence.lock().unwrap().contains(&(alice_id, carol)));
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_receive_transaction_routes_to_device_events() -> Result<()> {
+        use ruma::events::{dummy::ToDeviceDummyEvent, AnyToDeviceEvent};
+
+        let appservice = appservice(None, None).await?;
+
+        let alice = appservice.user(Some("_appservice_alice")).await?;
+        let bob = appservice.user(Some("_appservice_bob")).await?;
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        for client in [&alice, &bob] {
+            let received = received.clone();
+            let user_id = client.user_id().unwrap().to_owned();
+            client.add_event_handler(move |_ev: ToDeviceDummyEvent| {
+                received.lock().unwrap().push(user_id.clone());
+                future::ready(())
+            });
+        }
+
+        let mut transaction = push_events::v1::Request::new("to_device".into(), Vec::new());
+        transaction.to_device = vec![Raw::new(&json!({
+            "content": {},
+            "sender": "@bob:example.org",
+            "type": "m.dummy",
+            "to_user_id": alice.user_id().unwrap(),
+            "to_device_id": alice.device_id().unwrap(),
+        }))?
+        .cast::<AnyToDeviceEvent>()];
+
+        appservice.receive_transaction(transaction).await?;
+
+        assert_eq!(*received.lock().unwrap(), vec![alice.user_id().unwrap().to_owned()]);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "e2e-encryption")]
+    #[async_test]
+    async fn test_receive_transaction_routes_encryption_data() -> Result<()> {
+        use ruma::{uint, DeviceKeyAlgorithm};
+
+        let server = MockServer::start().await;
+        let appservice = appservice(Some(server.uri()), None).await?;
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/r0/keys/upload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "one_time_key_counts": { "signed_curve25519": 50 },
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/r0/keys/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "device_keys": {} })))
+            .mount(&server)
+            .await;
+
+        let alice = appservice.user(Some("_appservice_alice")).await?;
+        let bob = appservice.user(Some("_appservice_bob")).await?;
+        let alice_id = alice.user_id().unwrap().to_owned();
+        let alice_device_id = alice.device_id().unwrap().to_owned();
+
+        let mut transaction = push_events::v1::Request::new("encryption".into(), Vec::new());
+        transaction.device_one_time_keys_count = serde_json::from_value(json!({
+            alice_id.as_str(): { alice_device_id.as_str(): { "signed_curve25519": 42 } },
+        }))?;
+        transaction.device_unused_fallback_key_types = serde_json::from_value(json!({
+            alice_id.as_str(): { alice_device_id.as_str(): ["signed_curve25519"] },
+        }))?;
+        transaction.device_lists = serde_json::from_value(json!({
+            "changed": ["@carol:example.org"],
+            "left": [],
+        }))?;
+
+        // Device list changes concern every client, the key counts only the
+        // device they're addressed to.
+        let ephemeral = EphemeralEvents::from_transaction(&transaction);
+        let localparts = appservice.transaction_localparts(&transaction, &ephemeral).await?;
+        assert!(localparts.contains("_appservice_alice"));
+        assert!(localparts.contains("_appservice_bob"));
+
+        let mut response = sync_events::v3::Response::new("encryption".to_owned());
+        add_encryption_data(&mut response, &transaction, &alice_id, &alice_device_id);
+        assert_eq!(
+            response.device_one_time_keys_count.get(&DeviceKeyAlgorithm::SignedCurve25519),
+            Some(&uint!(42))
+        );
+        assert_eq!(
+            response.device_unused_fallback_key_types,
+            Some(vec![DeviceKeyAlgorithm::SignedCurve25519])
+        );
+        assert_eq!(response.device_lists.changed.len(), 1);
+
+        let mut response = sync_events::v3::Response::new("encryption".to_owned());
+        add_encryption_data(
+            &mut response,
+            &transaction,
+            bob.user_id().unwrap(),
+            bob.device_id().unwrap(),
+        );
+        assert!(response.device_one_time_keys_count.is_empty());
+        assert_eq!(response.device_unused_fallback_key_types, None);
+        assert_eq!(response.device_lists.changed.len(), 1);
+
+        // The clients upload their keys once they processed the transaction.
+        appservice.receive_transaction(transaction).await?;
+
+        let uploads: Vec<_> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|request| request.url.path() == "/_matrix/client/r0/keys/upload")
+            .collect();
+        for user_id in [&*alice_id, bob.user_id().unwrap()] {
+            assert!(
+                uploads.iter().any(|request| request
+                    .url
+                    .query_pairs()
+                    .any(|(key, value)| key == "user_id" && value == user_id.as_str())),
+                "{user_id} should have uploaded its keys"
+            );
+        }
+
+        Ok(())
+    }
+
     mod registration {
-        use ruma::api::appservice::Registration;
+        use assert_matches::assert_matches;
+        use matrix_sdk_test::async_test;
+        use ruma::{api::appservice::Registration, server_name};
 
-        use crate::{tests::registration_string, AppServiceRegistration, Result};
+        use crate::{
+            tests::registration_string, AppServiceBuilder, AppServiceRegistration, Error, Result,
+        };
 
         #[test]
         fn test_registration() -> Result<()> {
@@ -1028,5 +2228,70 @@ mod tests {
 
             Ok(())
         }
+
+        #[test]
+        fn test_registration_builder() -> Result<()> {
+            let registration =
+                AppServiceRegistration::builder("bridge", "http://localhost:9000", "_bridge")
+                    .user_namespace("@_bridge.*:localhost", true)
+                    .alias_namespace("#_bridge_.*:localhost", false)
+                    .rate_limited(false)
+                    .protocols(["irc"])
+                    .build()?;
+
+            assert_eq!(registration.as_token.len(), 64);
+            assert_ne!(registration.as_token, registration.hs_token);
+            registration.validate(server_name!("localhost"))?;
+
+            let yaml = registration.to_yaml_string()?;
+            let parsed = AppServiceRegistration::try_from_yaml_str(yaml)?;
+            assert_eq!(parsed.id, "bridge");
+            assert_eq!(parsed.hs_token, registration.hs_token);
+            assert_eq!(parsed.namespaces.users[0].regex, "@_bridge.*:localhost");
+            assert_eq!(parsed.protocols, Some(vec!["irc".to_owned()]));
+
+            Ok(())
+        }
+
+        #[test]
+        fn test_registration_validation() {
+            let result = AppServiceRegistration::builder("bridge", "http://localhost", "_bridge")
+                .user_namespace("@_bridge_(.*:localhost", true)
+                .build();
+            assert_matches!(result, Err(Error::InvalidNamespaceRegex { kind: "users", .. }));
+
+            let registration =
+                AppServiceRegistration::builder("bridge", "http://localhost", "_bridge")
+                    .user_namespace("@_bridge_.*:localhost", true)
+                    .build()
+                    .unwrap();
+            assert_matches!(
+                registration.validate(server_name!("localhost")),
+                Err(Error::SenderNotInNamespace(user_id)) if user_id.as_str() == "@_bridge:localhost"
+            );
+
+            let registration =
+                AppServiceRegistration::try_from_yaml_str(registration_string()).unwrap();
+            registration.validate(server_name!("localhost")).unwrap();
+        }
+
+        #[async_test]
+        async fn test_appservice_builder_validates_registration() -> Result<()> {
+            let registration =
+                AppServiceRegistration::builder("bridge", "http://localhost", "_bridge")
+                    .user_namespace("@_bridge_.*:localhost", true)
+                    .build()?;
+
+            let result = AppServiceBuilder::new(
+                "http://localhost:1234".parse()?,
+                "localhost".parse()?,
+                registration,
+            )
+            .build()
+            .await;
+            assert_matches!(result, Err(Error::SenderNotInNamespace(_)));
+
+            Ok(())
+        }
     }
 }
@@ -0,0 +1,120 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pool of appservice user clients.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use dashmap::DashMap;
+use matrix_sdk::Client;
+use tracing::debug;
+
+use crate::Localpart;
+
+/// The clients of the appservice users that are currently active.
+///
+/// If the pool has a capacity, the least recently used client is evicted once
+/// the pool is full. Evicted clients are built again from their stored
+/// session the next time they're needed, see
+/// [`AppService::user()`][crate::AppService::user]. The event handlers of an
+/// evicted client are lost, they must be registered again on the new client.
+///
+/// The client of the `sender_localpart` of the registration is never
+/// evicted.
+#[derive(Debug, Clone)]
+pub struct ClientPool {
+    clients: Arc<DashMap<Localpart, PooledClient>>,
+    clock: Arc<AtomicU64>,
+    capacity: Option<usize>,
+    sender_localpart: Localpart,
+}
+
+#[derive(Debug)]
+struct PooledClient {
+    client: Client,
+    last_used: AtomicU64,
+}
+
+impl ClientPool {
+    pub(crate) fn new(sender_localpart: Localpart, capacity: Option<usize>) -> Self {
+        Self { clients: Default::default(), clock: Default::default(), capacity, sender_localpart }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Get the client of the user with the given localpart, if it's active.
+    ///
+    /// This marks the client as recently used.
+    pub fn get(&self, localpart: &str) -> Option<Client> {
+        let entry = self.clients.get(localpart)?;
+        entry.last_used.store(self.tick(), Ordering::Relaxed);
+        Some(entry.client.clone())
+    }
+
+    /// Is the client of the user with the given localpart active.
+    pub fn contains(&self, localpart: &str) -> bool {
+        self.clients.contains_key(localpart)
+    }
+
+    /// Add a client to the pool, evicting the least recently used clients if
+    /// the pool is over capacity.
+    pub(crate) fn insert(&self, localpart: Localpart, client: Client) {
+        let last_used = AtomicU64::new(self.tick());
+        self.clients.insert(localpart, PooledClient { client, last_used });
+
+        let Some(capacity) = self.capacity else { return };
+        while self.clients.len() > capacity {
+            let least_recently_used = self
+                .clients
+                .iter()
+                .filter(|entry| *entry.key() != self.sender_localpart)
+                .min_by_key(|entry| entry.last_used.load(Ordering::Relaxed))
+                .map(|entry| entry.key().clone());
+
+            let Some(localpart) = least_recently_used else { break };
+            debug!(localpart, "Evicting appservice user client from the pool");
+            self.clients.remove(&localpart);
+        }
+    }
+
+    /// Remove the client of the user with the given localpart from the pool.
+    pub fn remove(&self, localpart: &str) -> Option<Client> {
+        self.clients.remove(localpart).map(|(_, entry)| entry.client)
+    }
+
+    /// The localparts of the users whose client is active.
+    pub fn localparts(&self) -> Vec<Localpart> {
+        self.clients.iter().map(|entry| entry.key().clone()).collect()
+    }
+
+    /// The number of active clients.
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Is the pool empty.
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    /// The maximum number of active clients, if any.
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+}
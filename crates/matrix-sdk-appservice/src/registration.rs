@@ -17,14 +17,21 @@
 use std::{fs::File, ops::Deref, path::PathBuf};
 
 use http::Uri;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use regex::Regex;
-use ruma::api::appservice::Registration;
+use ruma::{
+    api::appservice::{Namespace, Namespaces, Registration, RegistrationInit},
+    ServerName, UserId,
+};
 
 use crate::{Error, Result};
 
 pub type Host = String;
 pub type Port = u16;
 
+/// The length of the generated `as_token` and `hs_token`.
+const TOKEN_LENGTH: usize = 64;
+
 /// AppService Registration
 ///
 /// Wrapper around [`Registration`]. See also <https://matrix.org/docs/spec/application_service/r0.1.2#registration>.
@@ -50,6 +57,68 @@ impl AppServiceRegistration {
         Ok(Self { inner: serde_yaml::from_reader(file)? })
     }
 
+    /// Create a new [`AppServiceRegistrationBuilder`] to generate a
+    /// registration.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The unique ID of the appservice.
+    /// * `url` - The URL the homeserver should use to reach the appservice.
+    /// * `sender_localpart` - The localpart of the main user of the appservice.
+    pub fn builder(
+        id: impl Into<String>,
+        url: impl Into<String>,
+        sender_localpart: impl Into<String>,
+    ) -> AppServiceRegistrationBuilder {
+        AppServiceRegistrationBuilder::new(id.into(), url.into(), sender_localpart.into())
+    }
+
+    /// Serialize the registration to a yaml string, for the configuration of
+    /// the homeserver.
+    pub fn to_yaml_string(&self) -> Result<String> {
+        Ok(serde_yaml::to_string(&self.inner)?)
+    }
+
+    /// Write the registration to a yaml file, for the configuration of the
+    /// homeserver.
+    pub fn write_yaml_file(&self, path: impl Into<PathBuf>) -> Result<()> {
+        let file = File::create(path.into())?;
+        serde_yaml::to_writer(file, &self.inner)?;
+
+        Ok(())
+    }
+
+    /// Check that the registration can be used by an appservice on the
+    /// homeserver with the given server name.
+    ///
+    /// This checks that the required fields aren't empty, that the regexes of
+    /// all the namespaces compile and that the `sender_localpart` user is
+    /// covered by the user namespaces.
+    pub fn validate(&self, server_name: &ServerName) -> Result<()> {
+        let required_fields = [
+            ("id", &self.id),
+            ("url", &self.url),
+            ("as_token", &self.as_token),
+            ("hs_token", &self.hs_token),
+            ("sender_localpart", &self.sender_localpart),
+        ];
+        for (name, value) in required_fields {
+            if value.is_empty() {
+                return Err(Error::EmptyRegistrationField(name));
+            }
+        }
+
+        Uri::try_from(&self.url)?;
+
+        let namespaces = NamespaceCache::from_registration(self)?;
+        let sender = UserId::parse_with_server_name(self.sender_localpart.as_str(), server_name)?;
+        if !namespaces.users.iter().any(|regex| regex.is_match(sender.as_str())) {
+            return Err(Error::SenderNotInNamespace(sender));
+        }
+
+        Ok(())
+    }
+
     /// Get the host and port from the registration URL
     ///
     /// If no port is found it falls back to scheme defaults: 80 for http and
@@ -85,6 +154,133 @@ impl Deref for AppServiceRegistration {
     }
 }
 
+/// Builder for a new [`AppServiceRegistration`].
+///
+/// The `as_token` and `hs_token` are generated randomly, unless they're set
+/// explicitly.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn run() -> matrix_sdk_appservice::Result<()> {
+/// use matrix_sdk_appservice::AppServiceRegistration;
+///
+/// let registration = AppServiceRegistration::builder(
+///     "my_bridge",
+///     "http://localhost:9000",
+///     "_bridge",
+/// )
+/// .user_namespace(r"@_bridge_.*:example\.org", true)
+/// .user_namespace(r"@_bridge:example\.org", true)
+/// .alias_namespace(r"#_bridge_.*:example\.org", true)
+/// .rate_limited(false)
+/// .protocols(["irc"])
+/// .build()?;
+///
+/// registration.validate("example.org".try_into()?)?;
+/// registration.write_yaml_file("./registration.yaml")?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AppServiceRegistrationBuilder {
+    id: String,
+    url: String,
+    sender_localpart: String,
+    as_token: Option<String>,
+    hs_token: Option<String>,
+    namespaces: Namespaces,
+    rate_limited: Option<bool>,
+    protocols: Option<Vec<String>>,
+}
+
+impl AppServiceRegistrationBuilder {
+    fn new(id: String, url: String, sender_localpart: String) -> Self {
+        Self {
+            id,
+            url,
+            sender_localpart,
+            as_token: None,
+            hs_token: None,
+            namespaces: Namespaces::new(),
+            rate_limited: None,
+            protocols: None,
+        }
+    }
+
+    /// Set the token the appservice uses to authenticate with the
+    /// homeserver, instead of generating one.
+    pub fn as_token(mut self, as_token: impl Into<String>) -> Self {
+        self.as_token = Some(as_token.into());
+        self
+    }
+
+    /// Set the token the homeserver uses to authenticate with the
+    /// appservice, instead of generating one.
+    pub fn hs_token(mut self, hs_token: impl Into<String>) -> Self {
+        self.hs_token = Some(hs_token.into());
+        self
+    }
+
+    /// Add a regex for user IDs to the namespaces of the appservice.
+    pub fn user_namespace(mut self, regex: impl Into<String>, exclusive: bool) -> Self {
+        self.namespaces.users.push(Namespace::new(exclusive, regex.into()));
+        self
+    }
+
+    /// Add a regex for room aliases to the namespaces of the appservice.
+    pub fn alias_namespace(mut self, regex: impl Into<String>, exclusive: bool) -> Self {
+        self.namespaces.aliases.push(Namespace::new(exclusive, regex.into()));
+        self
+    }
+
+    /// Add a regex for room IDs to the namespaces of the appservice.
+    pub fn room_namespace(mut self, regex: impl Into<String>, exclusive: bool) -> Self {
+        self.namespaces.rooms.push(Namespace::new(exclusive, regex.into()));
+        self
+    }
+
+    /// Set whether requests from the users of the appservice are rate
+    /// limited.
+    pub fn rate_limited(mut self, rate_limited: bool) -> Self {
+        self.rate_limited = Some(rate_limited);
+        self
+    }
+
+    /// Set the third party protocols the appservice provides.
+    pub fn protocols(mut self, protocols: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.protocols = Some(protocols.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Build the registration.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if a namespace regex doesn't compile.
+    pub fn build(self) -> Result<AppServiceRegistration> {
+        let registration: Registration = RegistrationInit {
+            id: self.id,
+            url: self.url,
+            as_token: self.as_token.unwrap_or_else(generate_token),
+            hs_token: self.hs_token.unwrap_or_else(generate_token),
+            sender_localpart: self.sender_localpart,
+            namespaces: self.namespaces,
+            rate_limited: self.rate_limited,
+            protocols: self.protocols,
+        }
+        .into();
+
+        NamespaceCache::from_registration(&registration)?;
+
+        Ok(registration.into())
+    }
+}
+
+/// Generate a random token.
+fn generate_token() -> String {
+    thread_rng().sample_iter(Alphanumeric).take(TOKEN_LENGTH).map(char::from).collect()
+}
+
 /// Cache data for the registration namespaces.
 #[derive(Debug, Clone)]
 pub struct NamespaceCache {
@@ -101,24 +297,23 @@ pub struct NamespaceCache {
 impl NamespaceCache {
     /// Creates a new registration cache from a [`Registration`] value
     pub fn from_registration(registration: &Registration) -> Result<Self> {
-        let users = registration
-            .namespaces
-            .users
-            .iter()
-            .map(|user| Regex::new(&user.regex))
-            .collect::<Result<Vec<_>, _>>()?;
-        let aliases = registration
-            .namespaces
-            .aliases
-            .iter()
-            .map(|user| Regex::new(&user.regex))
-            .collect::<Result<Vec<_>, _>>()?;
-        let rooms = registration
-            .namespaces
-            .rooms
-            .iter()
-            .map(|user| Regex::new(&user.regex))
-            .collect::<Result<Vec<_>, _>>()?;
+        let users = compile_namespace("users", &registration.namespaces.users)?;
+        let aliases = compile_namespace("aliases", &registration.namespaces.aliases)?;
+        let rooms = compile_namespace("rooms", &registration.namespaces.rooms)?;
         Ok(NamespaceCache { users, aliases, rooms })
     }
 }
+
+/// Compile the regexes of the given namespaces.
+fn compile_namespace(kind: &'static str, namespaces: &[Namespace]) -> Result<Vec<Regex>> {
+    namespaces
+        .iter()
+        .map(|namespace| {
+            Regex::new(&namespace.regex).map_err(|source| Error::InvalidNamespaceRegex {
+                kind,
+                regex: namespace.regex.clone(),
+                source,
+            })
+        })
+        .collect()
+}
@@ -76,6 +76,9 @@ impl<'a> UserBuilder<'a> {
     /// This is primarily useful if you enable
     /// [`UserBuilder::login()`] and want to restore a session
     /// from a previous run.
+    ///
+    /// If no session is given, the session stored when the client of the user
+    /// was last built is restored, if any.
     pub fn restored_session(mut self, session: Session) -> Self {
         self.restored_session = Some(session);
         self
@@ -87,7 +90,7 @@ impl<'a> UserBuilder<'a> {
     /// This function returns an error if an invalid localpart is provided.
     pub async fn build(self) -> Result<Client> {
         if let Some(client) = self.appservice.clients.get(self.localpart) {
-            return Ok(client.clone());
+            return Ok(client);
         }
 
         let user_id = UserId::parse_with_server_name(self.localpart, &self.appservice.server_name)?;
@@ -97,9 +100,27 @@ impl<'a> UserBuilder<'a> {
             warn!("Client id '{user_id}' is not in the namespace")
         }
 
+        let is_sender = self.localpart == self.appservice.registration.sender_localpart;
+        let mut log_in = self.log_in;
+        let mut restored_session = self.restored_session;
+        if restored_session.is_none() && !is_sender {
+            restored_session = self.appservice.stored_session(self.localpart).await?;
+            // A stored session with its own access token comes from a login.
+            log_in |= restored_session
+                .as_ref()
+                .map_or(false, |s| s.access_token != self.appservice.registration.as_token);
+        }
+
         let mut builder = self.client_builder;
 
-        if !self.log_in && self.localpart != self.appservice.registration.sender_localpart {
+        #[cfg(feature = "sled")]
+        if let Some(store) = &self.appservice.user_sled_store {
+            // Localparts may contain slashes, but no percent signs.
+            let directory = self.localpart.replace('/', "%2F");
+            builder = builder.sled_store(store.path.join(directory), store.passphrase.as_deref());
+        }
+
+        if !log_in && !is_sender {
             builder = builder.assert_identity();
         }
 
@@ -110,9 +131,9 @@ impl<'a> UserBuilder<'a> {
             .await
             .map_err(ClientBuildError::assert_valid_builder_args)?;
 
-        let session = if let Some(session) = self.restored_session {
+        let session = if let Some(session) = restored_session {
             session
-        } else if self.log_in && self.localpart != self.appservice.registration.sender_localpart {
+        } else if log_in && !is_sender {
             let login_info =
                 login::v3::LoginInfo::ApplicationService(login::v3::ApplicationService::new(
                     UserIdentifier::UserIdOrLocalpart(self.localpart.to_owned()),
@@ -142,6 +163,9 @@ impl<'a> UserBuilder<'a> {
             }
         };
 
+        if !is_sender {
+            self.appservice.store_session(self.localpart, &session).await?;
+        }
         client.restore_session(session).await?;
 
         self.appservice.clients.insert(self.localpart.to_owned(), client.clone());
@@ -23,10 +23,10 @@ use std::{
 use axum::{
     async_trait,
     body::{Bytes, HttpBody},
-    extract::{FromRequest, FromRequestParts, Path},
+    extract::{rejection::PathRejection, FromRequest, FromRequestParts, Path},
     middleware::{self, Next},
     response::{ErrorResponse, IntoResponse, Response},
-    routing::{future::RouteFuture, get, put},
+    routing::{future::RouteFuture, get, post, put},
     BoxError, Extension, Json, Router, ServiceExt,
 };
 use http::StatusCode;
@@ -64,6 +64,15 @@ where
             .route("/_matrix/app/v1/users/:user_id", get(handlers::user))
             .route("/_matrix/app/v1/rooms/:room_id", get(handlers::room))
             .route("/_matrix/app/v1/transactions/:txn_id", put(handlers::transaction))
+            .route("/_matrix/app/v1/ping", post(handlers::ping))
+            .route("/_matrix/app/v1/thirdparty/protocol/:protocol", get(handlers::protocol))
+            .route("/_matrix/app/v1/thirdparty/user", get(handlers::user_id_users))
+            .route("/_matrix/app/v1/thirdparty/user/:protocol", get(handlers::protocol_users))
+            .route("/_matrix/app/v1/thirdparty/location", get(handlers::alias_locations))
+            .route(
+                "/_matrix/app/v1/thirdparty/location/:protocol",
+                get(handlers::protocol_locations),
+            )
             .route("/users/:user_id", get(handlers::user))
             .route("/rooms/:room_id", get(handlers::room))
             .route("/transactions/:txn_id", put(handlers::transaction))
@@ -143,9 +152,12 @@ where
         state: &S,
     ) -> Result<Self, Self::Rejection> {
         let (mut parts, body) = req.into_parts();
-        let path_params = Path::<Vec<String>>::from_request_parts(&mut parts, state)
-            .await
-            .map_err(IntoResponse::into_response)?;
+        let path_params = match Path::<Vec<String>>::from_request_parts(&mut parts, state).await {
+            Ok(Path(path_params)) => path_params,
+            // Some endpoints don't have path parameters
+            Err(PathRejection::MissingPathParams(_)) => Vec::new(),
+            Err(e) => return Err(e.into_response()),
+        };
         let bytes = Bytes::from_request(http::Request::new(body), state)
             .await
             .map_err(IntoResponse::into_response)?;
@@ -165,7 +177,12 @@ mod handlers {
     use http::StatusCode;
     use ruma::api::appservice::{
         event::push_events,
+        ping::send_ping,
         query::{query_room_alias, query_user_id},
+        thirdparty::{
+            get_location_for_protocol, get_location_for_room_alias, get_protocol,
+            get_user_for_protocol, get_user_for_user_id,
+        },
     };
     use serde::Serialize;
 
@@ -205,6 +222,80 @@ mod handlers {
         }
     }
 
+    pub async fn ping(
+        Extension(appservice): Extension<AppService>,
+        MatrixRequest(request): MatrixRequest<send_ping::v1::Request>,
+    ) -> impl IntoResponse {
+        if let Some(on_ping) = appservice.event_handler.ping.lock().await.as_mut() {
+            on_ping(appservice.clone(), request).await;
+        }
+        Json(EmptyObject {})
+    }
+
+    pub async fn protocol(
+        Extension(appservice): Extension<AppService>,
+        MatrixRequest(request): MatrixRequest<get_protocol::v1::Request>,
+    ) -> impl IntoResponse {
+        let protocol = match appservice.event_handler.protocols.lock().await.as_mut() {
+            Some(get_protocol) => get_protocol(appservice.clone(), request).await,
+            None => None,
+        };
+        protocol.map(Json).ok_or(StatusCode::NOT_FOUND)
+    }
+
+    pub async fn protocol_users(
+        Extension(appservice): Extension<AppService>,
+        MatrixRequest(request): MatrixRequest<get_user_for_protocol::v1::Request>,
+    ) -> impl IntoResponse {
+        let users = match appservice.event_handler.protocol_users.lock().await.as_mut() {
+            Some(get_users) => get_users(appservice.clone(), request).await,
+            None => Vec::new(),
+        };
+        non_empty(users)
+    }
+
+    pub async fn user_id_users(
+        Extension(appservice): Extension<AppService>,
+        MatrixRequest(request): MatrixRequest<get_user_for_user_id::v1::Request>,
+    ) -> impl IntoResponse {
+        let users = match appservice.event_handler.user_id_users.lock().await.as_mut() {
+            Some(get_users) => get_users(appservice.clone(), request).await,
+            None => Vec::new(),
+        };
+        non_empty(users)
+    }
+
+    pub async fn protocol_locations(
+        Extension(appservice): Extension<AppService>,
+        MatrixRequest(request): MatrixRequest<get_location_for_protocol::v1::Request>,
+    ) -> impl IntoResponse {
+        let locations = match appservice.event_handler.protocol_locations.lock().await.as_mut() {
+            Some(get_locations) => get_locations(appservice.clone(), request).await,
+            None => Vec::new(),
+        };
+        non_empty(locations)
+    }
+
+    pub async fn alias_locations(
+        Extension(appservice): Extension<AppService>,
+        MatrixRequest(request): MatrixRequest<get_location_for_room_alias::v1::Request>,
+    ) -> impl IntoResponse {
+        let locations = match appservice.event_handler.alias_locations.lock().await.as_mut() {
+            Some(get_locations) => get_locations(appservice.clone(), request).await,
+            None => Vec::new(),
+        };
+        non_empty(locations)
+    }
+
+    /// The lookup endpoints answer with a 404 if nothing was found.
+    fn non_empty<T: Serialize>(results: Vec<T>) -> Result<Json<Vec<T>>, StatusCode> {
+        if results.is_empty() {
+            Err(StatusCode::NOT_FOUND)
+        } else {
+            Ok(Json(results))
+        }
+    }
+
     pub async fn transaction(
         appservice: Extension<AppService>,
         MatrixRequest(request): MatrixRequest<push_events::v1::Request>,
@@ -47,7 +47,7 @@ use ruma::{
         },
         AnyGlobalAccountDataEvent, AnyRoomAccountDataEvent, AnyStrippedStateEvent,
         AnySyncEphemeralRoomEvent, AnySyncStateEvent, AnySyncTimelineEvent,
-        GlobalAccountDataEventType, StateEventType,
+        GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType,
     },
     push::{Action, PushConditionRoomCtx, Ruleset},
     serde::Raw,
@@ -58,7 +58,9 @@ use tracing::{debug, info, trace, warn};
 #[cfg(feature = "e2e-encryption")]
 use crate::error::Error;
 use crate::{
-    deserialized_responses::{AmbiguityChanges, MembersResponse, SyncTimelineEvent},
+    deserialized_responses::{
+        AmbiguityChanges, MembersResponse, SyncTimelineEvent, SyncToDeviceEvent,
+    },
     error::Result,
     rooms::{Room, RoomInfo, RoomType},
     store::{
@@ -145,6 +147,11 @@ impl BaseClient {
         self.store.set_session_tokens(tokens)
     }
 
+    /// Forget the session tokens, once they were revoked.
+    pub fn clear_session_tokens(&self) {
+        self.store.clear_session_tokens()
+    }
+
     /// Get the user login session.
     ///
     /// If the client is currently logged in, this will return a
@@ -307,6 +314,12 @@ impl BaseClient {
         let mut timeline = Timeline::new(limited, prev_batch);
         let mut push_context = self.get_push_room_context(room, room_info, changes).await?;
 
+        // Receipts can only be placed precisely on the events of the latest
+        // batch, keep it around if the room has no new events.
+        if !events.is_empty() {
+            room_info.read_receipts.start_batch();
+        }
+
         for event in events {
             #[allow(unused_mut)]
             let mut event: SyncTimelineEvent = event.into();
@@ -404,30 +417,33 @@ impl BaseClient {
                         push_context = self.get_push_room_context(room, room_info, changes).await?;
                     }
 
-                    if let Some(context) = &push_context {
-                        let actions = push_rules.get_actions(&event.event, context);
-
-                        if actions.iter().any(|a| matches!(a, Action::Notify)) {
-                            changes.add_notification(
-                                room_id,
-                                Notification::new(
-                                    actions.to_owned(),
-                                    event.event.clone(),
-                                    false,
-                                    room_id.to_owned(),
-                                    MilliSecondsSinceUnixEpoch::now(),
-                                ),
-                            );
-                        }
-                        // TODO if there is an
-                        // Action::SetTweak(Tweak::Highlight) we need to store
-                        // its value with the event so a client can show if the
-                        // event is highlighted
-                        // in the UI.
-                        // Requires the possibility to associate custom data
-                        // with events and to
-                        // store them.
+                    let actions: &[Action] = match &push_context {
+                        Some(context) => push_rules.get_actions(&event.event, context),
+                        None => &[],
+                    };
+
+                    if actions.iter().any(|a| matches!(a, Action::Notify)) {
+                        changes.add_notification(
+                            room_id,
+                            Notification::new(
+                                actions.to_owned(),
+                                event.event.clone(),
+                                false,
+                                room_id.to_owned(),
+                                MilliSecondsSinceUnixEpoch::now(),
+                            ),
+                        );
                     }
+                    // TODO if there is an
+                    // Action::SetTweak(Tweak::Highlight) we need to store
+                    // its value with the event so a client can show if the
+                    // event is highlighted
+                    // in the UI.
+                    // Requires the possibility to associate custom data
+                    // with events and to
+                    // store them.
+
+                    room_info.read_receipts.process_event(&event, user_id, actions);
                 }
                 Err(e) => {
                     warn!("Error deserializing event {:?}", e);
@@ -546,6 +562,32 @@ impl BaseClient {
         }
     }
 
+    /// Update the unread counts of the room with the read receipts and the
+    /// `m.fully_read` marker of the current user that are part of the given
+    /// changes.
+    ///
+    /// This must be called after the timeline of the room was handled, so the
+    /// receipts can point to events of the current response.
+    pub(crate) fn handle_read_receipts(
+        room_info: &mut RoomInfo,
+        own_user_id: &UserId,
+        changes: &StateChanges,
+    ) {
+        if let Some(receipts) = changes.receipts.get(&*room_info.room_id) {
+            room_info.read_receipts.process_receipts(receipts, own_user_id);
+        }
+
+        let fully_read = changes
+            .room_account_data
+            .get(&*room_info.room_id)
+            .and_then(|events| events.get(&RoomAccountDataEventType::FullyRead))
+            .and_then(|raw| raw.deserialize().ok());
+
+        if let Some(AnyRoomAccountDataEvent::FullyRead(event)) = fully_read {
+            room_info.read_receipts.mark_as_read_up_to(&event.content.event_id);
+        }
+    }
+
     pub(crate) async fn handle_account_data(
         &self,
         events: &[Raw<AnyGlobalAccountDataEvent>],
@@ -597,7 +639,7 @@ impl BaseClient {
         changed_devices: &api::sync::sync_events::DeviceLists,
         one_time_keys_counts: &BTreeMap<ruma::DeviceKeyAlgorithm, UInt>,
         unused_fallback_keys: Option<&[ruma::DeviceKeyAlgorithm]>,
-    ) -> Result<Vec<Raw<ruma::events::AnyToDeviceEvent>>> {
+    ) -> Result<Vec<SyncToDeviceEvent>> {
         if let Some(o) = self.olm_machine() {
             // Let the crypto machine handle the sync response, this
             // decrypts to-device events, but leaves room events alone.
@@ -611,7 +653,7 @@ impl BaseClient {
             )
             .await?)
         } else {
-            Ok(to_device_events)
+            Ok(to_device_events.into_iter().map(Into::into).collect())
         }
     }
 
@@ -703,6 +745,9 @@ impl BaseClient {
                 device_unused_fallback_key_types.as_deref(),
             )
             .await?;
+        #[cfg(not(feature = "e2e-encryption"))]
+        let to_device_events: Vec<SyncToDeviceEvent> =
+            to_device_events.into_iter().map(Into::into).collect();
 
         let mut changes = StateChanges::new(next_batch.clone());
         let mut ambiguity_cache = AmbiguityCache::new(self.store.inner.clone());
@@ -761,6 +806,8 @@ impl BaseClient {
             self.handle_room_account_data(&room_id, &new_info.account_data.events, &mut changes)
                 .await;
 
+            Self::handle_read_receipts(&mut room_info, room.own_user_id(), &changes);
+
             #[cfg(feature = "e2e-encryption")]
             if room_info.is_encrypted() {
                 if let Some(o) = self.olm_machine() {
@@ -28,6 +28,7 @@ mod client;
 pub mod deserialized_responses;
 mod error;
 pub mod media;
+mod read_receipts;
 mod rooms;
 mod session;
 #[cfg(feature = "experimental-sliding-sync")]
@@ -42,6 +43,7 @@ pub use http;
 #[cfg(feature = "e2e-encryption")]
 pub use matrix_sdk_crypto as crypto;
 pub use once_cell;
+pub use read_receipts::UnreadCounts;
 pub use rooms::{DisplayName, Room, RoomInfo, RoomMember, RoomType};
 pub use store::{StateChanges, StateStore, StoreError};
 pub use utils::{
@@ -0,0 +1,364 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Client-side computation of the unread counts of a room.
+//!
+//! The counts sent by the server can't take into account the content of
+//! encrypted events, so they are computed from the decrypted timeline events
+//! and their push actions instead. Only the counts and the positions of the
+//! user's latest read receipt, or `m.fully_read` marker, and of the latest
+//! event are stored.
+//!
+//! The counts are computed when the events are received: an event that
+//! couldn't be decrypted is counted as a message, but it isn't counted again
+//! once it's decrypted later on, and redacting an unread event doesn't
+//! subtract it from the counts.
+
+use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
+use ruma::{
+    events::{
+        receipt::{ReceiptEventContent, ReceiptThread, ReceiptType},
+        room::message::Relation,
+        AnySyncMessageLikeEvent, AnySyncTimelineEvent, SyncMessageLikeEvent,
+    },
+    push::{Action, Tweak},
+    EventId, OwnedEventId, OwnedUserId, UserId,
+};
+use serde::{Deserialize, Serialize};
+
+/// The unread counts of a room, computed client-side.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnreadCounts {
+    /// The number of unread messages, whether they notify or not.
+    pub messages: u64,
+    /// The number of unread events that notify, according to the push rules.
+    pub notifications: u64,
+    /// The number of unread events that are highlighted, according to the
+    /// push rules, e.g. because they mention the user.
+    pub mentions: u64,
+}
+
+impl UnreadCounts {
+    fn add(&mut self, event: &UnreadEvent) {
+        self.messages += u64::from(event.is_message);
+        self.notifications += u64::from(event.is_notification);
+        self.mentions += u64::from(event.is_mention);
+    }
+}
+
+/// An event of the current timeline batch, received after the latest read
+/// receipt.
+#[derive(Clone, Debug)]
+struct UnreadEvent {
+    event_id: OwnedEventId,
+    is_message: bool,
+    is_notification: bool,
+    is_mention: bool,
+}
+
+/// The events of the timeline batch that is being processed, they are only
+/// kept in memory.
+#[derive(Clone, Debug, Default)]
+struct TimelineBatch {
+    /// The unread events of the batch, oldest first.
+    events: Vec<UnreadEvent>,
+    /// The unread counts before the batch.
+    counts_before: UnreadCounts,
+    /// The latest event received before the batch.
+    latest_event_id_before: Option<OwnedEventId>,
+}
+
+/// The read receipts state of a room, kept in its `RoomInfo`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct RoomReadReceipts {
+    /// The current unread counts.
+    counts: UnreadCounts,
+    /// The event the latest read receipt of the user points to.
+    latest_read_event_id: Option<OwnedEventId>,
+    /// The latest event received in the timeline of the room.
+    latest_event_id: Option<OwnedEventId>,
+    /// The latest timeline batch, so receipts pointing to one of its events
+    /// can be taken into account.
+    #[serde(skip)]
+    batch: TimelineBatch,
+}
+
+impl RoomReadReceipts {
+    /// The current unread counts.
+    pub(crate) fn counts(&self) -> UnreadCounts {
+        self.counts
+    }
+
+    /// The latest event received in the timeline of the room.
+    pub(crate) fn latest_event_id(&self) -> Option<&EventId> {
+        self.latest_event_id.as_deref()
+    }
+
+    /// Start a new timeline batch, the events of the previous one are
+    /// forgotten.
+    ///
+    /// This should be called before the events of a non-empty timeline chunk
+    /// are processed.
+    ///
+    /// A read receipt can only be placed precisely if it points to one of the
+    /// events of the current batch, or to the latest event. Receipts pointing
+    /// to other events are most likely older than the current one and are
+    /// ignored.
+    pub(crate) fn start_batch(&mut self) {
+        self.batch = TimelineBatch {
+            events: Vec::new(),
+            counts_before: self.counts,
+            latest_event_id_before: self.latest_event_id.clone(),
+        };
+    }
+
+    /// Take a new timeline event into account.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event, decrypted if possible.
+    ///
+    /// * `own_user_id` - The ID of the current user, their own events mark the
+    /// room as read.
+    ///
+    /// * `actions` - The push actions of the event.
+    pub(crate) fn process_event(
+        &mut self,
+        event: &SyncTimelineEvent,
+        own_user_id: &UserId,
+        actions: &[Action],
+    ) {
+        let Some(event_id) = event.event_id() else {
+            return;
+        };
+
+        if self.batch.events.iter().any(|e| e.event_id == event_id) {
+            return;
+        }
+
+        self.latest_event_id = Some(event_id.clone());
+
+        let sender = event.event.get_field::<OwnedUserId>("sender").ok().flatten();
+
+        // Sending an event implies having read the room.
+        if sender.as_deref() == Some(own_user_id)
+            || self.latest_read_event_id.as_ref() == Some(&event_id)
+        {
+            self.mark_all_as_read(event_id);
+            return;
+        }
+
+        let is_latest_event_before = self.batch.latest_event_id_before.as_ref() == Some(&event_id);
+
+        let unread_event = UnreadEvent {
+            event_id,
+            is_message: counts_as_unread_message(event),
+            is_notification: actions.iter().any(|a| matches!(a, Action::Notify)),
+            is_mention: actions
+                .iter()
+                .any(|a| matches!(a, Action::SetTweak(Tweak::Highlight(true)))),
+        };
+
+        if is_latest_event_before {
+            // The server sent us again events we had already received, e.g.
+            // because the room came back into a sliding sync list. They were
+            // all counted already.
+            self.counts = self.batch.counts_before;
+        } else {
+            self.counts.add(&unread_event);
+        }
+
+        self.batch.events.push(unread_event);
+    }
+
+    /// Take the read receipts of the current user in the given receipt event
+    /// into account.
+    pub(crate) fn process_receipts(&mut self, content: &ReceiptEventContent, own_user_id: &UserId) {
+        for (event_id, receipts) in content.iter() {
+            let is_own_read_receipt = [ReceiptType::Read, ReceiptType::ReadPrivate]
+                .iter()
+                .filter_map(|receipt_type| receipts.get(receipt_type)?.get(own_user_id))
+                .any(|receipt| {
+                    matches!(receipt.thread, ReceiptThread::Unthreaded | ReceiptThread::Main)
+                });
+
+            if is_own_read_receipt {
+                self.mark_as_read_up_to(event_id);
+            }
+        }
+    }
+
+    /// Mark all the events up to the given one as read.
+    ///
+    /// Does nothing if the event is neither the latest event nor part of the
+    /// current timeline batch, e.g. because it's older than the current read
+    /// receipt.
+    pub(crate) fn mark_as_read_up_to(&mut self, event_id: &EventId) {
+        let position = self.batch.events.iter().position(|e| e.event_id == event_id);
+
+        if self.latest_event_id.as_deref() == Some(event_id) {
+            self.mark_all_as_read(event_id.to_owned());
+        } else if let Some(position) = position {
+            self.batch.events.drain(..=position);
+            self.latest_read_event_id = Some(event_id.to_owned());
+
+            // Everything before the batch is read as well, only the events
+            // of the batch after the receipt are still unread.
+            let mut counts = UnreadCounts::default();
+            for event in &self.batch.events {
+                counts.add(event);
+            }
+            self.counts = counts;
+        }
+    }
+
+    fn mark_all_as_read(&mut self, event_id: OwnedEventId) {
+        self.batch.events.clear();
+        self.latest_read_event_id = Some(event_id);
+        self.counts = UnreadCounts::default();
+    }
+}
+
+/// Whether the given event is a message the user would consider unread.
+///
+/// Edits, reactions, redacted events and state events don't count, events that
+/// couldn't be decrypted do.
+fn counts_as_unread_message(event: &SyncTimelineEvent) -> bool {
+    match event.event.deserialize() {
+        Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+            SyncMessageLikeEvent::Original(event),
+        ))) => !matches!(event.content.relates_to, Some(Relation::Replacement(_))),
+        Ok(AnySyncTimelineEvent::MessageLike(
+            AnySyncMessageLikeEvent::Sticker(SyncMessageLikeEvent::Original(_))
+            | AnySyncMessageLikeEvent::RoomEncrypted(SyncMessageLikeEvent::Original(_)),
+        )) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
+    use ruma::{
+        event_id,
+        events::{receipt::ReceiptEventContent, AnySyncTimelineEvent},
+        push::{Action, Tweak},
+        serde::Raw,
+        user_id, EventId, UserId,
+    };
+    use serde_json::json;
+
+    use super::{RoomReadReceipts, UnreadCounts};
+
+    fn message(event_id: &EventId, sender: &UserId) -> SyncTimelineEvent {
+        Raw::new(&json!({
+            "content": { "body": "Hello", "msgtype": "m.text" },
+            "event_id": event_id,
+            "origin_server_ts": 152037280,
+            "sender": sender,
+            "type": "m.room.message",
+        }))
+        .unwrap()
+        .cast::<AnySyncTimelineEvent>()
+        .into()
+    }
+
+    fn counts(messages: u64, notifications: u64, mentions: u64) -> UnreadCounts {
+        UnreadCounts { messages, notifications, mentions }
+    }
+
+    #[test]
+    fn events_are_counted_until_read() {
+        let own_user_id = user_id!("@me:example.org");
+        let other = user_id!("@other:example.org");
+        let mut read_receipts = RoomReadReceipts::default();
+
+        read_receipts.process_event(&message(event_id!("$1:example.org"), other), own_user_id, &[]);
+        read_receipts.process_event(
+            &message(event_id!("$2:example.org"), other),
+            own_user_id,
+            &[Action::Notify],
+        );
+        read_receipts.process_event(
+            &message(event_id!("$3:example.org"), other),
+            own_user_id,
+            &[Action::Notify, Action::SetTweak(Tweak::Highlight(true))],
+        );
+        assert_eq!(read_receipts.counts(), counts(3, 2, 1));
+
+        // The same event received twice is only counted once.
+        read_receipts.process_event(&message(event_id!("$3:example.org"), other), own_user_id, &[]);
+        assert_eq!(read_receipts.counts(), counts(3, 2, 1));
+
+        let receipt: ReceiptEventContent = serde_json::from_value(json!({
+            "$2:example.org": { "m.read": { own_user_id.as_str(): { "ts": 1 } } },
+        }))
+        .unwrap();
+        read_receipts.process_receipts(&receipt, own_user_id);
+        assert_eq!(read_receipts.counts(), counts(1, 1, 1));
+
+        // A receipt for an older event doesn't change anything.
+        read_receipts.mark_as_read_up_to(event_id!("$1:example.org"));
+        assert_eq!(read_receipts.counts(), counts(1, 1, 1));
+
+        // An event sent by the user marks the whole room as read.
+        read_receipts.process_event(
+            &message(event_id!("$4:example.org"), own_user_id),
+            own_user_id,
+            &[],
+        );
+        assert_eq!(read_receipts.counts(), UnreadCounts::default());
+        assert_eq!(read_receipts.latest_event_id(), Some(event_id!("$4:example.org")));
+
+        // Receiving the read event again doesn't count it.
+        read_receipts.process_event(
+            &message(event_id!("$4:example.org"), own_user_id),
+            own_user_id,
+            &[],
+        );
+        assert_eq!(read_receipts.counts(), UnreadCounts::default());
+    }
+
+    #[test]
+    fn resent_events_are_not_counted_again() {
+        let own_user_id = user_id!("@me:example.org");
+        let other = user_id!("@other:example.org");
+        let mut read_receipts = RoomReadReceipts::default();
+
+        read_receipts.start_batch();
+        read_receipts.process_event(&message(event_id!("$1:example.org"), other), own_user_id, &[]);
+        read_receipts.process_event(&message(event_id!("$2:example.org"), other), own_user_id, &[]);
+        assert_eq!(read_receipts.counts(), counts(2, 0, 0));
+
+        // Only the counts and the positions of the receipt and of the latest
+        // event are stored.
+        let stored: RoomReadReceipts =
+            serde_json::from_value(serde_json::to_value(&read_receipts).unwrap()).unwrap();
+        assert_eq!(stored.counts(), counts(2, 0, 0));
+        assert!(stored.batch.events.is_empty());
+
+        // The server sends the latest events of the room again, followed by a
+        // new one.
+        read_receipts.start_batch();
+        for event_id in [event_id!("$1:example.org"), event_id!("$2:example.org")] {
+            read_receipts.process_event(&message(event_id, other), own_user_id, &[]);
+        }
+        read_receipts.process_event(&message(event_id!("$3:example.org"), other), own_user_id, &[]);
+        assert_eq!(read_receipts.counts(), counts(3, 0, 0));
+
+        // A receipt for an event of the current batch is placed precisely.
+        read_receipts.mark_as_read_up_to(event_id!("$2:example.org"));
+        assert_eq!(read_receipts.counts(), counts(1, 0, 0));
+    }
+}
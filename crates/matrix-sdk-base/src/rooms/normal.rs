@@ -40,6 +40,7 @@ use tracing::debug;
 
 use super::{BaseRoomInfo, DisplayName, RoomMember};
 use crate::{
+    read_receipts::{RoomReadReceipts, UnreadCounts},
     store::{Result as StoreResult, StateStore, StateStoreExt},
     sync::UnreadNotificationsCount,
     MinimalStateEvent,
@@ -125,10 +126,25 @@ impl Room {
     }
 
     /// Get the unread notification counts.
+    ///
+    /// These counts are sent by the server, which can't look into encrypted
+    /// events. See [`Room::unread_counts`] for the counts computed by the
+    /// client.
     pub fn unread_notification_counts(&self) -> UnreadNotificationsCount {
         self.inner.read().unwrap().notification_counts
     }
 
+    /// Get the unread counts computed client-side, from the decrypted events,
+    /// their push actions, and the user's read receipt and fully read marker.
+    pub fn unread_counts(&self) -> UnreadCounts {
+        self.inner.read().unwrap().read_receipts.counts()
+    }
+
+    /// Get the ID of the latest event received in the timeline of this room.
+    pub fn latest_event_id(&self) -> Option<OwnedEventId> {
+        self.inner.read().unwrap().read_receipts.latest_event_id().map(ToOwned::to_owned)
+    }
+
     /// Check if the room has its members fully synced.
     ///
     /// Members might be missing if lazy member loading was enabled for the
@@ -533,6 +549,9 @@ pub struct RoomInfo {
     /// Base room info which holds some basic event contents important for the
     /// room state.
     pub(crate) base_info: BaseRoomInfo,
+    /// The state needed to compute the unread counts client-side.
+    #[serde(default)]
+    pub(crate) read_receipts: RoomReadReceipts,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -583,6 +602,7 @@ impl RoomInfo {
             sync_info: SyncInfo::NoState,
             encryption_state_synced: false,
             base_info: BaseRoomInfo::new(),
+            read_receipts: Default::default(),
         }
     }
 
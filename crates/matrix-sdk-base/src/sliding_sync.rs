@@ -1,12 +1,19 @@
+use std::collections::BTreeMap;
 #[cfg(feature = "e2e-encryption")]
 use std::ops::Deref;
 
-use ruma::api::client::sync::sync_events::{
-    v3::{self, Ephemeral},
-    v4,
-};
 #[cfg(feature = "e2e-encryption")]
 use ruma::UserId;
+use ruma::{
+    api::client::sync::sync_events::{
+        v3::{self, Ephemeral},
+        v4,
+    },
+    assign,
+    events::{AnyRoomAccountDataEvent, AnySyncEphemeralRoomEvent},
+    serde::Raw,
+    OwnedRoomId, RoomId,
+};
 use tracing::{debug, info, instrument};
 
 use super::BaseClient;
@@ -47,7 +54,7 @@ impl BaseClient {
             return Ok(SyncResponse::default());
         };
 
-        let v4::Extensions { to_device, e2ee, account_data, .. } = extensions;
+        let v4::Extensions { to_device, e2ee, account_data, receipts, typing, .. } = extensions;
 
         let to_device_events = to_device.map(|v4| v4.events).unwrap_or_default();
 
@@ -85,6 +92,9 @@ impl BaseClient {
             )
             .await?
         };
+        #[cfg(not(feature = "e2e-encryption"))]
+        let to_device_events: Vec<crate::deserialized_responses::SyncToDeviceEvent> =
+            to_device_events.into_iter().map(Into::into).collect();
 
         let store = self.store.clone();
         let mut changes = StateChanges::default();
@@ -96,6 +106,24 @@ impl BaseClient {
 
         let push_rules = self.get_push_rules(&changes).await?;
 
+        // The receipts and typing notifications come in through extensions,
+        // gather them by room so they are handled like the ephemeral events of
+        // a `/sync` v3 response.
+        let mut ephemeral_events: BTreeMap<OwnedRoomId, Vec<Raw<AnySyncEphemeralRoomEvent>>> =
+            BTreeMap::new();
+
+        if let Some(receipts) = receipts {
+            for (room_id, event) in receipts.rooms {
+                ephemeral_events.entry(room_id).or_default().push(event.cast());
+            }
+        }
+
+        if let Some(typing) = typing {
+            for (room_id, event) in typing.rooms {
+                ephemeral_events.entry(room_id).or_default().push(event.cast());
+            }
+        }
+
         let mut new_rooms = Rooms::default();
 
         for (room_id, room_data) in rooms.into_iter() {
@@ -141,27 +169,16 @@ impl BaseClient {
                     Default::default()
                 };
 
-                // FIXME not yet supported by sliding sync. see
-                // https://github.com/matrix-org/matrix-rust-sdk/issues/1014
-                // if let Some(event) =
-                //     room_data.ephemeral.events.iter().find_map(|e| match e.deserialize() {
-                //         Ok(AnySyncEphemeralRoomEvent::Receipt(event)) => Some(event.content),
-                //         _ => None,
-                //     })
-                // {
-                //     changes.add_receipts(&room_id, event);
-                // }
-
-                let room_account_data = if let Some(inner_account_data) = &account_data {
-                    if let Some(events) = inner_account_data.rooms.get(&room_id) {
-                        self.handle_room_account_data(&room_id, events, &mut changes).await;
-                        Some(events.to_vec())
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                };
+                let ephemeral = ephemeral_events.remove(&room_id).unwrap_or_default();
+                Self::handle_ephemeral_receipts(&room_id, &ephemeral, &mut changes);
+
+                let room_account_data = self
+                    .handle_sliding_sync_room_account_data(
+                        &room_id,
+                        account_data.as_ref(),
+                        &mut changes,
+                    )
+                    .await;
 
                 if room_data.limited {
                     room_info.mark_members_missing();
@@ -181,6 +198,8 @@ impl BaseClient {
                     )
                     .await?;
 
+                Self::handle_read_receipts(&mut room_info, room.own_user_id(), &changes);
+
                 #[cfg(feature = "e2e-encryption")]
                 if room_info.is_encrypted() {
                     if let Some(o) = self.olm_machine() {
@@ -209,8 +228,8 @@ impl BaseClient {
                     JoinedRoom::new(
                         timeline,
                         v3::State::with_events(room_data.required_state.clone()),
-                        room_account_data.unwrap_or_default(),
-                        Ephemeral::default(),
+                        room_account_data,
+                        assign!(Ephemeral::default(), { events: ephemeral }),
                         notification_count,
                     ),
                 );
@@ -219,6 +238,61 @@ impl BaseClient {
             }
         }
 
+        // The extensions may carry data for joined rooms which aren't part of
+        // the response otherwise, e.g. a new receipt in a room without new
+        // events.
+        let mut extension_only_rooms: Vec<OwnedRoomId> = ephemeral_events.keys().cloned().collect();
+        if let Some(account_data) = &account_data {
+            extension_only_rooms.extend(
+                account_data
+                    .rooms
+                    .keys()
+                    .filter(|room_id| {
+                        !new_rooms.join.contains_key(*room_id)
+                            && !new_rooms.invite.contains_key(*room_id)
+                            && !ephemeral_events.contains_key(*room_id)
+                    })
+                    .cloned(),
+            );
+        }
+
+        for room_id in extension_only_rooms {
+            let Some(room) = store.get_room(&room_id) else {
+                debug!(?room_id, "Ignoring extension data for an unknown room");
+                continue;
+            };
+
+            if room.room_type() != RoomType::Joined {
+                continue;
+            }
+
+            let ephemeral = ephemeral_events.remove(&room_id).unwrap_or_default();
+            Self::handle_ephemeral_receipts(&room_id, &ephemeral, &mut changes);
+
+            let room_account_data = self
+                .handle_sliding_sync_room_account_data(
+                    &room_id,
+                    account_data.as_ref(),
+                    &mut changes,
+                )
+                .await;
+
+            let mut room_info = room.clone_info();
+            Self::handle_read_receipts(&mut room_info, room.own_user_id(), &changes);
+            changes.add_room(room_info);
+
+            new_rooms.join.insert(
+                room_id,
+                JoinedRoom::new(
+                    Default::default(),
+                    Default::default(),
+                    room_account_data,
+                    assign!(Ephemeral::default(), { events: ephemeral }),
+                    room.unread_notification_counts(),
+                ),
+            );
+        }
+
         // TODO remove this, we're processing account data events here again
         // because we want to have the push rules in place before we process
         // rooms and their events, but we want to create the rooms before we
@@ -260,4 +334,117 @@ impl BaseClient {
             device_one_time_keys_count,
         })
     }
+
+    /// Store the receipts found in the given ephemeral events.
+    fn handle_ephemeral_receipts(
+        room_id: &RoomId,
+        ephemeral: &[Raw<AnySyncEphemeralRoomEvent>],
+        changes: &mut StateChanges,
+    ) {
+        if let Some(event) = ephemeral.iter().find_map(|e| match e.deserialize() {
+            Ok(AnySyncEphemeralRoomEvent::Receipt(event)) => Some(event.content),
+            _ => None,
+        }) {
+            changes.add_receipts(room_id, event);
+        }
+    }
+
+    /// Handle the account data of the given room from the account data
+    /// extension, and return the events.
+    async fn handle_sliding_sync_room_account_data(
+        &self,
+        room_id: &RoomId,
+        account_data: Option<&v4::AccountData>,
+        changes: &mut StateChanges,
+    ) -> Vec<Raw<AnyRoomAccountDataEvent>> {
+        let Some(events) = account_data.and_then(|a| a.rooms.get(room_id)) else {
+            return Vec::new();
+        };
+
+        self.handle_room_account_data(room_id, events, changes).await;
+        events.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_test::async_test;
+    use ruma::{
+        api::client::sync::sync_events::v4,
+        event_id,
+        events::receipt::{ReceiptThread, ReceiptType},
+        room_id, user_id,
+    };
+    use serde_json::json;
+
+    use super::BaseClient;
+    use crate::SessionMeta;
+
+    #[async_test]
+    async fn receipts_and_typing_extensions_are_processed() {
+        let user_id = user_id!("@alice:example.org");
+        let room_id = room_id!("!test:example.org");
+
+        let client = BaseClient::new();
+        client
+            .set_session_meta(SessionMeta {
+                user_id: user_id.to_owned(),
+                device_id: "FOOBAR".into(),
+            })
+            .await
+            .unwrap();
+
+        let response: v4::Response = serde_json::from_value(json!({
+            "pos": "0",
+            "lists": {},
+            "rooms": {
+                room_id.as_str(): { "name": "Test room", "initial": true },
+            },
+        }))
+        .unwrap();
+        client.process_sliding_sync(response).await.unwrap();
+
+        // The room isn't part of this response, only of the extensions.
+        let response: v4::Response = serde_json::from_value(json!({
+            "pos": "1",
+            "lists": {},
+            "rooms": {},
+            "extensions": {
+                "receipts": {
+                    "rooms": {
+                        room_id.as_str(): {
+                            "type": "m.receipt",
+                            "content": {
+                                "$read:example.org": {
+                                    "m.read": { user_id.as_str(): { "ts": 1436451550453u64 } },
+                                },
+                            },
+                        },
+                    },
+                },
+                "typing": {
+                    "rooms": {
+                        room_id.as_str(): {
+                            "type": "m.typing",
+                            "content": { "user_ids": [user_id] },
+                        },
+                    },
+                },
+            },
+        }))
+        .unwrap();
+        let sync_response = client.process_sliding_sync(response).await.unwrap();
+
+        let joined_room = sync_response.rooms.join.get(room_id).unwrap();
+        assert_eq!(joined_room.ephemeral.events.len(), 2);
+
+        let (event_id, _) = client
+            .get_room(room_id)
+            .unwrap()
+            .user_receipt(ReceiptType::Read, ReceiptThread::Unthreaded, user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event_id, event_id!("$read:example.org"));
+    }
 }
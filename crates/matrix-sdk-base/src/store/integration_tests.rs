@@ -848,6 +848,11 @@ macro_rules! statestore_integration_tests {
 
             assert_eq!(Some(value.as_ref()), read.as_deref());
 
+            let removed = store.remove_custom_value(key.as_bytes()).await?;
+            assert_eq!(Some(value.as_ref()), removed.as_deref());
+            assert_eq!(store.get_custom_value(key.as_bytes()).await?, None);
+            assert_eq!(store.remove_custom_value(key.as_bytes()).await?, None);
+
             Ok(())
         }
 
@@ -337,6 +337,13 @@ pub trait StateStore: AsyncTraitDeps {
     /// * `value` - The value to insert
     async fn set_custom_value(&self, key: &[u8], value: Vec<u8>) -> Result<Option<Vec<u8>>>;
 
+    /// Remove arbitrary data from the custom store and return it if existed
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to remove data from
+    async fn remove_custom_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
     /// Add a media file's content in the media store.
     ///
     /// # Arguments
@@ -578,6 +585,11 @@ impl Store {
         self.session_tokens.set(Some(tokens));
     }
 
+    /// Forget the current [`SessionTokens`], once they were revoked.
+    pub fn clear_session_tokens(&self) {
+        self.session_tokens.set(None);
+    }
+
     /// The current [`Session`] containing our user id, device ID, access
     /// token and optional refresh token.
     pub fn session(&self) -> Option<Session> {
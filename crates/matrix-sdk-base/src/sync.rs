@@ -16,7 +16,7 @@
 
 use std::collections::BTreeMap;
 
-use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
+use matrix_sdk_common::deserialized_responses::{SyncTimelineEvent, SyncToDeviceEvent};
 use ruma::{
     api::client::{
         push::get_notifications::v3::Notification,
@@ -25,7 +25,7 @@ use ruma::{
             DeviceLists, UnreadNotificationsCount as RumaUnreadNotificationsCount,
         },
     },
-    events::{AnyGlobalAccountDataEvent, AnyRoomAccountDataEvent, AnyToDeviceEvent},
+    events::{AnyGlobalAccountDataEvent, AnyRoomAccountDataEvent},
     serde::Raw,
     DeviceKeyAlgorithm, OwnedRoomId,
 };
@@ -45,8 +45,9 @@ pub struct SyncResponse {
     pub presence: Presence,
     /// The global private data created by this user.
     pub account_data: Vec<Raw<AnyGlobalAccountDataEvent>>,
-    /// Messages sent directly between devices.
-    pub to_device_events: Vec<Raw<AnyToDeviceEvent>>,
+    /// Messages sent directly between devices, encrypted ones are replaced by
+    /// their decrypted version.
+    pub to_device_events: Vec<SyncToDeviceEvent>,
     /// Information on E2E device updates.
     ///
     /// Only present on an incremental sync.
@@ -1,7 +1,7 @@
 use std::collections::BTreeMap;
 
 use ruma::{
-    events::{AnySyncTimelineEvent, AnyTimelineEvent},
+    events::{AnySyncTimelineEvent, AnyTimelineEvent, AnyToDeviceEvent},
     serde::Raw,
     DeviceKeyAlgorithm, OwnedDeviceId, OwnedEventId, OwnedUserId,
 };
@@ -31,6 +31,14 @@ pub enum AlgorithmInfo {
         /// key.
         sender_claimed_keys: BTreeMap<DeviceKeyAlgorithm, String>,
     },
+    /// The info if the event was encrypted using m.olm.v1.curve25519-aes-sha2
+    OlmV1Curve25519AesSha2 {
+        /// The curve25519 key of the device that sent us the event.
+        curve25519_key: String,
+        /// The signing keys the sender included in the encrypted payload of
+        /// the event. This map will usually contain a single ed25519 key.
+        sender_claimed_keys: BTreeMap<DeviceKeyAlgorithm, String>,
+    },
 }
 
 /// Struct containing information on how an event was decrypted.
@@ -85,6 +93,24 @@ impl From<TimelineEvent> for SyncTimelineEvent {
     }
 }
 
+/// A to-device event coming from a sync that holds optional encryption info.
+///
+/// Encrypted to-device events are replaced by their decrypted version.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SyncToDeviceEvent {
+    /// The actual event.
+    pub event: Raw<AnyToDeviceEvent>,
+    /// The encryption info about the event. Will be `None` if the event was not
+    /// encrypted, or if the info about its sender couldn't be loaded.
+    pub encryption_info: Option<EncryptionInfo>,
+}
+
+impl From<Raw<AnyToDeviceEvent>> for SyncToDeviceEvent {
+    fn from(inner: Raw<AnyToDeviceEvent>) -> Self {
+        Self { encryption_info: None, event: inner }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct TimelineEvent {
     /// The actual event.
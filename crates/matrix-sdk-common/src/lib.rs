@@ -6,6 +6,7 @@ pub use instant;
 pub mod deserialized_responses;
 pub mod executor;
 pub mod locks;
+pub mod sleep;
 pub mod timeout;
 
 /// Alias for `Send` on non-wasm, empty trait (implemented by everything) on
@@ -317,6 +317,25 @@ impl Device {
         self.inner.is_cross_signing_trusted(&self.own_identity, &self.device_owner_identity)
     }
 
+    /// Is this device signed by the self-signing key of its owner.
+    ///
+    /// Unlike [`is_cross_signing_trusted()`] this doesn't check if we trust the
+    /// identity of the owner, this is useful to find our own devices that
+    /// haven't been signed by our current cross signing identity.
+    ///
+    /// [`is_cross_signing_trusted()`]: #method.is_cross_signing_trusted
+    pub fn is_cross_signed_by_owner(&self) -> bool {
+        match &self.device_owner_identity {
+            Some(ReadOnlyUserIdentities::Own(identity)) => {
+                identity.is_device_signed(&self.inner).is_ok()
+            }
+            Some(ReadOnlyUserIdentities::Other(identity)) => {
+                identity.is_device_signed(&self.inner).is_ok()
+            }
+            None => false,
+        }
+    }
+
     /// Manually verify this device.
     ///
     /// This method will attempt to sign the device using our private cross
@@ -377,6 +396,33 @@ impl Device {
         self.inner.encrypt(self.verification_machine.store.inner(), event_type, content).await
     }
 
+    /// Encrypt an event of the given type for this `Device` using Olm.
+    ///
+    /// The Olm session that was used to encrypt the event is saved in the
+    /// store, the encrypted content needs to be sent out as an
+    /// `m.room.encrypted` to-device event.
+    ///
+    /// An Olm session with this device needs to exist, missing sessions can be
+    /// established using [`OlmMachine::get_missing_sessions()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the event that should be encrypted.
+    ///
+    /// * `content` - The content of the event that should be encrypted.
+    ///
+    /// [`OlmMachine::get_missing_sessions()`]: crate::OlmMachine::get_missing_sessions
+    pub async fn encrypt_event_raw(
+        &self,
+        event_type: &str,
+        content: &Value,
+    ) -> OlmResult<Raw<ToDeviceEncryptedEventContent>> {
+        let (session, encrypted) = self.encrypt(event_type, content.clone()).await?;
+        self.verification_machine.store.save_sessions(&[session]).await?;
+
+        Ok(encrypted)
+    }
+
     /// Encrypt the given inbound group session as a forwarded room key for this
     /// device.
     pub async fn encrypt_room_key_for_forwarding(
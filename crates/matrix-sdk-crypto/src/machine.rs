@@ -20,7 +20,9 @@ use std::{
 
 use dashmap::DashMap;
 use matrix_sdk_common::{
-    deserialized_responses::{AlgorithmInfo, EncryptionInfo, TimelineEvent, VerificationState},
+    deserialized_responses::{
+        AlgorithmInfo, EncryptionInfo, SyncToDeviceEvent, TimelineEvent, VerificationState,
+    },
     locks::Mutex,
 };
 use ruma::{
@@ -38,8 +40,8 @@ use ruma::{
         secret::request::SecretName, AnyMessageLikeEvent, AnyToDeviceEvent, MessageLikeEventContent,
     },
     serde::Raw,
-    DeviceId, DeviceKeyAlgorithm, OwnedDeviceId, OwnedDeviceKeyId, OwnedTransactionId, OwnedUserId,
-    RoomId, TransactionId, UInt, UserId,
+    DeviceId, DeviceKeyAlgorithm, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedDeviceKeyId,
+    OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UInt, UserId,
 };
 use serde_json::{value::to_raw_value, Value};
 use tracing::{
@@ -59,15 +61,16 @@ use crate::{
     gossiping::GossipMachine,
     identities::{user::UserIdentities, Device, IdentityManager, UserDevices},
     olm::{
-        Account, CrossSigningStatus, EncryptionSettings, ExportedRoomKey, IdentityKeys,
-        InboundGroupSession, OlmDecryptionInfo, PrivateCrossSigningIdentity, ReadOnlyAccount,
-        SessionType,
+        to_signable_json, Account, CrossSigningStatus, EncryptionSettings, ExportedRoomKey,
+        IdentityKeys, InboundGroupSession, OlmDecryptionInfo, PrivateCrossSigningIdentity,
+        ReadOnlyAccount, SessionType,
     },
     requests::{IncomingResponse, OutgoingRequest, UploadSigningKeysRequest},
     session_manager::{GroupSessionManager, SessionManager},
     store::{
-        Changes, DeviceChanges, DynCryptoStore, IdentityChanges, IntoCryptoStore, MemoryStore,
-        Result as StoreResult, SecretImportError, Store,
+        Changes, CryptoStoreLock, DeviceChanges, DynCryptoStore, IdentityChanges, IntoCryptoStore,
+        MemoryStore, Result as StoreResult, RoomKeyCounts, RoomKeyInfo, RoomKeyRetention,
+        SecretImportError, Store,
     },
     types::{
         events::{
@@ -121,6 +124,9 @@ pub struct OlmMachine {
     /// A state machine that handles creating room key backups.
     #[cfg(feature = "backups_v1")]
     backup_machine: BackupMachine,
+    /// The last generation of the crypto store we know of, if any, see
+    /// [`OlmMachine::maintain_crypto_store_generation`].
+    crypto_store_generation: Arc<Mutex<Option<u64>>>,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -134,6 +140,10 @@ impl std::fmt::Debug for OlmMachine {
 }
 
 impl OlmMachine {
+    /// The key of the custom value holding the generation counter of the
+    /// crypto store.
+    const CURRENT_GENERATION_STORE_KEY: &'static str = "generation-counter";
+
     /// Create a new memory based OlmMachine.
     ///
     /// The created machine will keep the encryption keys only in memory and
@@ -207,6 +217,7 @@ impl OlmMachine {
             identity_manager,
             #[cfg(feature = "backups_v1")]
             backup_machine,
+            crypto_store_generation: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -826,7 +837,7 @@ impl OlmMachine {
                 debug!("Received an `m.dummy` event");
             }
             AnyDecryptedOlmEvent::Custom(_) => {
-                warn!("Received an unexpected encrypted to-device event");
+                debug!("Received a custom encrypted to-device event");
             }
         }
 
@@ -922,12 +933,55 @@ impl OlmMachine {
         }
     }
 
+    /// Get the encryption info of a to-device event we decrypted using Olm.
+    async fn get_olm_encryption_info(
+        &self,
+        decrypted: &OlmDecryptionInfo,
+    ) -> StoreResult<EncryptionInfo> {
+        let sender = decrypted.result.event.sender();
+        let sender_key = decrypted.result.sender_key;
+        let sender_claimed_key = decrypted.result.event.keys().ed25519;
+
+        let device = self
+            .get_user_devices(sender, None)
+            .await?
+            .devices()
+            .find(|d| d.curve25519_key() == Some(sender_key));
+
+        let (verification_state, sender_device) = if let Some(device) = device {
+            // The device is only trusted if it also owns the signing key that
+            // the sender put into the encrypted payload.
+            if device.ed25519_key() == Some(sender_claimed_key)
+                && (device.is_our_own_device() || device.is_verified())
+            {
+                (VerificationState::Trusted, Some(device.device_id().to_owned()))
+            } else {
+                (VerificationState::Untrusted, Some(device.device_id().to_owned()))
+            }
+        } else {
+            (VerificationState::UnknownDevice, None)
+        };
+
+        Ok(EncryptionInfo {
+            sender: sender.to_owned(),
+            sender_device,
+            algorithm_info: AlgorithmInfo::OlmV1Curve25519AesSha2 {
+                curve25519_key: sender_key.to_base64(),
+                sender_claimed_keys: BTreeMap::from([(
+                    DeviceKeyAlgorithm::Ed25519,
+                    sender_claimed_key.to_base64(),
+                )]),
+            },
+            verification_state,
+        })
+    }
+
     #[instrument(skip_all, fields(sender, event_type, message_id))]
     async fn receive_to_device_event(
         &self,
         changes: &mut Changes,
         mut raw_event: Raw<AnyToDeviceEvent>,
-    ) -> OlmResult<Raw<AnyToDeviceEvent>> {
+    ) -> OlmResult<SyncToDeviceEvent> {
         Self::record_message_id(&raw_event);
 
         let event: ToDeviceEvents = match raw_event.deserialize_as() {
@@ -936,7 +990,7 @@ impl OlmMachine {
                 // Skip invalid events.
                 warn!("Received an invalid to-device event: {e}");
 
-                return Ok(raw_event);
+                return Ok(raw_event.into());
             }
         };
 
@@ -958,7 +1012,20 @@ impl OlmMachine {
                             }
                         }
 
-                        return Ok(raw_event);
+                        return Ok(raw_event.into());
+                    }
+                };
+
+                // The session was already advanced by the decryption, failing
+                // here would lose the changes below and wedge the session.
+                let encryption_info = match self.get_olm_encryption_info(&decrypted).await {
+                    Ok(info) => Some(info),
+                    Err(e) => {
+                        warn!(
+                            error = ?e,
+                            "Couldn't get the encryption info of a decrypted to-device event"
+                        );
+                        None
                     }
                 };
 
@@ -994,18 +1061,20 @@ impl OlmMachine {
                         raw_event = decrypted.result.raw_event;
                     }
                 }
+
+                return Ok(SyncToDeviceEvent { event: raw_event, encryption_info });
             }
 
             e => self.handle_to_device_event(&e).await,
         }
 
-        Ok(raw_event)
+        Ok(raw_event.into())
     }
 
     /// Handle a to-device and one-time key counts from a sync response.
     ///
     /// This will decrypt and handle to-device events returning the decrypted
-    /// versions of them.
+    /// versions of them, together with the info how they were decrypted.
     ///
     /// To decrypt an event from the room timeline call [`decrypt_room_event`].
     ///
@@ -1028,9 +1097,10 @@ impl OlmMachine {
         changed_devices: &DeviceLists,
         one_time_keys_counts: &BTreeMap<DeviceKeyAlgorithm, UInt>,
         unused_fallback_keys: Option<&[DeviceKeyAlgorithm]>,
-    ) -> OlmResult<Vec<Raw<AnyToDeviceEvent>>> {
+    ) -> OlmResult<Vec<SyncToDeviceEvent>> {
         // Remove verification objects that have expired or are done.
-        let mut events = self.verification_machine.garbage_collect();
+        let mut events: Vec<SyncToDeviceEvent> =
+            self.verification_machine.garbage_collect().into_iter().map(Into::into).collect();
 
         // Always save the account, a new session might get created which also
         // touches the account.
@@ -1048,8 +1118,8 @@ impl OlmMachine {
         }
 
         for raw_event in to_device_events {
-            let raw_event = self.receive_to_device_event(&mut changes, raw_event).await?;
-            events.push(raw_event);
+            let event = self.receive_to_device_event(&mut changes, raw_event).await?;
+            events.push(event);
         }
 
         let changed_sessions = self.key_request_machine.collect_incoming_key_requests().await?;
@@ -1510,6 +1580,169 @@ impl OlmMachine {
         Ok(exported)
     }
 
+    /// Get the number of room keys we have and how many of them are backed up.
+    pub async fn room_key_counts(&self) -> StoreResult<RoomKeyCounts> {
+        self.store.inbound_group_session_counts().await
+    }
+
+    /// Get metadata about all the room keys we have for the given room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room for which we should list the room keys.
+    pub async fn room_key_infos(&self, room_id: &RoomId) -> StoreResult<Vec<RoomKeyInfo>> {
+        Ok(self
+            .store
+            .get_inbound_group_sessions_for_room(room_id)
+            .await?
+            .iter()
+            .map(RoomKeyInfo::from)
+            .collect())
+    }
+
+    /// Remove room keys from the store according to the given retention
+    /// policy.
+    ///
+    /// Removed room keys can't be used to decrypt events anymore, unless
+    /// they get restored from a backup or get forwarded to us again.
+    ///
+    /// Returns the number of room keys that were removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `retention` - Describes which room keys should be removed.
+    pub async fn prune_room_keys(&self, retention: &RoomKeyRetention) -> StoreResult<usize> {
+        let sessions = match retention {
+            RoomKeyRetention::Rooms(rooms) => {
+                let mut sessions = Vec::new();
+
+                for room_id in rooms {
+                    sessions.extend(self.store.get_inbound_group_sessions_for_room(room_id).await?);
+                }
+
+                sessions
+            }
+            RoomKeyRetention::BackedUpOlderThan(max_age) => {
+                let now = MilliSecondsSinceUnixEpoch::now().get();
+                let max_age = UInt::try_from(max_age.as_millis()).unwrap_or(UInt::MAX);
+
+                self.store
+                    .get_inbound_group_sessions()
+                    .await?
+                    .into_iter()
+                    .filter(|s| {
+                        s.backed_up()
+                            && s.received_at()
+                                .map_or(false, |t| now.saturating_sub(t.get()) > max_age)
+                    })
+                    .collect()
+            }
+        };
+
+        let mut session_ids: BTreeMap<&RoomId, Vec<&str>> = BTreeMap::new();
+
+        for session in &sessions {
+            session_ids.entry(session.room_id()).or_default().push(session.session_id());
+        }
+
+        for (room_id, session_ids) in &session_ids {
+            self.store.delete_inbound_group_sessions(room_id, session_ids).await?;
+        }
+
+        info!(?retention, removed_count = sessions.len(), "Pruned room keys");
+
+        Ok(sessions.len())
+    }
+
+    /// Create a lock on the crypto store of this machine, which can be shared
+    /// with other processes using the same store.
+    ///
+    /// # Arguments
+    ///
+    /// * `lock_key` - The unique name of the lock, all the processes need to
+    /// use the same one.
+    ///
+    /// * `lock_holder` - A unique id of the current process.
+    pub fn create_store_lock(&self, lock_key: String, lock_holder: String) -> CryptoStoreLock {
+        CryptoStoreLock::new(self.store.crypto_store(), lock_key, lock_holder)
+    }
+
+    /// Detect whether another process modified the crypto store since we last
+    /// looked at it, and reload our in-memory caches if it did.
+    ///
+    /// Every process sharing the store bumps a generation counter stored in it
+    /// each time this method is called. If the counter isn't the one we wrote
+    /// last, somebody else used the store in the meantime and our cached
+    /// account, identity and sessions may be stale.
+    ///
+    /// This must be called while holding the cross-process
+    /// [`CryptoStoreLock`], before encrypting or decrypting anything. The
+    /// first call only records the current generation.
+    ///
+    /// Returns `true` if the caches were reloaded.
+    ///
+    /// [`CryptoStoreLock`]: crate::store::CryptoStoreLock
+    pub async fn maintain_crypto_store_generation(&self) -> StoreResult<bool> {
+        let mut known_generation = self.crypto_store_generation.lock().await;
+
+        let stored_generation =
+            self.store.get_custom_value(Self::CURRENT_GENERATION_STORE_KEY).await?;
+        let stored_generation =
+            stored_generation.and_then(|bytes| match <[u8; 8]>::try_from(bytes.as_slice()) {
+                Ok(bytes) => Some(u64::from_be_bytes(bytes)),
+                Err(_) => {
+                    warn!("The stored crypto store generation is malformed, resetting it");
+                    None
+                }
+            });
+
+        let reloaded = match (*known_generation, stored_generation) {
+            (Some(known), stored) if Some(known) != stored => {
+                debug!(
+                    known,
+                    ?stored,
+                    "The crypto store was modified by another process, reloading the caches"
+                );
+
+                self.reload_caches().await?;
+                true
+            }
+            _ => false,
+        };
+
+        let next_generation = stored_generation.map_or(0, |g| g.wrapping_add(1));
+
+        self.store
+            .set_custom_value(
+                Self::CURRENT_GENERATION_STORE_KEY,
+                next_generation.to_be_bytes().to_vec(),
+            )
+            .await?;
+
+        *known_generation = Some(next_generation);
+
+        Ok(reloaded)
+    }
+
+    /// Drop all the in-memory state that may be stale and load it again from
+    /// the store.
+    async fn reload_caches(&self) -> StoreResult<()> {
+        self.store.clear_caches().await;
+
+        if let Some(account) = self.store.load_account().await? {
+            self.account.update_from(&account).await;
+        }
+
+        if let Some(identity) = self.store.load_identity().await? {
+            *self.user_identity.lock().await = identity;
+        }
+
+        self.group_session_manager.session_cache().clear();
+        self.store.reset_tracked_users_cache().await;
+
+        Ok(())
+    }
+
     /// Get the status of the private cross signing keys.
     ///
     /// This can be used to check which private cross signing keys we have
@@ -1586,6 +1819,43 @@ impl OlmMachine {
         signatures
     }
 
+    /// Sign the `auth_data` of a server-side room key backup again, using our
+    /// device key and the current cross signing master key.
+    ///
+    /// After the cross signing identity has been reset, the signature of the
+    /// old master key can't be used to trust the backup anymore.
+    ///
+    /// Returns `None` if the `auth_data` doesn't contain a valid signature of
+    /// our own device, we don't want to vouch for a backup that we didn't
+    /// trust before.
+    pub async fn resign_backup_auth_data(
+        &self,
+        mut auth_data: Value,
+    ) -> Result<Option<Value>, SignatureError> {
+        let Some(signatures) = auth_data.get("signatures") else { return Ok(None) };
+        let mut signatures: Signatures = serde_json::from_value(signatures.clone())?;
+        let canonical_json = to_signable_json(auth_data.clone())?;
+
+        if self.account.has_signed_raw(&signatures, &canonical_json).is_err() {
+            return Ok(None);
+        }
+
+        let new_signatures = self.sign(&canonical_json).await;
+
+        for (key_id, signature) in new_signatures.get(self.user_id()).into_iter().flatten() {
+            if let Some(signature) = signature.as_ref().ok().and_then(|s| s.ed25519()) {
+                signatures.add_signature(self.user_id().to_owned(), key_id.clone(), signature);
+            }
+        }
+
+        auth_data
+            .as_object_mut()
+            .ok_or(SignatureError::NotAnObject)?
+            .insert("signatures".to_owned(), serde_json::to_value(signatures)?);
+
+        Ok(Some(auth_data))
+    }
+
     /// Get a reference to the backup related state machine.
     ///
     /// This state machine can be used to incrementally backup all room keys to
@@ -1608,9 +1878,10 @@ pub(crate) mod testing {
 
 #[cfg(test)]
 pub(crate) mod tests {
-    use std::{collections::BTreeMap, iter, sync::Arc};
+    use std::{collections::BTreeMap, iter, sync::Arc, time::Duration};
 
     use assert_matches::assert_matches;
+    use matrix_sdk_common::deserialized_responses::{AlgorithmInfo, VerificationState};
     use matrix_sdk_test::{async_test, test_json};
     use ruma::{
         api::{
@@ -1635,7 +1906,7 @@ pub(crate) mod tests {
         uint, user_id, DeviceId, DeviceKeyAlgorithm, DeviceKeyId, MilliSecondsSinceUnixEpoch,
         OwnedDeviceKeyId, UserId,
     };
-    use serde_json::json;
+    use serde_json::{json, Value};
     use vodozemac::{
         megolm::{GroupSession, SessionConfig},
         Ed25519PublicKey,
@@ -1645,13 +1916,14 @@ pub(crate) mod tests {
     use crate::{
         error::EventError,
         machine::OlmMachine,
-        olm::VerifyJson,
+        olm::{InboundGroupSession, VerifyJson},
+        store::{IntoCryptoStore, MemoryStore, RoomKeyRetention},
         types::{
             events::{
                 room::encrypted::{EncryptedToDeviceEvent, ToDeviceEncryptedEventContent},
                 ToDeviceEvent,
             },
-            DeviceKeys, SignedKey, SigningKeys,
+            DeviceKeys, Signatures, SignedKey, SigningKeys,
         },
         utilities::json_convert,
         verification::tests::{outgoing_request_to_event, request_to_event},
@@ -2020,6 +2292,44 @@ pub(crate) mod tests {
         }
     }
 
+    #[async_test]
+    async fn test_custom_to_device_event_decryption() {
+        let (alice, bob) = get_machine_pair_with_session().await;
+
+        let bob_device =
+            alice.get_device(&bob.user_id, &bob.device_id, None).await.unwrap().unwrap();
+
+        let content = json!({ "body": "It's a secret to everybody" });
+        let encrypted = bob_device.encrypt_event_raw("org.example.secret", &content).await.unwrap();
+
+        let event = json!({
+            "sender": alice.user_id(),
+            "type": "m.room.encrypted",
+            "content": encrypted,
+        });
+        let event = json_convert(&event).unwrap();
+
+        let decrypted = bob
+            .receive_sync_changes(vec![event], &Default::default(), &Default::default(), None)
+            .await
+            .unwrap();
+        let decrypted = &decrypted[0];
+
+        let event_type = decrypted.event.get_field::<String>("type").unwrap();
+        assert_eq!(event_type.as_deref(), Some("org.example.secret"));
+        let decrypted_content = decrypted.event.get_field::<Value>("content").unwrap();
+        assert_eq!(decrypted_content, Some(content));
+
+        let encryption_info = decrypted.encryption_info.as_ref().unwrap();
+        assert_eq!(encryption_info.sender, alice.user_id());
+        assert_eq!(encryption_info.sender_device.as_deref(), Some(alice.device_id()));
+        assert_matches!(
+            encryption_info.algorithm_info,
+            AlgorithmInfo::OlmV1Curve25519AesSha2 { .. }
+        );
+        assert_matches!(encryption_info.verification_state, VerificationState::Untrusted);
+    }
+
     #[async_test]
     async fn test_room_key_sharing() {
         let (alice, bob) = get_machine_pair_with_session().await;
@@ -2045,7 +2355,8 @@ pub(crate) mod tests {
             .await
             .unwrap();
 
-        let event = decrypted[0].deserialize().unwrap();
+        let event = decrypted[0].event.deserialize().unwrap();
+        assert!(decrypted[0].encryption_info.is_some());
 
         if let AnyToDeviceEvent::RoomKey(event) = event {
             assert_eq!(&event.sender, alice.user_id());
@@ -2523,4 +2834,136 @@ pub(crate) mod tests {
             Err(MegolmError::MismatchedIdentityKeys { .. })
         );
     }
+
+    #[async_test]
+    async fn resign_backup_auth_data_after_reset() {
+        let (machine, _) = get_prepared_machine().await;
+        machine.bootstrap_cross_signing(false).await.unwrap();
+
+        let mut auth_data = json!({
+            "public_key": "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo",
+        });
+        let canonical_json = serde_json::to_string(&auth_data).unwrap();
+        let signatures = machine.sign(&canonical_json).await;
+        auth_data["signatures"] = serde_json::to_value(&signatures).unwrap();
+
+        let old_master_key = machine.user_identity.lock().await.master_key_id().await.unwrap();
+        machine.bootstrap_cross_signing(true).await.unwrap();
+        let new_master_key = machine.user_identity.lock().await.master_key_id().await.unwrap();
+        assert_ne!(old_master_key, new_master_key);
+
+        let resigned = machine.resign_backup_auth_data(auth_data).await.unwrap().unwrap();
+        let signatures: Signatures =
+            serde_json::from_value(resigned["signatures"].clone()).unwrap();
+
+        let master_key = machine.get_identity(machine.user_id(), None).await.unwrap().unwrap();
+        let master_key = master_key.own().unwrap().master_key().get_first_key().unwrap();
+
+        master_key
+            .verify_canonicalized_json(
+                machine.user_id(),
+                &new_master_key,
+                &signatures,
+                &canonical_json,
+            )
+            .expect("The backup should be signed by the new master key");
+        machine
+            .account
+            .has_signed_raw(&signatures, &canonical_json)
+            .expect("The backup should still be signed by our device");
+    }
+
+    #[async_test]
+    async fn resign_backup_auth_data_requires_own_signature() {
+        let (machine, _) = get_prepared_machine().await;
+        machine.bootstrap_cross_signing(false).await.unwrap();
+
+        let auth_data = json!({
+            "public_key": "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo",
+            "signatures": {},
+        });
+
+        assert!(machine.resign_backup_auth_data(auth_data).await.unwrap().is_none());
+
+        let auth_data = json!({
+            "public_key": "hSDwCYkwp1R0i33ctD73Wg2/Og0mOBr066SpjqqbTmo",
+        });
+
+        assert!(machine.resign_backup_auth_data(auth_data).await.unwrap().is_none());
+    }
+
+    #[async_test]
+    async fn prune_room_keys() {
+        let machine = OlmMachine::new(alice_id(), alice_device_id()).await;
+        let room_id = room_id!("!test:localhost");
+        let left_room_id = room_id!("!left:localhost");
+
+        let (_, recent) = machine.account.create_group_session_pair_with_defaults(room_id).await;
+        let (_, old) = machine.account.create_group_session_pair_with_defaults(room_id).await;
+        let (_, left) = machine.account.create_group_session_pair_with_defaults(left_room_id).await;
+
+        let mut pickle = old.pickle().await;
+        pickle.received_at = Some(MilliSecondsSinceUnixEpoch(uint!(1)));
+        pickle.backed_up = true;
+        let old = InboundGroupSession::from_pickle(pickle).unwrap();
+        recent.mark_as_backed_up();
+
+        machine
+            .store
+            .save_inbound_group_sessions(&[recent.clone(), old.clone(), left.clone()])
+            .await
+            .unwrap();
+
+        assert_eq!(machine.room_key_counts().await.unwrap().total, 3);
+        let infos = machine.room_key_infos(left_room_id).await.unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].session_id, left.session_id());
+        assert_eq!(infos[0].sender_key, machine.identity_keys().curve25519);
+        assert!(!infos[0].imported);
+        assert!(!infos[0].backed_up);
+        assert!(infos[0].received_at.is_some());
+
+        let retention = RoomKeyRetention::BackedUpOlderThan(Duration::from_secs(60 * 60 * 24));
+        assert_eq!(machine.prune_room_keys(&retention).await.unwrap(), 1);
+        assert!(machine
+            .store
+            .get_inbound_group_session(room_id, old.session_id())
+            .await
+            .unwrap()
+            .is_none());
+
+        let retention = RoomKeyRetention::Rooms(vec![left_room_id.to_owned()]);
+        assert_eq!(machine.prune_room_keys(&retention).await.unwrap(), 1);
+        assert!(machine.room_key_infos(left_room_id).await.unwrap().is_empty());
+
+        let infos = machine.room_key_infos(room_id).await.unwrap();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].session_id, recent.session_id());
+    }
+
+    #[async_test]
+    async fn crypto_store_generation_reloads_caches() {
+        let store = MemoryStore::new().into_crypto_store();
+
+        let first =
+            OlmMachine::with_store(alice_id(), alice_device_id(), store.clone()).await.unwrap();
+        let second = OlmMachine::with_store(alice_id(), alice_device_id(), store).await.unwrap();
+
+        // The first calls only record the generation.
+        assert!(!first.maintain_crypto_store_generation().await.unwrap());
+        assert!(!second.maintain_crypto_store_generation().await.unwrap());
+
+        // The second machine modifies the account in the store.
+        assert!(!first.account.shared());
+        second.account.mark_as_shared();
+        second.store.save_account(second.account.inner.clone()).await.unwrap();
+
+        // The first machine notices that the store changed under its feet and
+        // picks up the modified account.
+        assert!(first.maintain_crypto_store_generation().await.unwrap());
+        assert!(first.account.shared());
+
+        // Nothing changed since then.
+        assert!(!first.maintain_crypto_store_generation().await.unwrap());
+    }
 }
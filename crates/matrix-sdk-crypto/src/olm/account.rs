@@ -703,7 +703,6 @@ impl ReadOnlyAccount {
     /// **Note**: Use this method with caution, the `canonical_json` needs to be
     /// correctly canonicalized and make sure that the object you are checking
     /// the signature for is allowed to be signed by our own device.
-    #[cfg(any(test, feature = "backups_v1"))]
     pub fn has_signed_raw(
         &self,
         signatures: &crate::types::Signatures,
@@ -757,6 +756,18 @@ impl ReadOnlyAccount {
         })
     }
 
+    /// Replace the state of this account with the state of the given one.
+    ///
+    /// Used to refresh the account after another process modified it in the
+    /// store, all the clones of this account will see the new state.
+    pub(crate) async fn update_from(&self, other: &ReadOnlyAccount) {
+        let pickle = other.inner.lock().await.pickle();
+        *self.inner.lock().await = pickle.into();
+
+        self.shared.store(other.shared(), Ordering::SeqCst);
+        self.uploaded_signed_key_count.store(other.uploaded_key_count(), Ordering::SeqCst);
+    }
+
     /// Generate the unsigned `DeviceKeys` from this ReadOnlyAccount
     pub fn unsigned_device_keys(&self) -> DeviceKeys {
         let identity_keys = self.identity_keys();
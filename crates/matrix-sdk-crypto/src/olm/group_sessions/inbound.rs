@@ -25,7 +25,7 @@ use matrix_sdk_common::locks::Mutex;
 use ruma::{
     events::{room::history_visibility::HistoryVisibility, AnyTimelineEvent},
     serde::Raw,
-    DeviceKeyAlgorithm, OwnedRoomId, RoomId,
+    DeviceKeyAlgorithm, MilliSecondsSinceUnixEpoch, OwnedRoomId, RoomId,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -56,10 +56,6 @@ use crate::{
     },
 };
 
-// TODO add creation times to the inbound group sessions so we can export
-// sessions that were created between some time period, this should only be set
-// for non-imported sessions.
-
 /// Inbound group session.
 ///
 /// Inbound group sessions are used to exchange room messages between a group of
@@ -80,6 +76,7 @@ pub struct InboundGroupSession {
     imported: bool,
     algorithm: Arc<EventEncryptionAlgorithm>,
     backed_up: Arc<AtomicBool>,
+    received_at: Option<MilliSecondsSinceUnixEpoch>,
 }
 
 impl InboundGroupSession {
@@ -127,6 +124,7 @@ impl InboundGroupSession {
             imported: false,
             algorithm: encryption_algorithm.into(),
             backed_up: AtomicBool::new(false).into(),
+            received_at: Some(MilliSecondsSinceUnixEpoch::now()),
         })
     }
 
@@ -180,6 +178,7 @@ impl InboundGroupSession {
             backed_up: self.backed_up(),
             history_visibility: self.history_visibility.as_ref().clone(),
             algorithm: (*self.algorithm).to_owned(),
+            received_at: self.received_at,
         }
     }
 
@@ -262,6 +261,7 @@ impl InboundGroupSession {
             backed_up: AtomicBool::from(pickle.backed_up).into(),
             algorithm: pickle.algorithm.into(),
             imported: pickle.imported,
+            received_at: pickle.received_at,
         })
     }
 
@@ -292,6 +292,14 @@ impl InboundGroupSession {
         self.imported
     }
 
+    /// The time at which we received or imported this session.
+    ///
+    /// Sessions that were stored before we started to record this won't have
+    /// a timestamp.
+    pub fn received_at(&self) -> Option<MilliSecondsSinceUnixEpoch> {
+        self.received_at
+    }
+
     /// Check if the `InboundGroupSession` is better than the given other
     /// `InboundGroupSession`
     pub async fn compare(&self, other: &InboundGroupSession) -> SessionOrdering {
@@ -438,6 +446,9 @@ pub struct PickledInboundGroupSession {
     /// The algorithm of this inbound group session.
     #[serde(default = "default_algorithm")]
     pub algorithm: EventEncryptionAlgorithm,
+    /// The time at which we received or imported the session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received_at: Option<MilliSecondsSinceUnixEpoch>,
 }
 
 fn default_algorithm() -> EventEncryptionAlgorithm {
@@ -463,6 +474,7 @@ impl TryFrom<&ExportedRoomKey> for InboundGroupSession {
             imported: true,
             algorithm: key.algorithm.to_owned().into(),
             backed_up: AtomicBool::from(false).into(),
+            received_at: Some(MilliSecondsSinceUnixEpoch::now()),
         })
     }
 }
@@ -488,6 +500,7 @@ impl From<&ForwardedMegolmV1AesSha2Content> for InboundGroupSession {
             imported: true,
             algorithm: EventEncryptionAlgorithm::MegolmV1AesSha2.into(),
             backed_up: AtomicBool::from(false).into(),
+            received_at: Some(MilliSecondsSinceUnixEpoch::now()),
         }
     }
 }
@@ -509,6 +522,7 @@ impl From<&ForwardedMegolmV2AesSha2Content> for InboundGroupSession {
             imported: true,
             algorithm: EventEncryptionAlgorithm::MegolmV1AesSha2.into(),
             backed_up: AtomicBool::from(false).into(),
+            received_at: Some(MilliSecondsSinceUnixEpoch::now()),
         }
     }
 }
@@ -33,7 +33,7 @@ pub use group_sessions::{
 };
 pub use session::{PickledSession, Session};
 pub use signing::{CrossSigningStatus, PickledCrossSigningIdentity, PrivateCrossSigningIdentity};
-pub(crate) use utility::{SignedJsonObject, VerifyJson};
+pub(crate) use utility::{to_signable_json, SignedJsonObject, VerifyJson};
 pub use vodozemac::{olm::IdentityKeys, Curve25519PublicKey};
 
 #[cfg(test)]
@@ -348,6 +348,12 @@ impl<'a> From<&'a KeysClaimResponse> for IncomingResponse<'a> {
     }
 }
 
+impl<'a> From<&'a SigningKeysUploadResponse> for IncomingResponse<'a> {
+    fn from(response: &'a SigningKeysUploadResponse) -> Self {
+        IncomingResponse::SigningKeysUpload(response)
+    }
+}
+
 impl<'a> From<&'a SignatureUploadResponse> for IncomingResponse<'a> {
     fn from(response: &'a SignatureUploadResponse) -> Self {
         IncomingResponse::SignatureUpload(response)
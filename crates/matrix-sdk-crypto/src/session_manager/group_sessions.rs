@@ -57,6 +57,13 @@ impl GroupSessionCache {
         self.sessions.insert(session.room_id().to_owned(), session);
     }
 
+    /// Forget all the cached sessions, they will be loaded again from the
+    /// store when needed.
+    pub(crate) fn clear(&self) {
+        self.sessions.clear();
+        self.sessions_being_shared.clear();
+    }
+
     /// Either get a session for the given room from the cache or load it from
     /// the store.
     ///
@@ -65,6 +65,11 @@ impl SessionStore {
     pub fn set_for_sender(&self, sender_key: &str, sessions: Vec<Session>) {
         self.entries.insert(sender_key.to_owned(), Arc::new(Mutex::new(sessions)));
     }
+
+    /// Remove all the sessions from the store.
+    pub fn clear(&self) {
+        self.entries.clear()
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -113,6 +118,27 @@ impl GroupSessionStore {
     pub fn get(&self, room_id: &RoomId, session_id: &str) -> Option<InboundGroupSession> {
         self.entries.get(room_id)?.get(session_id).cloned()
     }
+
+    /// Get all the group sessions that belong to the given room.
+    pub fn get_for_room(&self, room_id: &RoomId) -> Vec<InboundGroupSession> {
+        self.entries.get(room_id).map(|s| s.values().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Remove an inbound group session from the store.
+    ///
+    /// Returns true if the session was removed, false if the session wasn't
+    /// in the store.
+    pub fn remove(&self, room_id: &RoomId, session_id: &str) -> bool {
+        let removed = self
+            .entries
+            .get_mut(room_id)
+            .map(|mut s| s.remove(session_id).is_some())
+            .unwrap_or_default();
+
+        self.entries.remove_if(room_id, |_, s| s.is_empty());
+
+        removed
+    }
 }
 
 /// In-memory store holding the devices of users.
@@ -235,6 +261,12 @@ mod tests {
 
         let loaded_session = store.get(room_id, outbound.session_id()).unwrap();
         assert_eq!(inbound, loaded_session);
+        assert_eq!(store.get_for_room(room_id), vec![inbound]);
+
+        assert!(store.remove(room_id, outbound.session_id()));
+        assert!(!store.remove(room_id, outbound.session_id()));
+        assert!(store.get(room_id, outbound.session_id()).is_none());
+        assert_eq!(store.count(), 0);
     }
 
     #[async_test]
@@ -320,12 +320,58 @@ macro_rules! cryptostore_integration_tests {
                     .unwrap()
                     .unwrap();
                 assert_eq!(session, loaded_session);
+                assert_eq!(session.received_at(), loaded_session.received_at());
                 let export = loaded_session.export().await;
 
                 assert_eq!(store.get_inbound_group_sessions().await.unwrap().len(), 1);
                 assert_eq!(store.inbound_group_session_counts().await.unwrap().total, 1);
             }
 
+            #[async_test]
+            async fn delete_inbound_group_sessions() {
+                let (account, store) = get_loaded_store("delete_inbound_group_sessions").await;
+
+                let room_id = room_id!("!test:localhost");
+                let other_room_id = room_id!("!other:localhost");
+                let (_, first) = account.create_group_session_pair_with_defaults(room_id).await;
+                let (_, second) = account.create_group_session_pair_with_defaults(room_id).await;
+                let (_, other) =
+                    account.create_group_session_pair_with_defaults(other_room_id).await;
+
+                let changes = Changes {
+                    inbound_group_sessions: vec![first.clone(), second.clone(), other.clone()],
+                    ..Default::default()
+                };
+                store.save_changes(changes).await.expect("Can't save group sessions");
+
+                let mut sessions =
+                    store.get_inbound_group_sessions_for_room(room_id).await.unwrap();
+                sessions.sort_by(|a, b| a.session_id().cmp(b.session_id()));
+                let mut expected = vec![first.clone(), second.clone()];
+                expected.sort_by(|a, b| a.session_id().cmp(b.session_id()));
+                assert_eq!(sessions, expected);
+
+                store
+                    .delete_inbound_group_sessions(room_id, &[first.session_id(), "unknown"])
+                    .await
+                    .unwrap();
+
+                assert!(store
+                    .get_inbound_group_session(room_id, first.session_id())
+                    .await
+                    .unwrap()
+                    .is_none());
+                assert_eq!(
+                    store.get_inbound_group_sessions_for_room(room_id).await.unwrap(),
+                    vec![second]
+                );
+                assert_eq!(
+                    store.get_inbound_group_sessions_for_room(other_room_id).await.unwrap(),
+                    vec![other]
+                );
+                assert_eq!(store.inbound_group_session_counts().await.unwrap().total, 2);
+            }
+
             #[async_test]
             async fn test_tracked_users() {
                 let dir = "test_tracked_users";
@@ -646,6 +692,37 @@ macro_rules! cryptostore_integration_tests {
                     "The loaded version matches to the one we stored"
                 );
             }
+
+            #[async_test]
+            async fn custom_value_saving() {
+                let (_, store) = get_loaded_store("custom_value_saving").await;
+
+                assert!(store.get_custom_value("A").await.unwrap().is_none());
+
+                store.set_custom_value("A", b"first".to_vec()).await.unwrap();
+                store.set_custom_value("B", b"second".to_vec()).await.unwrap();
+                store.set_custom_value("A", b"third".to_vec()).await.unwrap();
+
+                assert_eq!(store.get_custom_value("A").await.unwrap(), Some(b"third".to_vec()));
+                assert_eq!(store.get_custom_value("B").await.unwrap(), Some(b"second".to_vec()));
+            }
+
+            #[async_test]
+            async fn leased_lock_taking() {
+                let (_, store) = get_loaded_store("leased_lock_taking").await;
+
+                assert!(store.try_take_leased_lock(60_000, "lock", "alice").await.unwrap());
+                assert!(!store.try_take_leased_lock(60_000, "lock", "bob").await.unwrap());
+                assert!(store.try_take_leased_lock(60_000, "other", "bob").await.unwrap());
+
+                // Renewing the lease works for the current holder.
+                assert!(store.try_take_leased_lock(60_000, "lock", "alice").await.unwrap());
+
+                // Once the lease expired, somebody else can take the lock.
+                assert!(store.try_take_leased_lock(0, "lock", "alice").await.unwrap());
+                assert!(store.try_take_leased_lock(60_000, "lock", "bob").await.unwrap());
+                assert!(!store.try_take_leased_lock(60_000, "lock", "alice").await.unwrap());
+            }
         }
     };
 }
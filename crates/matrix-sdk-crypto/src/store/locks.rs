@@ -0,0 +1,415 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A lock on the crypto store that can be shared between multiple processes.
+//!
+//! The lock is implemented on top of a lease stored in the crypto store, see
+//! [`CryptoStore::try_take_leased_lock`]. As long as at least one
+//! [`CryptoStoreLockGuard`] is alive, a background task keeps extending the
+//! lease. Once the last guard is dropped, the lease is released.
+//!
+//! If the lease can't be extended, because another process took it or because
+//! the store keeps failing, the lock is considered lost: the existing guards
+//! don't hold the lock anymore, see [`CryptoStoreLockGuard::check`], and
+//! taking the lock again fails until they are all dropped.
+//!
+//! [`CryptoStore::try_take_leased_lock`]: super::CryptoStore::try_take_leased_lock
+
+use std::{
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use matrix_sdk_common::{executor::spawn, locks::Mutex, sleep::sleep};
+use thiserror::Error;
+use tracing::{debug, error, instrument, trace, warn};
+
+use super::{CryptoStoreError, DynCryptoStore};
+
+/// For how long, in milliseconds, a lease is taken.
+const LEASE_DURATION_MS: u32 = 500;
+
+/// How often, in milliseconds, the lease is extended while the lock is held.
+///
+/// This needs to be noticeably smaller than [`LEASE_DURATION_MS`], so the
+/// lease doesn't expire while we still hold the lock.
+const EXTEND_LEASE_EVERY_MS: u64 = 150;
+
+/// After how many failed attempts in a row to extend the lease we consider the
+/// lock lost, since the lease is about to expire.
+const MAX_EXTEND_FAILURES: u32 = 3;
+
+/// The initial time, in milliseconds, to wait before retrying to take the lock.
+const INITIAL_BACKOFF_MS: u32 = 10;
+
+/// The maximum time, in milliseconds, to wait between two attempts of taking
+/// the lock.
+const MAX_BACKOFF_MS: u32 = 1000;
+
+/// For how long we wait for the lock by default, before giving up.
+///
+/// Another process holds the lock while it processes a whole sync response,
+/// which can take a while, so this needs to be generous.
+const DEFAULT_SPIN_LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Error type for the [`CryptoStoreLock`].
+#[derive(Debug, Error)]
+pub enum LockStoreError {
+    /// The backing store failed to take or extend the lease.
+    #[error(transparent)]
+    BackingStoreError(#[from] CryptoStoreError),
+
+    /// We couldn't take the lock before the timeout was reached.
+    #[error("the crypto store lock couldn't be taken in time")]
+    LockTimeout,
+
+    /// The lease of the lock couldn't be extended while we held it, another
+    /// process may hold the lock now.
+    #[error("the crypto store lock was lost while it was held")]
+    LockLost,
+}
+
+/// The holders of a [`CryptoStoreLock`] in this process.
+#[derive(Debug, Default)]
+struct HoldersState {
+    /// How many guards of the currently held lease are alive.
+    num_holders: u32,
+    /// Incremented every time the lease is lost, so the guards of a lost
+    /// lease can tell they don't hold the lock anymore.
+    generation: u64,
+    /// How many guards of a lost lease are still alive.
+    num_lost_holders: u32,
+}
+
+/// A lock on the crypto store, which is held across processes sharing the same
+/// store.
+///
+/// Cloning the lock gives a handle to the same lock; a process should use a
+/// single `CryptoStoreLock` for a given key.
+#[derive(Clone, Debug)]
+pub struct CryptoStoreLock {
+    store: Arc<DynCryptoStore>,
+    lock_key: String,
+    lock_holder: String,
+    /// The guards of this lock that are currently alive.
+    holders: Arc<StdMutex<HoldersState>>,
+    /// Serializes attempts to take, extend and release the lease. The boolean
+    /// tells whether the task extending the lease is running.
+    locking_attempt: Arc<Mutex<bool>>,
+}
+
+impl CryptoStoreLock {
+    /// Create a new lock on the given store.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - The store the lease will be persisted in.
+    ///
+    /// * `lock_key` - The unique name of the lock, shared by all the processes
+    /// wanting to take it.
+    ///
+    /// * `lock_holder` - A unique id of this process, used to know who holds
+    /// the lock.
+    pub fn new(store: Arc<DynCryptoStore>, lock_key: String, lock_holder: String) -> Self {
+        Self {
+            store,
+            lock_key,
+            lock_holder,
+            holders: Default::default(),
+            locking_attempt: Default::default(),
+        }
+    }
+
+    /// The unique id of this process, as given to [`CryptoStoreLock::new`].
+    pub fn lock_holder(&self) -> &str {
+        &self.lock_holder
+    }
+
+    /// Try to take the lock once, without waiting.
+    ///
+    /// Returns `None` if another process holds the lock, and
+    /// [`LockStoreError::LockLost`] if the lock was lost while guards of it
+    /// are still alive.
+    #[instrument(skip(self), fields(lock_key = %self.lock_key, holder = %self.lock_holder))]
+    pub async fn try_lock_once(&self) -> Result<Option<CryptoStoreLockGuard>, LockStoreError> {
+        let mut renew_task_running = self.locking_attempt.lock().await;
+
+        {
+            let mut holders = self.holders.lock().unwrap();
+
+            // Someone in this process still thinks they hold the lost lock, we
+            // can't take it again before they're done.
+            if holders.num_lost_holders > 0 {
+                return Err(LockStoreError::LockLost);
+            }
+
+            // We already hold the lock, the lease is kept alive by the renew
+            // task.
+            if holders.num_holders > 0 {
+                trace!("Reusing the already held crypto store lock");
+                return Ok(Some(self.new_guard(&mut holders)));
+            }
+        }
+
+        if !self
+            .store
+            .try_take_leased_lock(LEASE_DURATION_MS, &self.lock_key, &self.lock_holder)
+            .await?
+        {
+            trace!("The crypto store lock is held by another process");
+            return Ok(None);
+        }
+
+        debug!("Took the crypto store lock");
+        let guard = self.new_guard(&mut self.holders.lock().unwrap());
+
+        if !*renew_task_running {
+            *renew_task_running = true;
+            self.spawn_renew_task();
+        }
+
+        Ok(Some(guard))
+    }
+
+    fn new_guard(&self, holders: &mut HoldersState) -> CryptoStoreLockGuard {
+        holders.num_holders += 1;
+        CryptoStoreLockGuard { holders: self.holders.clone(), generation: holders.generation }
+    }
+
+    /// Take the lock, retrying with an exponential backoff if another process
+    /// holds it.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - For how long we wait for the lock. Once it has elapsed, we
+    /// give up with a [`LockStoreError::LockTimeout`]. Defaults to one
+    /// minute.
+    ///
+    /// Fails right away with [`LockStoreError::LockLost`] if the lock was
+    /// lost while guards of it are still alive.
+    pub async fn spin_lock(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<CryptoStoreLockGuard, LockStoreError> {
+        let timeout = timeout.unwrap_or(DEFAULT_SPIN_LOCK_TIMEOUT);
+        let mut backoff = INITIAL_BACKOFF_MS;
+        let mut waited = Duration::ZERO;
+
+        loop {
+            if let Some(guard) = self.try_lock_once().await? {
+                return Ok(guard);
+            }
+
+            if waited >= timeout {
+                return Err(LockStoreError::LockTimeout);
+            }
+
+            let delay = Duration::from_millis(backoff.into()).min(timeout - waited);
+            sleep(delay).await;
+
+            waited += delay;
+            backoff = backoff.saturating_mul(2).min(MAX_BACKOFF_MS);
+        }
+    }
+
+    /// Spawn the task which extends the lease as long as there are holders,
+    /// and releases it afterwards.
+    ///
+    /// If the lease can't be extended, the lock is marked as lost and the task
+    /// stops.
+    fn spawn_renew_task(&self) {
+        let this = self.clone();
+
+        spawn(async move {
+            let mut num_failures = 0;
+
+            loop {
+                sleep(Duration::from_millis(EXTEND_LEASE_EVERY_MS)).await;
+
+                let mut renew_task_running = this.locking_attempt.lock().await;
+
+                if this.holders.lock().unwrap().num_holders == 0 {
+                    // Nobody holds the lock anymore, release the lease by
+                    // letting it expire right away.
+                    if let Err(e) =
+                        this.store.try_take_leased_lock(0, &this.lock_key, &this.lock_holder).await
+                    {
+                        error!("Couldn't release the crypto store lock: {e}");
+                    } else {
+                        debug!("Released the crypto store lock");
+                    }
+
+                    *renew_task_running = false;
+                    break;
+                }
+
+                match this
+                    .store
+                    .try_take_leased_lock(LEASE_DURATION_MS, &this.lock_key, &this.lock_holder)
+                    .await
+                {
+                    Ok(true) => {
+                        num_failures = 0;
+                        continue;
+                    }
+                    Ok(false) => {
+                        warn!("Another process took the crypto store lock while we held it");
+                    }
+                    Err(e) => {
+                        error!("Couldn't extend the crypto store lock: {e}");
+
+                        num_failures += 1;
+                        if num_failures < MAX_EXTEND_FAILURES {
+                            continue;
+                        }
+                    }
+                }
+
+                this.mark_as_lost();
+                *renew_task_running = false;
+                break;
+            }
+        });
+    }
+
+    /// Mark the currently held lease as lost, the existing guards don't hold
+    /// the lock anymore.
+    fn mark_as_lost(&self) {
+        let mut holders = self.holders.lock().unwrap();
+        holders.generation += 1;
+        holders.num_lost_holders += holders.num_holders;
+        holders.num_holders = 0;
+    }
+}
+
+/// A guard on the [`CryptoStoreLock`].
+///
+/// The lock is held by this process as long as at least one guard is alive,
+/// unless the lock was lost in the meantime.
+#[derive(Debug)]
+pub struct CryptoStoreLockGuard {
+    holders: Arc<StdMutex<HoldersState>>,
+    /// The generation of the lease this guard was created for.
+    generation: u64,
+}
+
+impl CryptoStoreLockGuard {
+    /// Check that the lock is still held.
+    ///
+    /// Returns [`LockStoreError::LockLost`] if the lease couldn't be extended
+    /// since this guard was created, another process may hold the lock now.
+    pub fn check(&self) -> Result<(), LockStoreError> {
+        if self.holders.lock().unwrap().generation == self.generation {
+            Ok(())
+        } else {
+            Err(LockStoreError::LockLost)
+        }
+    }
+}
+
+impl Drop for CryptoStoreLockGuard {
+    fn drop(&mut self) {
+        let mut holders = self.holders.lock().unwrap();
+
+        if holders.generation == self.generation {
+            holders.num_holders -= 1;
+        } else {
+            holders.num_lost_holders -= 1;
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::time::Duration;
+
+    use matrix_sdk_test::async_test;
+
+    use super::{CryptoStoreLock, LockStoreError};
+    use crate::store::{IntoCryptoStore, MemoryStore};
+
+    #[async_test]
+    async fn test_lock_is_exclusive_between_holders() {
+        let store = MemoryStore::new().into_crypto_store();
+
+        let first = CryptoStoreLock::new(store.clone(), "key".to_owned(), "first".to_owned());
+        let second = CryptoStoreLock::new(store, "key".to_owned(), "second".to_owned());
+
+        let guard = first.try_lock_once().await.unwrap().expect("The lock should be free");
+
+        // The same process can take the lock again.
+        let reentrant = first.try_lock_once().await.unwrap();
+        assert!(reentrant.is_some());
+
+        // Another process can't.
+        assert!(second.try_lock_once().await.unwrap().is_none());
+        assert!(matches!(
+            second.spin_lock(Some(Duration::from_millis(50))).await,
+            Err(LockStoreError::LockTimeout)
+        ));
+
+        drop(reentrant);
+        drop(guard);
+
+        // Once all the guards are gone, the lease is released and the other
+        // process can take the lock.
+        let guard = second.spin_lock(None).await.unwrap();
+        assert!(first.try_lock_once().await.unwrap().is_none());
+        drop(guard);
+    }
+
+    #[async_test]
+    async fn test_lock_is_kept_alive_while_held() {
+        let store = MemoryStore::new().into_crypto_store();
+
+        let first = CryptoStoreLock::new(store.clone(), "key".to_owned(), "first".to_owned());
+        let second = CryptoStoreLock::new(store, "key".to_owned(), "second".to_owned());
+
+        let _guard = first.spin_lock(None).await.unwrap();
+
+        // Wait for longer than the lease duration, the renew task should have
+        // extended the lease in the meantime.
+        tokio::time::sleep(Duration::from_millis(800)).await;
+
+        assert!(second.try_lock_once().await.unwrap().is_none());
+    }
+
+    #[async_test]
+    async fn test_lock_is_lost_when_the_lease_is_taken() {
+        let store = MemoryStore::new().into_crypto_store();
+
+        let first = CryptoStoreLock::new(store.clone(), "key".to_owned(), "first".to_owned());
+        let second = CryptoStoreLock::new(store.clone(), "key".to_owned(), "second".to_owned());
+
+        let guard = first.spin_lock(None).await.unwrap();
+        guard.check().unwrap();
+
+        // Another process steals the lease, for example because ours expired
+        // while this process was suspended.
+        store.try_take_leased_lock(0, "key", "first").await.unwrap();
+        let _stolen = second.try_lock_once().await.unwrap().expect("The lease should be free");
+
+        // Wait for the renew task to notice.
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        assert!(matches!(guard.check(), Err(LockStoreError::LockLost)));
+        assert!(matches!(first.try_lock_once().await, Err(LockStoreError::LockLost)));
+        assert!(matches!(first.spin_lock(None).await, Err(LockStoreError::LockLost)));
+
+        // Once the guards of the lost lock are dropped, we can wait for the
+        // lock again.
+        drop(guard);
+        assert!(first.try_lock_once().await.unwrap().is_none());
+    }
+}
@@ -12,11 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::HashMap, convert::Infallible, sync::Arc};
+use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use dashmap::{DashMap, DashSet};
-use matrix_sdk_common::locks::Mutex;
+use dashmap::{mapref::entry::Entry, DashMap, DashSet};
+use matrix_sdk_common::{instant::Instant, locks::Mutex};
 use ruma::{
     DeviceId, OwnedDeviceId, OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UserId,
 };
@@ -51,6 +51,8 @@ pub struct MemoryStore {
     identities: Arc<DashMap<OwnedUserId, ReadOnlyUserIdentities>>,
     outgoing_key_requests: Arc<DashMap<OwnedTransactionId, GossipRequest>>,
     key_requests_by_info: Arc<DashMap<String, OwnedTransactionId>>,
+    custom_values: Arc<DashMap<String, Vec<u8>>>,
+    leases: Arc<DashMap<String, (String, Instant)>>,
 }
 
 impl Default for MemoryStore {
@@ -63,6 +65,8 @@ impl Default for MemoryStore {
             identities: Default::default(),
             outgoing_key_requests: Default::default(),
             key_requests_by_info: Default::default(),
+            custom_values: Default::default(),
+            leases: Default::default(),
         }
     }
 }
@@ -163,6 +167,25 @@ impl CryptoStore for MemoryStore {
         Ok(self.inbound_group_sessions.get_all())
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        Ok(self.inbound_group_sessions.get_for_room(room_id))
+    }
+
+    async fn delete_inbound_group_sessions(
+        &self,
+        room_id: &RoomId,
+        session_ids: &[&str],
+    ) -> Result<()> {
+        for session_id in session_ids {
+            self.inbound_group_sessions.remove(room_id, session_id);
+        }
+
+        Ok(())
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         let backed_up =
             self.get_inbound_group_sessions().await?.into_iter().filter(|s| s.backed_up()).count();
@@ -270,6 +293,48 @@ impl CryptoStore for MemoryStore {
     async fn load_backup_keys(&self) -> Result<BackupKeys> {
         Ok(BackupKeys::default())
     }
+
+    async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.custom_values.get(key).map(|v| v.clone()))
+    }
+
+    async fn set_custom_value(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.custom_values.insert(key.to_owned(), value);
+        Ok(())
+    }
+
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        let now = Instant::now();
+        let expiration = now + Duration::from_millis(lease_duration_ms.into());
+
+        match self.leases.entry(key.to_owned()) {
+            Entry::Occupied(mut entry) => {
+                let (current_holder, current_expiration) = entry.get_mut();
+
+                if current_holder == holder || *current_expiration <= now {
+                    *current_holder = holder.to_owned();
+                    *current_expiration = expiration;
+
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((holder.to_owned(), expiration));
+                Ok(true)
+            }
+        }
+    }
+
+    async fn clear_caches(&self) {
+        // The memory store is the source of truth, there's nothing to clear.
+    }
 }
 
 #[cfg(test)]
@@ -284,6 +349,21 @@ mod tests {
         store::{memorystore::MemoryStore, Changes, CryptoStore},
     };
 
+    #[async_test]
+    async fn test_leased_lock() {
+        let store = MemoryStore::new();
+
+        assert!(store.try_take_leased_lock(60_000, "lock", "alice").await.unwrap());
+        assert!(!store.try_take_leased_lock(60_000, "lock", "bob").await.unwrap());
+        // The holder can extend its lease.
+        assert!(store.try_take_leased_lock(60_000, "lock", "alice").await.unwrap());
+
+        // An expired lease can be taken over by somebody else.
+        assert!(store.try_take_leased_lock(0, "lock", "alice").await.unwrap());
+        assert!(store.try_take_leased_lock(60_000, "lock", "bob").await.unwrap());
+        assert!(!store.try_take_leased_lock(60_000, "lock", "alice").await.unwrap());
+    }
+
     #[async_test]
     async fn test_session_store() {
         let (account, session) = get_account_and_session().await;
@@ -43,16 +43,20 @@ use std::{
     fmt::Debug,
     ops::Deref,
     sync::{atomic::AtomicBool, Arc},
+    time::Duration,
 };
 
 use atomic::Ordering;
 use dashmap::DashSet;
 use matrix_sdk_common::locks::Mutex;
-use ruma::{events::secret::request::SecretName, DeviceId, OwnedDeviceId, OwnedUserId, UserId};
+use ruma::{
+    events::secret::request::SecretName, DeviceId, DeviceKeyAlgorithm, MilliSecondsSinceUnixEpoch,
+    OwnedDeviceId, OwnedRoomId, OwnedUserId, UserId,
+};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::{info, warn};
-use vodozemac::{megolm::SessionOrdering, Curve25519PublicKey};
+use vodozemac::{megolm::SessionOrdering, Curve25519PublicKey, Ed25519PublicKey};
 use zeroize::Zeroize;
 
 use crate::{
@@ -71,6 +75,7 @@ use crate::{
 
 pub mod caches;
 mod error;
+mod locks;
 mod memorystore;
 mod traits;
 
@@ -80,6 +85,7 @@ mod traits;
 pub mod integration_tests;
 
 pub use error::{CryptoStoreError, Result};
+pub use locks::{CryptoStoreLock, CryptoStoreLockGuard, LockStoreError};
 pub use memorystore::MemoryStore;
 pub use traits::{CryptoStore, DynCryptoStore, IntoCryptoStore};
 
@@ -224,6 +230,60 @@ pub struct RoomKeyCounts {
     pub backed_up: usize,
 }
 
+/// Metadata about a room key, an inbound group session, the store has.
+#[derive(Debug, Clone)]
+pub struct RoomKeyInfo {
+    /// The room the room key is used in.
+    pub room_id: OwnedRoomId,
+    /// The unique id of the room key.
+    pub session_id: String,
+    /// The public curve25519 key of the device that sent us the room key.
+    pub sender_key: Curve25519PublicKey,
+    /// The public ed25519 key the sender claimed to own, if any.
+    pub sender_claimed_ed25519_key: Option<Ed25519PublicKey>,
+    /// The first message index we can decrypt with this room key.
+    pub first_known_index: u32,
+    /// Was the room key imported from a file or a backup, or forwarded to us,
+    /// as opposed to being sent to us by the sender directly.
+    pub imported: bool,
+    /// Has the room key been backed up to the server.
+    pub backed_up: bool,
+    /// The time at which we received the room key, `None` for room keys that
+    /// were stored before we started to record this.
+    pub received_at: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+impl From<&InboundGroupSession> for RoomKeyInfo {
+    fn from(session: &InboundGroupSession) -> Self {
+        Self {
+            room_id: session.room_id().to_owned(),
+            session_id: session.session_id().to_owned(),
+            sender_key: session.sender_key(),
+            sender_claimed_ed25519_key: session
+                .signing_keys()
+                .get(&DeviceKeyAlgorithm::Ed25519)
+                .and_then(|k| k.ed25519()),
+            first_known_index: session.first_known_index(),
+            imported: session.has_been_imported(),
+            backed_up: session.backed_up(),
+            received_at: session.received_at(),
+        }
+    }
+}
+
+/// Describes which room keys should be removed when pruning the store.
+#[derive(Debug, Clone)]
+pub enum RoomKeyRetention {
+    /// Remove all the room keys of the given rooms, usually rooms we have
+    /// left.
+    Rooms(Vec<OwnedRoomId>),
+    /// Remove the room keys we received longer than the given duration ago,
+    /// but only if they have been backed up.
+    ///
+    /// Room keys without a known reception time are kept.
+    BackedUpOlderThan(Duration),
+}
+
 /// Stored versions of the backup keys.
 #[derive(Default, Debug)]
 pub struct BackupKeys {
@@ -296,6 +356,11 @@ impl Store {
         }
     }
 
+    /// The underlying crypto store, without any of the caching of this type.
+    pub(crate) fn crypto_store(&self) -> Arc<DynCryptoStore> {
+        self.inner.clone()
+    }
+
     /// UserId associated with this store
     pub fn user_id(&self) -> &UserId {
         &self.user_id
@@ -641,6 +706,16 @@ impl Store {
         Ok(())
     }
 
+    /// Forget the cached list of tracked users, it will be loaded again from
+    /// the store the next time it's needed.
+    pub(crate) async fn reset_tracked_users_cache(&self) {
+        let _lock = self.tracked_user_loading_lock.lock().await;
+
+        self.tracked_users_cache.clear();
+        self.users_for_key_query_cache.clear();
+        self.tracked_users_loaded.store(false, Ordering::SeqCst);
+    }
+
     /// Load the list of users for whom we are tracking their device lists and
     /// fill out our caches.
     ///
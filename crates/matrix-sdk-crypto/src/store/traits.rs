@@ -83,6 +83,32 @@ pub trait CryptoStore: AsyncTraitDeps {
     /// Get all the inbound group sessions we have stored.
     async fn get_inbound_group_sessions(&self) -> Result<Vec<InboundGroupSession>, Self::Error>;
 
+    /// Get all the inbound group sessions we have stored for the given room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room id of the room that the sessions belong to.
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>, Self::Error>;
+
+    /// Delete the given inbound group sessions from the store.
+    ///
+    /// Sessions that aren't in the store are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room id of the room that the sessions belong to.
+    ///
+    /// * `session_ids` - The unique ids of the sessions that should be
+    /// deleted.
+    async fn delete_inbound_group_sessions(
+        &self,
+        room_id: &RoomId,
+        session_ids: &[&str],
+    ) -> Result<(), Self::Error>;
+
     /// Get the number inbound group sessions we have and how many of them are
     /// backed up.
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts, Self::Error>;
@@ -186,6 +212,62 @@ pub trait CryptoStore: AsyncTraitDeps {
         &self,
         request_id: &TransactionId,
     ) -> Result<(), Self::Error>;
+
+    /// Get arbitrary data from the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to fetch data for.
+    async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    /// Put arbitrary data into the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to insert data into.
+    ///
+    /// * `value` - The value to insert.
+    async fn set_custom_value(&self, key: &str, value: Vec<u8>) -> Result<(), Self::Error>;
+
+    /// Try to take a leased lock.
+    ///
+    /// This attempts to take the lock with the given key for the given lease
+    /// duration:
+    ///
+    /// - if nobody holds the lock, or the previous lease has expired, we
+    /// acquire the lock,
+    /// - if we already hold the lock, the lease is extended,
+    /// - otherwise we don't get the lock.
+    ///
+    /// The check and the update need to happen atomically, since the store may
+    /// be shared with other processes.
+    ///
+    /// Returns whether taking the lock succeeded.
+    ///
+    /// Stores that can't be shared between processes can always grant the
+    /// lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `lease_duration_ms` - For how long, in milliseconds, the lock should
+    /// be held before it expires.
+    ///
+    /// * `key` - The unique name of the lock.
+    ///
+    /// * `holder` - The unique id of the holder of the lock.
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool, Self::Error>;
+
+    /// Clear any in-memory caches the store has.
+    ///
+    /// This needs to be called if another process might have modified the
+    /// store, since the caches could be out of sync with the underlying data.
+    /// The default implementation does nothing, for stores without caches.
+    async fn clear_caches(&self) {}
 }
 
 #[repr(transparent)]
@@ -234,6 +316,21 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
         self.0.get_inbound_group_sessions().await.map_err(Into::into)
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        self.0.get_inbound_group_sessions_for_room(room_id).await.map_err(Into::into)
+    }
+
+    async fn delete_inbound_group_sessions(
+        &self,
+        room_id: &RoomId,
+        session_ids: &[&str],
+    ) -> Result<()> {
+        self.0.delete_inbound_group_sessions(room_id, session_ids).await.map_err(Into::into)
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         self.0.inbound_group_session_counts().await.map_err(Into::into)
     }
@@ -312,6 +409,27 @@ impl<T: CryptoStore> CryptoStore for EraseCryptoStoreError<T> {
     async fn delete_outgoing_secret_requests(&self, request_id: &TransactionId) -> Result<()> {
         self.0.delete_outgoing_secret_requests(request_id).await.map_err(Into::into)
     }
+
+    async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.0.get_custom_value(key).await.map_err(Into::into)
+    }
+
+    async fn set_custom_value(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.0.set_custom_value(key, value).await.map_err(Into::into)
+    }
+
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        self.0.try_take_leased_lock(lease_duration_ms, key, holder).await.map_err(Into::into)
+    }
+
+    async fn clear_caches(&self) {
+        self.0.clear_caches().await
+    }
 }
 
 /// A type-erased [`CryptoStore`].
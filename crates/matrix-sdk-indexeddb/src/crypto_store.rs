@@ -33,8 +33,8 @@ use matrix_sdk_crypto::{
     TrackedUser,
 };
 use matrix_sdk_store_encryption::StoreCipher;
-use ruma::{DeviceId, OwnedDeviceId, RoomId, TransactionId, UserId};
-use serde::{de::DeserializeOwned, Serialize};
+use ruma::{DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, RoomId, TransactionId, UserId};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use wasm_bindgen::JsValue;
 use web_sys::IdbKeyRange;
 
@@ -61,6 +61,9 @@ mod KEYS {
     pub const SECRET_REQUESTS_BY_INFO: &str = "secret_requests_by_info";
     pub const KEY_REQUEST: &str = "key_request";
 
+    pub const CUSTOM_VALUES: &str = "custom_values";
+    pub const LEASES: &str = "leases";
+
     // KEYS
     pub const STORE_CIPHER: &str = "store_cipher";
     pub const ACCOUNT: &str = "account";
@@ -143,7 +146,7 @@ impl IndexeddbCryptoStore {
         let name = format!("{prefix:0}::matrix-sdk-crypto");
 
         // Open my_db v1
-        let mut db_req: OpenDbRequest = IdbDatabase::open_f64(&name, 1.1)?;
+        let mut db_req: OpenDbRequest = IdbDatabase::open_f64(&name, 1.2)?;
         db_req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
             let old_version = evt.old_version();
 
@@ -179,6 +182,14 @@ impl IndexeddbCryptoStore {
                 db.create_object_store(KEYS::INBOUND_GROUP_SESSIONS)?;
             }
 
+            if old_version < 1.2 {
+                // Added the stores for custom values and leased locks.
+                let db = evt.db();
+
+                db.create_object_store(KEYS::CUSTOM_VALUES)?;
+                db.create_object_store(KEYS::LEASES)?;
+            }
+
             Ok(())
         }));
 
@@ -743,6 +754,45 @@ impl_crypto_store! {
             .collect())
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        let range = self.encode_to_range(KEYS::INBOUND_GROUP_SESSIONS, room_id)?;
+        Ok(self
+            .inner
+            .transaction_on_one_with_mode(
+                KEYS::INBOUND_GROUP_SESSIONS,
+                IdbTransactionMode::Readonly,
+            )?
+            .object_store(KEYS::INBOUND_GROUP_SESSIONS)?
+            .get_all_with_key(&range)?
+            .await?
+            .iter()
+            .filter_map(|i| self.deserialize_value(i).ok())
+            .filter_map(|p| InboundGroupSession::from_pickle(p).ok())
+            .collect())
+    }
+
+    async fn delete_inbound_group_sessions(
+        &self,
+        room_id: &RoomId,
+        session_ids: &[&str],
+    ) -> Result<()> {
+        let tx = self.inner.transaction_on_one_with_mode(
+            KEYS::INBOUND_GROUP_SESSIONS,
+            IdbTransactionMode::Readwrite,
+        )?;
+        let sessions = tx.object_store(KEYS::INBOUND_GROUP_SESSIONS)?;
+
+        for session_id in session_ids {
+            let key = self.encode_key(KEYS::INBOUND_GROUP_SESSIONS, (room_id, *session_id));
+            sessions.delete(&key)?;
+        }
+
+        tx.await.into_result().map_err(|e| e.into())
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         let all = self.get_inbound_group_sessions().await?;
         let backed_up = all.iter().filter(|s| s.backed_up()).count();
@@ -951,6 +1001,74 @@ impl_crypto_store! {
 
         Ok(key)
     }
+
+    async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .inner
+            .transaction_on_one_with_mode(KEYS::CUSTOM_VALUES, IdbTransactionMode::Readonly)?
+            .object_store(KEYS::CUSTOM_VALUES)?
+            .get(&self.encode_key(KEYS::CUSTOM_VALUES, key))?
+            .await?
+            .map(|v| self.deserialize_value(v))
+            .transpose()?)
+    }
+
+    async fn set_custom_value(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let tx = self
+            .inner
+            .transaction_on_one_with_mode(KEYS::CUSTOM_VALUES, IdbTransactionMode::Readwrite)?;
+
+        tx.object_store(KEYS::CUSTOM_VALUES)?.put_key_val(
+            &self.encode_key(KEYS::CUSTOM_VALUES, key),
+            &self.serialize_value(&value)?,
+        )?;
+
+        tx.await.into_result().map_err(|e| e.into())
+    }
+
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        let key = self.encode_key(KEYS::LEASES, key);
+        let now: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
+        let expiration = now + u64::from(lease_duration_ms);
+
+        // Both the read and the write happen in the same read-write
+        // transaction, other tabs can't interleave with us.
+        let tx =
+            self.inner.transaction_on_one_with_mode(KEYS::LEASES, IdbTransactionMode::Readwrite)?;
+        let leases = tx.object_store(KEYS::LEASES)?;
+
+        let current: Option<Lease> =
+            leases.get(&key)?.await?.map(|l| self.deserialize_value(l)).transpose()?;
+        let can_take = current.map_or(true, |l| l.holder == holder || l.expiration <= now);
+
+        if can_take {
+            let lease = Lease { holder: holder.to_owned(), expiration };
+            leases.put_key_val(&key, &self.serialize_value(&lease)?)?;
+        }
+
+        tx.await.into_result()?;
+
+        Ok(can_take)
+    }
+
+    async fn clear_caches(&self) {
+        self.session_cache.clear();
+    }
+}
+
+/// A lease on a lock, shared between tabs.
+#[derive(Debug, Serialize, Deserialize)]
+struct Lease {
+    /// The unique id of the current holder of the lock.
+    holder: String,
+    /// The time, in milliseconds since the unix epoch, at which the lease
+    /// expires.
+    expiration: u64,
 }
 
 impl Drop for IndexeddbCryptoStore {
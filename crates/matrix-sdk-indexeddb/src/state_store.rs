@@ -1296,6 +1296,20 @@ impl IndexeddbStateStore {
         Ok(prev)
     }
 
+    async fn remove_custom_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let jskey = JsValue::from_str(core::str::from_utf8(key).map_err(StoreError::Codec)?);
+
+        let prev = self.get_custom_value_for_js(&jskey).await?;
+
+        let tx =
+            self.inner.transaction_on_one_with_mode(KEYS::CUSTOM, IdbTransactionMode::Readwrite)?;
+
+        tx.object_store(KEYS::CUSTOM)?.delete(&jskey)?;
+
+        tx.await.into_result().map_err(IndexeddbStateStoreError::from)?;
+        Ok(prev)
+    }
+
     async fn remove_media_content(&self, request: &MediaRequest) -> Result<()> {
         let key = self
             .encode_key(KEYS::MEDIA, (request.source.unique_key(), request.format.unique_key()));
@@ -1509,6 +1523,10 @@ impl StateStore for IndexeddbStateStore {
         self.set_custom_value(key, value).await.map_err(|e| e.into())
     }
 
+    async fn remove_custom_value(&self, key: &[u8]) -> StoreResult<Option<Vec<u8>>> {
+        self.remove_custom_value(key).await.map_err(|e| e.into())
+    }
+
     async fn add_media_content(&self, request: &MediaRequest, data: Vec<u8>) -> StoreResult<()> {
         self.add_media_content(request, data).await.map_err(|e| e.into())
     }
@@ -38,6 +38,12 @@ pub enum DecodingError {
     /// The QR code data doesn't contain valid ed25519 keys.
     #[error("the QR code contains invalid ed25519 keys: {0}")]
     Keys(#[from] vodozemac::KeyError),
+    /// The login QR code data isn't valid JSON or is missing fields.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// The login QR code data uses an unsupported secure channel algorithm.
+    #[error("the login QR code uses an unsupported rendezvous algorithm: {0}")]
+    Algorithm(String),
 }
 
 /// Error type describing errors that happen while QR data is being encoded.
@@ -50,4 +56,7 @@ pub enum EncodingError {
     /// Error encoding the given flow id, the flow id is too large.
     #[error("The verification flow id length can't be converted into a u16: {0}")]
     FlowId(#[from] std::num::TryFromIntError),
+    /// Error serializing the login QR code data.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
 }
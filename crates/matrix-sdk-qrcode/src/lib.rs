@@ -17,10 +17,14 @@
 #![warn(missing_debug_implementations, missing_docs)]
 
 mod error;
+mod login;
 mod types;
 mod utils;
 
 pub use error::{DecodingError, EncodingError};
+pub use login::{
+    QrLoginData, QrLoginIntent, RendezvousDetails, RendezvousTransport, RENDEZVOUS_ALGORITHM,
+};
 pub use qrcode;
 pub use types::{
     QrVerificationData, SelfVerificationData, SelfVerificationNoMasterKey, VerificationData,
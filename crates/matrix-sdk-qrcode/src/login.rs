@@ -0,0 +1,259 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types for the QR codes used to sign in a new device, as defined in
+//! [MSC3906].
+//!
+//! [MSC3906]: https://github.com/matrix-org/matrix-spec-proposals/pull/3906
+
+use qrcode::{EcLevel, QrCode};
+use serde::{Deserialize, Serialize};
+use url::Url;
+use vodozemac::Curve25519PublicKey;
+
+use crate::error::{DecodingError, EncodingError};
+
+/// The identifier of the secure channel algorithm defined in [MSC3903].
+///
+/// [MSC3903]: https://github.com/matrix-org/matrix-spec-proposals/pull/3903
+pub const RENDEZVOUS_ALGORITHM: &str = "org.matrix.msc3903.rendezvous.v2.curve25519-aes-sha256";
+
+/// The intent of the device that is presenting a login QR code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QrLoginIntent {
+    /// The QR code is shown by a new device that wants to be signed in.
+    #[serde(rename = "login.start")]
+    LoginStart,
+    /// The QR code is shown by an existing, signed in, device that offers to
+    /// sign in a new device.
+    #[serde(rename = "login.reciprocate")]
+    LoginReciprocate,
+}
+
+/// The transport that should be used to exchange messages between the two
+/// devices, as defined in [MSC3886].
+///
+/// [MSC3886]: https://github.com/matrix-org/matrix-spec-proposals/pull/3886
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum RendezvousTransport {
+    /// A simple HTTP rendezvous session.
+    #[serde(rename = "org.matrix.msc3886.http.v1")]
+    Http {
+        /// The URL of the rendezvous session.
+        uri: Url,
+    },
+}
+
+/// The details of the rendezvous session a login QR code points to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RendezvousDetails {
+    /// The algorithm of the secure channel, only [`RENDEZVOUS_ALGORITHM`] is
+    /// supported.
+    pub algorithm: String,
+    /// The ephemeral Curve25519 key of the device that presents the QR code.
+    #[serde(with = "curve25519_base64")]
+    pub key: Curve25519PublicKey,
+    /// The transport which should be used to reach the other device.
+    pub transport: RendezvousTransport,
+}
+
+/// The data of a login QR code, as defined in [MSC3906].
+///
+/// [MSC3906]: https://github.com/matrix-org/matrix-spec-proposals/pull/3906
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QrLoginData {
+    /// The rendezvous session the scanning device should connect to.
+    pub rendezvous: RendezvousDetails,
+    /// The intent of the device presenting the QR code.
+    pub intent: QrLoginIntent,
+    /// The homeserver of the signed in device, only present if the intent is
+    /// [`QrLoginIntent::LoginReciprocate`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homeserver: Option<Url>,
+}
+
+impl TryFrom<&[u8]> for QrLoginData {
+    type Error = DecodingError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Self::from_bytes(value)
+    }
+}
+
+impl TryFrom<Vec<u8>> for QrLoginData {
+    type Error = DecodingError;
+
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        Self::from_bytes(value)
+    }
+}
+
+impl QrLoginData {
+    /// Create the QR code data a new device presents to be signed in.
+    ///
+    /// # Arguments
+    ///
+    /// * `rendezvous_url` - The URL of the rendezvous session the new device
+    /// created.
+    ///
+    /// * `key` - The ephemeral Curve25519 key of the new device.
+    pub fn new_login_start(rendezvous_url: Url, key: Curve25519PublicKey) -> Self {
+        Self {
+            rendezvous: RendezvousDetails {
+                algorithm: RENDEZVOUS_ALGORITHM.to_owned(),
+                key,
+                transport: RendezvousTransport::Http { uri: rendezvous_url },
+            },
+            intent: QrLoginIntent::LoginStart,
+            homeserver: None,
+        }
+    }
+
+    /// Parse the decoded payload of a login QR code.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The raw bytes of a decoded QR code.
+    ///
+    /// # Example
+    /// ```
+    /// # use matrix_sdk_qrcode::{QrLoginData, DecodingError};
+    /// # fn main() -> Result<(), DecodingError> {
+    /// let data = br#"{
+    ///     "rendezvous": {
+    ///         "algorithm": "org.matrix.msc3903.rendezvous.v2.curve25519-aes-sha256",
+    ///         "key": "W8b5c8ic0BVKFbDd9RTJidMoTCLvpbMTYCfoK69Q0XI",
+    ///         "transport": {
+    ///             "type": "org.matrix.msc3886.http.v1",
+    ///             "uri": "https://rendezvous.example.org/abc-def"
+    ///         }
+    ///     },
+    ///     "intent": "login.start"
+    /// }"#;
+    ///
+    /// let result = QrLoginData::from_bytes(data)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_bytes(bytes: impl AsRef<[u8]>) -> Result<Self, DecodingError> {
+        let data: Self = serde_json::from_slice(bytes.as_ref())?;
+
+        if data.rendezvous.algorithm != RENDEZVOUS_ALGORITHM {
+            Err(DecodingError::Algorithm(data.rendezvous.algorithm))
+        } else {
+            Ok(data)
+        }
+    }
+
+    /// Encode the `QrLoginData` into a vector of bytes that can be encoded as
+    /// a QR code.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EncodingError> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// Encode the `QrLoginData` into a `QrCode`.
+    ///
+    /// This method turns the `QrLoginData` into a QR code that can be rendered
+    /// and presented to be scanned.
+    pub fn to_qr_code(&self) -> Result<QrCode, EncodingError> {
+        let data = self.to_bytes()?;
+
+        // Unlike the verification QR codes, the login data is a JSON string so
+        // letting the encoder pick the version and data segments is fine.
+        Ok(QrCode::with_error_correction_level(data, EcLevel::L)?)
+    }
+
+    /// Get the URL of the rendezvous session this QR code points to.
+    pub fn rendezvous_url(&self) -> &Url {
+        match &self.rendezvous.transport {
+            RendezvousTransport::Http { uri } => uri,
+        }
+    }
+}
+
+mod curve25519_base64 {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use vodozemac::Curve25519PublicKey;
+
+    pub fn serialize<S>(key: &Curve25519PublicKey, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&key.to_base64())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Curve25519PublicKey, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let key = String::deserialize(deserializer)?;
+        Curve25519PublicKey::from_base64(&key).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+    use vodozemac::Curve25519PublicKey;
+
+    use super::{QrLoginData, QrLoginIntent};
+    use crate::DecodingError;
+
+    const KEY: &str = "W8b5c8ic0BVKFbDd9RTJidMoTCLvpbMTYCfoK69Q0XI";
+
+    #[test]
+    fn login_data_roundtrip() {
+        let url = Url::parse("https://rendezvous.example.org/abc-def").unwrap();
+        let key = Curve25519PublicKey::from_base64(KEY).unwrap();
+
+        let data = QrLoginData::new_login_start(url.clone(), key);
+        let encoded = data.to_bytes().unwrap();
+        let decoded = QrLoginData::from_bytes(encoded).unwrap();
+
+        assert_eq!(data, decoded);
+        assert_eq!(decoded.intent, QrLoginIntent::LoginStart);
+        assert_eq!(decoded.rendezvous_url(), &url);
+        assert!(decoded.homeserver.is_none());
+
+        data.to_qr_code().expect("The login data should fit into a QR code");
+    }
+
+    #[test]
+    fn decode_invalid_algorithm() {
+        let data = format!(
+            r#"{{
+                "rendezvous": {{
+                    "algorithm": "m.unknown",
+                    "key": "{KEY}",
+                    "transport": {{
+                        "type": "org.matrix.msc3886.http.v1",
+                        "uri": "https://rendezvous.example.org/abc-def"
+                    }}
+                }},
+                "intent": "login.reciprocate",
+                "homeserver": "https://matrix.example.org"
+            }}"#
+        );
+
+        let result = QrLoginData::from_bytes(data);
+        assert!(matches!(result, Err(DecodingError::Algorithm(a)) if a == "m.unknown"));
+    }
+
+    #[test]
+    fn decode_invalid_json() {
+        let result = QrLoginData::from_bytes(b"MATRIX\x02\x02");
+        assert!(matches!(result, Err(DecodingError::Json(_))));
+    }
+}
@@ -35,8 +35,8 @@ use matrix_sdk_crypto::{
     TrackedUser,
 };
 use matrix_sdk_store_encryption::StoreCipher;
-use ruma::{DeviceId, OwnedDeviceId, RoomId, TransactionId, UserId};
-use serde::{de::DeserializeOwned, Serialize};
+use ruma::{DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, RoomId, TransactionId, UserId};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sled::{
     transaction::{ConflictableTransactionError, TransactionError},
     Batch, Config, Db, IVec, Transactional, Tree,
@@ -58,6 +58,8 @@ const INBOUND_GROUP_TABLE_NAME: &str = "crypto-store-inbound-group-sessions";
 const OUTBOUND_GROUP_TABLE_NAME: &str = "crypto-store-outbound-group-sessions";
 const SECRET_REQUEST_BY_INFO_TABLE: &str = "crypto-store-secret-request-by-info";
 const TRACKED_USERS_TABLE: &str = "crypto-store-secret-tracked-users";
+const CUSTOM_VALUES_TABLE: &str = "crypto-store-custom-values";
+const LEASES_TABLE: &str = "crypto-store-leases";
 
 impl EncodeKey for InboundGroupSession {
     fn encode(&self) -> Vec<u8> {
@@ -185,6 +187,9 @@ pub struct SledCryptoStore {
     identities: Tree,
 
     tracked_users: Tree,
+
+    custom_values: Tree,
+    leases: Tree,
 }
 
 impl std::fmt::Debug for SledCryptoStore {
@@ -386,6 +391,9 @@ impl SledCryptoStore {
         let unsent_secret_requests = db.open_tree("unsent_secret_requests")?;
         let secret_requests_by_info = db.open_tree("secret_requests_by_info")?;
 
+        let custom_values = db.open_tree("custom_values")?;
+        let leases = db.open_tree("leases")?;
+
         let session_cache = SessionStore::new();
 
         let database = Self {
@@ -406,6 +414,8 @@ impl SledCryptoStore {
             tracked_users,
             olm_hashes,
             identities,
+            custom_values,
+            leases,
         };
 
         database.upgrade().await?;
@@ -797,6 +807,35 @@ impl CryptoStore for SledCryptoStore {
         Ok(pickles?.into_iter().filter_map(|p| InboundGroupSession::from_pickle(p).ok()).collect())
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        let key = self.encode_key(INBOUND_GROUP_TABLE_NAME, room_id);
+        self.inbound_group_sessions
+            .scan_prefix(key)
+            .map(|p| self.deserialize_value(&p.map_err(CryptoStoreError::backend)?.1))
+            .map(|p| Ok(InboundGroupSession::from_pickle(p?)?))
+            .collect()
+    }
+
+    async fn delete_inbound_group_sessions(
+        &self,
+        room_id: &RoomId,
+        session_ids: &[&str],
+    ) -> Result<()> {
+        let mut batch = Batch::default();
+
+        for session_id in session_ids {
+            batch.remove(self.encode_key(INBOUND_GROUP_TABLE_NAME, (room_id, *session_id)));
+        }
+
+        self.inbound_group_sessions.apply_batch(batch).map_err(CryptoStoreError::backend)?;
+        self.inner.flush_async().await.map_err(CryptoStoreError::backend)?;
+
+        Ok(())
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         let pickles: Vec<PickledInboundGroupSession> = self
             .inbound_group_sessions
@@ -1010,6 +1049,77 @@ impl CryptoStore for SledCryptoStore {
 
         Ok(key)
     }
+
+    async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.custom_values
+            .get(self.encode_key(CUSTOM_VALUES_TABLE, key))
+            .map_err(CryptoStoreError::backend)?
+            .map(|v| self.deserialize_value(&v))
+            .transpose()
+    }
+
+    async fn set_custom_value(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        self.custom_values
+            .insert(self.encode_key(CUSTOM_VALUES_TABLE, key), self.serialize_value(&value)?)
+            .map_err(CryptoStoreError::backend)?;
+        self.inner.flush_async().await.map_err(CryptoStoreError::backend)?;
+
+        Ok(())
+    }
+
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        let key = self.encode_key(LEASES_TABLE, key);
+        let now: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
+        let expiration = now + u64::from(lease_duration_ms);
+
+        let ret: Result<bool, TransactionError<CryptoStoreError>> =
+            self.leases.transaction(|leases| {
+                let current: Option<Lease> = leases
+                    .get(&key)?
+                    .map(|l| self.deserialize_value(&l))
+                    .transpose()
+                    .map_err(ConflictableTransactionError::Abort)?;
+
+                let can_take = current.map_or(true, |l| l.holder == holder || l.expiration <= now);
+
+                if can_take {
+                    let lease = Lease { holder: holder.to_owned(), expiration };
+                    let lease = self
+                        .serialize_value(&lease)
+                        .map_err(ConflictableTransactionError::Abort)?;
+                    leases.insert(key.as_slice(), lease)?;
+                }
+
+                Ok(can_take)
+            });
+
+        let taken = ret.map_err(CryptoStoreError::backend)?;
+
+        if taken {
+            self.inner.flush_async().await.map_err(CryptoStoreError::backend)?;
+        }
+
+        Ok(taken)
+    }
+
+    async fn clear_caches(&self) {
+        self.session_cache.clear();
+    }
+}
+
+/// A lease on a lock, shared between processes.
+#[derive(Debug, Serialize, Deserialize)]
+struct Lease {
+    /// The unique id of the current holder of the lock.
+    holder: String,
+    /// The time, in milliseconds since the unix epoch, at which the lease
+    /// expires.
+    expiration: u64,
 }
 
 #[cfg(test)]
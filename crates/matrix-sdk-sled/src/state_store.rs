@@ -1263,6 +1263,14 @@ impl SledStateStore {
         ret
     }
 
+    async fn remove_custom_value(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let key = self.encode_key(CUSTOM, EncodeUnchecked::from(key));
+        let ret = self.custom.remove(key)?.map(|v| self.deserialize_value(&v)).transpose();
+        self.inner.flush_async().await?;
+
+        ret
+    }
+
     async fn remove_media_content(&self, request: &MediaRequest) -> Result<()> {
         self.media.remove(
             self.encode_key(MEDIA, (request.source.unique_key(), request.format.unique_key())),
@@ -1598,6 +1606,10 @@ impl StateStore for SledStateStore {
         self.set_custom_value(key, value).await.map_err(Into::into)
     }
 
+    async fn remove_custom_value(&self, key: &[u8]) -> StoreResult<Option<Vec<u8>>> {
+        self.remove_custom_value(key).await.map_err(Into::into)
+    }
+
     async fn add_media_content(&self, request: &MediaRequest, data: Vec<u8>) -> StoreResult<()> {
         self.add_media_content(request, data).await.map_err(Into::into)
     }
@@ -0,0 +1,13 @@
+CREATE TABLE "custom_value" (
+    "key" BLOB PRIMARY KEY NOT NULL,
+    "value" BLOB NOT NULL
+);
+
+-- Leased locks that are shared between the processes that use this database.
+CREATE TABLE "lease_lock" (
+    "key" BLOB PRIMARY KEY NOT NULL,
+    "holder" TEXT NOT NULL,
+    -- The time, in milliseconds since the unix epoch, at which the lease
+    -- expires.
+    "expiration" INTEGER NOT NULL
+);
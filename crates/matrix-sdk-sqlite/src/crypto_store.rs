@@ -32,7 +32,7 @@ use matrix_sdk_crypto::{
     TrackedUser,
 };
 use matrix_sdk_store_encryption::StoreCipher;
-use ruma::{DeviceId, OwnedDeviceId, RoomId, TransactionId, UserId};
+use ruma::{DeviceId, MilliSecondsSinceUnixEpoch, OwnedDeviceId, RoomId, TransactionId, UserId};
 use rusqlite::OptionalExtension;
 use serde::{de::DeserializeOwned, Serialize};
 use tokio::fs;
@@ -172,7 +172,7 @@ impl SqliteCryptoStore {
     }
 }
 
-const DATABASE_VERSION: u8 = 2;
+const DATABASE_VERSION: u8 = 3;
 
 async fn run_migrations(conn: &SqliteConn) -> rusqlite::Result<()> {
     let kv_exists = conn
@@ -221,6 +221,13 @@ async fn run_migrations(conn: &SqliteConn) -> rusqlite::Result<()> {
         .await?;
     }
 
+    if version < 3 {
+        conn.with_transaction(|txn| {
+            txn.execute_batch(include_str!("../migrations/003_custom_values_and_leases.sql"))
+        })
+        .await?;
+    }
+
     conn.set_kv("version", vec![DATABASE_VERSION]).await?;
 
     Ok(())
@@ -241,6 +248,11 @@ trait SqliteConnectionExt {
         data: &[u8],
         backed_up: bool,
     ) -> rusqlite::Result<()>;
+    fn delete_inbound_group_session(
+        &self,
+        room_id: &[u8],
+        session_id: &[u8],
+    ) -> rusqlite::Result<()>;
 
     fn set_outbound_group_session(&self, room_id: &[u8], data: &[u8]) -> rusqlite::Result<()>;
 
@@ -291,6 +303,18 @@ impl SqliteConnectionExt for rusqlite::Connection {
         Ok(())
     }
 
+    fn delete_inbound_group_session(
+        &self,
+        room_id: &[u8],
+        session_id: &[u8],
+    ) -> rusqlite::Result<()> {
+        self.execute(
+            "DELETE FROM inbound_group_session WHERE room_id = ? AND session_id = ?",
+            (room_id, session_id),
+        )?;
+        Ok(())
+    }
+
     fn set_outbound_group_session(&self, room_id: &[u8], data: &[u8]) -> rusqlite::Result<()> {
         self.execute(
             "INSERT INTO outbound_group_session (room_id, data) \
@@ -382,6 +406,20 @@ trait SqliteObjectCryptoStoreExt: SqliteObjectExt {
             .await?)
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: Key,
+    ) -> Result<Vec<(Vec<u8>, bool)>> {
+        Ok(self
+            .prepare(
+                "SELECT data, backed_up FROM inbound_group_session WHERE room_id = ?",
+                |mut stmt| {
+                    stmt.query((room_id,))?.mapped(|row| Ok((row.get(0)?, row.get(1)?))).collect()
+                },
+            )
+            .await?)
+    }
+
     async fn get_inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         let total = self
             .query_row("SELECT count(*) FROM inbound_group_session", (), |row| row.get(0))
@@ -515,6 +553,44 @@ trait SqliteObjectCryptoStoreExt: SqliteObjectExt {
         self.execute("DELETE FROM key_requests WHERE request_id = ?", (request_id,)).await?;
         Ok(())
     }
+
+    async fn get_custom_value(&self, key: Key) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .query_row("SELECT value FROM custom_value WHERE key = ?", (key,), |row| row.get(0))
+            .await
+            .optional()?)
+    }
+
+    async fn set_custom_value(&self, key: Key, value: Vec<u8>) -> Result<()> {
+        self.execute(
+            "INSERT INTO custom_value (key, value) VALUES (?1, ?2)
+             ON CONFLICT (key) DO UPDATE SET value = ?2",
+            (key, value),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn try_take_leased_lock(
+        &self,
+        key: Key,
+        holder: String,
+        now: u64,
+        expiration: u64,
+    ) -> Result<bool> {
+        // The upsert only touches an existing row if we already hold the lock
+        // or if the lease has expired, which makes the check atomic.
+        let changed = self
+            .execute(
+                "INSERT INTO lease_lock (key, holder, expiration) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (key) DO UPDATE SET holder = ?2, expiration = ?3
+                 WHERE holder = ?2 OR expiration <= ?4",
+                (key, holder, expiration, now),
+            )
+            .await?;
+
+        Ok(changed > 0)
+    }
 }
 
 #[async_trait]
@@ -761,6 +837,46 @@ impl CryptoStore for SqliteCryptoStore {
             .collect()
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        let room_id = self.encode_key("inbound_group_session", room_id.as_bytes());
+        self.acquire()
+            .await?
+            .get_inbound_group_sessions_for_room(room_id)
+            .await?
+            .into_iter()
+            .map(|(value, backed_up)| {
+                let pickle = self.deserialize_pickled_inbound_group_session(&value, backed_up)?;
+                Ok(InboundGroupSession::from_pickle(pickle)?)
+            })
+            .collect()
+    }
+
+    async fn delete_inbound_group_sessions(
+        &self,
+        room_id: &RoomId,
+        session_ids: &[&str],
+    ) -> Result<()> {
+        let room_id = self.encode_key("inbound_group_session", room_id.as_bytes());
+        let session_ids: Vec<_> =
+            session_ids.iter().map(|id| self.encode_key("inbound_group_session", id)).collect();
+
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| {
+                for session_id in &session_ids {
+                    txn.delete_inbound_group_session(&room_id, session_id)?;
+                }
+
+                Ok::<_, Error>(())
+            })
+            .await?;
+
+        Ok(())
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         Ok(self.acquire().await?.get_inbound_group_session_counts().await?)
     }
@@ -946,6 +1062,38 @@ impl CryptoStore for SqliteCryptoStore {
         let request_id = self.encode_key("key_requests", request_id.as_bytes());
         Ok(self.acquire().await?.delete_key_request(request_id).await?)
     }
+
+    async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let key = self.encode_key("custom_value", key);
+        let Some(value) = self.acquire().await?.get_custom_value(key).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(self.deserialize_value(&value)?))
+    }
+
+    async fn set_custom_value(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let key = self.encode_key("custom_value", key);
+        let value = self.serialize_value(&value)?;
+        self.acquire().await?.set_custom_value(key, value).await
+    }
+
+    async fn try_take_leased_lock(
+        &self,
+        lease_duration_ms: u32,
+        key: &str,
+        holder: &str,
+    ) -> Result<bool> {
+        let key = self.encode_key("lease_lock", key);
+        let now: u64 = MilliSecondsSinceUnixEpoch::now().get().into();
+        let expiration = now + u64::from(lease_duration_ms);
+
+        self.acquire().await?.try_take_leased_lock(key, holder.to_owned(), now, expiration).await
+    }
+
+    async fn clear_caches(&self) {
+        self.session_cache.clear();
+    }
 }
 
 #[cfg(test)]
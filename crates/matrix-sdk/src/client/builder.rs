@@ -36,7 +36,7 @@ use tracing::{
 };
 use url::Url;
 
-use super::{Client, ClientInner};
+use super::{Client, ClientInner, SessionPersistence, SessionPersistenceHook};
 use crate::{
     config::RequestConfig,
     error::RumaApiError,
@@ -93,6 +93,7 @@ pub struct ClientBuilder {
     appservice_mode: bool,
     server_versions: Option<Box<[MatrixVersion]>>,
     handle_refresh_tokens: bool,
+    session_persistence: Option<SessionPersistenceHook>,
     root_span: Span,
 }
 
@@ -115,6 +116,7 @@ impl ClientBuilder {
             appservice_mode: false,
             server_versions: None,
             handle_refresh_tokens: false,
+            session_persistence: None,
             root_span,
         }
     }
@@ -319,16 +321,61 @@ impl ClientBuilder {
     ///
     /// * The access token and refresh token need to be watched for changes,
     ///   using [`Client::session_tokens_signal()`] for example, to be able to
-    ///   [restore the session] later.
+    ///   [restore the session] later. A [session persistence hook] can be used
+    ///   instead to save the new tokens as soon as they're received.
     ///
     /// [refreshing access tokens]: https://spec.matrix.org/v1.3/client-server-api/#refreshing-access-tokens
     /// [`UnknownToken`]: ruma::api::client::error::ErrorKind::UnknownToken
     /// [restore the session]: Client::restore_session
+    /// [session persistence hook]: Self::session_persistence
     pub fn handle_refresh_tokens(mut self) -> Self {
         self.handle_refresh_tokens = true;
         self
     }
 
+    /// Set the hook to persist the session when its tokens are refreshed.
+    ///
+    /// When the tokens are refreshed, the `Client` waits for the hook to save
+    /// the session before using the new tokens. If the hook fails, the refresh
+    /// fails with [`RefreshTokenError::SessionPersistence`], but the new
+    /// tokens are still used since the previous refresh token might not be
+    /// valid anymore. Saving them is retried before every following request,
+    /// until the hook succeeds.
+    ///
+    /// The hook can be an async closure receiving the [`Session`] to save, or
+    /// a type implementing [`SessionPersistence`]. Only the latter can load
+    /// the saved session with [`SessionPersistence::load_session()`], which
+    /// is required by the [cross-process refresh lock]: with a closure, the
+    /// tokens refreshed by another process are never picked up.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use matrix_sdk::{Client, Session, SessionPersistenceError};
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # async fn save_to_keychain(_: Session) -> std::io::Result<()> { Ok(()) }
+    ///
+    /// let client = Client::builder()
+    ///     .homeserver_url("http://example.com")
+    ///     .handle_refresh_tokens()
+    ///     .session_persistence(|session: Session| async move {
+    ///         save_to_keychain(session).await?;
+    ///         Ok::<_, SessionPersistenceError>(())
+    ///     })
+    ///     .build()
+    ///     .await?;
+    /// # anyhow::Ok(()) });
+    /// ```
+    ///
+    /// [`RefreshTokenError::SessionPersistence`]: crate::RefreshTokenError::SessionPersistence
+    /// [`Session`]: crate::Session
+    /// [cross-process refresh lock]: Client::enable_cross_process_refresh_lock
+    pub fn session_persistence(mut self, persistence: impl SessionPersistence + 'static) -> Self {
+        self.session_persistence = Some(SessionPersistenceHook(Arc::new(persistence)));
+        self
+    }
+
     /// Create a [`Client`] with the options set on this builder.
     ///
     /// # Errors
@@ -378,6 +425,8 @@ impl ClientBuilder {
         let http_client = HttpClient::new(inner_http_client.clone(), self.request_config);
 
         let mut authentication_issuer: Option<Url> = None;
+        #[cfg(feature = "experimental-oidc")]
+        let mut authentication_account: Option<Url> = None;
         #[cfg(feature = "experimental-sliding-sync")]
         let mut sliding_sync_proxy: Option<Url> = None;
         let homeserver = match homeserver_cfg {
@@ -401,8 +450,13 @@ impl ClientBuilder {
                         err => ClientBuildError::Http(err),
                     })?;
 
-                if let Some(issuer) = well_known.authentication.map(|auth| auth.issuer) {
-                    authentication_issuer = Url::parse(&issuer).ok();
+                if let Some(authentication) = well_known.authentication {
+                    authentication_issuer = Url::parse(&authentication.issuer).ok();
+                    #[cfg(feature = "experimental-oidc")]
+                    {
+                        authentication_account =
+                            authentication.account.and_then(|account| Url::parse(&account).ok());
+                    }
                 }
                 #[cfg(feature = "experimental-sliding-sync")]
                 if let Some(proxy) = well_known.sliding_sync_proxy.map(|p| p.url) {
@@ -422,6 +476,8 @@ impl ClientBuilder {
         let inner = Arc::new(ClientInner {
             homeserver,
             authentication_issuer,
+            #[cfg(feature = "experimental-oidc")]
+            oidc_data: crate::oidc::OidcData::new(authentication_account),
             #[cfg(feature = "experimental-sliding-sync")]
             sliding_sync_proxy,
             http_client,
@@ -431,16 +487,30 @@ impl ClientBuilder {
             group_session_locks: Default::default(),
             #[cfg(feature = "e2e-encryption")]
             key_claim_lock: Default::default(),
+            #[cfg(feature = "e2e-encryption")]
+            cross_signing_reset_lock: Default::default(),
+            #[cfg(feature = "e2e-encryption")]
+            cross_process_store_lock: Default::default(),
+            #[cfg(feature = "backups-v1")]
+            backup_state: Default::default(),
             members_request_locks: Default::default(),
             encryption_state_request_locks: Default::default(),
             typing_notice_times: Default::default(),
+            send_queues: Default::default(),
             event_handlers: Default::default(),
             notification_handlers: Default::default(),
             appservice_mode: self.appservice_mode,
+            #[cfg(feature = "appservice")]
+            appservice_transaction_log_lock: Default::default(),
             respect_login_well_known: self.respect_login_well_known,
             sync_beat: event_listener::Event::new(),
             handle_refresh_tokens: self.handle_refresh_tokens,
             refresh_token_lock: Mutex::new(Ok(())),
+            session_persistence: self.session_persistence,
+            unpersisted_session_tokens: Default::default(),
+            #[cfg(feature = "e2e-encryption")]
+            cross_process_refresh_lock: Default::default(),
+            session_change_subscribers: Default::default(),
         });
 
         debug!("Done building the Client");
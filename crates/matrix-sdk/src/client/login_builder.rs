@@ -168,16 +168,22 @@ impl LoginBuilder {
         let homeserver = self.client.homeserver().await;
         info!(homeserver = homeserver.as_str(), identifier = ?self.login_method.id(), "Logging in");
 
+        let client = self.client.clone();
+        let response = self.send_request().await?;
+        client.receive_login_response(&response).await?;
+
+        Ok(response)
+    }
+
+    /// Send the login request, without updating the session of the client.
+    pub(super) async fn send_request(self) -> Result<login::v3::Response> {
         let request = assign!(login::v3::Request::new(self.login_method.into_login_info()), {
             device_id: self.device_id.map(Into::into),
             initial_device_display_name: self.initial_device_display_name,
             refresh_token: self.request_refresh_token,
         });
 
-        let response = self.client.send(request, Some(RequestConfig::short_retry())).await?;
-        self.client.receive_login_response(&response).await?;
-
-        Ok(response)
+        Ok(self.client.send(request, Some(RequestConfig::short_retry())).await?)
     }
 }
 
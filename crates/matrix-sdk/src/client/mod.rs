@@ -18,7 +18,10 @@ use std::{
     fmt::{self, Debug},
     future::Future,
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as StdMutex, Weak,
+    },
 };
 
 #[cfg(target_arch = "wasm32")]
@@ -26,6 +29,7 @@ use async_once_cell::OnceCell;
 use dashmap::DashMap;
 use futures_core::stream::Stream;
 use futures_signals::signal::Signal;
+use futures_util::channel::mpsc;
 use matrix_sdk_base::{
     BaseClient, RoomType, SendOutsideWasm, Session, SessionMeta, SessionTokens, StateStore,
     SyncOutsideWasm,
@@ -71,11 +75,11 @@ use serde::de::DeserializeOwned;
 use tokio::sync::OnceCell;
 #[cfg(feature = "e2e-encryption")]
 use tracing::error;
-use tracing::{debug, field::display, info, instrument, trace, Instrument, Span};
+use tracing::{debug, field::display, info, instrument, trace, warn, Instrument, Span};
 use url::Url;
 
 #[cfg(feature = "e2e-encryption")]
-use crate::encryption::Encryption;
+use crate::encryption::{CryptoStoreLock, Encryption};
 use crate::{
     config::RequestConfig,
     error::{HttpError, HttpResult},
@@ -83,19 +87,22 @@ use crate::{
         EventHandler, EventHandlerDropGuard, EventHandlerHandle, EventHandlerStore, SyncEvent,
     },
     http_client::HttpClient,
-    room,
+    room::{self, send_queue::SendQueueData},
     sync::SyncResponse,
     Account, Error, Media, RefreshTokenError, Result, RumaApiError,
 };
 
 mod builder;
 mod login_builder;
+mod session_persistence;
 
 #[cfg(feature = "sso-login")]
 pub use self::login_builder::SsoLoginBuilder;
+pub(crate) use self::session_persistence::SessionPersistenceHook;
 pub use self::{
     builder::{ClientBuildError, ClientBuilder},
     login_builder::LoginBuilder,
+    session_persistence::{SessionPersistence, SessionPersistenceError},
 };
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -110,6 +117,27 @@ type NotificationHandlerFn =
 type NotificationHandlerFn =
     Box<dyn Fn(Notification, room::Room, Client) -> NotificationHandlerFut>;
 
+/// The number of transaction IDs an appservice client remembers.
+#[cfg(feature = "appservice")]
+const APPSERVICE_TXN_LOG_SIZE: u64 = 1000;
+/// The custom store key prefix marking a transaction ID as processed.
+#[cfg(feature = "appservice")]
+const APPSERVICE_TXN_ID_PREFIX: &[u8] = b"appservice.txn_id.";
+/// The custom store key prefix of the slots of the transaction log.
+#[cfg(feature = "appservice")]
+const APPSERVICE_TXN_LOG_PREFIX: &[u8] = b"appservice.txn_log.";
+/// The custom store key of the position of the next transaction log slot.
+#[cfg(feature = "appservice")]
+const APPSERVICE_TXN_LOG_HEAD: &[u8] = b"appservice.txn_log_head";
+/// The custom store key of the unbounded transaction ID list of older
+/// versions.
+#[cfg(feature = "appservice")]
+const APPSERVICE_TXN_ID_LEGACY: &[u8] = b"appservice.txn_id";
+
+/// The key of the cross-process lock on the refresh of the tokens.
+#[cfg(feature = "e2e-encryption")]
+const CROSS_PROCESS_REFRESH_LOCK_KEY: &str = "refresh_token_lock";
+
 /// Enum controlling if a loop running callbacks should continue or abort.
 ///
 /// This is mainly used in the [`sync_with_callback`] method, the return value
@@ -124,6 +152,31 @@ pub enum LoopCtrl {
     Break,
 }
 
+/// A change of the session of a [`Client`], see
+/// [`Client::subscribe_to_session_changes()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SessionChange {
+    /// The access token was refreshed.
+    ///
+    /// The new tokens should be persisted to be able to restore the session.
+    TokensRefreshed,
+    /// The homeserver invalidated the access token with a [soft logout].
+    ///
+    /// The session can be restored with
+    /// [`Client::restore_after_soft_logout()`], which keeps the device and
+    /// its encryption keys.
+    ///
+    /// [soft logout]: https://spec.matrix.org/v1.5/client-server-api/#soft-logout
+    SoftLoggedOut,
+    /// The homeserver doesn't know the access token and it couldn't be
+    /// refreshed.
+    ///
+    /// The session can't be used anymore, and the user needs to log in again
+    /// with a new device.
+    UnknownToken,
+}
+
 /// An async/await enabled Matrix client.
 ///
 /// All of the state is held in an `Arc` so the `Client` can be cloned freely.
@@ -133,16 +186,40 @@ pub struct Client {
     pub(crate) root_span: Span,
 }
 
+/// A weak reference to a [`Client`], for background tasks that shouldn't keep
+/// the client alive.
+#[derive(Clone)]
+pub(crate) struct WeakClient {
+    client: Weak<ClientInner>,
+    root_span: Span,
+}
+
+impl WeakClient {
+    /// Create a weak reference to the given client.
+    pub(crate) fn from_client(client: &Client) -> Self {
+        Self { client: Arc::downgrade(&client.inner), root_span: client.root_span.clone() }
+    }
+
+    /// Get the client back, if it wasn't dropped yet.
+    pub(crate) fn get(&self) -> Option<Client> {
+        let inner = self.client.upgrade()?;
+        Some(Client { inner, root_span: self.root_span.clone() })
+    }
+}
+
 pub(crate) struct ClientInner {
     /// The URL of the homeserver to connect to.
     homeserver: RwLock<Url>,
     /// The OIDC Provider that is trusted by the homeserver.
     authentication_issuer: Option<RwLock<Url>>,
+    /// The state of the authentication with the OIDC Provider.
+    #[cfg(feature = "experimental-oidc")]
+    pub(crate) oidc_data: crate::oidc::OidcData,
     /// The sliding sync proxy that is trusted by the homeserver.
     #[cfg(feature = "experimental-sliding-sync")]
     sliding_sync_proxy: Option<RwLock<Url>>,
     /// The underlying HTTP client.
-    http_client: HttpClient,
+    pub(crate) http_client: HttpClient,
     /// User session data.
     base_client: BaseClient,
     /// The Matrix versions the server supports (well-known ones only)
@@ -154,10 +231,26 @@ pub(crate) struct ClientInner {
     /// Lock making sure we're only doing one key claim request at a time.
     #[cfg(feature = "e2e-encryption")]
     pub(crate) key_claim_lock: Mutex<()>,
+    /// Lock making sure we're only resetting our cross signing identity once
+    /// at a time, holds whether a reset is still waiting for its keys to be
+    /// uploaded.
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) cross_signing_reset_lock: Mutex<bool>,
+    /// The lock on the crypto store shared with other processes, if enabled
+    /// with [`Encryption::enable_cross_process_store_lock`].
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) cross_process_store_lock: Mutex<Option<CryptoStoreLock>>,
+    /// The state of the server-side key backup, see
+    /// [`Backups::state`](crate::encryption::backups::Backups::state).
+    #[cfg(feature = "backups-v1")]
+    pub(crate) backup_state:
+        futures_signals::signal::Mutable<crate::encryption::backups::BackupState>,
     pub(crate) members_request_locks: DashMap<OwnedRoomId, Arc<Mutex<()>>>,
     /// Locks for requests on the encryption state of rooms.
     pub(crate) encryption_state_request_locks: DashMap<OwnedRoomId, Arc<Mutex<()>>>,
     pub(crate) typing_notice_times: DashMap<OwnedRoomId, Instant>,
+    /// The send queue of each room, see [`room::Joined::send_queue`].
+    pub(crate) send_queues: DashMap<OwnedRoomId, Arc<SendQueueData>>,
     /// Event handlers. See `add_event_handler`.
     pub(crate) event_handlers: EventHandlerStore,
     /// Notification handlers. See `register_notification_handler`.
@@ -166,6 +259,10 @@ pub(crate) struct ClientInner {
     /// This is low-level functionality. For an high-level API check the
     /// `matrix_sdk_appservice` crate.
     appservice_mode: bool,
+    /// Lock making sure only one transaction at a time is recorded in the
+    /// appservice transaction log, since recording it moves the log head.
+    #[cfg(feature = "appservice")]
+    appservice_transaction_log_lock: Mutex<()>,
     /// Whether the client should update its homeserver URL with the discovery
     /// information present in the login response.
     respect_login_well_known: bool,
@@ -174,6 +271,20 @@ pub(crate) struct ClientInner {
     handle_refresh_tokens: bool,
     /// Lock making sure we're only doing one token refresh at a time.
     refresh_token_lock: Mutex<Result<(), RefreshTokenError>>,
+    /// The hook to persist the session when the tokens change, see
+    /// [`ClientBuilder::session_persistence`].
+    session_persistence: Option<SessionPersistenceHook>,
+    /// Whether the session persistence hook failed to save the current
+    /// tokens, saving them is retried before the next request.
+    unpersisted_session_tokens: AtomicBool,
+    /// The lock on the crypto store making sure only one process refreshes the
+    /// tokens at a time, if enabled with
+    /// [`Client::enable_cross_process_refresh_lock`].
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) cross_process_refresh_lock: Mutex<Option<CryptoStoreLock>>,
+    /// The subscribers to the changes of the session, see
+    /// [`Client::subscribe_to_session_changes`].
+    pub(crate) session_change_subscribers: StdMutex<Vec<mpsc::UnboundedSender<SessionChange>>>,
     /// An event that can be listened on to wait for a successful sync. The
     /// event will only be fired if a sync loop is running. Can be used for
     /// synchronization, e.g. if we send out a request to create a room, we can
@@ -217,7 +328,7 @@ impl Client {
     /// # Arguments
     ///
     /// * `homeserver_url` - The new URL to use.
-    async fn set_homeserver(&self, homeserver_url: Url) {
+    pub(crate) async fn set_homeserver(&self, homeserver_url: Url) {
         let mut homeserver = self.inner.homeserver.write().await;
         *homeserver = homeserver_url;
     }
@@ -252,11 +363,15 @@ impl Client {
     /// Process a [transaction] received from the homeserver which has been
     /// converted into a sync response.
     ///
+    /// The IDs of the last processed transactions are remembered in the state
+    /// store, a transaction that was already processed is skipped. A
+    /// transaction is only remembered once it was processed successfully, so
+    /// the homeserver can retry it if an error is returned.
+    ///
     /// # Arguments
     ///
     /// * `transaction_id` - The id of the transaction, used to guard against
-    ///   the same transaction being sent twice. This guarding currently isn't
-    ///   implemented.
+    ///   the same transaction being sent twice.
     /// * `sync_response` - The sync response converted from a transaction
     ///   received from the homeserver.
     ///
@@ -267,28 +382,113 @@ impl Client {
         transaction_id: &TransactionId,
         sync_response: sync_events::v3::Response,
     ) -> Result<()> {
-        const TXN_ID_KEY: &[u8] = b"appservice.txn_id";
+        self.migrate_transaction_log(APPSERVICE_TXN_LOG_SIZE).await?;
+
+        if self.transaction_processed(transaction_id).await? {
+            // We already encountered this transaction id before, so we exit early instead
+            // of processing further.
+            //
+            // Spec: https://spec.matrix.org/v1.3/application-service-api/#pushing-events
+            return Ok(());
+        }
+
+        self.process_sync(sync_response).await?;
+        self.record_transaction(transaction_id, APPSERVICE_TXN_LOG_SIZE).await?;
+
+        // Upload our device keys or claim one-time keys if the transaction
+        // made this necessary, there's no sync loop that would do it for us.
+        #[cfg(feature = "e2e-encryption")]
+        if let Err(e) = self.send_outgoing_requests().await {
+            error!(error = ?e, "Error while sending outgoing E2EE requests");
+        }
+
+        Ok(())
+    }
+
+    /// Has the transaction with the given ID already been processed.
+    #[cfg(feature = "appservice")]
+    async fn transaction_processed(&self, transaction_id: &TransactionId) -> Result<bool> {
+        let key = [APPSERVICE_TXN_ID_PREFIX, transaction_id.as_bytes()].concat();
+        Ok(self.store().get_custom_value(&key).await?.is_some())
+    }
+
+    /// Import the transaction IDs recorded by older versions into the
+    /// transaction log.
+    ///
+    /// Older versions kept all the transaction IDs in a single unbounded list,
+    /// only the last `window_size` ones are kept.
+    #[cfg(feature = "appservice")]
+    async fn migrate_transaction_log(&self, window_size: u64) -> Result<()> {
+        let _guard = self.inner.appservice_transaction_log_lock.lock().await;
+        let store = self.store();
+
+        if store.get_custom_value(APPSERVICE_TXN_LOG_HEAD).await?.is_some() {
+            return Ok(());
+        }
+
+        let Some(legacy) = store.get_custom_value(APPSERVICE_TXN_ID_LEGACY).await? else {
+            return Ok(());
+        };
+
+        // The IDs are separated by a NULL byte, oldest first.
+        let transaction_ids: Vec<_> =
+            legacy.split(|b| *b == b'\0').filter(|id| !id.is_empty()).collect();
+        let window_size_usize = usize::try_from(window_size).unwrap_or(usize::MAX);
+        let start = transaction_ids.len().saturating_sub(window_size_usize);
 
+        let mut head: u64 = 0;
+        for &transaction_id in &transaction_ids[start..] {
+            let slot_key = [APPSERVICE_TXN_LOG_PREFIX, head.to_string().as_bytes()].concat();
+            store.set_custom_value(&slot_key, transaction_id.to_vec()).await?;
+
+            let key = [APPSERVICE_TXN_ID_PREFIX, transaction_id].concat();
+            store.set_custom_value(&key, Vec::new()).await?;
+
+            head += 1;
+        }
+
+        store.set_custom_value(APPSERVICE_TXN_LOG_HEAD, head.to_be_bytes().to_vec()).await?;
+        store.remove_custom_value(APPSERVICE_TXN_ID_LEGACY).await?;
+
+        Ok(())
+    }
+
+    /// Remember that the transaction with the given ID was processed.
+    ///
+    /// The transaction IDs are kept in a ring buffer of `window_size` slots,
+    /// the oldest transaction ID gets forgotten once the log is full.
+    #[cfg(feature = "appservice")]
+    async fn record_transaction(
+        &self,
+        transaction_id: &TransactionId,
+        window_size: u64,
+    ) -> Result<()> {
+        let _guard = self.inner.appservice_transaction_log_lock.lock().await;
         let store = self.store();
-        let store_tokens = store.get_custom_value(TXN_ID_KEY).await?;
-        let mut txn_id_bytes = transaction_id.as_bytes().to_vec();
-        if let Some(mut store_tokens) = store_tokens {
-            // The data is separated by a NULL byte.
-            let mut store_tokens_split = store_tokens.split(|x| *x == b'\0');
-            if store_tokens_split.any(|x| x == transaction_id.as_bytes()) {
-                // We already encountered this transaction id before, so we exit early instead
-                // of processing further.
-                //
-                // Spec: https://spec.matrix.org/v1.3/application-service-api/#pushing-events
-                return Ok(());
+
+        let head = match store.get_custom_value(APPSERVICE_TXN_LOG_HEAD).await? {
+            Some(bytes) => {
+                let bytes = bytes.try_into().map_err(|_| {
+                    matrix_sdk_base::StoreError::Backend(
+                        "invalid appservice transaction log head".into(),
+                    )
+                })?;
+                u64::from_be_bytes(bytes)
             }
-            store_tokens.push(b'\0');
-            store_tokens.append(&mut txn_id_bytes);
-            self.store().set_custom_value(TXN_ID_KEY, store_tokens).await?;
-        } else {
-            self.store().set_custom_value(TXN_ID_KEY, txn_id_bytes).await?;
+            None => 0,
+        };
+
+        let slot_key =
+            [APPSERVICE_TXN_LOG_PREFIX, (head % window_size).to_string().as_bytes()].concat();
+        if let Some(evicted) =
+            store.set_custom_value(&slot_key, transaction_id.as_bytes().to_vec()).await?
+        {
+            store.remove_custom_value(&[APPSERVICE_TXN_ID_PREFIX, &evicted].concat()).await?;
         }
-        self.process_sync(sync_response).await?;
+
+        let key = [APPSERVICE_TXN_ID_PREFIX, transaction_id.as_bytes()].concat();
+        store.set_custom_value(&key, Vec::new()).await?;
+        store.set_custom_value(APPSERVICE_TXN_LOG_HEAD, (head + 1).to_be_bytes().to_vec()).await?;
 
         Ok(())
     }
@@ -495,6 +695,100 @@ impl Client {
         self.base_client().session_tokens().signal_cloned()
     }
 
+    /// Subscribe to the changes of the session of this client.
+    ///
+    /// This allows to learn that the access token was refreshed, or that the
+    /// session was invalidated by the homeserver, without inspecting the
+    /// errors of every request. Several requests failing at the same time can
+    /// lead to the same change being emitted several times.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use futures::StreamExt;
+    /// use matrix_sdk::{Client, SessionChange};
+    /// # use matrix_sdk::Session;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # fn persist_session(_: Option<Session>) {};
+    /// # fn ask_password() -> String { String::new() };
+    ///
+    /// let homeserver = "http://example.com";
+    /// let client = Client::builder()
+    ///     .homeserver_url(homeserver)
+    ///     .handle_refresh_tokens()
+    ///     .build()
+    ///     .await?;
+    ///
+    /// let mut session_changes = client.subscribe_to_session_changes();
+    /// while let Some(change) = session_changes.next().await {
+    ///     match change {
+    ///         SessionChange::TokensRefreshed => persist_session(client.session()),
+    ///         SessionChange::SoftLoggedOut => {
+    ///             let login = client.login_username("user", &ask_password());
+    ///             client.restore_after_soft_logout(login).await?;
+    ///             persist_session(client.session());
+    ///         }
+    ///         _ => {
+    ///             // The user needs to log in again.
+    ///             break;
+    ///         }
+    ///     }
+    /// }
+    /// # anyhow::Ok(()) });
+    /// ```
+    pub fn subscribe_to_session_changes(&self) -> impl Stream<Item = SessionChange> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.inner.session_change_subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Enable the cross-process lock on the refresh of the tokens.
+    ///
+    /// This is needed if multiple processes, for example an app and its
+    /// notification extension, share the same session and crypto store. Once
+    /// enabled, only one process refreshes the tokens at a time, and the
+    /// tokens refreshed by another process are loaded with
+    /// [`SessionPersistence::load_session()`] instead of being refreshed
+    /// again, since the previous refresh token might not be valid anymore.
+    ///
+    /// The lock is stored in the crypto store, so this requires the
+    /// `e2e-encryption` feature and a logged in `Client`, otherwise
+    /// [`Error::NoOlmMachine`] is returned. The [session persistence hook]
+    /// must be a type implementing [`SessionPersistence::load_session()`]:
+    /// an async closure can't load the session, so the tokens refreshed by
+    /// another process would never be picked up.
+    ///
+    /// Does nothing if the lock has already been enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `lock_holder` - A unique id of the current process, for example
+    /// `"main-app"` or `"notification-extension"`.
+    ///
+    /// [session persistence hook]: ClientBuilder::session_persistence
+    #[cfg(feature = "e2e-encryption")]
+    pub async fn enable_cross_process_refresh_lock(&self, lock_holder: String) -> Result<()> {
+        let olm = self.olm_machine().ok_or(Error::NoOlmMachine)?;
+        let mut lock = self.inner.cross_process_refresh_lock.lock().await;
+
+        if lock.is_none() {
+            *lock =
+                Some(olm.create_store_lock(CROSS_PROCESS_REFRESH_LOCK_KEY.to_owned(), lock_holder));
+        }
+
+        Ok(())
+    }
+
+    fn broadcast_session_change(&self, change: SessionChange) {
+        debug!(?change, "The session changed");
+        self.inner
+            .session_change_subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.unbounded_send(change).is_ok());
+    }
+
     /// Get the whole session info of this client.
     ///
     /// Will be `None` if the client has not been logged in.
@@ -526,6 +820,12 @@ impl Client {
         Media::new(self.clone())
     }
 
+    /// Get the OpenID Connect authentication API of the client.
+    #[cfg(feature = "experimental-oidc")]
+    pub fn oidc(&self) -> crate::oidc::Oidc {
+        crate::oidc::Oidc::new(self.clone())
+    }
+
     /// Register a handler for a specific event type.
     ///
     /// The handler is a function or closure with one or more arguments. The
@@ -1209,6 +1509,7 @@ impl Client {
         }
 
         self.inner.base_client.receive_login_response(response).await?;
+        room::send_queue::resume_send_queues(self);
 
         Ok(())
     }
@@ -1290,11 +1591,73 @@ impl Client {
             self.root_span.record("ed25519_key", key);
         }
 
+        room::send_queue::resume_send_queues(self);
+
         debug!("Done restoring session");
 
         Ok(())
     }
 
+    /// Restore the session after a [soft logout], by logging in again.
+    ///
+    /// Contrary to a new login, this keeps the device ID of the session, so
+    /// the state and the encryption keys of the device are kept too. The
+    /// new tokens replace the ones of the session and should be persisted.
+    ///
+    /// The new session is saved with the [session persistence hook], if any.
+    /// The subscribers to the session changes receive a
+    /// [`SessionChange::TokensRefreshed`].
+    ///
+    /// # Arguments
+    ///
+    /// * `login` - The login request to send, created with one of the login
+    ///   methods of the `Client`. Its device ID is replaced with the one of the
+    ///   session.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use matrix_sdk::Client;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let client = Client::new("http://example.com".parse()?).await?;
+    ///
+    /// let login = client.login_username("example", "my-password");
+    /// client.restore_after_soft_logout(login).await?;
+    /// # anyhow::Ok(()) });
+    /// ```
+    ///
+    /// [soft logout]: https://spec.matrix.org/v1.5/client-server-api/#soft-logout
+    /// [session persistence hook]: ClientBuilder::session_persistence
+    #[instrument(skip_all, parent = &self.root_span)]
+    pub async fn restore_after_soft_logout(
+        &self,
+        login: LoginBuilder,
+    ) -> Result<login::v3::Response> {
+        let meta = self.session_meta().ok_or(Error::AuthenticationRequired)?;
+        let (user_id, device_id) = (meta.user_id.clone(), meta.device_id.clone());
+
+        debug!("Restoring the session after a soft logout");
+
+        let response = login.device_id(device_id.as_str()).send_request().await?;
+        if response.user_id != user_id || response.device_id != device_id {
+            return Err(Error::SessionMismatch);
+        }
+
+        let tokens = SessionTokens {
+            access_token: response.access_token.clone(),
+            refresh_token: response.refresh_token.clone(),
+        };
+        // The previous tokens are not valid anymore, so the new ones are used
+        // even if they couldn't be persisted.
+        let persisted = self.persist_session_tokens(&tokens).await;
+        self.base_client().set_session_tokens(tokens);
+        self.broadcast_session_change(SessionChange::TokensRefreshed);
+        persisted?;
+
+        Ok(response)
+    }
+
     /// Refresh the access token.
     ///
     /// When support for [refreshing access tokens] is activated on both the
@@ -1314,6 +1677,10 @@ impl Client {
     /// It can also be called at any time when a refresh token is available, it
     /// will invalidate the previous access token.
     ///
+    /// If the client was registered with an OIDC Provider with the `Oidc` API,
+    /// the access token is refreshed with the provider instead of the
+    /// homeserver.
+    ///
     /// The new tokens in the response will be used by the `Client` and should
     /// be persisted to be able to [restore the session]. The response will
     /// always contain an access token that replaces the previous one. It
@@ -1385,53 +1752,20 @@ impl Client {
         let lock = self.inner.refresh_token_lock.try_lock();
 
         if let Some(mut guard) = lock {
-            let Some(mut session_tokens) = self.session_tokens() else {
-                *guard = Err(RefreshTokenError::RefreshTokenRequired);
-                return Err(RefreshTokenError::RefreshTokenRequired.into());
+            let res = self.refresh_access_token_locked().await;
+
+            *guard = match &res {
+                Ok(_) => Ok(()),
+                Err(HttpError::RefreshToken(refresh_error)) => Err(refresh_error.clone()),
+                Err(error) => match error.as_ruma_api_error() {
+                    Some(RumaApiError::ClientApi(api_error)) => {
+                        Err(RefreshTokenError::ClientApi(api_error.to_owned()))
+                    }
+                    _ => Err(RefreshTokenError::UnableToRefreshToken),
+                },
             };
 
-            let refresh_token = session_tokens
-                .refresh_token
-                .clone()
-                .ok_or(RefreshTokenError::RefreshTokenRequired)?;
-            let request = refresh_token::v3::Request::new(refresh_token);
-
-            let res = self
-                .inner
-                .http_client
-                .send(
-                    request,
-                    None,
-                    self.homeserver().await.to_string(),
-                    self.access_token().as_deref(),
-                    self.user_id(),
-                    self.server_versions().await?,
-                )
-                .await;
-
-            match res {
-                Ok(res) => {
-                    *guard = Ok(());
-
-                    session_tokens.update_with_refresh_response(&res);
-
-                    self.base_client().set_session_tokens(session_tokens);
-
-                    // TODO: Let ffi client to know that tokens have changed
-
-                    Ok(Some(res))
-                }
-                Err(error) => {
-                    *guard = match error.as_ruma_api_error() {
-                        Some(RumaApiError::ClientApi(api_error)) => {
-                            Err(RefreshTokenError::ClientApi(api_error.to_owned()))
-                        }
-                        _ => Err(RefreshTokenError::UnableToRefreshToken),
-                    };
-
-                    Err(error)
-                }
-            }
+            res
         } else {
             match *self.inner.refresh_token_lock.lock().await {
                 Ok(_) => Ok(None),
@@ -1440,6 +1774,160 @@ impl Client {
         }
     }
 
+    /// Refresh the access token, while holding the refresh token lock.
+    async fn refresh_access_token_locked(&self) -> HttpResult<Option<refresh_token::v3::Response>> {
+        let Some(mut session_tokens) = self.session_tokens() else {
+            return Err(RefreshTokenError::RefreshTokenRequired.into());
+        };
+        let refresh_token =
+            session_tokens.refresh_token.clone().ok_or(RefreshTokenError::RefreshTokenRequired)?;
+
+        // Make sure no other process refreshes the tokens at the same time.
+        #[cfg(feature = "e2e-encryption")]
+        let _cross_process_guard = {
+            let lock = self.inner.cross_process_refresh_lock.lock().await.clone();
+            match lock {
+                Some(lock) => {
+                    // Don't wait for the other process longer than for a
+                    // request.
+                    let timeout = self.inner.http_client.request_config.timeout;
+                    let guard = lock
+                        .spin_lock(Some(timeout))
+                        .await
+                        .map_err(|error| RefreshTokenError::CrossProcessLock(Arc::new(error)))?;
+
+                    if self.reload_tokens_refreshed_elsewhere(&session_tokens).await? {
+                        return Ok(None);
+                    }
+
+                    Some(guard)
+                }
+                None => None,
+            }
+        };
+
+        // If the lease of the lock was lost while loading the tokens, another
+        // process might be refreshing them right now.
+        #[cfg(feature = "e2e-encryption")]
+        if let Some(guard) = &_cross_process_guard {
+            guard.check().map_err(|error| RefreshTokenError::CrossProcessLock(Arc::new(error)))?;
+        }
+
+        let res = self.send_refresh_token_request(refresh_token).await?;
+        session_tokens.update_with_refresh_response(&res);
+
+        // The new tokens are persisted before they are used. The previous
+        // refresh token might not be valid anymore, so they are used even if
+        // they couldn't be persisted, saving them is retried later.
+        let persisted = self.persist_session_tokens(&session_tokens).await;
+        self.base_client().set_session_tokens(session_tokens);
+        self.broadcast_session_change(SessionChange::TokensRefreshed);
+        persisted?;
+
+        Ok(Some(res))
+    }
+
+    /// Use the tokens persisted by another process, if it refreshed them since
+    /// they were loaded by this one.
+    ///
+    /// Returns `true` if the tokens were replaced.
+    #[cfg(feature = "e2e-encryption")]
+    async fn reload_tokens_refreshed_elsewhere(&self, tokens: &SessionTokens) -> HttpResult<bool> {
+        let Some(persistence) = &self.inner.session_persistence else {
+            return Ok(false);
+        };
+
+        // If our tokens couldn't be persisted, the persisted ones are older
+        // than ours and must not replace them.
+        if self.inner.unpersisted_session_tokens.load(Ordering::SeqCst) {
+            return Ok(false);
+        }
+
+        let Some(session) = persistence
+            .0
+            .load_session()
+            .await
+            .map_err(|error| RefreshTokenError::SessionPersistence(error.into()))?
+        else {
+            return Ok(false);
+        };
+
+        if self.user_id() != Some(&*session.user_id)
+            || self.device_id() != Some(&*session.device_id)
+            || session.access_token == tokens.access_token
+        {
+            return Ok(false);
+        }
+
+        debug!("The tokens were refreshed by another process");
+        let (_, tokens) = session.into_parts();
+        self.base_client().set_session_tokens(tokens);
+        self.broadcast_session_change(SessionChange::TokensRefreshed);
+
+        Ok(true)
+    }
+
+    /// Persist the session with the given tokens with the session persistence
+    /// hook, if any.
+    ///
+    /// If the hook fails, the tokens are marked as unpersisted, see
+    /// [`Client::retry_session_persistence()`].
+    async fn persist_session_tokens(&self, tokens: &SessionTokens) -> HttpResult<()> {
+        let (Some(persistence), Some(meta)) =
+            (&self.inner.session_persistence, self.session_meta())
+        else {
+            return Ok(());
+        };
+
+        let session = Session::from_parts(meta.clone(), tokens.clone());
+        let result = persistence.0.save_session(&session).await;
+        self.inner.unpersisted_session_tokens.store(result.is_err(), Ordering::SeqCst);
+
+        result.map_err(|error| RefreshTokenError::SessionPersistence(error.into()))?;
+        Ok(())
+    }
+
+    /// Try to persist the current tokens again, if the session persistence
+    /// hook failed to save them.
+    async fn retry_session_persistence(&self) {
+        if !self.inner.unpersisted_session_tokens.load(Ordering::SeqCst) {
+            return;
+        }
+        let Some(tokens) = self.session_tokens() else {
+            return;
+        };
+
+        debug!("Retrying to persist the session");
+        if let Err(error) = self.persist_session_tokens(&tokens).await {
+            warn!("Couldn't persist the session: {error}");
+        }
+    }
+
+    /// Send the request to refresh the access token, to the OIDC Provider if
+    /// the client is registered with one, or to the homeserver otherwise.
+    async fn send_refresh_token_request(
+        &self,
+        refresh_token: String,
+    ) -> HttpResult<refresh_token::v3::Response> {
+        #[cfg(feature = "experimental-oidc")]
+        if self.oidc().client_credentials().is_some() {
+            return self.oidc().refresh_access_token(&refresh_token).await;
+        }
+
+        let request = refresh_token::v3::Request::new(refresh_token);
+        self.inner
+            .http_client
+            .send(
+                request,
+                None,
+                self.homeserver().await.to_string(),
+                self.access_token().as_deref(),
+                self.user_id(),
+                self.server_versions().await?,
+            )
+            .await
+    }
+
     /// Register a user to the server.
     ///
     /// # Arguments
@@ -1764,31 +2252,7 @@ impl Client {
         Request: OutgoingRequest + Clone + Debug,
         HttpError: From<FromHttpResponseError<Request::EndpointError>>,
     {
-        let res = self.send_inner(request.clone(), config, None).await;
-
-        // If this is an `M_UNKNOWN_TOKEN` error and refresh token handling is active,
-        // try to refresh the token and retry the request.
-        if self.inner.handle_refresh_tokens {
-            if let Err(Some(ErrorKind::UnknownToken { .. })) =
-                res.as_ref().map_err(HttpError::client_api_error_kind)
-            {
-                if let Err(refresh_error) = self.refresh_access_token().await {
-                    match &refresh_error {
-                        HttpError::RefreshToken(RefreshTokenError::RefreshTokenRequired) => {
-                            // Refreshing access tokens is not supported by
-                            // this `Session`, ignore.
-                        }
-                        _ => {
-                            return Err(refresh_error);
-                        }
-                    }
-                } else {
-                    return self.send_inner(request, config, None).await;
-                }
-            }
-        }
-
-        res
+        self.send_handling_unknown_token(request, config, None).await
     }
 
     #[cfg(feature = "experimental-sliding-sync")]
@@ -1804,33 +2268,80 @@ impl Client {
         Request: OutgoingRequest + Clone + Debug,
         HttpError: From<FromHttpResponseError<Request::EndpointError>>,
     {
+        self.send_handling_unknown_token(request, config, homeserver).await
+    }
+
+    /// Send the request and handle an `M_UNKNOWN_TOKEN` error.
+    ///
+    /// If refresh token handling is active, the access token is refreshed and
+    /// the request is retried. If the session can't be used anymore, the
+    /// subscribers to the session changes are notified.
+    async fn send_handling_unknown_token<Request>(
+        &self,
+        request: Request,
+        config: Option<RequestConfig>,
+        homeserver: Option<String>,
+    ) -> HttpResult<Request::IncomingResponse>
+    where
+        Request: OutgoingRequest + Clone + Debug,
+        HttpError: From<FromHttpResponseError<Request::EndpointError>>,
+    {
+        self.retry_session_persistence().await;
+
         let res = self.send_inner(request.clone(), config, homeserver.clone()).await;
+        let Some(soft_logout) = unknown_token_soft_logout(&res) else {
+            return res;
+        };
 
-        // If this is an `M_UNKNOWN_TOKEN` error and refresh token handling is active,
-        // try to refresh the token and retry the request.
         if self.inner.handle_refresh_tokens {
-            if let Err(Some(ErrorKind::UnknownToken { .. })) =
-                res.as_ref().map_err(HttpError::client_api_error_kind)
-            {
-                if let Err(refresh_error) = self.refresh_access_token().await {
-                    match &refresh_error {
-                        HttpError::RefreshToken(RefreshTokenError::RefreshTokenRequired) => {
-                            // Refreshing access tokens is not supported by
-                            // this `Session`, ignore.
-                        }
-                        _ => {
-                            return Err(refresh_error);
-                        }
+            let previous_access_token = self.access_token();
+            let refreshed = match self.refresh_access_token().await {
+                Ok(_) => true,
+                Err(HttpError::RefreshToken(RefreshTokenError::SessionPersistence(error)))
+                    if self.access_token() != previous_access_token =>
+                {
+                    // The tokens were refreshed, they will be persisted again
+                    // before the next request.
+                    warn!("Couldn't persist the refreshed session: {error}");
+                    true
+                }
+                Err(HttpError::RefreshToken(RefreshTokenError::RefreshTokenRequired)) => {
+                    // Refreshing access tokens is not supported by this
+                    // `Session`, ignore.
+                    false
+                }
+                Err(refresh_error) => {
+                    // Only a refresh token that was refused means that the
+                    // session can't be used anymore, other errors might be
+                    // temporary.
+                    if let Some(soft_logout) = invalid_refresh_token_soft_logout(&refresh_error) {
+                        self.broadcast_unknown_token(soft_logout);
                     }
-                } else {
-                    return self.send_inner(request, config, homeserver).await;
+                    return Err(refresh_error);
                 }
+            };
+
+            if refreshed {
+                let res = self.send_inner(request, config, homeserver).await;
+                if let Some(soft_logout) = unknown_token_soft_logout(&res) {
+                    self.broadcast_unknown_token(soft_logout);
+                }
+                return res;
             }
         }
 
+        self.broadcast_unknown_token(soft_logout);
         res
     }
 
+    fn broadcast_unknown_token(&self, soft_logout: bool) {
+        self.broadcast_session_change(if soft_logout {
+            SessionChange::SoftLoggedOut
+        } else {
+            SessionChange::UnknownToken
+        });
+    }
+
     async fn send_inner<Request>(
         &self,
         request: Request,
@@ -2446,6 +2957,38 @@ impl Client {
     }
 }
 
+/// If the given error of a token refresh means that the refresh token was
+/// refused, get whether the session was invalidated with a soft logout.
+///
+/// That's the case for an `M_UNKNOWN_TOKEN` error, or an `invalid_grant` error
+/// from the OpenID Connect Provider. Other errors, like server errors, rate
+/// limiting or a failure to persist the new tokens, return `None`.
+fn invalid_refresh_token_soft_logout(error: &HttpError) -> Option<bool> {
+    if let Some(ErrorKind::UnknownToken { soft_logout }) = error.client_api_error_kind() {
+        return Some(*soft_logout);
+    }
+
+    #[cfg(feature = "experimental-oidc")]
+    if let HttpError::RefreshToken(RefreshTokenError::Oidc(error)) = error {
+        if let crate::oidc::OidcError::Provider { error, .. } = &**error {
+            if error == "invalid_grant" {
+                return Some(false);
+            }
+        }
+    }
+
+    None
+}
+
+/// If the given response is an `M_UNKNOWN_TOKEN` error, get whether the
+/// access token was invalidated with a soft logout.
+fn unknown_token_soft_logout<T>(res: &HttpResult<T>) -> Option<bool> {
+    match res.as_ref().map_err(HttpError::client_api_error_kind) {
+        Err(Some(ErrorKind::UnknownToken { soft_logout })) => Some(*soft_logout),
+        _ => None,
+    }
+}
+
 // The http mocking library is not supported for wasm32
 #[cfg(all(test, not(target_arch = "wasm32")))]
 pub(crate) mod tests {
@@ -2645,4 +3188,160 @@ pub(crate) mod tests {
         client.set_homeserver(homeserver.clone()).await;
         assert_eq!(client.homeserver().await, homeserver);
     }
+
+    #[cfg(feature = "appservice")]
+    #[async_test]
+    async fn bounded_transaction_log() {
+        use ruma::TransactionId;
+
+        let client = logged_in_client(None).await;
+        let first = TransactionId::new();
+        let second = TransactionId::new();
+        let third = TransactionId::new();
+
+        client.record_transaction(&first, 2).await.unwrap();
+        client.record_transaction(&second, 2).await.unwrap();
+        assert!(client.transaction_processed(&first).await.unwrap());
+        assert!(client.transaction_processed(&second).await.unwrap());
+        assert!(!client.transaction_processed(&third).await.unwrap());
+
+        client.record_transaction(&third, 2).await.unwrap();
+        assert!(!client.transaction_processed(&first).await.unwrap());
+        assert!(client.transaction_processed(&second).await.unwrap());
+        assert!(client.transaction_processed(&third).await.unwrap());
+    }
+
+    #[cfg(feature = "appservice")]
+    #[async_test]
+    async fn legacy_transaction_ids_are_migrated() {
+        use ruma::TransactionId;
+
+        let client = logged_in_client(None).await;
+        let [first, second, third, fourth]: [&TransactionId; 4] =
+            ["first".into(), "second".into(), "third".into(), "fourth".into()];
+
+        client
+            .store()
+            .set_custom_value(APPSERVICE_TXN_ID_LEGACY, b"first\0second\0third".to_vec())
+            .await
+            .unwrap();
+
+        client.migrate_transaction_log(2).await.unwrap();
+        assert!(client.store().get_custom_value(APPSERVICE_TXN_ID_LEGACY).await.unwrap().is_none());
+
+        // Only the most recent IDs are kept.
+        assert!(!client.transaction_processed(first).await.unwrap());
+        assert!(client.transaction_processed(second).await.unwrap());
+        assert!(client.transaction_processed(third).await.unwrap());
+
+        // New transactions continue the log after the imported IDs.
+        client.record_transaction(fourth, 2).await.unwrap();
+        assert!(!client.transaction_processed(second).await.unwrap());
+        assert!(client.transaction_processed(third).await.unwrap());
+        assert!(client.transaction_processed(fourth).await.unwrap());
+    }
+
+    #[cfg(feature = "e2e-encryption")]
+    #[async_test]
+    async fn cross_process_refresh_lock() {
+        use std::sync::{Arc, Mutex as StdMutex};
+
+        use matrix_sdk_base::{crypto::store::MemoryStore as MemoryCryptoStore, Session};
+        use ruma::{api::MatrixVersion, device_id, user_id};
+        use serde_json::json;
+        use wiremock::matchers::body_partial_json;
+
+        use crate::{config::StoreConfig, SessionPersistence, SessionPersistenceError};
+
+        /// A session storage shared by several processes.
+        #[derive(Clone, Default)]
+        struct Keychain(Arc<StdMutex<Option<Session>>>);
+
+        #[async_trait::async_trait]
+        impl SessionPersistence for Keychain {
+            async fn save_session(&self, session: &Session) -> Result<(), SessionPersistenceError> {
+                *self.0.lock().unwrap() = Some(session.clone());
+                Ok(())
+            }
+
+            async fn load_session(&self) -> Result<Option<Session>, SessionPersistenceError> {
+                Ok(self.0.lock().unwrap().clone())
+            }
+        }
+
+        let server = MockServer::start().await;
+        let crypto_store = Arc::new(MemoryCryptoStore::new());
+        let keychain = Keychain::default();
+        let session = Session {
+            access_token: "1234".to_owned(),
+            refresh_token: Some("abcd".to_owned()),
+            user_id: user_id!("@example:localhost").to_owned(),
+            device_id: device_id!("DEVICEID").to_owned(),
+        };
+        *keychain.0.lock().unwrap() = Some(session.clone());
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/v3/refresh"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(&*test_json::REFRESH_TOKEN_WITH_REFRESH_TOKEN),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .named("`POST /refresh`")
+            .mount(&server)
+            .await;
+
+        let mut clients = Vec::new();
+        for lock_holder in ["main-app", "notification-extension"] {
+            let client = test_client_builder(Some(server.uri()))
+                .request_config(RequestConfig::new().disable_retry())
+                .server_versions([MatrixVersion::V1_3])
+                .store_config(StoreConfig::new().crypto_store(crypto_store.clone()))
+                .session_persistence(keychain.clone())
+                .build()
+                .await
+                .unwrap();
+            client.restore_session(session.clone()).await.unwrap();
+            client.enable_cross_process_refresh_lock(lock_holder.to_owned()).await.unwrap();
+            clients.push(client);
+        }
+
+        // The first process refreshes the tokens with the homeserver.
+        assert!(clients[0].refresh_access_token().await.unwrap().is_some());
+        assert_eq!(clients[0].access_token().as_deref(), Some("9012"));
+        assert_eq!(keychain.0.lock().unwrap().as_ref().unwrap().access_token, "9012");
+
+        // The second process loads the tokens refreshed by the first one.
+        assert!(clients[1].refresh_access_token().await.unwrap().is_none());
+        assert_eq!(clients[1].access_token().as_deref(), Some("9012"));
+        assert_eq!(clients[1].refresh_token().as_deref(), Some("wxyz"));
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/v3/refresh"))
+            .and(body_partial_json(json!({ "refresh_token": "wxyz" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "3456",
+                "refresh_token": "mnop",
+            })))
+            .expect(1)
+            .named("second `POST /refresh`")
+            .mount(&server)
+            .await;
+
+        // When both processes refresh the tokens at the same time, only one of
+        // them does it with the homeserver and the other one waits for it.
+        let (first, second) = futures_util::future::join(
+            clients[0].refresh_access_token(),
+            clients[1].refresh_access_token(),
+        )
+        .await;
+        let refreshed = [first.unwrap(), second.unwrap()];
+        assert_eq!(refreshed.iter().filter(|response| response.is_some()).count(), 1);
+
+        for client in &clients {
+            assert_eq!(client.access_token().as_deref(), Some("3456"));
+            assert_eq!(client.refresh_token().as_deref(), Some("mnop"));
+        }
+    }
 }
@@ -0,0 +1,74 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fmt, future::Future, sync::Arc};
+
+use async_trait::async_trait;
+use matrix_sdk_base::{SendOutsideWasm, Session, SyncOutsideWasm};
+
+/// The error returned by a [`SessionPersistence`] hook.
+pub type SessionPersistenceError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A hook to persist the session of a [`Client`] when its tokens change, see
+/// [`ClientBuilder::session_persistence()`].
+///
+/// This is implemented for async closures taking the [`Session`] to save.
+///
+/// [`Client`]: crate::Client
+/// [`ClientBuilder::session_persistence()`]: crate::ClientBuilder::session_persistence
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait SessionPersistence: SendOutsideWasm + SyncOutsideWasm {
+    /// Save the given session.
+    ///
+    /// This is called before the new tokens of the session are used by the
+    /// `Client`. If it fails, the tokens are used anyway and this is called
+    /// again before the next request.
+    async fn save_session(&self, session: &Session) -> Result<(), SessionPersistenceError>;
+
+    /// Load the last saved session, if any.
+    ///
+    /// This is used to pick up the tokens refreshed by another process when
+    /// the [cross-process refresh lock] is enabled. The default
+    /// implementation, also used by async closures, doesn't load anything, so
+    /// it must be implemented for the lock to work.
+    ///
+    /// [cross-process refresh lock]: crate::Client::enable_cross_process_refresh_lock
+    async fn load_session(&self) -> Result<Option<Session>, SessionPersistenceError> {
+        Ok(None)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<F, Fut> SessionPersistence for F
+where
+    F: Fn(Session) -> Fut + SendOutsideWasm + SyncOutsideWasm,
+    Fut: Future<Output = Result<(), SessionPersistenceError>> + SendOutsideWasm,
+{
+    async fn save_session(&self, session: &Session) -> Result<(), SessionPersistenceError> {
+        self(session.clone()).await
+    }
+}
+
+/// A [`SessionPersistence`] hook shared by the builder and the `Client`.
+#[derive(Clone)]
+pub(crate) struct SessionPersistenceHook(pub(crate) Arc<dyn SessionPersistence>);
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for SessionPersistenceHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SessionPersistenceHook")
+    }
+}
@@ -0,0 +1,514 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Server-side backups of room keys.
+//!
+//! Room keys get encrypted with the public part of a backup key and uploaded
+//! to the homeserver, the private part, the [`RecoveryKey`], is needed to
+//! download and decrypt them again, for example on a new device.
+//!
+//! Get a [`Backups`] object with [`Encryption::backups()`].
+//!
+//! [`Encryption::backups()`]: crate::encryption::Encryption::backups
+
+use futures_core::Stream;
+use futures_signals::signal::SignalExt;
+pub use matrix_sdk_base::crypto::store::RecoveryKey;
+use matrix_sdk_base::crypto::{
+    backups::{DecodeError, OlmPkDecryptionError},
+    olm::{BackedUpRoomKey, ExportedRoomKey},
+    types::RoomKeyBackupInfo,
+    OlmMachine, RoomKeyImportResult,
+};
+use ruma::{
+    api::client::{
+        backup::{
+            create_backup_version, delete_backup_version, get_backup_keys, get_latest_backup_info,
+            EncryptedSessionData, KeyBackupData,
+        },
+        error::ErrorKind,
+    },
+    serde::Raw,
+};
+use serde_json::json;
+use thiserror::Error;
+use tracing::{debug, instrument, warn};
+
+use crate::{Client, Error as SdkError, Result};
+
+/// The name of the only backup algorithm we support.
+const MEGOLM_BACKUP_V1: &str = "m.megolm_backup.v1.curve25519-aes-sha2";
+
+/// Errors that can happen while managing a server-side key backup.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The homeserver doesn't have a key backup.
+    #[error("the homeserver doesn't have a key backup")]
+    NoBackup,
+
+    /// The key backup on the homeserver uses an algorithm we don't support.
+    #[error("the key backup uses an unsupported algorithm: {0}")]
+    UnsupportedAlgorithm(String),
+
+    /// The given recovery key doesn't belong to the key backup on the
+    /// homeserver.
+    #[error("the recovery key doesn't match the public key of the key backup")]
+    KeyMismatch,
+
+    /// The recovery key couldn't be decoded.
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+
+    /// A backed up room key couldn't be decrypted.
+    #[error(transparent)]
+    Decryption(#[from] OlmPkDecryptionError),
+
+    /// A new random recovery key couldn't be created.
+    #[error("couldn't create a random recovery key: {0}")]
+    RecoveryKeyCreation(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// The state of the server-side key backup of a [`Client`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackupState {
+    /// We don't know if there's a backup or we don't use it.
+    #[default]
+    Unknown,
+    /// A new backup is being created.
+    Creating,
+    /// A backup we used before is being enabled again.
+    Resuming,
+    /// Room keys are being uploaded to the backup as they are received.
+    Enabled,
+    /// Room keys are being downloaded from the backup.
+    Downloading,
+    /// The backup is being disabled and deleted from the homeserver.
+    Disabling,
+}
+
+/// The server-side key backup manager of a [`Client`].
+///
+/// While the backup is [enabled](BackupState::Enabled), room keys are
+/// uploaded as part of the sync loop.
+#[derive(Debug, Clone)]
+pub struct Backups {
+    client: Client,
+}
+
+impl Backups {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    fn olm_machine(&self) -> Result<&OlmMachine> {
+        self.client.olm_machine().ok_or(SdkError::NoOlmMachine)
+    }
+
+    /// The current state of the key backup.
+    pub fn state(&self) -> BackupState {
+        self.client.inner.backup_state.get()
+    }
+
+    /// Get a stream of updates to the state of the key backup.
+    ///
+    /// The stream starts with the current state.
+    pub fn state_stream(&self) -> impl Stream<Item = BackupState> {
+        self.client.inner.backup_state.signal().to_stream()
+    }
+
+    fn set_state(&self, state: BackupState) {
+        self.client.inner.backup_state.set(state);
+    }
+
+    /// Create a new key backup on the homeserver and start uploading our room
+    /// keys to it.
+    ///
+    /// Returns the recovery key of the new backup, it needs to be shown to the
+    /// user or stored somewhere safe, room keys can't be restored from the
+    /// backup without it.
+    #[instrument(skip(self))]
+    pub async fn enable(&self) -> Result<RecoveryKey> {
+        self.set_state(BackupState::Creating);
+
+        let result = self.create_backup().await;
+        self.set_state(if result.is_ok() { BackupState::Enabled } else { BackupState::Unknown });
+
+        result
+    }
+
+    async fn create_backup(&self) -> Result<RecoveryKey> {
+        let olm = self.olm_machine()?;
+
+        let recovery_key =
+            RecoveryKey::new().map_err(|e| Error::RecoveryKeyCreation(Box::new(e)))?;
+        let public_key = recovery_key.megolm_v1_public_key();
+
+        // The auth data only contains the public key, so its serialization is
+        // already canonical JSON.
+        let mut auth_data = json!({ "public_key": public_key.to_base64() });
+        let signatures = olm.sign(&auth_data.to_string()).await;
+        auth_data["signatures"] = serde_json::to_value(signatures)?;
+
+        let algorithm = json!({ "algorithm": MEGOLM_BACKUP_V1, "auth_data": auth_data });
+        let request = create_backup_version::v3::Request::new(Raw::new(&algorithm)?.cast());
+        let response = self.client.send(request, None).await?;
+
+        debug!(version = %response.version, "Created a new key backup");
+
+        self.activate(olm, &recovery_key, response.version).await?;
+
+        Ok(recovery_key)
+    }
+
+    /// Enable a backup we used before again, using the recovery key and
+    /// version we stored in the crypto store.
+    ///
+    /// Does nothing if we don't have a stored recovery key, or if the stored
+    /// version isn't the current backup on the homeserver anymore.
+    ///
+    /// Returns whether the backup has been enabled.
+    #[instrument(skip(self))]
+    pub async fn resume(&self) -> Result<bool> {
+        let olm = self.olm_machine()?;
+        let backup_keys = olm.backup_machine().get_backup_keys().await?;
+
+        let (Some(recovery_key), Some(version)) =
+            (backup_keys.recovery_key, backup_keys.backup_version)
+        else {
+            debug!("We don't have a stored recovery key, not resuming the backup");
+            return Ok(false);
+        };
+
+        self.set_state(BackupState::Resuming);
+
+        let result = self.resume_helper(olm, recovery_key, version).await;
+        self.set_state(if matches!(result, Ok(true)) {
+            BackupState::Enabled
+        } else {
+            BackupState::Unknown
+        });
+
+        result
+    }
+
+    async fn resume_helper(
+        &self,
+        olm: &OlmMachine,
+        recovery_key: RecoveryKey,
+        version: String,
+    ) -> Result<bool> {
+        let current_version = self.current_backup_version(&recovery_key).await?;
+
+        if current_version != version {
+            warn!(%version, %current_version, "Our key backup was replaced, not resuming it");
+            return Ok(false);
+        }
+
+        self.activate(olm, &recovery_key, version).await?;
+
+        Ok(true)
+    }
+
+    /// Download and import all the room keys of the current backup on the
+    /// homeserver, and start uploading our room keys to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `recovery_key` - The base58 encoded recovery key of the backup.
+    ///
+    /// * `progress_listener` - A closure that will be called with the number of
+    /// imported room keys and the total number of room keys, while the keys
+    /// get imported.
+    #[instrument(skip_all)]
+    pub async fn restore(
+        &self,
+        recovery_key: &str,
+        progress_listener: impl Fn(usize, usize),
+    ) -> Result<RoomKeyImportResult> {
+        let recovery_key = RecoveryKey::from_base58(recovery_key).map_err(Error::from)?;
+
+        self.set_state(BackupState::Downloading);
+
+        let result = self.download(&recovery_key, progress_listener).await;
+        self.set_state(if result.is_ok() { BackupState::Enabled } else { BackupState::Unknown });
+
+        result
+    }
+
+    async fn download(
+        &self,
+        recovery_key: &RecoveryKey,
+        progress_listener: impl Fn(usize, usize),
+    ) -> Result<RoomKeyImportResult> {
+        let olm = self.olm_machine()?;
+        let version = self.current_backup_version(recovery_key).await?;
+
+        let request = get_backup_keys::v3::Request::new(version.clone());
+        let response = self.client.send(request, None).await?;
+
+        let mut room_keys = Vec::new();
+
+        for (room_id, room_backup) in response.rooms {
+            for (session_id, key_backup_data) in room_backup.sessions {
+                let session_data = match decrypt_session_data(recovery_key, key_backup_data) {
+                    Ok(session_data) => session_data,
+                    Err(e) => {
+                        warn!(%room_id, %session_id, error = ?e, "Couldn't decrypt a room key");
+                        continue;
+                    }
+                };
+
+                room_keys.push(ExportedRoomKey {
+                    algorithm: session_data.algorithm,
+                    room_id: room_id.clone(),
+                    sender_key: session_data.sender_key,
+                    session_id,
+                    session_key: session_data.session_key,
+                    sender_claimed_keys: session_data.sender_claimed_keys,
+                    forwarding_curve25519_key_chain: session_data.forwarding_curve25519_key_chain,
+                });
+            }
+        }
+
+        let result = olm.import_room_keys(room_keys, true, progress_listener).await?;
+
+        debug!(
+            imported_count = result.imported_count,
+            total_count = result.total_count,
+            "Restored room keys from the key backup"
+        );
+
+        self.activate(olm, recovery_key, version).await?;
+
+        Ok(result)
+    }
+
+    /// Stop uploading room keys and delete the current backup from the
+    /// homeserver.
+    #[instrument(skip(self))]
+    pub async fn disable(&self) -> Result<()> {
+        let olm = self.olm_machine()?;
+
+        self.set_state(BackupState::Disabling);
+
+        let result = self.disable_helper(olm).await;
+        self.set_state(BackupState::Unknown);
+
+        result
+    }
+
+    async fn disable_helper(&self, olm: &OlmMachine) -> Result<()> {
+        let backup_machine = olm.backup_machine();
+        let backup_keys = backup_machine.get_backup_keys().await?;
+
+        // Delete the backup on the server first, so we keep the recovery key if
+        // that fails and disabling can be retried.
+        if let Some(version) = backup_keys.backup_version {
+            let request = delete_backup_version::v3::Request::new(version);
+            self.client.send(request, None).await?;
+        }
+
+        backup_machine.disable_backup().await?;
+        backup_machine.save_recovery_key(None, None).await?;
+
+        Ok(())
+    }
+
+    /// Get the version of the current backup on the homeserver, making sure
+    /// that it belongs to the given recovery key.
+    async fn current_backup_version(&self, recovery_key: &RecoveryKey) -> Result<String> {
+        let request = get_latest_backup_info::v3::Request::new();
+        let response = match self.client.send(request, None).await {
+            Ok(r) => r,
+            Err(e) if e.client_api_error_kind() == Some(&ErrorKind::NotFound) => {
+                return Err(Error::NoBackup.into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let auth_data = match response.algorithm.deserialize_as()? {
+            RoomKeyBackupInfo::MegolmBackupV1Curve25519AesSha2(auth_data) => auth_data,
+            RoomKeyBackupInfo::Other { algorithm, .. } => {
+                return Err(Error::UnsupportedAlgorithm(algorithm).into());
+            }
+        };
+
+        if auth_data.public_key.to_base64() != recovery_key.megolm_v1_public_key().to_base64() {
+            return Err(Error::KeyMismatch.into());
+        }
+
+        Ok(response.version)
+    }
+
+    /// Start uploading room keys to the backup with the given version, and
+    /// remember its recovery key so the backup can be resumed later.
+    async fn activate(
+        &self,
+        olm: &OlmMachine,
+        recovery_key: &RecoveryKey,
+        version: String,
+    ) -> Result<()> {
+        let backup_machine = olm.backup_machine();
+
+        let backup_key = recovery_key.megolm_v1_public_key();
+        backup_key.set_version(version.clone());
+
+        backup_machine.enable_backup_v1(backup_key).await?;
+        backup_machine
+            .save_recovery_key(
+                Some(RecoveryKey::from_bytes(recovery_key.as_bytes())),
+                Some(version),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Upload a batch of room keys to the backup, if it's enabled.
+    pub(crate) async fn upload_room_keys(&self) -> Result<()> {
+        let olm = self.olm_machine()?;
+
+        if let Some(request) = olm.backup_machine().backup().await? {
+            self.client.send_outgoing_request(request).await?;
+        }
+
+        Ok(())
+    }
+}
+
+fn decrypt_session_data(
+    recovery_key: &RecoveryKey,
+    key_backup_data: Raw<KeyBackupData>,
+) -> Result<BackedUpRoomKey> {
+    let key_backup_data = key_backup_data.deserialize()?;
+    let EncryptedSessionData { ephemeral, ciphertext, mac, .. } =
+        key_backup_data.session_data.deserialize()?;
+
+    let plaintext = recovery_key
+        .decrypt_v1(mac.encode(), ephemeral.encode(), ciphertext.encode())
+        .map_err(Error::from)?;
+
+    Ok(serde_json::from_str(&plaintext)?)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use matrix_sdk_test::async_test;
+    use serde_json::{json, Value};
+    use wiremock::{
+        matchers::{method, path_regex},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{BackupState, Error, RecoveryKey};
+    use crate::{test_utils::logged_in_client, Error as SdkError};
+
+    #[async_test]
+    async fn test_enable_creates_a_signed_backup() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/.*/room_keys/version"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "version": "1" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let backups = client.encryption().backups();
+        assert_eq!(backups.state(), BackupState::Unknown);
+
+        let recovery_key = backups.enable().await.unwrap();
+        assert_eq!(backups.state(), BackupState::Enabled);
+
+        let request: Value =
+            server.received_requests().await.unwrap().pop().unwrap().body_json().unwrap();
+
+        assert_eq!(request["algorithm"], "m.megolm_backup.v1.curve25519-aes-sha2");
+        assert_eq!(
+            request["auth_data"]["public_key"],
+            recovery_key.megolm_v1_public_key().to_base64()
+        );
+        assert!(request["auth_data"]["signatures"].is_object());
+
+        let backup_keys =
+            client.olm_machine().unwrap().backup_machine().get_backup_keys().await.unwrap();
+        assert_eq!(backup_keys.backup_version.as_deref(), Some("1"));
+        assert_eq!(backup_keys.recovery_key.unwrap().to_base64(), recovery_key.to_base64());
+    }
+
+    #[async_test]
+    async fn test_disable_keeps_the_recovery_key_if_the_deletion_fails() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/.*/room_keys/version"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "version": "1" })))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path_regex(r"^/_matrix/client/.*/room_keys/version/1"))
+            .respond_with(ResponseTemplate::new(500).set_body_json(json!({
+                "errcode": "M_UNKNOWN",
+                "error": "Internal server error",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let backups = client.encryption().backups();
+        let recovery_key = backups.enable().await.unwrap();
+
+        backups.disable().await.unwrap_err();
+
+        // The backup still exists on the server, so we must still be able to
+        // upload keys to it and to disable it again.
+        let backup_keys =
+            client.olm_machine().unwrap().backup_machine().get_backup_keys().await.unwrap();
+        assert_eq!(backup_keys.backup_version.as_deref(), Some("1"));
+        assert_eq!(backup_keys.recovery_key.unwrap().to_base64(), recovery_key.to_base64());
+    }
+
+    #[async_test]
+    async fn test_restore_with_the_wrong_recovery_key() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        let backup_key = RecoveryKey::new().unwrap().megolm_v1_public_key();
+
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/_matrix/client/.*/room_keys/version"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "algorithm": "m.megolm_backup.v1.curve25519-aes-sha2",
+                "auth_data": {
+                    "public_key": backup_key.to_base64(),
+                    "signatures": {},
+                },
+                "count": 0,
+                "etag": "0",
+                "version": "1",
+            })))
+            .mount(&server)
+            .await;
+
+        let backups = client.encryption().backups();
+        let recovery_key = RecoveryKey::new().unwrap().to_base58();
+
+        let error = backups.restore(&recovery_key, |_, _| {}).await.unwrap_err();
+
+        assert!(matches!(error, SdkError::Backup(Error::KeyMismatch)));
+        assert_eq!(backups.state(), BackupState::Unknown);
+    }
+}
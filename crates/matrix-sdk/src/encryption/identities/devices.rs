@@ -12,13 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::ops::Deref;
+use std::{collections::BTreeMap, ops::Deref};
 
 use matrix_sdk_base::crypto::{
-    store::CryptoStoreError, Device as BaseDevice, LocalTrust, ReadOnlyDevice,
+    store::CryptoStoreError, Device as BaseDevice, LocalTrust, OlmError, ReadOnlyDevice,
     UserDevices as BaseUserDevices,
 };
 use ruma::{events::key::verification::VerificationMethod, DeviceId};
+use serde_json::Value;
+use tracing::warn;
 
 use super::ManualVerifyError;
 use crate::{
@@ -386,6 +388,72 @@ impl Device {
         self.inner.is_verified()
     }
 
+    /// Is this device signed by the self-signing key of its owner.
+    ///
+    /// This doesn't take into account whether we trust the owner, for our own
+    /// devices this tells us if they are signed by our current cross signing
+    /// identity.
+    pub fn is_cross_signed_by_owner(&self) -> bool {
+        self.inner.is_cross_signed_by_owner()
+    }
+
+    /// Encrypt a custom to-device event for this device and send it out.
+    ///
+    /// The event is encrypted using Olm, if we don't share an Olm session with
+    /// the device yet, a new one will be established first.
+    ///
+    /// The receiving side will pass the decrypted event to the event handlers
+    /// registered using [`Client::add_event_handler()`], together with the
+    /// [`EncryptionInfo`] describing who sent the event.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the event, e.g. `org.example.call.invite`.
+    ///
+    /// * `content` - The content of the event.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, ruma::{device_id, user_id}};
+    /// # use serde_json::json;
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let alice = user_id!("@alice:example.org");
+    /// # let homeserver = Url::parse("http://example.com")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let device =
+    ///     client.encryption().get_device(alice, device_id!("DEVICEID")).await?;
+    ///
+    /// if let Some(device) = device {
+    ///     device
+    ///         .encrypt_and_send_custom_to_device(
+    ///             "org.example.ping",
+    ///             json!({ "body": "Ping!" }),
+    ///         )
+    ///         .await?;
+    /// }
+    /// # anyhow::Ok(()) });
+    /// ```
+    ///
+    /// [`EncryptionInfo`]: crate::deserialized_responses::EncryptionInfo
+    pub async fn encrypt_and_send_custom_to_device(
+        &self,
+        event_type: &str,
+        content: Value,
+    ) -> Result<()> {
+        self.client.claim_one_time_keys(std::iter::once(self.user_id())).await?;
+
+        let encrypted = self.inner.encrypt_event_raw(event_type, &content).await?;
+        let messages = BTreeMap::from([(
+            self.user_id().to_owned(),
+            BTreeMap::from([(self.device_id().to_owned().into(), encrypted.cast())]),
+        )]);
+
+        self.client.send_encrypted_to_device(messages).await
+    }
+
     /// Set the local trust state of the device to the given state.
     ///
     /// This won't affect any cross signing verification state, this only sets
@@ -423,4 +491,53 @@ impl UserDevices {
 
         self.inner.devices().map(move |d| Device { inner: d, client: client.clone() })
     }
+
+    /// Encrypt a custom to-device event for all the devices of the user and
+    /// send it out.
+    ///
+    /// This works like [`Device::encrypt_and_send_custom_to_device()`], our
+    /// own device is skipped, as are devices that we couldn't establish an
+    /// Olm session with, e.g. because they ran out of one-time keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the event, e.g. `org.example.call.invite`.
+    ///
+    /// * `content` - The content of the event.
+    pub async fn encrypt_and_send_custom_to_device(
+        &self,
+        event_type: &str,
+        content: Value,
+    ) -> Result<()> {
+        let Some(user_id) = self.inner.devices().next().map(|d| d.user_id().to_owned()) else {
+            return Ok(());
+        };
+
+        self.client.claim_one_time_keys(std::iter::once(user_id.as_ref())).await?;
+
+        let mut user_messages = BTreeMap::new();
+
+        for device in self.inner.devices().filter(|d| !d.is_our_own_device()) {
+            match device.encrypt_event_raw(event_type, &content).await {
+                Ok(encrypted) => {
+                    user_messages.insert(device.device_id().to_owned().into(), encrypted.cast());
+                }
+                Err(OlmError::MissingSession) => {
+                    warn!(
+                        user_id = ?device.user_id(),
+                        device_id = ?device.device_id(),
+                        "Not sending a custom to-device event to a device we couldn't \
+                         establish an Olm session with",
+                    );
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if user_messages.is_empty() {
+            return Ok(());
+        }
+
+        self.client.send_encrypted_to_device(BTreeMap::from([(user_id, user_messages)])).await
+    }
 }
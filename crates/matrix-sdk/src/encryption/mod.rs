@@ -16,13 +16,18 @@
 #![doc = include_str!("../docs/encryption.md")]
 #![cfg_attr(target_arch = "wasm32", allow(unused_imports))]
 
+#[cfg(feature = "backups-v1")]
+pub mod backups;
 pub mod identities;
+#[cfg(feature = "experimental-qr-login")]
+pub mod qr_login;
 pub mod verification;
 use std::{
     collections::{BTreeMap, HashSet},
     io::{Read, Write},
     iter,
     path::PathBuf,
+    time::Duration,
 };
 
 use futures_util::stream::{self, StreamExt};
@@ -31,6 +36,10 @@ pub use matrix_sdk_base::crypto::{
         SessionCreationError as MegolmSessionCreationError,
         SessionExportError as OlmSessionExportError,
     },
+    store::{
+        CryptoStoreLock, CryptoStoreLockGuard, LockStoreError, RoomKeyCounts, RoomKeyInfo,
+        RoomKeyRetention,
+    },
     vodozemac, CryptoStoreError, DecryptorError, EventError, KeyExportError, LocalTrust,
     MediaEncryptionInfo, MegolmError, OlmError, RoomKeyImportResult, SecretImportError,
     SessionCreationError, SignatureError, VERSION,
@@ -52,7 +61,11 @@ use ruma::{
         },
         uiaa::AuthData,
     },
-    assign, DeviceId, OwnedUserId, TransactionId, UserId,
+    assign,
+    events::{AnyToDeviceEventContent, ToDeviceEventType},
+    serde::Raw,
+    to_device::DeviceIdOrAllDevices,
+    DeviceId, OwnedUserId, RoomId, TransactionId, UserId,
 };
 use tracing::{debug, instrument, trace, warn};
 
@@ -346,6 +359,27 @@ impl Client {
         self.send(request, None).await
     }
 
+    /// Send out to-device events that have been encrypted for each of the
+    /// recipient devices using Olm.
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) async fn send_encrypted_to_device(
+        &self,
+        messages: BTreeMap<
+            OwnedUserId,
+            BTreeMap<DeviceIdOrAllDevices, Raw<AnyToDeviceEventContent>>,
+        >,
+    ) -> Result<()> {
+        let request = ToDeviceRequest {
+            event_type: ToDeviceEventType::RoomEncrypted,
+            txn_id: TransactionId::new(),
+            messages,
+        };
+
+        self.send_to_device(&request).await?;
+
+        Ok(())
+    }
+
     #[cfg(feature = "e2e-encryption")]
     pub(crate) async fn send_verification_request(
         &self,
@@ -449,6 +483,11 @@ impl Client {
             })
             .await;
 
+        #[cfg(feature = "backups-v1")]
+        if let Err(e) = self.encryption().backups().upload_room_keys().await {
+            warn!(error = ?e, "Error when uploading room keys to the key backup");
+        }
+
         Ok(())
     }
 }
@@ -465,10 +504,19 @@ pub struct Encryption {
 
 #[cfg(feature = "e2e-encryption")]
 impl Encryption {
+    /// The key of the cross-process lock on the crypto store.
+    const CROSS_PROCESS_LOCK_KEY: &'static str = "cross_process_lock";
+
     pub(crate) fn new(client: Client) -> Self {
         Self { client }
     }
 
+    /// Get the manager of the server-side key backup.
+    #[cfg(feature = "backups-v1")]
+    pub fn backups(&self) -> backups::Backups {
+        backups::Backups::new(self.client.clone())
+    }
+
     /// Get the public ed25519 key of our own device. This is usually what is
     /// called the fingerprint of the device.
     pub async fn ed25519_key(&self) -> Option<String> {
@@ -711,6 +759,247 @@ impl Encryption {
         Ok(())
     }
 
+    /// Replace our cross signing identity with a freshly created one.
+    ///
+    /// This is useful if the private cross signing keys have been lost, or if
+    /// they might have been compromised.
+    ///
+    /// **Warning**: This throws away the trust that has been established
+    /// using the old identity, our other devices and the users that have
+    /// verified us will need to be verified again.
+    ///
+    /// A new set of cross signing keys is created and uploaded, afterwards
+    /// our own device gets signed with the new self-signing key. If there's
+    /// a server-side key backup which our device trusts, its `auth_data` will
+    /// be signed with the new master key as well.
+    ///
+    /// Use [`Encryption::own_devices_not_cross_signed()`] to find the devices
+    /// that need to be verified again, or deleted, once the reset is done.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth_data` - Uploading the new keys requires user interactive auth,
+    /// the first request needs to set this to `None` and will fail with an
+    /// `UiaaResponse`. The new keys are kept around if the upload of the keys
+    /// or of the signature of our device fails, the next call will retry the
+    /// upload of the same keys, as the homeserver expects, instead of creating
+    /// yet another identity.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use matrix_sdk::{ruma::api::client::uiaa, Client};
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://example.com")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// if let Err(e) = client.encryption().reset_cross_signing(None).await {
+    ///     if let Some(response) = e.as_uiaa_response() {
+    ///         let mut password = uiaa::Password::new(
+    ///             uiaa::UserIdentifier::UserIdOrLocalpart("example".to_owned()),
+    ///             "wordpass".to_owned(),
+    ///         );
+    ///         password.session = response.session.clone();
+    ///
+    ///         client
+    ///             .encryption()
+    ///             .reset_cross_signing(Some(uiaa::AuthData::Password(password)))
+    ///             .await?;
+    ///     } else {
+    ///         return Err(e.into());
+    ///     }
+    /// }
+    ///
+    /// for device in client.encryption().own_devices_not_cross_signed().await? {
+    ///     println!("Device {} needs to be verified again", device.device_id());
+    /// }
+    /// # anyhow::Ok(()) });
+    /// ```
+    pub async fn reset_cross_signing(&self, auth_data: Option<AuthData>) -> Result<()> {
+        let olm = self.client.olm_machine().ok_or(Error::AuthenticationRequired)?;
+        let mut reset_pending = self.client.inner.cross_signing_reset_lock.lock().await;
+
+        // If a previous reset failed to upload its keys, most likely because
+        // user interactive auth was required, we need to upload the very same
+        // keys again.
+        let (request, signature_request) = olm.bootstrap_cross_signing(!*reset_pending).await?;
+        *reset_pending = true;
+
+        let request = assign!(UploadSigningKeysRequest::new(), {
+            auth: auth_data,
+            master_key: request.master_key.map(|c| c.to_raw()),
+            self_signing_key: request.self_signing_key.map(|c| c.to_raw()),
+            user_signing_key: request.user_signing_key.map(|c| c.to_raw()),
+        });
+
+        let response = self.client.send(request, None).await?;
+        self.client.mark_request_as_sent(&TransactionId::new(), &response).await?;
+
+        // Our device isn't signed by the new identity until this succeeds, a
+        // retry needs to upload the same keys and signatures again.
+        self.client.send(signature_request, None).await?;
+        *reset_pending = false;
+
+        // Fetch our devices again, so their signatures are checked against the
+        // new identity.
+        let device_keys = BTreeMap::from([(olm.user_id().to_owned(), Vec::new())]);
+        self.client.keys_query(&TransactionId::new(), device_keys).await?;
+
+        self.resign_backup(olm).await
+    }
+
+    /// Sign the current server-side key backup with our new cross signing
+    /// identity.
+    async fn resign_backup(&self, olm: &matrix_sdk_base::crypto::OlmMachine) -> Result<()> {
+        use ruma::api::client::{
+            backup::{get_latest_backup_info, update_backup_version},
+            error::ErrorKind,
+        };
+
+        let request = get_latest_backup_info::v3::Request::new();
+        let response = match self.client.send(request, None).await {
+            Ok(r) => r,
+            Err(e) if e.client_api_error_kind() == Some(&ErrorKind::NotFound) => {
+                debug!("There's no server-side key backup, not signing it");
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut algorithm: serde_json::Value = response.algorithm.deserialize_as()?;
+        let Some(auth_data) = algorithm.get_mut("auth_data").map(serde_json::Value::take) else {
+            warn!(
+                version = %response.version,
+                "The server-side key backup is missing its auth data"
+            );
+            return Ok(());
+        };
+
+        match olm.resign_backup_auth_data(auth_data).await {
+            Ok(Some(auth_data)) => {
+                algorithm["auth_data"] = auth_data;
+
+                let request = update_backup_version::v3::Request::new(
+                    response.version,
+                    Raw::new(&algorithm)?.cast(),
+                );
+                self.client.send(request, None).await?;
+            }
+            Ok(None) => {
+                debug!(
+                    version = %response.version,
+                    "The server-side key backup isn't signed by our device, not signing it"
+                );
+            }
+            Err(e) => {
+                warn!(
+                    version = %response.version,
+                    error = ?e,
+                    "Couldn't sign the server-side key backup with the new identity"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get our own devices that aren't signed by our current cross signing
+    /// identity.
+    ///
+    /// After our cross signing identity has been reset using
+    /// [`Encryption::reset_cross_signing()`], these devices should either be
+    /// verified again or be removed using [`Client::delete_devices()`].
+    ///
+    /// This will always return an error if the client hasn't been logged in.
+    pub async fn own_devices_not_cross_signed(&self) -> Result<Vec<Device>> {
+        let user_id = self.client.user_id().ok_or(Error::AuthenticationRequired)?;
+        let devices = self.get_user_devices(user_id).await?;
+
+        Ok(devices.devices().filter(|d| !d.is_cross_signed_by_owner()).collect())
+    }
+
+    /// Start signing in this client by presenting a QR code to a device that is
+    /// already signed in, as defined in [MSC3906].
+    ///
+    /// This creates a new session on the given rendezvous server, the returned
+    /// [`LoginWithQrCode`] holds the data that should be presented as a QR
+    /// code.
+    ///
+    /// # Arguments
+    ///
+    /// * `rendezvous_server` - The URL of a [MSC3886] rendezvous server.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://example.com")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// let rendezvous_server = Url::parse("https://rendezvous.example.org")?;
+    /// let login =
+    ///     client.encryption().login_with_qr_code(&rendezvous_server).await?;
+    ///
+    /// // Render the QR code and present it to the user.
+    /// let qr_code = login.qr_code_data().to_qr_code()?;
+    ///
+    /// let login = login.wait_for_scan().await?;
+    /// println!("Check that the other device displays {}", login.check_code());
+    ///
+    /// login.finish(Some("My new device")).await?;
+    /// # anyhow::Ok(()) });
+    /// ```
+    ///
+    /// [MSC3906]: https://github.com/matrix-org/matrix-spec-proposals/pull/3906
+    /// [MSC3886]: https://github.com/matrix-org/matrix-spec-proposals/pull/3886
+    #[cfg(feature = "experimental-qr-login")]
+    pub async fn login_with_qr_code(
+        &self,
+        rendezvous_server: &url::Url,
+    ) -> Result<qr_login::LoginWithQrCode, qr_login::QrLoginError> {
+        qr_login::LoginWithQrCode::new(self.client.clone(), rendezvous_server).await
+    }
+
+    /// Sign in a new device that presented a login QR code, as defined in
+    /// [MSC3906].
+    ///
+    /// This sets up a secure channel with the new device, the login will only
+    /// be granted once [`GrantLoginWithQrCode::grant()`] is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data that was decoded from the scanned QR code.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, encryption::qr_login::QrLoginData};
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://example.com")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// # let scanned_bytes: Vec<u8> = unimplemented!();
+    /// let data = QrLoginData::from_bytes(scanned_bytes)?;
+    /// let grant = client.encryption().grant_login_with_qr_code(&data).await?;
+    ///
+    /// println!("Check that the new device displays {}", grant.check_code());
+    /// let device_id = grant.grant().await?;
+    /// # anyhow::Ok(()) });
+    /// ```
+    ///
+    /// [MSC3906]: https://github.com/matrix-org/matrix-spec-proposals/pull/3906
+    /// [`GrantLoginWithQrCode::grant()`]: qr_login::GrantLoginWithQrCode::grant
+    #[cfg(feature = "experimental-qr-login")]
+    pub async fn grant_login_with_qr_code(
+        &self,
+        data: &qr_login::QrLoginData,
+    ) -> Result<qr_login::GrantLoginWithQrCode, qr_login::QrLoginError> {
+        qr_login::GrantLoginWithQrCode::new(self.client.clone(), data).await
+    }
+
     /// Export E2EE keys that match the given predicate encrypting them with the
     /// given passphrase.
     ///
@@ -845,16 +1134,131 @@ impl Encryption {
 
         Ok(olm.import_room_keys(import, false, |_, _| {}).await?)
     }
+
+    /// Get the number of room keys we have stored and how many of them are
+    /// backed up.
+    pub async fn room_key_counts(&self) -> Result<RoomKeyCounts> {
+        let olm = self.client.olm_machine().ok_or(Error::AuthenticationRequired)?;
+        Ok(olm.room_key_counts().await?)
+    }
+
+    /// Get metadata about the room keys we have stored for the given room.
+    pub async fn room_key_infos(&self, room_id: &RoomId) -> Result<Vec<RoomKeyInfo>> {
+        let olm = self.client.olm_machine().ok_or(Error::AuthenticationRequired)?;
+        Ok(olm.room_key_infos(room_id).await?)
+    }
+
+    /// Remove room keys we don't want to keep anymore from the store.
+    ///
+    /// Returns the number of room keys that were removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `retention` - Describes which room keys should be removed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use matrix_sdk::{Client, encryption::RoomKeyRetention};
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// // Forget room keys older than 90 days, as long as they are backed up.
+    /// let max_age = Duration::from_secs(60 * 60 * 24 * 90);
+    /// let removed = client
+    ///     .encryption()
+    ///     .prune_room_keys(RoomKeyRetention::BackedUpOlderThan(max_age))
+    ///     .await?;
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn prune_room_keys(&self, retention: RoomKeyRetention) -> Result<usize> {
+        let olm = self.client.olm_machine().ok_or(Error::AuthenticationRequired)?;
+        Ok(olm.prune_room_keys(&retention).await?)
+    }
+
+    /// Remove all the room keys of the rooms we have left from the store.
+    ///
+    /// Returns the number of room keys that were removed.
+    pub async fn prune_room_keys_of_left_rooms(&self) -> Result<usize> {
+        let rooms = self.client.left_rooms().iter().map(|r| r.room_id().to_owned()).collect();
+        self.prune_room_keys(RoomKeyRetention::Rooms(rooms)).await
+    }
+
+    /// Enable the cross-process lock on the crypto store.
+    ///
+    /// This is needed if multiple processes, for example an app and its
+    /// notification extension, share the same crypto store. Once enabled, the
+    /// client takes the lock while it's decrypting the events of a sync
+    /// response or encrypting events. The in-memory caches of the client are
+    /// reloaded whenever another process modified the store in the meantime.
+    ///
+    /// Does nothing if the lock has already been enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `lock_holder` - A unique id of the current process, for example
+    /// `"main-app"` or `"notification-extension"`.
+    pub async fn enable_cross_process_store_lock(&self, lock_holder: String) -> Result<()> {
+        let olm = self.client.olm_machine().ok_or(Error::NoOlmMachine)?;
+        let mut lock = self.client.inner.cross_process_store_lock.lock().await;
+
+        if lock.is_none() {
+            *lock =
+                Some(olm.create_store_lock(Self::CROSS_PROCESS_LOCK_KEY.to_owned(), lock_holder));
+        }
+
+        Ok(())
+    }
+
+    /// Take the cross-process lock on the crypto store, waiting for other
+    /// processes to release it if needed.
+    ///
+    /// If another process modified the crypto store since we last held the
+    /// lock, the in-memory caches are reloaded before this returns.
+    ///
+    /// Returns `None` if the lock hasn't been enabled with
+    /// [`Encryption::enable_cross_process_store_lock`].
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - For how long to wait for the lock, see
+    /// [`CryptoStoreLock::spin_lock`].
+    pub async fn spin_lock_store(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<Option<CryptoStoreLockGuard>> {
+        let Some(lock) = self.client.inner.cross_process_store_lock.lock().await.clone() else {
+            return Ok(None);
+        };
+
+        let guard = lock.spin_lock(timeout).await?;
+        self.maintain_store_generation().await?;
+
+        Ok(Some(guard))
+    }
+
+    async fn maintain_store_generation(&self) -> Result<()> {
+        let olm = self.client.olm_machine().ok_or(Error::NoOlmMachine)?;
+
+        if olm.maintain_crypto_store_generation().await? {
+            debug!("The crypto store was modified by another process, reloaded the caches");
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use matrix_sdk_test::{async_test, test_json, EventBuilder, JoinedRoomBuilder, StateTestEvent};
     use ruma::{
+        api::client::uiaa::{AuthData, Dummy},
         event_id,
         events::{reaction::ReactionEventContent, relation::Annotation},
     };
-    use serde_json::json;
+    use serde_json::{json, Value};
     use wiremock::{
         matchers::{header, method, path_regex},
         Mock, MockServer, ResponseTemplate,
@@ -910,4 +1314,151 @@ mod tests {
             .await
             .expect("Sending the reaction should not fail");
     }
+
+    #[async_test]
+    async fn test_reset_cross_signing_retries_with_the_same_keys() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/.*/keys/device_signing/upload"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "flows": [{ "stages": ["m.login.dummy"] }],
+                "params": {},
+                "session": "xxxxxx",
+            })))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/.*/keys/device_signing/upload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/.*/keys/signatures/upload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "failures": {} })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/.*/keys/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/_matrix/client/.*/room_keys/version"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+                "errcode": "M_NOT_FOUND",
+                "error": "No current backup version",
+            })))
+            .mount(&server)
+            .await;
+
+        let olm = client.olm_machine().unwrap();
+        olm.bootstrap_cross_signing(false).await.unwrap();
+        let old_keys = olm.export_cross_signing_keys().await.unwrap();
+
+        let error = client.encryption().reset_cross_signing(None).await.unwrap_err();
+        let uiaa = error.as_uiaa_response().expect("The upload should require UIAA");
+
+        let mut dummy = Dummy::new();
+        dummy.session = uiaa.session.clone();
+        client.encryption().reset_cross_signing(Some(AuthData::Dummy(dummy))).await.unwrap();
+
+        let uploads: Vec<Value> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|r| r.url.path().ends_with("/keys/device_signing/upload"))
+            .map(|r| r.body_json().unwrap())
+            .collect();
+
+        assert_eq!(uploads.len(), 2);
+        assert_eq!(uploads[0]["master_key"], uploads[1]["master_key"]);
+        assert_eq!(uploads[1]["auth"]["session"], "xxxxxx");
+
+        let new_keys = olm.export_cross_signing_keys().await.unwrap();
+        assert_ne!(old_keys.master_key, new_keys.master_key);
+    }
+
+    #[async_test]
+    async fn test_reset_cross_signing_retries_after_a_failed_signature_upload() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/.*/keys/device_signing/upload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/.*/keys/signatures/upload"))
+            .respond_with(ResponseTemplate::new(500).set_body_json(json!({
+                "errcode": "M_UNKNOWN",
+                "error": "Internal server error",
+            })))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/.*/keys/signatures/upload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "failures": {} })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/.*/keys/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/_matrix/client/.*/room_keys/version"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+                "errcode": "M_NOT_FOUND",
+                "error": "No current backup version",
+            })))
+            .mount(&server)
+            .await;
+
+        let olm = client.olm_machine().unwrap();
+        olm.bootstrap_cross_signing(false).await.unwrap();
+
+        client
+            .encryption()
+            .reset_cross_signing(None)
+            .await
+            .expect_err("The signature upload should fail");
+        let pending_keys = olm.export_cross_signing_keys().await.unwrap();
+
+        client.encryption().reset_cross_signing(None).await.unwrap();
+
+        let uploads: Vec<Value> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|r| r.url.path().ends_with("/keys/device_signing/upload"))
+            .map(|r| r.body_json().unwrap())
+            .collect();
+
+        assert_eq!(uploads.len(), 2);
+        assert_eq!(uploads[0]["master_key"], uploads[1]["master_key"]);
+
+        let new_keys = olm.export_cross_signing_keys().await.unwrap();
+        assert_eq!(pending_keys.master_key, new_keys.master_key);
+    }
 }
@@ -0,0 +1,122 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+
+use matrix_sdk_base::crypto::CrossSigningKeyExport;
+use ruma::OwnedDeviceId;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The login protocol from [MSC3906], the existing device hands a login token
+/// it obtained using [MSC3882] over to the new device.
+///
+/// [MSC3906]: https://github.com/matrix-org/matrix-spec-proposals/pull/3906
+/// [MSC3882]: https://github.com/matrix-org/matrix-spec-proposals/pull/3882
+pub(crate) const LOGIN_TOKEN_PROTOCOL: &str = "org.matrix.msc3906.login_token";
+
+/// The messages the two devices exchange over the secure channel.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub(crate) enum QrLoginMessage {
+    /// The existing device offers a list of login protocols and tells the new
+    /// device which homeserver it should use.
+    #[serde(rename = "m.login.protocols")]
+    Protocols { protocols: Vec<String>, homeserver: Url },
+
+    /// The new device picked one of the offered protocols.
+    #[serde(rename = "m.login.protocol_accepted")]
+    ProtocolAccepted { protocol: String },
+
+    /// The existing device hands over a login token.
+    #[serde(rename = "m.login.token")]
+    LoginToken { login_token: String },
+
+    /// The new device has logged in and uploaded its device keys.
+    #[serde(rename = "m.login.success")]
+    Success { device_id: OwnedDeviceId, device_key: String },
+
+    /// The existing device has signed the device keys of the new device.
+    #[serde(rename = "m.login.verified")]
+    Verified {
+        verifying_device_id: OwnedDeviceId,
+        verifying_device_key: String,
+        master_key: String,
+    },
+
+    /// The existing device shares the private cross-signing keys.
+    #[serde(rename = "m.login.secrets")]
+    Secrets(CrossSigningSecrets),
+
+    /// One of the devices aborted the login.
+    #[serde(rename = "m.login.declined")]
+    Declined,
+}
+
+impl QrLoginMessage {
+    /// The type of the message, used to report unexpected messages.
+    pub(crate) fn message_type(&self) -> &'static str {
+        match self {
+            Self::Protocols { .. } => "m.login.protocols",
+            Self::ProtocolAccepted { .. } => "m.login.protocol_accepted",
+            Self::LoginToken { .. } => "m.login.token",
+            Self::Success { .. } => "m.login.success",
+            Self::Verified { .. } => "m.login.verified",
+            Self::Secrets(_) => "m.login.secrets",
+            Self::Declined => "m.login.declined",
+        }
+    }
+}
+
+/// The private cross-signing keys, encoded as unpadded base64.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct CrossSigningSecrets {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    master_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    self_signing_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_signing_key: Option<String>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for CrossSigningSecrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CrossSigningSecrets")
+            .field("master_key", &self.master_key.is_some())
+            .field("self_signing_key", &self.self_signing_key.is_some())
+            .field("user_signing_key", &self.user_signing_key.is_some())
+            .finish()
+    }
+}
+
+impl From<CrossSigningKeyExport> for CrossSigningSecrets {
+    fn from(export: CrossSigningKeyExport) -> Self {
+        Self {
+            master_key: export.master_key.clone(),
+            self_signing_key: export.self_signing_key.clone(),
+            user_signing_key: export.user_signing_key.clone(),
+        }
+    }
+}
+
+impl From<CrossSigningSecrets> for CrossSigningKeyExport {
+    fn from(secrets: CrossSigningSecrets) -> Self {
+        Self {
+            master_key: secrets.master_key,
+            self_signing_key: secrets.self_signing_key,
+            user_signing_key: secrets.user_signing_key,
+        }
+    }
+}
@@ -0,0 +1,705 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sign in a new device by scanning a QR code, as defined in [MSC3906].
+//!
+//! The new device creates a rendezvous session and presents a QR code using
+//! [`Encryption::login_with_qr_code()`]. An existing, signed in, device scans
+//! the QR code and passes the data to
+//! [`Encryption::grant_login_with_qr_code()`].
+//!
+//! The two devices then set up a secure channel over the rendezvous session
+//! and display a [`CheckCode`] the user should compare. Once the user confirms
+//! that the codes match, the existing device hands a login token to the new
+//! device, cross-signs it and sends it the private cross-signing keys.
+//!
+//! [MSC3906]: https://github.com/matrix-org/matrix-spec-proposals/pull/3906
+//! [`Encryption::login_with_qr_code()`]: crate::encryption::Encryption::login_with_qr_code
+//! [`Encryption::grant_login_with_qr_code()`]: crate::encryption::Encryption::grant_login_with_qr_code
+
+use std::collections::BTreeMap;
+
+pub use matrix_sdk_base::crypto::matrix_sdk_qrcode::{
+    QrLoginData, QrLoginIntent, RendezvousDetails, RendezvousTransport,
+};
+use matrix_sdk_base::crypto::{CryptoStoreError, SecretImportError};
+use ruma::{api::client::session::get_login_token, OwnedDeviceId, TransactionId};
+use thiserror::Error;
+use tracing::{debug, info};
+use url::Url;
+
+pub use self::secure_channel::CheckCode;
+use self::{
+    messages::{QrLoginMessage, LOGIN_TOKEN_PROTOCOL},
+    secure_channel::{EstablishedSecureChannel, SecureChannel},
+};
+use super::identities::ManualVerifyError;
+use crate::{Client, Error, HttpError};
+
+mod messages;
+mod rendezvous;
+mod secure_channel;
+
+/// Errors that can happen while talking to the rendezvous server.
+#[derive(Debug, Error)]
+pub enum RendezvousError {
+    /// The HTTP request to the rendezvous server failed.
+    #[error(transparent)]
+    Http(#[from] HttpError),
+
+    /// The rendezvous server responded with an unexpected status code.
+    #[error("the rendezvous server responded with an unexpected status code: {0}")]
+    UnexpectedStatus(http::StatusCode),
+
+    /// The rendezvous server didn't include a required header in its
+    /// response.
+    #[error("the rendezvous server response is missing the {0} header")]
+    MissingHeader(&'static str),
+
+    /// The rendezvous server returned an invalid session URL.
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+
+    /// The rendezvous session doesn't exist anymore, it either expired or the
+    /// other side cancelled it.
+    #[error("the rendezvous session has expired or was cancelled")]
+    SessionNotFound,
+
+    /// The other side didn't send a message in time.
+    #[error("timed out waiting for a message from the other device")]
+    Timeout,
+}
+
+/// Errors that can happen while establishing or using the secure channel.
+#[derive(Debug, Error)]
+pub enum SecureChannelError {
+    /// The rendezvous session failed.
+    #[error(transparent)]
+    Rendezvous(#[from] RendezvousError),
+
+    /// A message couldn't be serialized or deserialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// The other device sent an invalid ephemeral key.
+    #[error(transparent)]
+    Key(#[from] vodozemac::KeyError),
+
+    /// A message couldn't be decrypted, the other side likely derived a
+    /// different key.
+    #[error("a message couldn't be decrypted using the secure channel key")]
+    Decryption,
+
+    /// The other device didn't send the expected message to set up the secure
+    /// channel.
+    #[error("the secure channel couldn't be established, unexpected initiation message")]
+    InvalidInitiateMessage,
+}
+
+/// Errors that can happen while logging in or granting a login with a QR code.
+#[derive(Debug, Error)]
+pub enum QrLoginError {
+    /// The secure channel failed.
+    #[error(transparent)]
+    SecureChannel(#[from] SecureChannelError),
+
+    /// An ordinary error coming from the SDK, i.e. when we fail to send out a
+    /// HTTP request or if there's an error with the storage layer.
+    #[error(transparent)]
+    Sdk(#[from] Error),
+
+    /// The other device declined the login.
+    #[error("the other device declined the login")]
+    Declined,
+
+    /// The devices don't support a common login protocol.
+    #[error("the other device doesn't support any of our login protocols")]
+    UnsupportedProtocol,
+
+    /// The other device sent a message we didn't expect at this point of the
+    /// login.
+    #[error("received an unexpected message from the other device: {0}")]
+    UnexpectedMessage(&'static str),
+
+    /// The signed in device doesn't have the private cross-signing keys it
+    /// needs to sign the new device.
+    #[error("the private cross-signing keys of the signed in device are missing")]
+    MissingCrossSigningKeys,
+
+    /// The new device couldn't be found on the homeserver or its keys don't
+    /// match the keys it sent over the secure channel.
+    #[error("the device keys of the new device {0} couldn't be verified")]
+    DeviceMismatch(OwnedDeviceId),
+
+    /// The master key the signed in device sent doesn't match the master key
+    /// the homeserver has.
+    #[error("the master key of the signed in device doesn't match the one on the homeserver")]
+    MasterKeyMismatch,
+
+    /// Signing the new device or our own identity failed.
+    #[error(transparent)]
+    Verification(#[from] ManualVerifyError),
+
+    /// The private cross-signing keys couldn't be imported.
+    #[error(transparent)]
+    SecretImport(#[from] SecretImportError),
+}
+
+impl From<HttpError> for QrLoginError {
+    fn from(e: HttpError) -> Self {
+        Self::Sdk(e.into())
+    }
+}
+
+impl From<CryptoStoreError> for QrLoginError {
+    fn from(e: CryptoStoreError) -> Self {
+        Self::Sdk(e.into())
+    }
+}
+
+fn unexpected(message: QrLoginMessage) -> QrLoginError {
+    QrLoginError::UnexpectedMessage(message.message_type())
+}
+
+/// A new device that waits for a signed in device to scan its QR code.
+///
+/// Created using [`Encryption::login_with_qr_code()`].
+///
+/// [`Encryption::login_with_qr_code()`]: crate::encryption::Encryption::login_with_qr_code
+#[derive(Debug)]
+pub struct LoginWithQrCode {
+    client: Client,
+    channel: SecureChannel,
+}
+
+impl LoginWithQrCode {
+    pub(crate) async fn new(client: Client, rendezvous_server: &Url) -> Result<Self, QrLoginError> {
+        let http = client.inner.http_client.inner.clone();
+        let channel = SecureChannel::new(http, rendezvous_server).await?;
+
+        Ok(Self { client, channel })
+    }
+
+    /// The data that should be encoded into a QR code and presented to the
+    /// signed in device.
+    ///
+    /// Use [`QrLoginData::to_qr_code()`] to render it.
+    pub fn qr_code_data(&self) -> QrLoginData {
+        self.channel.qr_code_data()
+    }
+
+    /// Wait for the signed in device to scan the QR code and set up the secure
+    /// channel.
+    pub async fn wait_for_scan(self) -> Result<ScannedLoginWithQrCode, QrLoginError> {
+        let channel = self.channel.connect().await?;
+        debug!(check_code = %channel.check_code(), "The QR code has been scanned");
+
+        Ok(ScannedLoginWithQrCode { client: self.client, channel })
+    }
+}
+
+/// A new device whose QR code was scanned by a signed in device.
+///
+/// The [`CheckCode`] should be displayed, the login should only be finished
+/// if the user confirms that the other device displays the same code.
+#[derive(Debug)]
+pub struct ScannedLoginWithQrCode {
+    client: Client,
+    channel: EstablishedSecureChannel,
+}
+
+impl ScannedLoginWithQrCode {
+    /// The check code that should be compared with the one the signed in
+    /// device displays.
+    pub fn check_code(&self) -> CheckCode {
+        self.channel.check_code()
+    }
+
+    /// Log in using the login token the signed in device hands over, wait for
+    /// it to cross-sign us and import the private cross-signing keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_display_name` - The display name the new device should get.
+    pub async fn finish(self, device_display_name: Option<&str>) -> Result<(), QrLoginError> {
+        let Self { client, mut channel } = self;
+
+        let homeserver = match channel.receive_json().await? {
+            QrLoginMessage::Protocols { protocols, homeserver } => {
+                if !protocols.iter().any(|p| p == LOGIN_TOKEN_PROTOCOL) {
+                    channel.send_json(&QrLoginMessage::Declined).await?;
+                    return Err(QrLoginError::UnsupportedProtocol);
+                }
+
+                homeserver
+            }
+            QrLoginMessage::Declined => return Err(QrLoginError::Declined),
+            message => return Err(unexpected(message)),
+        };
+
+        client.set_homeserver(homeserver).await;
+        channel
+            .send_json(&QrLoginMessage::ProtocolAccepted {
+                protocol: LOGIN_TOKEN_PROTOCOL.to_owned(),
+            })
+            .await?;
+
+        let login_token = match channel.receive_json().await? {
+            QrLoginMessage::LoginToken { login_token } => login_token,
+            QrLoginMessage::Declined => return Err(QrLoginError::Declined),
+            message => return Err(unexpected(message)),
+        };
+
+        let mut login = client.login_token(&login_token);
+
+        if let Some(name) = device_display_name {
+            login = login.initial_device_display_name(name);
+        }
+
+        let response = login.send().await?;
+        info!(device_id = %response.device_id, "Logged in using the QR code login token");
+
+        // Upload our device keys, the signed in device needs them to sign us.
+        client.send_outgoing_requests().await?;
+
+        let olm = client.olm_machine().ok_or(Error::NoOlmMachine)?;
+        let user_id = olm.user_id().to_owned();
+
+        channel
+            .send_json(&QrLoginMessage::Success {
+                device_id: olm.device_id().to_owned(),
+                device_key: olm.identity_keys().ed25519.to_base64(),
+            })
+            .await?;
+
+        let master_key = match channel.receive_json().await? {
+            QrLoginMessage::Verified { verifying_device_id, master_key, .. } => {
+                debug!(%verifying_device_id, "The signed in device has verified us");
+                master_key
+            }
+            QrLoginMessage::Declined => return Err(QrLoginError::Declined),
+            message => return Err(unexpected(message)),
+        };
+
+        let secrets = match channel.receive_json().await? {
+            QrLoginMessage::Secrets(secrets) => secrets,
+            QrLoginMessage::Declined => return Err(QrLoginError::Declined),
+            message => return Err(unexpected(message)),
+        };
+
+        channel.close().await?;
+
+        // Fetch our own identity, it should contain the master key the other
+        // device told us about.
+        client
+            .keys_query(&TransactionId::new(), BTreeMap::from([(user_id.clone(), Vec::new())]))
+            .await?;
+
+        let identity = client
+            .encryption()
+            .get_user_identity(&user_id)
+            .await?
+            .ok_or(QrLoginError::MasterKeyMismatch)?;
+
+        if identity.master_key().get_first_key().map(|k| k.to_base64()) != Some(master_key) {
+            return Err(QrLoginError::MasterKeyMismatch);
+        }
+
+        olm.import_cross_signing_keys(secrets.into()).await?;
+        identity.verify().await?;
+
+        Ok(())
+    }
+}
+
+/// A signed in device that scanned the QR code of a new device.
+///
+/// Created using [`Encryption::grant_login_with_qr_code()`].
+///
+/// [`Encryption::grant_login_with_qr_code()`]: crate::encryption::Encryption::grant_login_with_qr_code
+#[derive(Debug)]
+pub struct GrantLoginWithQrCode {
+    client: Client,
+    channel: EstablishedSecureChannel,
+}
+
+impl GrantLoginWithQrCode {
+    pub(crate) async fn new(client: Client, data: &QrLoginData) -> Result<Self, QrLoginError> {
+        if data.intent != QrLoginIntent::LoginStart {
+            return Err(QrLoginError::UnsupportedProtocol);
+        }
+
+        let olm = client.olm_machine().ok_or(Error::AuthenticationRequired)?;
+
+        if !olm.cross_signing_status().await.has_self_signing {
+            return Err(QrLoginError::MissingCrossSigningKeys);
+        }
+
+        let http = client.inner.http_client.inner.clone();
+        let channel = EstablishedSecureChannel::from_qr_code(http, data).await?;
+
+        Ok(Self { client, channel })
+    }
+
+    /// The check code that should be compared with the one the new device
+    /// displays.
+    pub fn check_code(&self) -> CheckCode {
+        self.channel.check_code()
+    }
+
+    /// Let the new device know that the user didn't confirm the login.
+    pub async fn decline(mut self) -> Result<(), QrLoginError> {
+        Ok(self.channel.send_json(&QrLoginMessage::Declined).await?)
+    }
+
+    /// Hand a login token over to the new device, cross-sign it once it logged
+    /// in and share the private cross-signing keys with it.
+    ///
+    /// Returns the device ID of the new device.
+    pub async fn grant(self) -> Result<OwnedDeviceId, QrLoginError> {
+        let Self { client, mut channel } = self;
+
+        let homeserver = client.homeserver().await;
+        channel
+            .send_json(&QrLoginMessage::Protocols {
+                protocols: vec![LOGIN_TOKEN_PROTOCOL.to_owned()],
+                homeserver,
+            })
+            .await?;
+
+        match channel.receive_json().await? {
+            QrLoginMessage::ProtocolAccepted { protocol } if protocol == LOGIN_TOKEN_PROTOCOL => {}
+            QrLoginMessage::ProtocolAccepted { .. } => {
+                return Err(QrLoginError::UnsupportedProtocol)
+            }
+            QrLoginMessage::Declined => return Err(QrLoginError::Declined),
+            message => return Err(unexpected(message)),
+        }
+
+        let response = client.send(get_login_token::v1::Request::new(), None).await?;
+        channel
+            .send_json(&QrLoginMessage::LoginToken { login_token: response.login_token })
+            .await?;
+
+        let (device_id, device_key) = match channel.receive_json().await? {
+            QrLoginMessage::Success { device_id, device_key } => (device_id, device_key),
+            QrLoginMessage::Declined => return Err(QrLoginError::Declined),
+            message => return Err(unexpected(message)),
+        };
+
+        let olm = client.olm_machine().ok_or(Error::NoOlmMachine)?;
+        let user_id = olm.user_id().to_owned();
+
+        // The new device uploaded its keys before it told us about its device
+        // ID, a single key query should find it.
+        client
+            .keys_query(
+                &TransactionId::new(),
+                BTreeMap::from([(user_id.clone(), vec![device_id.clone()])]),
+            )
+            .await?;
+
+        let device = client
+            .encryption()
+            .get_device(&user_id, &device_id)
+            .await?
+            .filter(|d| d.ed25519_key().map(|k| k.to_base64()).as_ref() == Some(&device_key));
+
+        let Some(device) = device else {
+            channel.send_json(&QrLoginMessage::Declined).await?;
+            return Err(QrLoginError::DeviceMismatch(device_id));
+        };
+
+        device.verify().await?;
+
+        let master_key = client
+            .encryption()
+            .get_user_identity(&user_id)
+            .await?
+            .and_then(|i| i.master_key().get_first_key())
+            .ok_or(QrLoginError::MissingCrossSigningKeys)?;
+
+        channel
+            .send_json(&QrLoginMessage::Verified {
+                verifying_device_id: olm.device_id().to_owned(),
+                verifying_device_key: olm.identity_keys().ed25519.to_base64(),
+                master_key: master_key.to_base64(),
+            })
+            .await?;
+
+        let secrets =
+            olm.export_cross_signing_keys().await.ok_or(QrLoginError::MissingCrossSigningKeys)?;
+        channel.send_json(&QrLoginMessage::Secrets(secrets.into())).await?;
+
+        info!(%device_id, "Signed in a new device using a QR code");
+
+        Ok(device_id)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use assert_matches::assert_matches;
+    use matrix_sdk_base::crypto::{OlmMachine, OutgoingRequests};
+    use matrix_sdk_test::{async_test, test_json};
+    use ruma::{device_id, user_id};
+    use serde_json::json;
+    use url::Url;
+    use wiremock::{
+        matchers::{method, path, path_regex},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{
+        messages::{QrLoginMessage, LOGIN_TOKEN_PROTOCOL},
+        secure_channel::{tests::MockRendezvous, EstablishedSecureChannel, SecureChannel},
+        QrLoginError,
+    };
+    use crate::test_utils::{logged_in_client, no_retry_test_client};
+
+    async fn scan(
+        client: &crate::Client,
+        rendezvous_server: &Url,
+    ) -> (super::ScannedLoginWithQrCode, EstablishedSecureChannel) {
+        let login = client.encryption().login_with_qr_code(rendezvous_server).await.unwrap();
+        let qr_code_data = login.qr_code_data();
+        let http = std::sync::Arc::new(reqwest::Client::new());
+
+        let (new_device, existing_device) = tokio::join!(
+            login.wait_for_scan(),
+            EstablishedSecureChannel::from_qr_code(http, &qr_code_data)
+        );
+
+        (new_device.unwrap(), existing_device.unwrap())
+    }
+
+    #[async_test]
+    async fn unsupported_protocol() {
+        let server = MockServer::start().await;
+        let rendezvous_server = MockRendezvous::mount(&server).await;
+        let client = no_retry_test_client(Some(server.uri())).await;
+
+        let (new_device, mut existing_device) = scan(&client, &rendezvous_server).await;
+        assert_eq!(new_device.check_code(), existing_device.check_code());
+
+        let homeserver = Url::parse(&server.uri()).unwrap();
+        existing_device
+            .send_json(&QrLoginMessage::Protocols {
+                protocols: vec!["m.unknown".to_owned()],
+                homeserver,
+            })
+            .await
+            .unwrap();
+
+        let (result, message) =
+            tokio::join!(new_device.finish(None), existing_device.receive_json::<QrLoginMessage>());
+
+        assert_matches!(result, Err(QrLoginError::UnsupportedProtocol));
+        assert_matches!(message, Ok(QrLoginMessage::Declined));
+    }
+
+    #[async_test]
+    async fn login_with_token() {
+        let server = MockServer::start().await;
+        let rendezvous_server = MockRendezvous::mount(&server).await;
+        let client = no_retry_test_client(Some(server.uri())).await;
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/r0/login"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "abc123",
+                "device_id": "NEWDEVICE",
+                "user_id": "@example:localhost",
+            })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/r0/keys/upload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::KEYS_UPLOAD))
+            .mount(&server)
+            .await;
+
+        let (new_device, mut existing_device) = scan(&client, &rendezvous_server).await;
+
+        let existing_device = async move {
+            let homeserver = Url::parse(&server.uri()).unwrap();
+            existing_device
+                .send_json(&QrLoginMessage::Protocols {
+                    protocols: vec![LOGIN_TOKEN_PROTOCOL.to_owned()],
+                    homeserver,
+                })
+                .await
+                .unwrap();
+
+            assert_matches!(
+                existing_device.receive_json().await,
+                Ok(QrLoginMessage::ProtocolAccepted { protocol }) if protocol == LOGIN_TOKEN_PROTOCOL
+            );
+
+            existing_device
+                .send_json(&QrLoginMessage::LoginToken { login_token: "token".to_owned() })
+                .await
+                .unwrap();
+
+            let device_id = assert_matches!(
+                existing_device.receive_json().await,
+                Ok(QrLoginMessage::Success { device_id, .. }) => device_id
+            );
+
+            existing_device.send_json(&QrLoginMessage::Declined).await.unwrap();
+
+            device_id
+        };
+
+        let (result, device_id) =
+            tokio::join!(new_device.finish(Some("New device")), existing_device);
+
+        assert_eq!(device_id.as_str(), "NEWDEVICE");
+        assert_matches!(result, Err(QrLoginError::Declined));
+        assert!(client.logged_in());
+    }
+
+    #[async_test]
+    async fn grant_login() {
+        let server = MockServer::start().await;
+        let rendezvous_server = MockRendezvous::mount(&server).await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        let olm = client.olm_machine().unwrap();
+        olm.bootstrap_cross_signing(false).await.unwrap();
+
+        // The new device, its device keys are what the homeserver returns
+        // once it has logged in.
+        let new_device =
+            OlmMachine::new(user_id!("@example:localhost"), device_id!("NEWDEVICE")).await;
+        let device_keys = new_device
+            .outgoing_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .find_map(|r| match r.request() {
+                OutgoingRequests::KeysUpload(request) => request.device_keys.clone(),
+                _ => None,
+            })
+            .expect("A new device should upload its device keys");
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/.*/login/(get_)?token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "login_token": "token",
+                "expires_in_ms": 120_000,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/r0/keys/query"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "device_keys": {
+                    "@example:localhost": {
+                        "NEWDEVICE": device_keys,
+                    },
+                },
+            })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/r0/keys/signatures/upload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "failures": {} })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let http = std::sync::Arc::new(reqwest::Client::new());
+        let channel = SecureChannel::new(http, &rendezvous_server).await.unwrap();
+        let qr_code_data = channel.qr_code_data();
+
+        let (mut new_device_channel, grant) = tokio::join!(
+            channel.connect(),
+            client.encryption().grant_login_with_qr_code(&qr_code_data)
+        );
+        let mut new_device_channel = new_device_channel.unwrap();
+        let grant = grant.unwrap();
+        assert_eq!(grant.check_code(), new_device_channel.check_code());
+
+        let new_device_side = async move {
+            assert_matches!(
+                new_device_channel.receive_json().await,
+                Ok(QrLoginMessage::Protocols { protocols, .. })
+                    if protocols == [LOGIN_TOKEN_PROTOCOL]
+            );
+
+            new_device_channel
+                .send_json(&QrLoginMessage::ProtocolAccepted {
+                    protocol: LOGIN_TOKEN_PROTOCOL.to_owned(),
+                })
+                .await
+                .unwrap();
+
+            assert_matches!(
+                new_device_channel.receive_json().await,
+                Ok(QrLoginMessage::LoginToken { login_token }) if login_token == "token"
+            );
+
+            new_device_channel
+                .send_json(&QrLoginMessage::Success {
+                    device_id: new_device.device_id().to_owned(),
+                    device_key: new_device.identity_keys().ed25519.to_base64(),
+                })
+                .await
+                .unwrap();
+
+            let master_key = assert_matches!(
+                new_device_channel.receive_json().await,
+                Ok(QrLoginMessage::Verified { verifying_device_id, master_key, .. }) => {
+                    assert_eq!(verifying_device_id.as_str(), "DEVICEID");
+                    master_key
+                }
+            );
+
+            assert_matches!(
+                new_device_channel.receive_json().await,
+                Ok(QrLoginMessage::Secrets(_))
+            );
+
+            master_key
+        };
+
+        let (result, master_key) = tokio::join!(grant.grant(), new_device_side);
+
+        assert_eq!(result.unwrap().as_str(), "NEWDEVICE");
+
+        let identity = client
+            .encryption()
+            .get_user_identity(user_id!("@example:localhost"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(identity.master_key().get_first_key().unwrap().to_base64(), master_key);
+
+        let signatures: serde_json::Value = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|r| r.url.path().ends_with("/keys/signatures/upload"))
+            .unwrap()
+            .body_json()
+            .unwrap();
+        assert!(signatures["@example:localhost"].get("NEWDEVICE").is_some());
+    }
+}
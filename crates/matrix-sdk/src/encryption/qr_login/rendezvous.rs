@@ -0,0 +1,188 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A client for the simple HTTP rendezvous protocol defined in [MSC3886].
+//!
+//! [MSC3886]: https://github.com/matrix-org/matrix-spec-proposals/pull/3886
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use http::{
+    header::{HeaderName, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH, LOCATION},
+    Method, StatusCode,
+};
+use matrix_sdk_common::{sleep::sleep, timeout::timeout};
+use tracing::{debug, trace};
+use url::Url;
+
+use super::RendezvousError;
+use crate::{http_client::DEFAULT_REQUEST_TIMEOUT, HttpSend};
+
+/// How long we wait between two polls of the rendezvous session.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long we wait for the other side to send a message before we give up.
+///
+/// This needs to leave the user enough time to compare the check codes.
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// An HTTP rendezvous session, a mailbox holding a single message that two
+/// devices take turns to replace.
+///
+/// Every message we send or receive updates the `ETag` we remember, this lets
+/// us detect when the other side has put a new message into the mailbox.
+pub(crate) struct RendezvousChannel {
+    http: Arc<dyn HttpSend>,
+    url: Url,
+    etag: Option<String>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for RendezvousChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RendezvousChannel")
+            .field("url", &self.url)
+            .field("etag", &self.etag)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RendezvousChannel {
+    /// Create a new rendezvous session on the given rendezvous server.
+    pub(crate) async fn create(
+        http: Arc<dyn HttpSend>,
+        rendezvous_server: &Url,
+    ) -> Result<Self, RendezvousError> {
+        let request = build_request(Method::POST, rendezvous_server, None, Bytes::new());
+        let response = http.send_request(request, DEFAULT_REQUEST_TIMEOUT).await?;
+
+        if response.status() != StatusCode::CREATED {
+            return Err(RendezvousError::UnexpectedStatus(response.status()));
+        }
+
+        let location = header_value(&response, LOCATION.as_str())
+            .ok_or(RendezvousError::MissingHeader("Location"))?;
+        // The location may be relative to the rendezvous server.
+        let url = rendezvous_server.join(&location)?;
+        let etag = header_value(&response, ETAG.as_str());
+
+        debug!(%url, "Created a new rendezvous session");
+
+        Ok(Self { http, url, etag })
+    }
+
+    /// Connect to an existing rendezvous session, usually one we found in a
+    /// QR code.
+    pub(crate) fn connect(http: Arc<dyn HttpSend>, url: Url) -> Self {
+        Self { http, url, etag: None }
+    }
+
+    /// The URL of the rendezvous session.
+    pub(crate) fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Put a new message into the rendezvous session.
+    pub(crate) async fn send(&mut self, body: Vec<u8>) -> Result<(), RendezvousError> {
+        let condition = self.etag.as_deref().map(|etag| (IF_MATCH, etag));
+        let request = build_request(Method::PUT, &self.url, condition, body.into());
+        let response = self.http.send_request(request, DEFAULT_REQUEST_TIMEOUT).await?;
+
+        match response.status() {
+            StatusCode::ACCEPTED => {
+                self.etag = header_value(&response, ETAG.as_str());
+                trace!(etag = ?self.etag, "Sent a message to the rendezvous session");
+
+                Ok(())
+            }
+            StatusCode::NOT_FOUND => Err(RendezvousError::SessionNotFound),
+            status => Err(RendezvousError::UnexpectedStatus(status)),
+        }
+    }
+
+    /// Wait for the other side to put a new message into the rendezvous
+    /// session.
+    ///
+    /// This polls the rendezvous session until the message changes, until
+    /// the session expires or until [`RECEIVE_TIMEOUT`] has elapsed.
+    pub(crate) async fn receive(&mut self) -> Result<Vec<u8>, RendezvousError> {
+        timeout(Box::pin(self.poll()), RECEIVE_TIMEOUT)
+            .await
+            .map_err(|_| RendezvousError::Timeout)?
+    }
+
+    async fn poll(&mut self) -> Result<Vec<u8>, RendezvousError> {
+        loop {
+            let condition = self.etag.as_deref().map(|etag| (IF_NONE_MATCH, etag));
+            let request = build_request(Method::GET, &self.url, condition, Bytes::new());
+            let response = self.http.send_request(request, DEFAULT_REQUEST_TIMEOUT).await?;
+
+            match response.status() {
+                StatusCode::OK => {
+                    let etag = header_value(&response, ETAG.as_str());
+                    let changed = etag.is_none() || etag != self.etag;
+                    self.etag = etag;
+
+                    // A freshly created session has an empty body, nobody
+                    // has sent a message yet.
+                    if changed && !response.body().is_empty() {
+                        trace!(etag = ?self.etag, "Received a message from the rendezvous session");
+                        return Ok(response.into_body().to_vec());
+                    }
+                }
+                StatusCode::NOT_MODIFIED => {}
+                StatusCode::NOT_FOUND => return Err(RendezvousError::SessionNotFound),
+                status => return Err(RendezvousError::UnexpectedStatus(status)),
+            }
+
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Delete the rendezvous session, letting the other side know that we're
+    /// done.
+    pub(crate) async fn cancel(self) -> Result<(), RendezvousError> {
+        let request = build_request(Method::DELETE, &self.url, None, Bytes::new());
+        let response = self.http.send_request(request, DEFAULT_REQUEST_TIMEOUT).await?;
+
+        match response.status() {
+            StatusCode::NO_CONTENT | StatusCode::NOT_FOUND => Ok(()),
+            status => Err(RendezvousError::UnexpectedStatus(status)),
+        }
+    }
+}
+
+fn build_request(
+    method: Method,
+    url: &Url,
+    condition: Option<(HeaderName, &str)>,
+    body: Bytes,
+) -> http::Request<Bytes> {
+    let mut builder = http::Request::builder().method(method).uri(url.as_str());
+
+    if !body.is_empty() {
+        builder = builder.header(CONTENT_TYPE, "application/json");
+    }
+
+    if let Some((header, etag)) = condition {
+        builder = builder.header(header, etag);
+    }
+
+    builder.body(body).expect("We should always be able to build a rendezvous request")
+}
+
+fn header_value(response: &http::Response<Bytes>, name: &str) -> Option<String> {
+    response.headers().get(name).and_then(|v| v.to_str().ok()).map(ToOwned::to_owned)
+}
@@ -0,0 +1,420 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The ECIES based secure channel defined in [MSC3903].
+//!
+//! The device presenting the QR code puts an ephemeral Curve25519 key into the
+//! QR code, the scanning device replies with its own ephemeral key and an
+//! encrypted initiation message. Both sides then derive the same AES-256-GCM
+//! key and a short check code the user can compare on both devices.
+//!
+//! [MSC3903]: https://github.com/matrix-org/matrix-spec-proposals/pull/3903
+
+use std::{fmt, sync::Arc};
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use hkdf::Hkdf;
+use matrix_sdk_base::crypto::matrix_sdk_qrcode::QrLoginData;
+use rand::{thread_rng, RngCore};
+use ruma::serde::Base64;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::Sha256;
+use url::Url;
+use vodozemac::{Curve25519PublicKey, Curve25519SecretKey};
+use zeroize::Zeroizing;
+
+use super::{rendezvous::RendezvousChannel, SecureChannelError};
+use crate::HttpSend;
+
+const LOGIN_INITIATE_MESSAGE: &[u8] = b"MATRIX_QR_CODE_LOGIN_INITIATE";
+const LOGIN_OK_MESSAGE: &[u8] = b"MATRIX_QR_CODE_LOGIN_OK";
+const KEY_INFO_PREFIX: &str = "MATRIX_QR_CODE_LOGIN|";
+const CHECK_CODE_INFO_PREFIX: &str = "MATRIX_QR_CODE_LOGIN_CHECKCODE|";
+const NONCE_SIZE: usize = 12;
+
+/// A short code derived from the secure channel key.
+///
+/// Both devices display the check code, the user should compare them before
+/// continuing. If they don't match the channel was intercepted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckCode {
+    bytes: [u8; 2],
+}
+
+impl CheckCode {
+    /// Get the check code as a two digit number.
+    pub fn to_digits(&self) -> u8 {
+        (self.bytes[0] % 10) * 10 + self.bytes[1] % 10
+    }
+}
+
+impl fmt::Display for CheckCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}", self.to_digits())
+    }
+}
+
+/// The first message the scanning device sends, it contains its ephemeral
+/// key next to the encrypted initiation message.
+#[derive(Debug, Serialize, Deserialize)]
+struct InitialMessage {
+    key: String,
+    #[serde(flatten)]
+    payload: EncryptedPayload,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedPayload {
+    iv: Base64,
+    ciphertext: Base64,
+}
+
+/// A secure channel that waits for the other device to scan our QR code.
+pub(crate) struct SecureChannel {
+    channel: RendezvousChannel,
+    secret_key: Curve25519SecretKey,
+    public_key: Curve25519PublicKey,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for SecureChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureChannel")
+            .field("channel", &self.channel)
+            .field("public_key", &self.public_key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SecureChannel {
+    /// Create a new rendezvous session and the ephemeral key that will be
+    /// shown in the QR code.
+    pub(crate) async fn new(
+        http: Arc<dyn HttpSend>,
+        rendezvous_server: &Url,
+    ) -> Result<Self, SecureChannelError> {
+        let channel = RendezvousChannel::create(http, rendezvous_server).await?;
+        let secret_key = Curve25519SecretKey::new();
+        let public_key = Curve25519PublicKey::from(&secret_key);
+
+        Ok(Self { channel, secret_key, public_key })
+    }
+
+    /// The data that should be put into a QR code and presented to the other
+    /// device.
+    pub(crate) fn qr_code_data(&self) -> QrLoginData {
+        QrLoginData::new_login_start(self.channel.url().clone(), self.public_key)
+    }
+
+    /// Wait for the other device to scan the QR code and establish the secure
+    /// channel.
+    pub(crate) async fn connect(mut self) -> Result<EstablishedSecureChannel, SecureChannelError> {
+        let message = self.channel.receive().await?;
+        let InitialMessage { key, payload } = serde_json::from_slice(&message)?;
+
+        let their_key = Curve25519PublicKey::from_base64(&key)?;
+        let (cipher, check_code) =
+            derive_keys(&self.secret_key, &their_key, &self.public_key, &their_key);
+
+        let mut channel = EstablishedSecureChannel { channel: self.channel, cipher, check_code };
+
+        if channel.decrypt(payload)?.as_slice() != LOGIN_INITIATE_MESSAGE {
+            return Err(SecureChannelError::InvalidInitiateMessage);
+        }
+
+        channel.send(LOGIN_OK_MESSAGE).await?;
+
+        Ok(channel)
+    }
+}
+
+/// A secure channel both devices have agreed on.
+pub(crate) struct EstablishedSecureChannel {
+    channel: RendezvousChannel,
+    cipher: Aes256Gcm,
+    check_code: CheckCode,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for EstablishedSecureChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EstablishedSecureChannel")
+            .field("channel", &self.channel)
+            .field("check_code", &self.check_code)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EstablishedSecureChannel {
+    /// Establish a secure channel with the device that presented the given QR
+    /// code.
+    pub(crate) async fn from_qr_code(
+        http: Arc<dyn HttpSend>,
+        data: &QrLoginData,
+    ) -> Result<Self, SecureChannelError> {
+        let channel = RendezvousChannel::connect(http, data.rendezvous_url().clone());
+
+        let secret_key = Curve25519SecretKey::new();
+        let public_key = Curve25519PublicKey::from(&secret_key);
+        let their_key = data.rendezvous.key;
+
+        let (cipher, check_code) = derive_keys(&secret_key, &their_key, &their_key, &public_key);
+        let mut secure_channel = Self { channel, cipher, check_code };
+
+        let payload = secure_channel.encrypt(LOGIN_INITIATE_MESSAGE);
+        let message = InitialMessage { key: public_key.to_base64(), payload };
+        secure_channel.channel.send(serde_json::to_vec(&message)?).await?;
+
+        if secure_channel.receive().await?.as_slice() != LOGIN_OK_MESSAGE {
+            return Err(SecureChannelError::InvalidInitiateMessage);
+        }
+
+        Ok(secure_channel)
+    }
+
+    /// The check code both devices should display.
+    pub(crate) fn check_code(&self) -> CheckCode {
+        self.check_code
+    }
+
+    /// Encrypt and send a message to the other device.
+    pub(crate) async fn send(&mut self, message: &[u8]) -> Result<(), SecureChannelError> {
+        let payload = self.encrypt(message);
+        Ok(self.channel.send(serde_json::to_vec(&payload)?).await?)
+    }
+
+    /// Wait for a message from the other device and decrypt it.
+    pub(crate) async fn receive(&mut self) -> Result<Zeroizing<Vec<u8>>, SecureChannelError> {
+        let message = self.channel.receive().await?;
+        let payload: EncryptedPayload = serde_json::from_slice(&message)?;
+
+        self.decrypt(payload)
+    }
+
+    /// Serialize the message as JSON, encrypt and send it to the other device.
+    pub(crate) async fn send_json(
+        &mut self,
+        message: &impl Serialize,
+    ) -> Result<(), SecureChannelError> {
+        let message = Zeroizing::new(serde_json::to_vec(message)?);
+        self.send(&message).await
+    }
+
+    /// Wait for a message from the other device and deserialize it from JSON.
+    pub(crate) async fn receive_json<T: DeserializeOwned>(
+        &mut self,
+    ) -> Result<T, SecureChannelError> {
+        let message = self.receive().await?;
+        Ok(serde_json::from_slice(&message)?)
+    }
+
+    /// Close the underlying rendezvous session.
+    pub(crate) async fn close(self) -> Result<(), SecureChannelError> {
+        Ok(self.channel.cancel().await?)
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> EncryptedPayload {
+        let mut nonce = [0u8; NONCE_SIZE];
+        thread_rng().fill_bytes(&mut nonce);
+
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .expect("We should always be able to encrypt a message with AES-GCM");
+
+        EncryptedPayload { iv: Base64::new(nonce.to_vec()), ciphertext: Base64::new(ciphertext) }
+    }
+
+    fn decrypt(&self, payload: EncryptedPayload) -> Result<Zeroizing<Vec<u8>>, SecureChannelError> {
+        if payload.iv.as_bytes().len() != NONCE_SIZE {
+            return Err(SecureChannelError::Decryption);
+        }
+
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(payload.iv.as_bytes()), payload.ciphertext.as_bytes())
+            .map_err(|_| SecureChannelError::Decryption)?;
+
+        Ok(Zeroizing::new(plaintext))
+    }
+}
+
+/// Derive the AES-256-GCM key and the check code from the Diffie-Hellman
+/// shared secret of the two ephemeral keys.
+///
+/// The key of the device that presented the QR code always goes first into
+/// the HKDF info, so both sides end up with the same keys.
+fn derive_keys(
+    our_secret_key: &Curve25519SecretKey,
+    their_key: &Curve25519PublicKey,
+    generator_key: &Curve25519PublicKey,
+    scanner_key: &Curve25519PublicKey,
+) -> (Aes256Gcm, CheckCode) {
+    let shared_secret = our_secret_key.diffie_hellman(their_key);
+    let hkdf = Hkdf::<Sha256>::new(None, shared_secret.as_bytes());
+
+    let keys = format!("{}|{}", generator_key.to_base64(), scanner_key.to_base64());
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    hkdf.expand(format!("{KEY_INFO_PREFIX}{keys}").as_bytes(), key.as_mut_slice())
+        .expect("We should be able to expand a 32 byte key");
+
+    let mut bytes = [0u8; 2];
+    hkdf.expand(format!("{CHECK_CODE_INFO_PREFIX}{keys}").as_bytes(), &mut bytes)
+        .expect("We should be able to expand the check code");
+
+    let cipher = Aes256Gcm::new_from_slice(key.as_slice())
+        .expect("The derived key should have the correct length");
+
+    (cipher, CheckCode { bytes })
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use matrix_sdk_test::async_test;
+    use serde_json::json;
+    use url::Url;
+    use wiremock::{
+        matchers::{method, path, path_regex},
+        Mock, MockServer, Request, Respond, ResponseTemplate,
+    };
+
+    use super::{EstablishedSecureChannel, SecureChannel};
+
+    /// An in-memory implementation of an MSC3886 rendezvous server.
+    #[derive(Clone, Default)]
+    pub(crate) struct MockRendezvous {
+        sessions: Arc<Mutex<HashMap<String, (usize, Vec<u8>)>>>,
+        counter: Arc<AtomicUsize>,
+    }
+
+    impl MockRendezvous {
+        pub(crate) async fn mount(server: &MockServer) -> Url {
+            let rendezvous = Self::default();
+
+            Mock::given(method("POST"))
+                .and(path("/rendezvous"))
+                .respond_with(rendezvous.clone())
+                .mount(server)
+                .await;
+
+            Mock::given(path_regex("^/rendezvous/.+")).respond_with(rendezvous).mount(server).await;
+
+            Url::parse(&format!("{}/rendezvous", server.uri())).unwrap()
+        }
+    }
+
+    impl Respond for MockRendezvous {
+        fn respond(&self, request: &Request) -> ResponseTemplate {
+            let mut sessions = self.sessions.lock().unwrap();
+            let id = request.url.path().trim_start_matches("/rendezvous").trim_matches('/');
+            let etag = |version: usize| format!("\"{version}\"");
+
+            let header = |name: &str| {
+                request.headers.get(&name.into()).map(|values| values.as_str().to_owned())
+            };
+
+            match request.method.to_string().as_str() {
+                "POST" => {
+                    let id = format!("session-{}", self.counter.fetch_add(1, Ordering::SeqCst));
+                    sessions.insert(id.clone(), (0, request.body.clone()));
+
+                    ResponseTemplate::new(201)
+                        .insert_header("Location", format!("/rendezvous/{id}").as_str())
+                        .insert_header("ETag", etag(0).as_str())
+                }
+                "PUT" => {
+                    let Some((version, body)) = sessions.get_mut(id) else {
+                        return ResponseTemplate::new(404);
+                    };
+
+                    if let Some(if_match) = header("If-Match") {
+                        if if_match != etag(*version) {
+                            return ResponseTemplate::new(412);
+                        }
+                    }
+
+                    *version += 1;
+                    *body = request.body.clone();
+
+                    ResponseTemplate::new(202).insert_header("ETag", etag(*version).as_str())
+                }
+                "GET" => {
+                    let Some((version, body)) = sessions.get(id) else {
+                        return ResponseTemplate::new(404);
+                    };
+
+                    if let Some(if_none_match) = header("If-None-Match") {
+                        if if_none_match == etag(*version) {
+                            return ResponseTemplate::new(304);
+                        }
+                    }
+
+                    ResponseTemplate::new(200)
+                        .insert_header("ETag", etag(*version).as_str())
+                        .set_body_bytes(body.clone())
+                }
+                "DELETE" => {
+                    sessions.remove(id);
+                    ResponseTemplate::new(204)
+                }
+                _ => ResponseTemplate::new(405),
+            }
+        }
+    }
+
+    fn http_client() -> Arc<reqwest::Client> {
+        Arc::new(reqwest::Client::new())
+    }
+
+    #[async_test]
+    async fn secure_channel_roundtrip() {
+        let server = MockServer::start().await;
+        let rendezvous_server = MockRendezvous::mount(&server).await;
+
+        let channel = SecureChannel::new(http_client(), &rendezvous_server).await.unwrap();
+        let qr_code_data = channel.qr_code_data();
+
+        let (alice, bob) = tokio::join!(
+            channel.connect(),
+            EstablishedSecureChannel::from_qr_code(http_client(), &qr_code_data)
+        );
+
+        let mut alice = alice.expect("The QR code presenter should establish the channel");
+        let mut bob = bob.expect("The QR code scanner should establish the channel");
+
+        assert_eq!(alice.check_code(), bob.check_code());
+
+        bob.send_json(&json!({ "type": "m.login.progress" })).await.unwrap();
+        let message: serde_json::Value = alice.receive_json().await.unwrap();
+        assert_eq!(message, json!({ "type": "m.login.progress" }));
+
+        alice.send(b"It's a secret to everybody").await.unwrap();
+        assert_eq!(bob.receive().await.unwrap().as_slice(), b"It's a secret to everybody");
+
+        alice.close().await.unwrap();
+        bob.receive().await.expect_err("The rendezvous session should be gone");
+    }
+}
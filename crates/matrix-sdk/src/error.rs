@@ -20,7 +20,7 @@ use std::io::Error as IoError;
 use matrix_sdk_base::crypto::ScanError;
 #[cfg(feature = "e2e-encryption")]
 use matrix_sdk_base::crypto::{
-    CryptoStoreError, DecryptorError, KeyExportError, MegolmError, OlmError,
+    store::LockStoreError, CryptoStoreError, DecryptorError, KeyExportError, MegolmError, OlmError,
 };
 use matrix_sdk_base::{Error as SdkBaseError, StoreError};
 use reqwest::Error as ReqwestError;
@@ -30,7 +30,7 @@ use ruma::{
         error::{FromHttpResponseError, IntoHttpError},
     },
     events::tag::InvalidUserTagName,
-    IdParseError,
+    IdParseError, OwnedTransactionId,
 };
 use serde_json::Error as JsonError;
 use thiserror::Error;
@@ -195,6 +195,12 @@ pub enum Error {
     #[error(transparent)]
     CryptoStoreError(#[from] CryptoStoreError),
 
+    /// An error occurred while taking the cross-process lock on the crypto
+    /// store.
+    #[cfg(feature = "e2e-encryption")]
+    #[error(transparent)]
+    CrossProcessLockError(#[from] LockStoreError),
+
     /// An error occurred during a E2EE operation.
     #[cfg(feature = "e2e-encryption")]
     #[error(transparent)]
@@ -227,6 +233,12 @@ pub enum Error {
     #[error(transparent)]
     QrCodeScanError(#[from] ScanError),
 
+    /// An error occurred while creating, restoring or uploading a server-side
+    /// key backup.
+    #[cfg(feature = "backups-v1")]
+    #[error(transparent)]
+    Backup(#[from] crate::encryption::backups::Error),
+
     /// An error encountered when trying to parse a user tag name.
     #[error(transparent)]
     UserTagName(#[from] InvalidUserTagName),
@@ -246,11 +258,25 @@ pub enum Error {
     #[error(transparent)]
     Timeline(#[from] crate::room::timeline::Error),
 
+    /// The login to restore a session returned a different user or device.
+    #[error("the login returned a different user or device than the session")]
+    SessionMismatch,
+
+    /// An error occurred during an OpenID Connect authentication.
+    #[cfg(feature = "experimental-oidc")]
+    #[error(transparent)]
+    Oidc(#[from] crate::oidc::OidcError),
+
     /// The client is in inconsistent state. This happens when we set a room to
     /// a specific type, but then cannot get it in this type.
     #[error("The internal client state is inconsistent.")]
     InconsistentState,
 
+    /// The data of an attachment waiting in a send queue couldn't be found in
+    /// the store.
+    #[error("the data of the queued attachment {0} is missing")]
+    MissingAttachmentData(OwnedTransactionId),
+
     /// An other error was raised
     /// this might happen because encryption was enabled on the base-crate
     /// but not here and that raised.
@@ -409,6 +435,7 @@ pub enum ImageError {
 /// [`Client::refresh_access_token()`]: crate::Client::refresh_access_token()
 /// [handling refresh tokens]: crate::ClientBuilder::handle_refresh_tokens()
 #[derive(Debug, Error, Clone)]
+#[non_exhaustive]
 pub enum RefreshTokenError {
     /// The Matrix endpoint returned an error.
     #[error(transparent)]
@@ -422,4 +449,18 @@ pub enum RefreshTokenError {
     /// not be forwarded.
     #[error("the access token could not be refreshed")]
     UnableToRefreshToken,
+
+    /// The OpenID Connect Provider returned an error.
+    #[cfg(feature = "experimental-oidc")]
+    #[error(transparent)]
+    Oidc(std::sync::Arc<crate::oidc::OidcError>),
+
+    /// The session persistence hook failed to save or load the session.
+    #[error("the session couldn't be persisted: {0}")]
+    SessionPersistence(std::sync::Arc<dyn std::error::Error + Send + Sync>),
+
+    /// The cross-process lock on the refresh of the tokens couldn't be taken.
+    #[cfg(feature = "e2e-encryption")]
+    #[error(transparent)]
+    CrossProcessLock(std::sync::Arc<LockStoreError>),
 }
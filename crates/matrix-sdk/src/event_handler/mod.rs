@@ -47,7 +47,7 @@ use std::{
 use anymap2::any::CloneAnySendSync;
 use futures_util::stream::{FuturesUnordered, StreamExt};
 use matrix_sdk_base::{
-    deserialized_responses::{EncryptionInfo, SyncTimelineEvent},
+    deserialized_responses::{EncryptionInfo, SyncTimelineEvent, SyncToDeviceEvent},
     SendOutsideWasm, SyncOutsideWasm,
 };
 use ruma::{events::AnySyncStateEvent, serde::Raw, OwnedRoomId};
@@ -344,6 +344,33 @@ impl Client {
         Ok(())
     }
 
+    pub(crate) async fn handle_sync_to_device_events(
+        &self,
+        to_device_events: &[SyncToDeviceEvent],
+    ) -> serde_json::Result<()> {
+        #[derive(Deserialize)]
+        struct ExtractType<'a> {
+            #[serde(borrow, rename = "type")]
+            event_type: Cow<'a, str>,
+        }
+
+        for item in to_device_events {
+            let event_type = item.event.deserialize_as::<ExtractType<'_>>()?.event_type;
+            let encryption_info = item.encryption_info.as_ref();
+
+            self.call_event_handlers(
+                &None,
+                item.event.json(),
+                HandlerKind::ToDevice,
+                &event_type,
+                encryption_info,
+            )
+            .await;
+        }
+
+        Ok(())
+    }
+
     pub(crate) async fn handle_sync_state_events(
         &self,
         room: &Option<room::Room>,
@@ -20,7 +20,7 @@ pub use async_trait::async_trait;
 pub use bytes;
 pub use matrix_sdk_base::{
     deserialized_responses, DisplayName, Room as BaseRoom, RoomInfo, RoomMember as BaseRoomMember,
-    RoomType, Session, StateChanges, StoreError,
+    RoomType, Session, StateChanges, StoreError, UnreadCounts,
 };
 pub use matrix_sdk_common::*;
 pub use reqwest;
@@ -35,6 +35,8 @@ mod error;
 pub mod event_handler;
 mod http_client;
 pub mod media;
+#[cfg(feature = "experimental-oidc")]
+pub mod oidc;
 pub mod room;
 pub mod sync;
 
@@ -49,7 +51,10 @@ mod events;
 pub use account::Account;
 #[cfg(feature = "sso-login")]
 pub use client::SsoLoginBuilder;
-pub use client::{Client, ClientBuildError, ClientBuilder, LoginBuilder, LoopCtrl};
+pub use client::{
+    Client, ClientBuildError, ClientBuilder, LoginBuilder, LoopCtrl, SessionChange,
+    SessionPersistence, SessionPersistenceError,
+};
 #[cfg(feature = "image-proc")]
 pub use error::ImageError;
 pub use error::{Error, HttpError, HttpResult, RefreshTokenError, Result, RumaApiError};
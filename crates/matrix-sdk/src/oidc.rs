@@ -0,0 +1,1055 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Native OpenID Connect authentication ([MSC3861]).
+//!
+//! The homeserver delegates authentication to an OpenID Connect Provider (OP),
+//! advertised in its [well-known] information ([MSC2965]), see
+//! [`Client::authentication_issuer()`]. Logging in with the OP is done with
+//! the following steps:
+//!
+//! 1. Register the client with the OP with [`Oidc::register_client()`]. The
+//!    returned [`ClientCredentials`] should be persisted to be able to [restore
+//!    the registration] later.
+//! 2. Get the URL to open in a browser with [`Oidc::url_for_login()`].
+//! 3. Once the user is redirected to the redirect URI, finish the login with
+//!    [`Oidc::finish_login()`].
+//!
+//! The access token is refreshed with the OP by
+//! [`Client::refresh_access_token()`], which is called automatically when
+//! [refresh tokens are handled] by the `Client`.
+//!
+//! [MSC3861]: https://github.com/matrix-org/matrix-spec-proposals/pull/3861
+//! [MSC2965]: https://github.com/matrix-org/matrix-spec-proposals/pull/2965
+//! [well-known]: https://spec.matrix.org/v1.5/client-server-api/#well-known-uri
+//! [restore the registration]: Oidc::restore_registered_client()
+//! [refresh tokens are handled]: crate::ClientBuilder::handle_refresh_tokens()
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Mutex as StdMutex, RwLock as StdRwLock},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use bytes::Bytes;
+use http::{header, Method, StatusCode};
+use matrix_sdk_base::Session;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use ruma::{
+    api::client::{account::whoami, session::refresh_token},
+    assign, DeviceId, OwnedDeviceId,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::{debug, instrument};
+use url::{form_urlencoded, Url};
+
+use crate::{Client, HttpError, RefreshTokenError, Result};
+
+/// The scope to get access to the client-server API.
+const SCOPE_MATRIX_API: &str = "urn:matrix:org.matrix.msc2967.client:api:*";
+
+/// The prefix of the scope to request a device ID.
+const SCOPE_MATRIX_DEVICE_PREFIX: &str = "urn:matrix:org.matrix.msc2967.client:device:";
+
+/// The length of the random strings generated for the `state` and the PKCE
+/// code verifier.
+const RANDOM_STRING_LENGTH: usize = 64;
+
+/// Errors that can happen when using the [`Oidc`] API.
+#[derive(Debug, Error)]
+pub enum OidcError {
+    /// The homeserver doesn't advertise an OpenID Connect Provider, and no
+    /// client was registered with one.
+    #[error("the homeserver doesn't advertise an OpenID Connect Provider")]
+    NoAuthenticationIssuer,
+
+    /// The issuer in the metadata of the OpenID Connect Provider doesn't match
+    /// the one it was discovered from.
+    #[error("the issuer `{actual}` in the provider metadata doesn't match `{expected}`")]
+    IssuerMismatch {
+        /// The issuer the metadata was requested from.
+        expected: String,
+        /// The issuer in the metadata.
+        actual: String,
+    },
+
+    /// The client was not registered with the OpenID Connect Provider.
+    #[error("the client is not registered with the OpenID Connect Provider")]
+    NotRegistered,
+
+    /// The OpenID Connect Provider doesn't support dynamic client
+    /// registration.
+    #[error("the OpenID Connect Provider doesn't support dynamic client registration")]
+    RegistrationNotSupported,
+
+    /// The OpenID Connect Provider doesn't support token revocation.
+    #[error("the OpenID Connect Provider doesn't support token revocation")]
+    RevocationNotSupported,
+
+    /// The `state` of the callback URI doesn't match a login in progress.
+    #[error("the callback URI doesn't match a login in progress")]
+    InvalidState,
+
+    /// A parameter is missing from the callback URI.
+    #[error("the callback URI is missing the `{0}` parameter")]
+    MissingCallbackParameter(&'static str),
+
+    /// The user didn't authorize the login, or the OpenID Connect Provider
+    /// refused it.
+    #[error("the authorization failed: {error}")]
+    Authorization {
+        /// The error code.
+        error: String,
+        /// The human-readable description of the error, if any.
+        description: Option<String>,
+    },
+
+    /// The OpenID Connect Provider returned an error.
+    #[error("the OpenID Connect Provider returned an error ({status}): {error}")]
+    Provider {
+        /// The HTTP status code of the response.
+        status: StatusCode,
+        /// The error code, if the response contained one.
+        error: String,
+        /// The human-readable description of the error, if any.
+        description: Option<String>,
+    },
+
+    /// The client was not logged in.
+    #[error("the client is not logged in")]
+    NotLoggedIn,
+
+    /// The homeserver logged in a different device than the one that was
+    /// requested in the scope of the authorization.
+    #[error("the homeserver logged in the device `{actual}` instead of `{expected}`")]
+    DeviceIdMismatch {
+        /// The device ID that was requested.
+        expected: OwnedDeviceId,
+        /// The device ID the homeserver returned.
+        actual: OwnedDeviceId,
+    },
+
+    /// An error occurred when building an HTTP request.
+    #[error(transparent)]
+    Request(#[from] http::Error),
+
+    /// An error occurred when sending an HTTP request.
+    #[error(transparent)]
+    Http(#[from] HttpError),
+
+    /// An error occurred when (de)serializing JSON.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// An error occurred when parsing a URL.
+    #[error(transparent)]
+    Url(#[from] url::ParseError),
+}
+
+/// The metadata of an OpenID Connect Provider.
+///
+/// Only the fields used by the [`Oidc`] API are deserialized.
+#[derive(Debug, Clone, Deserialize)]
+#[non_exhaustive]
+pub struct ProviderMetadata {
+    /// The URL of the issuer.
+    pub issuer: String,
+    /// The URL of the authorization endpoint.
+    pub authorization_endpoint: Url,
+    /// The URL of the token endpoint.
+    pub token_endpoint: Url,
+    /// The URL of the dynamic client registration endpoint, if supported.
+    pub registration_endpoint: Option<Url>,
+    /// The URL of the token revocation endpoint, if supported.
+    pub revocation_endpoint: Option<Url>,
+    /// The URL of the account management page, if any.
+    pub account_management_uri: Option<Url>,
+}
+
+/// The metadata of a client, to register it with an OpenID Connect Provider.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientMetadata {
+    /// The URIs the user can be redirected to after the authorization.
+    pub redirect_uris: Vec<Url>,
+    /// The name of the client, presented to the user.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+    /// The URL of the home page of the client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_uri: Option<Url>,
+    /// The URL of the logo of the client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo_uri: Option<Url>,
+    /// The URL of the policy of the client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy_uri: Option<Url>,
+    /// The URL of the terms of service of the client.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tos_uri: Option<Url>,
+    /// The type of application, either `native` or `web`.
+    pub application_type: String,
+    /// The grant types the client uses.
+    pub grant_types: Vec<String>,
+    /// The response types the client uses.
+    pub response_types: Vec<String>,
+    /// The authentication method of the client at the token endpoint.
+    pub token_endpoint_auth_method: String,
+}
+
+impl ClientMetadata {
+    /// Create the metadata of a native public client using the
+    /// authorization code grant with the given redirect URIs.
+    pub fn new(redirect_uris: Vec<Url>) -> Self {
+        Self {
+            redirect_uris,
+            client_name: None,
+            client_uri: None,
+            logo_uri: None,
+            policy_uri: None,
+            tos_uri: None,
+            application_type: "native".to_owned(),
+            grant_types: vec!["authorization_code".to_owned(), "refresh_token".to_owned()],
+            response_types: vec!["code".to_owned()],
+            token_endpoint_auth_method: "none".to_owned(),
+        }
+    }
+}
+
+/// The credentials of a client registered with an OpenID Connect Provider.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ClientCredentials {
+    /// The ID of the client.
+    pub client_id: String,
+    /// The secret of the client, for confidential clients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for ClientCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientCredentials")
+            .field("client_id", &self.client_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The data needed to start a login with an OpenID Connect Provider.
+#[derive(Debug, Clone)]
+pub struct OidcAuthorizationData {
+    /// The URL to open in a browser to log in.
+    pub url: Url,
+    /// The unique identifier of this login, to
+    /// [abort it](Oidc::abort_login).
+    pub state: String,
+}
+
+/// An action to present on the account management page of the OpenID Connect
+/// Provider.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum AccountManagementAction {
+    /// View the profile of the user.
+    Profile,
+    /// View the list of sessions of the user.
+    SessionsList,
+    /// View the details of the session with the given device ID.
+    SessionView(OwnedDeviceId),
+    /// End the session with the given device ID.
+    SessionEnd(OwnedDeviceId),
+}
+
+impl AccountManagementAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Profile => "org.matrix.profile",
+            Self::SessionsList => "org.matrix.sessions_list",
+            Self::SessionView(_) => "org.matrix.session_view",
+            Self::SessionEnd(_) => "org.matrix.session_end",
+        }
+    }
+
+    fn device_id(&self) -> Option<&DeviceId> {
+        match self {
+            Self::SessionView(device_id) | Self::SessionEnd(device_id) => Some(device_id),
+            Self::Profile | Self::SessionsList => None,
+        }
+    }
+}
+
+/// The OpenID Connect state of a [`Client`].
+#[derive(Debug, Default)]
+pub(crate) struct OidcData {
+    /// The account management URL advertised in the well-known information of
+    /// the homeserver.
+    account: Option<Url>,
+    /// The issuer the client was registered with, if it was restored.
+    issuer: StdRwLock<Option<Url>>,
+    /// The metadata of the provider, once it was discovered.
+    provider_metadata: StdRwLock<Option<ProviderMetadata>>,
+    /// The credentials of the registered client.
+    credentials: StdRwLock<Option<ClientCredentials>>,
+    /// The logins in progress, by `state`.
+    authorizations: StdMutex<HashMap<String, AuthorizationValidationData>>,
+}
+
+impl OidcData {
+    pub(crate) fn new(account: Option<Url>) -> Self {
+        Self { account, ..Default::default() }
+    }
+}
+
+/// The data needed to validate the callback of a login in progress.
+#[derive(Debug)]
+struct AuthorizationValidationData {
+    redirect_uri: Url,
+    code_verifier: String,
+    device_id: OwnedDeviceId,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// A high-level API to authenticate with an OpenID Connect Provider.
+#[derive(Debug, Clone)]
+pub struct Oidc {
+    client: Client,
+}
+
+impl Oidc {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    fn data(&self) -> &OidcData {
+        &self.client.inner.oidc_data
+    }
+
+    /// The issuer of the OpenID Connect Provider.
+    ///
+    /// This is the issuer the client was registered with if it was
+    /// [restored](Self::restore_registered_client), or the one advertised by
+    /// the homeserver otherwise.
+    pub async fn issuer(&self) -> Option<Url> {
+        let issuer = self.data().issuer.read().unwrap().clone();
+        match issuer {
+            Some(issuer) => Some(issuer),
+            None => self.client.authentication_issuer().await,
+        }
+    }
+
+    /// The credentials of the client, if it was registered with the OpenID
+    /// Connect Provider.
+    pub fn client_credentials(&self) -> Option<ClientCredentials> {
+        self.data().credentials.read().unwrap().clone()
+    }
+
+    /// Restore the registration of the client with the OpenID Connect
+    /// Provider, to be able to use a session that was logged in with it.
+    ///
+    /// # Arguments
+    ///
+    /// * `issuer` - The issuer the client was registered with.
+    ///
+    /// * `credentials` - The credentials returned by
+    ///   [`register_client()`](Self::register_client).
+    pub fn restore_registered_client(&self, issuer: Url, credentials: ClientCredentials) {
+        let data = self.data();
+        *data.issuer.write().unwrap() = Some(issuer);
+        *data.provider_metadata.write().unwrap() = None;
+        *data.credentials.write().unwrap() = Some(credentials);
+    }
+
+    /// Get the metadata of the OpenID Connect Provider.
+    ///
+    /// The metadata is discovered on the first call and cached afterwards.
+    pub async fn provider_metadata(&self) -> Result<ProviderMetadata, OidcError> {
+        if let Some(metadata) = self.data().provider_metadata.read().unwrap().clone() {
+            return Ok(metadata);
+        }
+
+        let issuer = self.issuer().await.ok_or(OidcError::NoAuthenticationIssuer)?;
+        let issuer = issuer.as_str().trim_end_matches('/');
+        let url = Url::parse(&format!("{issuer}/.well-known/openid-configuration"))?;
+
+        let request = http::Request::get(url.as_str()).body(Bytes::new())?;
+        let metadata: ProviderMetadata = self.send_json(request).await?;
+
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            return Err(OidcError::IssuerMismatch {
+                expected: issuer.to_owned(),
+                actual: metadata.issuer,
+            });
+        }
+
+        *self.data().provider_metadata.write().unwrap() = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    /// Register the client with the OpenID Connect Provider, with [dynamic
+    /// client registration].
+    ///
+    /// The returned credentials should be persisted with the issuer, to be
+    /// able to [restore the registration] later.
+    ///
+    /// [dynamic client registration]: https://openid.net/specs/openid-connect-registration-1_0.html
+    /// [restore the registration]: Self::restore_registered_client
+    #[instrument(skip_all)]
+    pub async fn register_client(
+        &self,
+        client_metadata: &ClientMetadata,
+    ) -> Result<ClientCredentials, OidcError> {
+        let issuer = self.issuer().await.ok_or(OidcError::NoAuthenticationIssuer)?;
+        let metadata = self.provider_metadata().await?;
+        let endpoint = metadata.registration_endpoint.ok_or(OidcError::RegistrationNotSupported)?;
+
+        let request = http::Request::builder()
+            .method(Method::POST)
+            .uri(endpoint.as_str())
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(client_metadata)?.into())?;
+        let credentials: ClientCredentials = self.send_json(request).await?;
+
+        debug!(client_id = %credentials.client_id, "Registered the client");
+
+        let data = self.data();
+        *data.issuer.write().unwrap() = Some(issuer);
+        *data.credentials.write().unwrap() = Some(credentials.clone());
+
+        Ok(credentials)
+    }
+
+    /// Get the URL to open in a browser to log in with the OpenID Connect
+    /// Provider, with the authorization code flow and [PKCE].
+    ///
+    /// Once the user is redirected to the redirect URI, the login can be
+    /// completed with [`finish_login()`](Self::finish_login).
+    ///
+    /// # Arguments
+    ///
+    /// * `redirect_uri` - The URI the user is redirected to after the
+    ///   authorization. It must be one of the redirect URIs the client was
+    ///   registered with.
+    ///
+    /// * `device_id` - The device ID to use for the session. A random one is
+    ///   generated if it is `None`.
+    ///
+    /// [PKCE]: https://datatracker.ietf.org/doc/html/rfc7636
+    pub async fn url_for_login(
+        &self,
+        redirect_uri: &Url,
+        device_id: Option<OwnedDeviceId>,
+    ) -> Result<OidcAuthorizationData, OidcError> {
+        let credentials = self.client_credentials().ok_or(OidcError::NotRegistered)?;
+        let metadata = self.provider_metadata().await?;
+
+        let device_id = device_id.unwrap_or_else(DeviceId::new);
+        let state = random_string();
+        let code_verifier = random_string();
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+        let scope = format!("openid {SCOPE_MATRIX_API} {SCOPE_MATRIX_DEVICE_PREFIX}{device_id}");
+
+        let mut url = metadata.authorization_endpoint;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &credentials.client_id)
+            .append_pair("redirect_uri", redirect_uri.as_str())
+            .append_pair("scope", &scope)
+            .append_pair("state", &state)
+            .append_pair("code_challenge", &code_challenge)
+            .append_pair("code_challenge_method", "S256");
+
+        self.data().authorizations.lock().unwrap().insert(
+            state.clone(),
+            AuthorizationValidationData {
+                redirect_uri: redirect_uri.clone(),
+                code_verifier,
+                device_id,
+            },
+        );
+
+        Ok(OidcAuthorizationData { url, state })
+    }
+
+    /// Abort the login in progress with the given `state`.
+    pub fn abort_login(&self, state: &str) {
+        self.data().authorizations.lock().unwrap().remove(state);
+    }
+
+    /// Finish the login with the URI the user was redirected to after the
+    /// authorization.
+    ///
+    /// This exchanges the authorization code for the tokens and restores the
+    /// session of the `Client`.
+    ///
+    /// Fails with [`OidcError::DeviceIdMismatch`] if the homeserver didn't log
+    /// in the device that was requested in [`url_for_login()`].
+    ///
+    /// [`url_for_login()`]: Self::url_for_login
+    #[instrument(skip_all)]
+    pub async fn finish_login(&self, callback_uri: &Url) -> Result<()> {
+        let mut state = None;
+        let mut code = None;
+        let mut error = None;
+        let mut description = None;
+
+        for (key, value) in callback_uri.query_pairs() {
+            match &*key {
+                "state" => state = Some(value.into_owned()),
+                "code" => code = Some(value.into_owned()),
+                "error" => error = Some(value.into_owned()),
+                "error_description" => description = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+
+        let state = state.ok_or(OidcError::MissingCallbackParameter("state"))?;
+        let validation_data = self
+            .data()
+            .authorizations
+            .lock()
+            .unwrap()
+            .remove(&state)
+            .ok_or(OidcError::InvalidState)?;
+
+        if let Some(error) = error {
+            return Err(OidcError::Authorization { error, description }.into());
+        }
+        let code = code.ok_or(OidcError::MissingCallbackParameter("code"))?;
+
+        let tokens = self
+            .request_tokens(&[
+                ("grant_type", "authorization_code"),
+                ("code", &code),
+                ("redirect_uri", validation_data.redirect_uri.as_str()),
+                ("code_verifier", &validation_data.code_verifier),
+            ])
+            .await?;
+
+        let whoami = self
+            .client
+            .inner
+            .http_client
+            .send(
+                whoami::v3::Request::new(),
+                None,
+                self.client.homeserver().await.to_string(),
+                Some(&tokens.access_token),
+                None,
+                self.client.server_versions().await?,
+            )
+            .await?;
+
+        let expected = validation_data.device_id;
+        if let Some(actual) = whoami.device_id.filter(|device_id| *device_id != expected) {
+            return Err(OidcError::DeviceIdMismatch { expected, actual }.into());
+        }
+
+        let session = Session {
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+            user_id: whoami.user_id,
+            device_id: expected,
+        };
+        self.client.restore_session(session).await
+    }
+
+    /// Refresh the access token with the OpenID Connect Provider.
+    ///
+    /// This is called by [`Client::refresh_access_token()`] when the client is
+    /// registered.
+    pub(crate) async fn refresh_access_token(
+        &self,
+        refresh_token: &str,
+    ) -> Result<refresh_token::v3::Response, HttpError> {
+        let tokens = self
+            .request_tokens(&[("grant_type", "refresh_token"), ("refresh_token", refresh_token)])
+            .await
+            .map_err(|error| match error {
+                OidcError::Http(error) => error,
+                error => RefreshTokenError::Oidc(error.into()).into(),
+            })?;
+
+        Ok(assign!(refresh_token::v3::Response::new(tokens.access_token), {
+            refresh_token: tokens.refresh_token,
+            expires_in_ms: tokens.expires_in.map(std::time::Duration::from_secs),
+        }))
+    }
+
+    /// Get the URL of the account management page of the OpenID Connect
+    /// Provider, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The action to present on the page, if any.
+    pub async fn account_management_url(
+        &self,
+        action: Option<AccountManagementAction>,
+    ) -> Result<Option<Url>, OidcError> {
+        let metadata = self.provider_metadata().await?;
+        let Some(mut url) = metadata.account_management_uri.or_else(|| self.data().account.clone())
+        else {
+            return Ok(None);
+        };
+
+        if let Some(action) = action {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("action", action.as_str());
+            if let Some(device_id) = action.device_id() {
+                query.append_pair("device_id", device_id.as_str());
+            }
+        }
+
+        Ok(Some(url))
+    }
+
+    /// Log out by revoking the tokens of the session with the OpenID Connect
+    /// Provider.
+    ///
+    /// The tokens of the session are cleared afterwards, so
+    /// [`Client::session_tokens()`] returns `None` and the `Client` can't send
+    /// authenticated requests anymore.
+    #[instrument(skip_all)]
+    pub async fn logout(&self) -> Result<(), OidcError> {
+        let tokens = self.client.session_tokens().ok_or(OidcError::NotLoggedIn)?;
+        let metadata = self.provider_metadata().await?;
+        let endpoint = metadata.revocation_endpoint.ok_or(OidcError::RevocationNotSupported)?;
+
+        if let Some(refresh_token) = &tokens.refresh_token {
+            self.revoke_token(&endpoint, refresh_token, "refresh_token").await?;
+        }
+        self.revoke_token(&endpoint, &tokens.access_token, "access_token").await?;
+        self.client.base_client().clear_session_tokens();
+
+        debug!("Revoked the tokens of the session");
+        Ok(())
+    }
+
+    async fn revoke_token(
+        &self,
+        endpoint: &Url,
+        token: &str,
+        token_type_hint: &str,
+    ) -> Result<(), OidcError> {
+        let request =
+            self.form_request(endpoint, &[("token", token), ("token_type_hint", token_type_hint)])?;
+        self.send(request).await?;
+        Ok(())
+    }
+
+    async fn request_tokens(&self, params: &[(&str, &str)]) -> Result<TokenResponse, OidcError> {
+        let metadata = self.provider_metadata().await?;
+        let request = self.form_request(&metadata.token_endpoint, params)?;
+        self.send_json(request).await
+    }
+
+    /// Build a `POST` request to the given endpoint of the provider with the
+    /// given parameters and the client credentials as a form.
+    fn form_request(
+        &self,
+        endpoint: &Url,
+        params: &[(&str, &str)],
+    ) -> Result<http::Request<Bytes>, OidcError> {
+        let credentials = self.client_credentials().ok_or(OidcError::NotRegistered)?;
+
+        let mut body = form_urlencoded::Serializer::new(String::new());
+        body.extend_pairs(params).append_pair("client_id", &credentials.client_id);
+        if let Some(client_secret) = &credentials.client_secret {
+            body.append_pair("client_secret", client_secret);
+        }
+
+        Ok(http::Request::builder()
+            .method(Method::POST)
+            .uri(endpoint.as_str())
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(body.finish().into())?)
+    }
+
+    async fn send(&self, request: http::Request<Bytes>) -> Result<Bytes, OidcError> {
+        let http_client = &self.client.inner.http_client;
+        let response =
+            http_client.inner.send_request(request, http_client.request_config.timeout).await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.into_body());
+        }
+
+        let (error, description) =
+            match serde_json::from_slice::<ProviderErrorResponse>(response.body()) {
+                Ok(response) => (response.error, response.error_description),
+                Err(_) => (status.canonical_reason().unwrap_or("unknown").to_owned(), None),
+            };
+        Err(OidcError::Provider { status, error, description })
+    }
+
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: http::Request<Bytes>,
+    ) -> Result<T, OidcError> {
+        let body = self.send(request).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+fn random_string() -> String {
+    thread_rng().sample_iter(Alphanumeric).take(RANDOM_STRING_LENGTH).map(char::from).collect()
+}
+
+// The http mocking library is not supported for wasm32
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use assert_matches::assert_matches;
+    use matrix_sdk_base::Session;
+    use matrix_sdk_test::async_test;
+    use ruma::{api::MatrixVersion, device_id, owned_device_id, user_id, UserId};
+    use serde_json::json;
+    use url::Url;
+    use wiremock::{
+        matchers::{body_string_contains, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{AccountManagementAction, ClientCredentials, ClientMetadata, OidcError};
+    use crate::{config::RequestConfig, test_utils::test_client_builder, Client, Error};
+
+    fn credentials() -> ClientCredentials {
+        ClientCredentials { client_id: "01CLIENT".to_owned(), client_secret: None }
+    }
+
+    async fn mock_provider_metadata(server: &MockServer) -> Url {
+        let issuer = format!("{}/oidc/", server.uri());
+
+        Mock::given(method("GET"))
+            .and(path("/oidc/.well-known/openid-configuration"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{issuer}authorize"),
+                "token_endpoint": format!("{issuer}token"),
+                "registration_endpoint": format!("{issuer}register"),
+                "revocation_endpoint": format!("{issuer}revoke"),
+                "response_types_supported": ["code"],
+                "code_challenge_methods_supported": ["S256"],
+            })))
+            .mount(server)
+            .await;
+
+        Url::parse(&issuer).unwrap()
+    }
+
+    async fn registered_client(server: &MockServer) -> Client {
+        let issuer = mock_provider_metadata(server).await;
+        let client = test_client_builder(Some(server.uri()))
+            .request_config(RequestConfig::new().disable_retry())
+            .handle_refresh_tokens()
+            .build()
+            .await
+            .unwrap();
+        client.oidc().restore_registered_client(issuer, credentials());
+        client
+    }
+
+    async fn restore_session(client: &Client) {
+        let session = Session {
+            access_token: "1234".to_owned(),
+            refresh_token: Some("abcd".to_owned()),
+            user_id: user_id!("@example:localhost").to_owned(),
+            device_id: device_id!("DEVICEID").to_owned(),
+        };
+        client.restore_session(session).await.unwrap();
+    }
+
+    #[async_test]
+    async fn discover_and_register() {
+        let server = MockServer::start().await;
+        let server_url = server.uri();
+        let domain = server_url.strip_prefix("http://").unwrap();
+        let alice = UserId::parse(format!("@alice:{domain}")).unwrap();
+        let issuer = mock_provider_metadata(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/.well-known/matrix/client"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "m.homeserver": { "base_url": server_url },
+                "org.matrix.msc2965.authentication": {
+                    "issuer": issuer,
+                    "account": format!("{issuer}account"),
+                },
+            })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/oidc/register"))
+            .and(body_string_contains("\"token_endpoint_auth_method\":\"none\""))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({
+                "client_id": "01CLIENT",
+                "client_id_issued_at": 1_676_000_000,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = Client::builder()
+            .server_name(alice.server_name())
+            .server_versions([MatrixVersion::V1_0])
+            .build()
+            .await
+            .unwrap();
+        let oidc = client.oidc();
+        assert_eq!(oidc.issuer().await, Some(issuer.clone()));
+        assert!(oidc.client_credentials().is_none());
+
+        let metadata = oidc.provider_metadata().await.unwrap();
+        assert_eq!(metadata.token_endpoint.as_str(), format!("{issuer}token"));
+
+        let redirect_uri = Url::parse("http://127.0.0.1/callback").unwrap();
+        let credentials =
+            oidc.register_client(&ClientMetadata::new(vec![redirect_uri])).await.unwrap();
+        assert_eq!(credentials.client_id, "01CLIENT");
+        assert_eq!(oidc.client_credentials().unwrap().client_id, "01CLIENT");
+
+        let url = oidc
+            .account_management_url(Some(AccountManagementAction::SessionView(owned_device_id!(
+                "DEVICEID"
+            ))))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            format!("{issuer}account?action=org.matrix.session_view&device_id=DEVICEID")
+        );
+    }
+
+    #[async_test]
+    async fn login_with_authorization_code() {
+        let server = MockServer::start().await;
+        let client = registered_client(&server).await;
+        let oidc = client.oidc();
+
+        let redirect_uri = Url::parse("http://127.0.0.1/callback").unwrap();
+        let data =
+            oidc.url_for_login(&redirect_uri, Some(owned_device_id!("DEVICEID"))).await.unwrap();
+
+        assert_eq!(data.url.path(), "/oidc/authorize");
+        let query: std::collections::HashMap<_, _> = data.url.query_pairs().into_owned().collect();
+        assert_eq!(query["response_type"], "code");
+        assert_eq!(query["client_id"], "01CLIENT");
+        assert_eq!(query["redirect_uri"], redirect_uri.as_str());
+        assert_eq!(query["state"], data.state);
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert_eq!(query["code_challenge"].len(), 43);
+        assert!(query["scope"].contains("urn:matrix:org.matrix.msc2967.client:device:DEVICEID"));
+
+        Mock::given(method("POST"))
+            .and(path("/oidc/token"))
+            .and(body_string_contains("grant_type=authorization_code"))
+            .and(body_string_contains("code=s3cr3t"))
+            .and(body_string_contains("code_verifier="))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "1234",
+                "refresh_token": "abcd",
+                "token_type": "Bearer",
+                "expires_in": 300,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/account/whoami"))
+            .and(header("authorization", "Bearer 1234"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "user_id": "@example:localhost",
+                "device_id": "DEVICEID",
+            })))
+            .mount(&server)
+            .await;
+
+        // A callback for another login is rejected.
+        let callback = Url::parse("http://127.0.0.1/callback?state=unknown&code=s3cr3t").unwrap();
+        assert_matches!(
+            oidc.finish_login(&callback).await,
+            Err(Error::Oidc(OidcError::InvalidState))
+        );
+
+        let mut callback = redirect_uri.clone();
+        callback.query_pairs_mut().append_pair("state", &data.state).append_pair("code", "s3cr3t");
+        oidc.finish_login(&callback).await.unwrap();
+
+        let session = client.session().unwrap();
+        assert_eq!(session.user_id, "@example:localhost");
+        assert_eq!(session.device_id, "DEVICEID");
+        assert_eq!(session.access_token, "1234");
+        assert_eq!(session.refresh_token.as_deref(), Some("abcd"));
+
+        // The login can't be completed twice.
+        assert_matches!(
+            oidc.finish_login(&callback).await,
+            Err(Error::Oidc(OidcError::InvalidState))
+        );
+    }
+
+    #[async_test]
+    async fn login_with_another_device_id_fails() {
+        let server = MockServer::start().await;
+        let client = registered_client(&server).await;
+        let oidc = client.oidc();
+
+        let redirect_uri = Url::parse("http://127.0.0.1/callback").unwrap();
+        let data =
+            oidc.url_for_login(&redirect_uri, Some(owned_device_id!("DEVICEID"))).await.unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/oidc/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "1234",
+                "refresh_token": "abcd",
+                "token_type": "Bearer",
+            })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/account/whoami"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "user_id": "@example:localhost",
+                "device_id": "OTHERDEVICE",
+            })))
+            .mount(&server)
+            .await;
+
+        let mut callback = redirect_uri;
+        callback.query_pairs_mut().append_pair("state", &data.state).append_pair("code", "s3cr3t");
+        assert_matches!(
+            oidc.finish_login(&callback).await,
+            Err(Error::Oidc(OidcError::DeviceIdMismatch { expected, actual }))
+                if expected == "DEVICEID" && actual == "OTHERDEVICE"
+        );
+        assert!(client.session().is_none());
+    }
+
+    #[async_test]
+    async fn login_denied() {
+        let server = MockServer::start().await;
+        let client = registered_client(&server).await;
+        let oidc = client.oidc();
+
+        let redirect_uri = Url::parse("http://127.0.0.1/callback").unwrap();
+        let data = oidc.url_for_login(&redirect_uri, None).await.unwrap();
+
+        let mut callback = redirect_uri;
+        callback
+            .query_pairs_mut()
+            .append_pair("state", &data.state)
+            .append_pair("error", "access_denied");
+        assert_matches!(
+            oidc.finish_login(&callback).await,
+            Err(Error::Oidc(OidcError::Authorization { error, .. })) if error == "access_denied"
+        );
+        assert!(client.session().is_none());
+    }
+
+    #[async_test]
+    async fn refresh_access_token_with_provider() {
+        let server = MockServer::start().await;
+        let client = registered_client(&server).await;
+        restore_session(&client).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/account/whoami"))
+            .and(header("authorization", "Bearer 1234"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "errcode": "M_UNKNOWN_TOKEN",
+                "error": "Access token has expired",
+                "soft_logout": true,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/oidc/token"))
+            .and(body_string_contains("grant_type=refresh_token"))
+            .and(body_string_contains("refresh_token=abcd"))
+            .and(body_string_contains("client_id=01CLIENT"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "5678",
+                "refresh_token": "efgh",
+                "token_type": "Bearer",
+                "expires_in": 300,
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/account/whoami"))
+            .and(header("authorization", "Bearer 5678"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "user_id": "@example:localhost",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        client.whoami().await.unwrap();
+
+        let tokens = client.session_tokens().unwrap();
+        assert_eq!(tokens.access_token, "5678");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("efgh"));
+    }
+
+    #[async_test]
+    async fn logout_revokes_tokens() {
+        let server = MockServer::start().await;
+        let client = registered_client(&server).await;
+        restore_session(&client).await;
+
+        Mock::given(method("POST"))
+            .and(path("/oidc/revoke"))
+            .and(body_string_contains("token_type_hint=refresh_token"))
+            .and(body_string_contains("token=abcd"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/oidc/revoke"))
+            .and(body_string_contains("token_type_hint=access_token"))
+            .and(body_string_contains("token=1234"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        client.oidc().logout().await.unwrap();
+        assert!(client.session_tokens().is_none());
+    }
+}
@@ -33,7 +33,7 @@ use ruma::{
 use serde_json::Value;
 use tracing::{debug, instrument};
 
-use super::Left;
+use super::{send_queue::SendQueue, Left};
 use crate::{
     attachment::AttachmentConfig, error::HttpResult, room::Common, BaseRoom, Client, Result,
     RoomType,
@@ -240,6 +240,31 @@ impl Joined {
         Ok(())
     }
 
+    /// Get the queue of the events waiting to be sent to this room.
+    ///
+    /// The first call loads the events that couldn't be sent before the app
+    /// was closed from the state store, and resumes sending them.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, ruma::{events::room::message::RoomMessageEventContent, room_id}};
+    /// # use url::Url;
+    /// # async {
+    /// # let homeserver = Url::parse("http://localhost:8080")?;
+    /// # let client = Client::new(homeserver).await?;
+    /// # let room_id = room_id!("!test:localhost");
+    /// if let Some(room) = client.get_joined_room(&room_id) {
+    ///     let queue = room.send_queue().await?;
+    ///     let content = RoomMessageEventContent::text_plain("Hello world");
+    ///     let txn_id = queue.push_message(content, None).await?;
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn send_queue(&self) -> Result<SendQueue> {
+        SendQueue::new(self.clone()).await
+    }
+
     /// Send a request to set a single receipt.
     ///
     /// # Arguments
@@ -295,6 +320,27 @@ impl Joined {
         Ok(())
     }
 
+    /// Mark the room as read, up to its latest event.
+    ///
+    /// This sets both the fully-read marker and the public read receipt on the
+    /// latest event of the timeline. The [unread counts] are updated once the
+    /// receipt comes back through the sync.
+    ///
+    /// If the room has no known latest event, this is a no-op.
+    ///
+    /// [unread counts]: crate::BaseRoom::unread_counts
+    #[instrument(skip_all, parent = &self.client.root_span)]
+    pub async fn mark_as_read(&self) -> Result<()> {
+        let Some(event_id) = self.inner.latest_event_id() else {
+            return Ok(());
+        };
+
+        self.send_multiple_receipts(
+            Receipts::new().fully_read_marker(event_id.clone()).public_read_receipt(event_id),
+        )
+        .await
+    }
+
     /// Enable End-to-end encryption in this room.
     ///
     /// This method will be a noop if encryption is already enabled, otherwise
@@ -622,6 +668,10 @@ impl Joined {
                     // TODO query keys here?
                 }
 
+                // Sharing the room key and encrypting modify the crypto store,
+                // make sure no other process is using it in the meantime.
+                let _guard = self.client.encryption().spin_lock_store(None).await?;
+
                 self.preshare_room_key().await?;
 
                 let olm = self.client.olm_machine().expect("Olm machine wasn't started");
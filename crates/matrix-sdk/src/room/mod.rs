@@ -9,6 +9,7 @@ mod invited;
 mod joined;
 mod left;
 mod member;
+pub mod send_queue;
 #[cfg(feature = "experimental-timeline")]
 pub mod timeline;
 
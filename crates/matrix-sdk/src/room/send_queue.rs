@@ -0,0 +1,823 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A persistent queue of events waiting to be sent to a room.
+//!
+//! Events pushed to the [`SendQueue`] of a room are saved in the state store
+//! and sent one after the other, in order, by a background task. If sending an
+//! event fails because of the network, a server error or rate limiting, the
+//! task waits and tries again, with an exponential backoff. Events that the
+//! server refuses, or that still can't be sent after a few attempts, are
+//! dropped from the queue, and reported as failed.
+//!
+//! Since the queue is persisted, events that couldn't be sent before the app
+//! was closed are sent again as soon as the session is restored.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex as StdMutex},
+    time::Duration,
+};
+
+use futures_core::Stream;
+use futures_util::channel::mpsc;
+use matrix_sdk_base::locks::Mutex;
+use matrix_sdk_common::{executor::spawn, sleep::sleep};
+use mime::Mime;
+use ruma::{
+    api::client::error::ErrorKind,
+    events::{
+        AnyMessageLikeEventContent, EventContent, EventContentFromType, MessageLikeEventContent,
+    },
+    serde::Raw,
+    OwnedEventId, OwnedRoomId, OwnedTransactionId, TransactionId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, instrument, warn};
+
+use super::Joined;
+use crate::{
+    attachment::AttachmentConfig, client::WeakClient, Client, Error, HttpError, Result,
+    RumaApiError,
+};
+
+/// The prefix of the key under which the queue of a room is saved.
+const QUEUE_KEY_PREFIX: &str = "send_queue::";
+/// The prefix of the key under which the data of a queued attachment is saved.
+const ATTACHMENT_KEY_PREFIX: &str = "send_queue_attachment::";
+
+/// The delay before the first retry of an event that couldn't be sent.
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// The maximum delay between two retries of an event that couldn't be sent.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+/// The number of times sending an event is attempted before giving up on it.
+const MAX_SEND_ATTEMPTS: u32 = 10;
+
+/// An event waiting in a [`SendQueue`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueuedEvent {
+    /// The transaction ID the event will be sent with.
+    pub transaction_id: OwnedTransactionId,
+    /// What will be sent.
+    pub kind: QueuedEventKind,
+}
+
+impl QueuedEvent {
+    /// The content of this event, if it is a message.
+    pub fn message_content(&self) -> Option<AnyMessageLikeEventContent> {
+        match &self.kind {
+            QueuedEventKind::Message { event_type, content } => {
+                match AnyMessageLikeEventContent::from_parts(event_type, content.json()) {
+                    Ok(content) => Some(content),
+                    Err(e) => {
+                        error!("Failed to deserialize the content of a queued event: {e}");
+                        None
+                    }
+                }
+            }
+            QueuedEventKind::Attachment { .. } => None,
+        }
+    }
+}
+
+/// The kind of a [`QueuedEvent`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QueuedEventKind {
+    /// A message-like event.
+    Message {
+        /// The type of the event.
+        event_type: String,
+        /// The content of the event.
+        content: Raw<AnyMessageLikeEventContent>,
+    },
+    /// An attachment, uploaded right before its event is sent.
+    ///
+    /// The data of the attachment is saved separately in the state store.
+    Attachment {
+        /// The body of the message, usually the file name.
+        body: String,
+        /// The media type of the attachment.
+        content_type: String,
+    },
+}
+
+/// An update of the events in a [`SendQueue`].
+#[derive(Clone, Debug)]
+pub enum SendQueueUpdate {
+    /// The event was sent successfully, and removed from the queue.
+    Sent {
+        /// The transaction ID of the event.
+        transaction_id: OwnedTransactionId,
+        /// The event ID assigned by the server.
+        event_id: OwnedEventId,
+    },
+    /// The server refused the event, or it couldn't be sent after
+    /// several attempts. It was removed from the queue.
+    SendingFailed {
+        /// The transaction ID of the event.
+        transaction_id: OwnedTransactionId,
+        /// Why sending the event failed.
+        error: Arc<Error>,
+    },
+    /// Sending the event failed, it will be retried after the given delay.
+    ///
+    /// The queue is paused in the meantime, to keep the events in order.
+    Retrying {
+        /// The transaction ID of the event.
+        transaction_id: OwnedTransactionId,
+        /// Why sending the event failed.
+        error: Arc<Error>,
+        /// The delay before the next attempt.
+        delay: Duration,
+    },
+    /// The event was removed from the queue with [`SendQueue::cancel`].
+    Cancelled {
+        /// The transaction ID of the event.
+        transaction_id: OwnedTransactionId,
+    },
+    /// The content of the event was replaced with [`SendQueue::edit`].
+    Edited {
+        /// The transaction ID of the event.
+        transaction_id: OwnedTransactionId,
+        /// The new content of the event.
+        content: AnyMessageLikeEventContent,
+    },
+}
+
+/// The data shared by all the [`SendQueue`]s of a room.
+#[derive(Debug, Default)]
+pub(crate) struct SendQueueData {
+    state: Mutex<SendQueueState>,
+    subscribers: StdMutex<Vec<mpsc::UnboundedSender<SendQueueUpdate>>>,
+}
+
+#[derive(Debug, Default)]
+struct SendQueueState {
+    /// Whether the events saved in the state store were loaded.
+    loaded: bool,
+    /// The events waiting to be sent, oldest first.
+    events: VecDeque<QueuedEvent>,
+    /// The transaction ID of the event currently being sent, if any.
+    sending: Option<OwnedTransactionId>,
+    /// Whether the background task sending the events is running.
+    is_running: bool,
+}
+
+/// The queue of events waiting to be sent to a room.
+///
+/// Get the queue of a room with [`Joined::send_queue`]. See the
+/// [module documentation](self) for more details.
+#[derive(Clone, Debug)]
+pub struct SendQueue {
+    room: Joined,
+    data: Arc<SendQueueData>,
+}
+
+impl SendQueue {
+    pub(super) async fn new(room: Joined) -> Result<Self> {
+        let data =
+            room.client.inner.send_queues.entry(room.room_id().to_owned()).or_default().clone();
+        let queue = Self { room, data };
+
+        let mut state = queue.data.state.lock().await;
+
+        if !state.loaded {
+            let saved =
+                queue.room.client.store().get_custom_value(queue.storage_key().as_bytes()).await?;
+
+            if let Some(saved) = saved.filter(|saved| !saved.is_empty()) {
+                state.events = serde_json::from_slice(&saved)?;
+            }
+
+            state.loaded = true;
+        }
+
+        queue.run_if_needed(&mut state);
+        drop(state);
+
+        Ok(queue)
+    }
+
+    /// Add a message to the end of the queue.
+    ///
+    /// Returns the transaction ID the message will be sent with.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content of the message event.
+    ///
+    /// * `txn_id` - The transaction ID to send the message with. If `None`, a
+    ///   new one is generated.
+    pub async fn push_message(
+        &self,
+        content: impl MessageLikeEventContent,
+        txn_id: Option<&TransactionId>,
+    ) -> Result<OwnedTransactionId> {
+        let transaction_id = txn_id.map_or_else(TransactionId::new, ToOwned::to_owned);
+        let kind = QueuedEventKind::Message {
+            event_type: content.event_type().to_string(),
+            content: Raw::new(&content)?.cast(),
+        };
+
+        self.push(QueuedEvent { transaction_id: transaction_id.clone(), kind }).await?;
+
+        Ok(transaction_id)
+    }
+
+    /// Add an attachment to the end of the queue.
+    ///
+    /// The attachment is uploaded when it reaches the front of the queue, and
+    /// is sent without a thumbnail or additional info.
+    ///
+    /// Returns the transaction ID the message will be sent with.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - A textual representation of the media, usually the file name.
+    ///
+    /// * `content_type` - The type of the media.
+    ///
+    /// * `data` - The raw data of the media.
+    ///
+    /// * `txn_id` - The transaction ID to send the message with. If `None`, a
+    ///   new one is generated.
+    pub async fn push_attachment(
+        &self,
+        body: &str,
+        content_type: &Mime,
+        data: Vec<u8>,
+        txn_id: Option<&TransactionId>,
+    ) -> Result<OwnedTransactionId> {
+        let transaction_id = txn_id.map_or_else(TransactionId::new, ToOwned::to_owned);
+
+        self.room
+            .client
+            .store()
+            .set_custom_value(attachment_key(&transaction_id).as_bytes(), data)
+            .await?;
+
+        let kind = QueuedEventKind::Attachment {
+            body: body.to_owned(),
+            content_type: content_type.to_string(),
+        };
+
+        self.push(QueuedEvent { transaction_id: transaction_id.clone(), kind }).await?;
+
+        Ok(transaction_id)
+    }
+
+    /// Remove an event from the queue.
+    ///
+    /// Returns `false` if the event is not in the queue anymore or is being
+    /// sent right now.
+    pub async fn cancel(&self, txn_id: &TransactionId) -> Result<bool> {
+        let mut state = self.data.state.lock().await;
+
+        if state.sending.as_deref() == Some(txn_id) {
+            return Ok(false);
+        }
+
+        let Some(position) = state.events.iter().position(|e| *e.transaction_id == *txn_id) else {
+            return Ok(false);
+        };
+
+        let event = state.events.remove(position).expect("the position was just found");
+        self.save(&state).await?;
+        drop(state);
+
+        self.remove_attachment_data(&event).await;
+        self.notify(SendQueueUpdate::Cancelled { transaction_id: event.transaction_id });
+
+        Ok(true)
+    }
+
+    /// Replace the content of a message waiting in the queue.
+    ///
+    /// Returns `false` if the event is not in the queue anymore, is being sent
+    /// right now, or is an attachment.
+    pub async fn edit(
+        &self,
+        txn_id: &TransactionId,
+        content: AnyMessageLikeEventContent,
+    ) -> Result<bool> {
+        let mut state = self.data.state.lock().await;
+
+        if state.sending.as_deref() == Some(txn_id) {
+            return Ok(false);
+        }
+
+        let Some(event) = state.events.iter_mut().find(|e| *e.transaction_id == *txn_id) else {
+            return Ok(false);
+        };
+
+        if !matches!(event.kind, QueuedEventKind::Message { .. }) {
+            return Ok(false);
+        }
+
+        event.kind = QueuedEventKind::Message {
+            event_type: content.event_type().to_string(),
+            content: Raw::new(&content)?.cast(),
+        };
+
+        self.save(&state).await?;
+        drop(state);
+
+        self.notify(SendQueueUpdate::Edited { transaction_id: txn_id.to_owned(), content });
+
+        Ok(true)
+    }
+
+    /// The events waiting in the queue, oldest first.
+    pub async fn events(&self) -> Vec<QueuedEvent> {
+        self.data.state.lock().await.events.iter().cloned().collect()
+    }
+
+    /// Get a stream of the updates of the events in the queue.
+    pub fn subscribe(&self) -> impl Stream<Item = SendQueueUpdate> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.data.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    async fn push(&self, event: QueuedEvent) -> Result<()> {
+        let mut state = self.data.state.lock().await;
+
+        state.events.push_back(event);
+        self.save(&state).await?;
+        self.run_if_needed(&mut state);
+
+        Ok(())
+    }
+
+    fn storage_key(&self) -> String {
+        format!("{QUEUE_KEY_PREFIX}{}", self.room.room_id())
+    }
+
+    async fn save(&self, state: &SendQueueState) -> Result<()> {
+        let value = serde_json::to_vec(&state.events)?;
+        self.room.client.store().set_custom_value(self.storage_key().as_bytes(), value).await?;
+
+        Ok(())
+    }
+
+    async fn remove_attachment_data(&self, event: &QueuedEvent) {
+        if !matches!(event.kind, QueuedEventKind::Attachment { .. }) {
+            return;
+        }
+
+        let key = attachment_key(&event.transaction_id);
+        if let Err(e) = self.room.client.store().remove_custom_value(key.as_bytes()).await {
+            error!("Failed to remove the data of a queued attachment: {e}");
+        }
+    }
+
+    fn notify(&self, update: SendQueueUpdate) {
+        self.data
+            .subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.unbounded_send(update.clone()).is_ok());
+    }
+
+    /// Spawn the task sending the events, unless it's already running or there
+    /// is nothing to send.
+    fn run_if_needed(&self, state: &mut SendQueueState) {
+        if state.is_running || state.events.is_empty() {
+            return;
+        }
+
+        state.is_running = true;
+
+        let client = WeakClient::from_client(&self.room.client);
+        let room_id = self.room.room_id().to_owned();
+        let data = self.data.clone();
+        spawn(async move { Self::run(client, room_id, data).await });
+    }
+
+    /// Send the events of the queue, until it is empty.
+    ///
+    /// The task only holds a weak reference to the client, so it stops when the
+    /// client is dropped, or when the room isn't joined anymore.
+    #[instrument(skip(client, data))]
+    async fn run(client: WeakClient, room_id: OwnedRoomId, data: Arc<SendQueueData>) {
+        let mut retry_delay = MIN_RETRY_DELAY;
+        let mut attempts = 0;
+        let mut retried_txn_id: Option<OwnedTransactionId> = None;
+
+        loop {
+            let Some(room) = client.get().and_then(|client| client.get_joined_room(&room_id))
+            else {
+                data.state.lock().await.is_running = false;
+                break;
+            };
+            let queue = Self { room, data: data.clone() };
+
+            let event = {
+                let mut state = queue.data.state.lock().await;
+
+                let Some(event) = state.events.front().cloned() else {
+                    state.is_running = false;
+                    break;
+                };
+
+                state.sending = Some(event.transaction_id.clone());
+                event
+            };
+
+            let transaction_id = event.transaction_id.clone();
+
+            // Start over if the event that was retried was cancelled meanwhile.
+            if retried_txn_id.as_ref() != Some(&transaction_id) {
+                retry_delay = MIN_RETRY_DELAY;
+                attempts = 0;
+            }
+
+            attempts += 1;
+            retried_txn_id = None;
+
+            match queue.send(&event).await {
+                Ok(event_id) => {
+                    queue.finish(&event).await;
+                    queue.notify(SendQueueUpdate::Sent { transaction_id, event_id });
+                }
+                Err(error) => match retry_after(&error, retry_delay) {
+                    Some(delay) if attempts < MAX_SEND_ATTEMPTS => {
+                        warn!(?transaction_id, ?delay, "Failed to send a queued event: {error}");
+
+                        queue.data.state.lock().await.sending = None;
+                        queue.notify(SendQueueUpdate::Retrying {
+                            transaction_id: transaction_id.clone(),
+                            error: Arc::new(error),
+                            delay,
+                        });
+
+                        // Don't keep the client alive while waiting.
+                        drop(queue);
+                        sleep(delay).await;
+
+                        retried_txn_id = Some(transaction_id);
+                        retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                    }
+                    _ => {
+                        error!(?transaction_id, attempts, "Failed to send a queued event: {error}");
+
+                        queue.finish(&event).await;
+                        queue.notify(SendQueueUpdate::SendingFailed {
+                            transaction_id,
+                            error: Arc::new(error),
+                        });
+                    }
+                },
+            }
+        }
+    }
+
+    async fn send(&self, event: &QueuedEvent) -> Result<OwnedEventId> {
+        let txn_id = Some(&*event.transaction_id);
+
+        let response = match &event.kind {
+            QueuedEventKind::Message { event_type, content } => {
+                let content = serde_json::from_str(content.json().get())?;
+                self.room.send_raw(content, event_type, txn_id).await?
+            }
+            QueuedEventKind::Attachment { body, content_type } => {
+                let data = self
+                    .room
+                    .client
+                    .store()
+                    .get_custom_value(attachment_key(&event.transaction_id).as_bytes())
+                    .await?
+                    .ok_or_else(|| Error::MissingAttachmentData(event.transaction_id.clone()))?;
+                let content_type = content_type.parse().unwrap_or(mime::APPLICATION_OCTET_STREAM);
+                let config = AttachmentConfig::new().txn_id(&event.transaction_id);
+
+                self.room.send_attachment(body, &content_type, data, config).await?
+            }
+        };
+
+        Ok(response.event_id)
+    }
+
+    /// Remove the given event from the queue, once it was sent or refused.
+    async fn finish(&self, event: &QueuedEvent) {
+        let mut state = self.data.state.lock().await;
+
+        state.sending = None;
+        state.events.retain(|e| e.transaction_id != event.transaction_id);
+
+        if let Err(e) = self.save(&state).await {
+            error!("Failed to save the send queue: {e}");
+        }
+
+        drop(state);
+        self.remove_attachment_data(event).await;
+    }
+}
+
+/// Start sending the events that were left in the queues of the joined rooms
+/// of the given client, for example before the app was closed.
+pub(crate) fn resume_send_queues(client: &Client) {
+    let client = WeakClient::from_client(client);
+
+    spawn(async move {
+        let Some(client) = client.get() else { return };
+
+        for room in client.joined_rooms() {
+            if let Err(e) = room.send_queue().await {
+                error!(room_id = ?room.room_id(), "Failed to resume the send queue: {e}");
+            }
+        }
+    });
+}
+
+fn attachment_key(txn_id: &TransactionId) -> String {
+    format!("{ATTACHMENT_KEY_PREFIX}{txn_id}")
+}
+
+/// How long to wait before trying to send an event again after the given
+/// error, or `None` if the error is permanent.
+fn retry_after(error: &Error, retry_delay: Duration) -> Option<Duration> {
+    let Error::Http(error) = error else {
+        return None;
+    };
+
+    if let HttpError::Reqwest(_) = error {
+        // The network is down, or the server is unreachable.
+        return Some(retry_delay);
+    }
+
+    if let Some(ErrorKind::LimitExceeded { retry_after_ms }) = error.client_api_error_kind() {
+        return Some(retry_after_ms.unwrap_or(retry_delay));
+    }
+
+    let status_code = match error.as_ruma_api_error()? {
+        RumaApiError::ClientApi(e) => e.status_code,
+        RumaApiError::Other(e) => e.status_code,
+        RumaApiError::Uiaa(_) => return None,
+    };
+
+    status_code.is_server_error().then_some(retry_delay)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use assert_matches::assert_matches;
+    use futures_util::StreamExt;
+    use matrix_sdk_base::{store::MemoryStore, Session};
+    use matrix_sdk_common::{sleep::sleep, timeout::timeout};
+    use matrix_sdk_test::{async_test, test_json};
+    use ruma::{
+        device_id, event_id, events::room::message::RoomMessageEventContent, user_id, TransactionId,
+    };
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path_regex},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{SendQueueUpdate, MAX_SEND_ATTEMPTS, QUEUE_KEY_PREFIX};
+    use crate::{
+        config::{RequestConfig, StoreConfig, SyncSettings},
+        test_utils::test_client_builder,
+        Client,
+    };
+
+    async fn client_with_store(server: &MockServer, store: Arc<MemoryStore>) -> Client {
+        let client = test_client_builder(Some(server.uri()))
+            .request_config(RequestConfig::new().disable_retry())
+            .store_config(StoreConfig::new().state_store(store))
+            .build()
+            .await
+            .unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            refresh_token: None,
+            user_id: user_id!("@example:localhost").to_owned(),
+            device_id: device_id!("DEVICEID").to_owned(),
+        };
+        client.restore_session(session).await.unwrap();
+
+        client
+    }
+
+    async fn synced_client() -> (Client, MockServer) {
+        synced_client_with_store(Default::default()).await
+    }
+
+    async fn synced_client_with_store(store: Arc<MemoryStore>) -> (Client, MockServer) {
+        let server = MockServer::start().await;
+        let client = client_with_store(&server, store).await;
+
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/_matrix/client/r0/sync"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::SYNC))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/_matrix/client/r0/rooms/.*/state/m.*room.*encryption.?"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(&*test_json::NOT_FOUND))
+            .mount(&server)
+            .await;
+
+        client.sync_once(SyncSettings::new().timeout(Duration::from_millis(3000))).await.unwrap();
+
+        (client, server)
+    }
+
+    #[async_test]
+    async fn test_events_are_sent_in_order() {
+        let (client, server) = synced_client().await;
+
+        Mock::given(method("PUT"))
+            .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/m.room.message/first"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$first" })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/m.room.message/second"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$second" })),
+            )
+            .mount(&server)
+            .await;
+
+        let room = client.get_joined_room(&test_json::DEFAULT_SYNC_ROOM_ID).unwrap();
+        let queue = room.send_queue().await.unwrap();
+        let mut updates = queue.subscribe();
+
+        let first: &TransactionId = "first".into();
+        let second: &TransactionId = "second".into();
+        queue
+            .push_message(RoomMessageEventContent::text_plain("First"), Some(first))
+            .await
+            .unwrap();
+        queue
+            .push_message(RoomMessageEventContent::text_plain("Second"), Some(second))
+            .await
+            .unwrap();
+
+        let update = updates.next().await.unwrap();
+        assert_matches!(
+            update,
+            SendQueueUpdate::Sent { transaction_id, event_id }
+                if *transaction_id == *first && *event_id == *event_id!("$first")
+        );
+        let update = updates.next().await.unwrap();
+        assert_matches!(
+            update,
+            SendQueueUpdate::Sent { transaction_id, event_id }
+                if *transaction_id == *second && *event_id == *event_id!("$second")
+        );
+
+        assert!(queue.events().await.is_empty());
+    }
+
+    #[async_test]
+    async fn test_queue_is_persisted_and_paused_when_rate_limited() {
+        let (client, server) = synced_client().await;
+
+        Mock::given(method("PUT"))
+            .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+            .respond_with(ResponseTemplate::new(429).set_body_json(json!({
+                "errcode": "M_LIMIT_EXCEEDED",
+                "error": "Too many requests",
+                "retry_after_ms": 60_000,
+            })))
+            .mount(&server)
+            .await;
+
+        let room = client.get_joined_room(&test_json::DEFAULT_SYNC_ROOM_ID).unwrap();
+        let queue = room.send_queue().await.unwrap();
+        let mut updates = queue.subscribe();
+
+        let txn_id =
+            queue.push_message(RoomMessageEventContent::text_plain("Hello"), None).await.unwrap();
+
+        let update = updates.next().await.unwrap();
+        assert_matches!(
+            update,
+            SendQueueUpdate::Retrying { transaction_id, delay, .. }
+                if *transaction_id == *txn_id && delay == Duration::from_secs(60)
+        );
+
+        // The event stays in the queue, and in the store.
+        let key = format!("{QUEUE_KEY_PREFIX}{}", room.room_id());
+        let saved = client.store().get_custom_value(key.as_bytes()).await.unwrap().unwrap();
+        let saved: serde_json::Value = serde_json::from_slice(&saved).unwrap();
+        assert_eq!(saved[0]["transaction_id"], txn_id.as_str());
+
+        // It can be cancelled while the queue is paused.
+        assert!(queue.cancel(&txn_id).await.unwrap());
+        assert!(queue.events().await.is_empty());
+
+        let update = updates.next().await.unwrap();
+        assert_matches!(
+            update,
+            SendQueueUpdate::Cancelled { transaction_id } if *transaction_id == *txn_id
+        );
+    }
+
+    #[async_test]
+    async fn test_event_fails_after_too_many_attempts() {
+        let (client, server) = synced_client().await;
+
+        Mock::given(method("PUT"))
+            .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+            .respond_with(ResponseTemplate::new(429).set_body_json(json!({
+                "errcode": "M_LIMIT_EXCEEDED",
+                "error": "Too many requests",
+                "retry_after_ms": 1,
+            })))
+            .expect(u64::from(MAX_SEND_ATTEMPTS))
+            .mount(&server)
+            .await;
+
+        let room = client.get_joined_room(&test_json::DEFAULT_SYNC_ROOM_ID).unwrap();
+        let queue = room.send_queue().await.unwrap();
+        let mut updates = queue.subscribe();
+
+        let txn_id =
+            queue.push_message(RoomMessageEventContent::text_plain("Hello"), None).await.unwrap();
+
+        for _ in 1..MAX_SEND_ATTEMPTS {
+            let update = updates.next().await.unwrap();
+            assert_matches!(
+                update,
+                SendQueueUpdate::Retrying { transaction_id, .. } if *transaction_id == *txn_id
+            );
+        }
+
+        // The event is given up on after the last attempt.
+        let update = updates.next().await.unwrap();
+        assert_matches!(
+            update,
+            SendQueueUpdate::SendingFailed { transaction_id, .. } if *transaction_id == *txn_id
+        );
+        assert!(queue.events().await.is_empty());
+    }
+
+    #[async_test]
+    async fn test_queue_is_resumed_when_the_session_is_restored() {
+        let store = Arc::new(MemoryStore::new());
+        let (client, server) = synced_client_with_store(store.clone()).await;
+
+        Mock::given(method("PUT"))
+            .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+            .respond_with(ResponseTemplate::new(429).set_body_json(json!({
+                "errcode": "M_LIMIT_EXCEEDED",
+                "error": "Too many requests",
+                "retry_after_ms": 60_000,
+            })))
+            .mount(&server)
+            .await;
+
+        let room = client.get_joined_room(&test_json::DEFAULT_SYNC_ROOM_ID).unwrap();
+        let queue = room.send_queue().await.unwrap();
+        let mut updates = queue.subscribe();
+
+        queue.push_message(RoomMessageEventContent::text_plain("Hello"), None).await.unwrap();
+        assert_matches!(updates.next().await, Some(SendQueueUpdate::Retrying { .. }));
+
+        // The app is closed while the queue is paused…
+        drop((queue, room, client));
+        server.reset().await;
+
+        Mock::given(method("PUT"))
+            .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$sent" })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // … and the event is sent as soon as the session is restored.
+        let _client = client_with_store(&server, store).await;
+
+        let sent = async {
+            while !server
+                .received_requests()
+                .await
+                .unwrap()
+                .iter()
+                .any(|request| request.url.path().contains("/send/"))
+            {
+                sleep(Duration::from_millis(10)).await;
+            }
+        };
+        timeout(Box::pin(sent), Duration::from_secs(5)).await.unwrap();
+    }
+}
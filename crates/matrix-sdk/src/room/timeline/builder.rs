@@ -12,12 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
+use futures_core::Stream;
+use futures_util::{pin_mut, StreamExt};
 use matrix_sdk_base::{
     deserialized_responses::{EncryptionInfo, SyncTimelineEvent},
     locks::Mutex,
 };
+use matrix_sdk_common::executor::spawn;
 use ruma::events::fully_read::FullyReadEventContent;
 use tracing::error;
 
@@ -26,7 +29,10 @@ use super::{
     to_device::{handle_forwarded_room_key_event, handle_room_key_event},
     Timeline, TimelineEventHandlerHandles,
 };
-use crate::room;
+use crate::{
+    room::{self, send_queue::SendQueueUpdate},
+    RoomType,
+};
 
 /// Builder that allows creating and configuring various parts of a
 /// [`Timeline`].
@@ -138,6 +144,29 @@ impl TimelineBuilder {
             handles.push(fully_read_handle);
         }
 
+        if room.room_type() == RoomType::Joined {
+            let joined = room::Joined { inner: room.clone() };
+
+            match joined.send_queue().await {
+                Ok(send_queue) => {
+                    // Subscribe first, so no update is missed for the events
+                    // that are already in the queue.
+                    let updates = send_queue.subscribe();
+
+                    for event in send_queue.events().await {
+                        if let Some(content) = event.message_content() {
+                            inner.handle_local_event(event.transaction_id, content).await;
+                        }
+                    }
+
+                    spawn(handle_send_queue_updates(Arc::downgrade(&inner), updates));
+                }
+                Err(e) => {
+                    error!("Failed to load the send queue of the room: {e}");
+                }
+            }
+        }
+
         let client = room.client.clone();
         let timeline = Timeline {
             inner,
@@ -161,3 +190,20 @@ impl TimelineBuilder {
         timeline
     }
 }
+
+/// Forward the updates of the send queue of the room to the timeline, as long
+/// as it's alive.
+async fn handle_send_queue_updates(
+    inner: Weak<TimelineInner>,
+    updates: impl Stream<Item = SendQueueUpdate>,
+) {
+    pin_mut!(updates);
+
+    while let Some(update) = updates.next().await {
+        let Some(inner) = inner.upgrade() else {
+            break;
+        };
+
+        inner.handle_send_queue_update(update).await;
+    }
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{fmt, ops::Deref, sync::Arc};
+use std::{fmt, ops::Deref, sync::Arc, time::Duration};
 
 use indexmap::IndexMap;
 use matrix_sdk_base::deserialized_responses::{EncryptionInfo, TimelineEvent};
@@ -234,6 +234,14 @@ pub enum EventSendState {
         /// Details about how sending the event failed.
         error: Arc<Error>,
     },
+    /// Sending the local event failed, it will be retried after the given
+    /// delay.
+    Retrying {
+        /// Details about how sending the event failed.
+        error: Arc<Error>,
+        /// The delay before the next attempt.
+        delay: Duration,
+    },
     /// The local event has been sent successfully to the server.
     Sent {
         /// The event ID assigned by the server.
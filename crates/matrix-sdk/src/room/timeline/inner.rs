@@ -53,7 +53,7 @@ use super::{
 };
 use crate::{
     events::SyncTimelineEventWithoutContent,
-    room::{self, timeline::event_item::RemoteEventTimelineItem},
+    room::{self, send_queue::SendQueueUpdate, timeline::event_item::RemoteEventTimelineItem},
     Error, Result,
 };
 
@@ -227,6 +227,89 @@ impl<P: ProfileProvider> TimelineInner<P> {
         state.items.set(idx, Arc::new(new_item));
     }
 
+    /// Update the local echo of an event of the send queue of the room.
+    pub(super) async fn handle_send_queue_update(&self, update: SendQueueUpdate) {
+        match update {
+            SendQueueUpdate::Sent { transaction_id, event_id } => {
+                self.update_event_send_state(&transaction_id, EventSendState::Sent { event_id })
+                    .await;
+            }
+            SendQueueUpdate::SendingFailed { transaction_id, error } => {
+                self.update_event_send_state(
+                    &transaction_id,
+                    EventSendState::SendingFailed { error },
+                )
+                .await;
+            }
+            SendQueueUpdate::Retrying { transaction_id, error, delay } => {
+                self.update_event_send_state(
+                    &transaction_id,
+                    EventSendState::Retrying { error, delay },
+                )
+                .await;
+            }
+            SendQueueUpdate::Cancelled { transaction_id } => {
+                self.discard_local_echo(&transaction_id).await;
+            }
+            SendQueueUpdate::Edited { transaction_id, content } => {
+                self.replace_local_echo_content(&transaction_id, content).await;
+            }
+        }
+    }
+
+    /// Remove the local echo of an event that won't be sent.
+    #[instrument(skip_all, fields(txn_id))]
+    pub(super) async fn discard_local_echo(&self, txn_id: &TransactionId) {
+        let mut state = self.state.lock().await;
+
+        let Some((idx, _)) =
+            rfind_event_item(&state.items, |it| it.transaction_id() == Some(txn_id))
+        else {
+            warn!("Local echo not found, can't discard it");
+            return;
+        };
+
+        state.items.remove(idx);
+
+        // Remove the day divider if the local echo was the only item of its day.
+        if idx > 0
+            && state.items[idx - 1].is_day_divider()
+            && state.items.get(idx).map_or(true, |item| item.is_virtual())
+        {
+            state.items.remove(idx - 1);
+        }
+    }
+
+    /// Replace the content of the local echo of an event that wasn't sent yet.
+    #[instrument(skip_all, fields(txn_id))]
+    pub(super) async fn replace_local_echo_content(
+        &self,
+        txn_id: &TransactionId,
+        content: AnyMessageLikeEventContent,
+    ) {
+        let AnyMessageLikeEventContent::RoomMessage(content) = content else {
+            debug!("Ignoring new content of unsupported type for local echo");
+            return;
+        };
+
+        let mut state = self.state.lock().await;
+
+        let Some((idx, item)) =
+            rfind_event_item(&state.items, |it| it.transaction_id() == Some(txn_id))
+        else {
+            warn!("Local echo not found, can't replace its content");
+            return;
+        };
+
+        let new_content = TimelineItemContent::Message(Message {
+            msgtype: content.msgtype,
+            in_reply_to: content.relates_to.and_then(InReplyToDetails::from_relation),
+            edited: false,
+        });
+        let new_item = TimelineItem::Event(item.with_content(new_content));
+        state.items.set(idx, Arc::new(new_item));
+    }
+
     /// Handle a back-paginated event.
     ///
     /// Returns the number of timeline updates that were made.
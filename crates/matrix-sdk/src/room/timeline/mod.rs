@@ -22,9 +22,11 @@ use eyeball_im::{VectorDiff, VectorSubscriber};
 use futures_core::Stream;
 use im::Vector;
 use matrix_sdk_base::locks::Mutex;
+use mime::Mime;
 use pin_project_lite::pin_project;
 use ruma::{
-    assign, events::AnyMessageLikeEventContent, EventId, MilliSecondsSinceUnixEpoch, TransactionId,
+    assign, events::AnyMessageLikeEventContent, EventId, MilliSecondsSinceUnixEpoch,
+    OwnedTransactionId, TransactionId,
 };
 use thiserror::Error;
 use tracing::{error, instrument, warn};
@@ -238,10 +240,14 @@ impl Timeline {
     /// For simplicity, this method doesn't currently allow custom message
     /// types.
     ///
+    /// The message is pushed to the [send queue] of the room, which sends the
+    /// events in order and retries when the network comes back, even after a
+    /// restart of the app.
+    ///
     /// If the encryption feature is enabled, this method will transparently
     /// encrypt the room message if the room is encrypted.
     ///
-    /// If sending the message fails, the local echo item will change its
+    /// If the server refuses the message, the local echo item will change its
     /// `send_state` to [`EventSendState::SendingFailed`].
     ///
     /// # Arguments
@@ -260,6 +266,7 @@ impl Timeline {
     ///       corresponding [`SyncMessageLikeEvent`], but only for the *sending*
     ///       device. Other devices will not see it.
     ///
+    /// [send queue]: crate::room::send_queue
     /// [`MessageLikeUnsigned`]: ruma::events::MessageLikeUnsigned
     /// [`SyncMessageLikeEvent`]: ruma::events::SyncMessageLikeEvent
     #[instrument(skip(self, content), parent = &self.inner.room().client.root_span, fields(room_id = ?self.room().room_id()))]
@@ -271,13 +278,66 @@ impl Timeline {
         // Not ideal, but works for now.
         let room = Joined { inner: self.room().clone() };
 
-        let response = room.send(content, Some(&txn_id)).await;
-
-        let send_state = match response {
-            Ok(response) => EventSendState::Sent { event_id: response.event_id },
-            Err(error) => EventSendState::SendingFailed { error: Arc::new(error) },
+        let result = match room.send_queue().await {
+            Ok(queue) => queue.push_message(content, Some(&txn_id)).await.map(|_| ()),
+            Err(error) => Err(error),
         };
-        self.inner.update_event_send_state(&txn_id, send_state).await;
+
+        if let Err(error) = result {
+            let send_state = EventSendState::SendingFailed { error: Arc::new(error) };
+            self.inner.update_event_send_state(&txn_id, send_state).await;
+        }
+    }
+
+    /// Send an attachment to the room through its [send queue].
+    ///
+    /// No local echo is added to the timeline for attachments, the event
+    /// appears once it's received back from the server.
+    ///
+    /// Returns the transaction ID the attachment will be sent with.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - A textual representation of the media, usually the file name.
+    ///
+    /// * `content_type` - The type of the media.
+    ///
+    /// * `data` - The raw data of the media.
+    ///
+    /// [send queue]: crate::room::send_queue
+    #[instrument(skip(self, data), fields(room_id = ?self.room().room_id()))]
+    pub async fn send_attachment(
+        &self,
+        body: &str,
+        content_type: &Mime,
+        data: Vec<u8>,
+    ) -> Result<OwnedTransactionId> {
+        let room = Joined { inner: self.room().clone() };
+        room.send_queue().await?.push_attachment(body, content_type, data, None).await
+    }
+
+    /// Cancel the sending of a message that is still waiting in the send
+    /// queue, and remove its local echo.
+    ///
+    /// Returns `false` if the message was already sent or is being sent right
+    /// now.
+    pub async fn cancel_send(&self, txn_id: &TransactionId) -> Result<bool> {
+        let room = Joined { inner: self.room().clone() };
+        room.send_queue().await?.cancel(txn_id).await
+    }
+
+    /// Replace the content of a message that is still waiting in the send
+    /// queue, and update its local echo.
+    ///
+    /// Returns `false` if the message was already sent or is being sent right
+    /// now.
+    pub async fn edit_queued(
+        &self,
+        txn_id: &TransactionId,
+        content: AnyMessageLikeEventContent,
+    ) -> Result<bool> {
+        let room = Joined { inner: self.room().clone() };
+        room.send_queue().await?.edit(txn_id, content).await
     }
 
     /// Fetch unavailable details about the event with the given ID.
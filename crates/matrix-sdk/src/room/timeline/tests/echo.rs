@@ -1,4 +1,4 @@
-use std::{io, sync::Arc};
+use std::{io, sync::Arc, time::Duration};
 
 use assert_matches::assert_matches;
 use eyeball_im::VectorDiff;
@@ -6,13 +6,19 @@ use futures_util::StreamExt;
 use matrix_sdk_test::async_test;
 use ruma::{
     event_id,
-    events::{room::message::RoomMessageEventContent, AnyMessageLikeEventContent},
+    events::{
+        room::message::{MessageType, RoomMessageEventContent},
+        AnyMessageLikeEventContent,
+    },
 };
 use serde_json::json;
 
 use super::{TestTimeline, ALICE, BOB};
 use crate::{
-    room::timeline::{event_item::EventSendState, EventTimelineItem},
+    room::{
+        send_queue::SendQueueUpdate,
+        timeline::{event_item::EventSendState, EventTimelineItem, TimelineItemContent},
+    },
     Error,
 };
 
@@ -155,3 +161,96 @@ async fn remote_echo_new_position() {
     let item = assert_matches!(stream.next().await, Some(VectorDiff::PushBack { value }) => value);
     assert_matches!(item.as_event().unwrap(), EventTimelineItem::Remote(_));
 }
+
+#[async_test]
+async fn queued_local_echo_shows_retries() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    let txn_id = timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("echo"),
+        ))
+        .await;
+
+    let _day_divider =
+        assert_matches!(stream.next().await, Some(VectorDiff::PushBack { value }) => value);
+    let _local_echo =
+        assert_matches!(stream.next().await, Some(VectorDiff::PushBack { value }) => value);
+
+    // Sending the event failed, it will be tried again…
+    let some_io_error = Error::Io(io::Error::new(io::ErrorKind::Other, "this is a test"));
+    timeline
+        .inner
+        .handle_send_queue_update(SendQueueUpdate::Retrying {
+            transaction_id: txn_id.clone(),
+            error: Arc::new(some_io_error),
+            delay: Duration::from_secs(2),
+        })
+        .await;
+
+    let item =
+        assert_matches!(stream.next().await, Some(VectorDiff::Set { index: 1, value }) => value);
+    let event = item.as_event().unwrap().as_local().unwrap();
+    assert_matches!(
+        event.send_state,
+        EventSendState::Retrying { delay, .. } if delay == Duration::from_secs(2)
+    );
+
+    // … and it was sent successfully in the end.
+    timeline
+        .inner
+        .handle_send_queue_update(SendQueueUpdate::Sent {
+            transaction_id: txn_id,
+            event_id: event_id!("$W6mZSLWMmfuQQ9jhZWeTxFIM").to_owned(),
+        })
+        .await;
+
+    let item =
+        assert_matches!(stream.next().await, Some(VectorDiff::Set { index: 1, value }) => value);
+    let event = item.as_event().unwrap().as_local().unwrap();
+    assert_matches!(event.send_state, EventSendState::Sent { .. });
+}
+
+#[async_test]
+async fn queued_local_echo_edit_and_cancel() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    let txn_id = timeline
+        .handle_local_event(AnyMessageLikeEventContent::RoomMessage(
+            RoomMessageEventContent::text_plain("echo"),
+        ))
+        .await;
+
+    let _day_divider =
+        assert_matches!(stream.next().await, Some(VectorDiff::PushBack { value }) => value);
+    let _local_echo =
+        assert_matches!(stream.next().await, Some(VectorDiff::PushBack { value }) => value);
+
+    // The content of the queued event is replaced…
+    timeline
+        .inner
+        .handle_send_queue_update(SendQueueUpdate::Edited {
+            transaction_id: txn_id.clone(),
+            content: RoomMessageEventContent::text_plain("edited echo").into(),
+        })
+        .await;
+
+    let item =
+        assert_matches!(stream.next().await, Some(VectorDiff::Set { index: 1, value }) => value);
+    let event = item.as_event().unwrap().as_local().unwrap();
+    let msg = assert_matches!(&event.content, TimelineItemContent::Message(msg) => msg);
+    let text = assert_matches!(msg.msgtype(), MessageType::Text(text) => text);
+    assert_eq!(text.body, "edited echo");
+
+    // … then the event is cancelled, which removes the local echo…
+    timeline
+        .inner
+        .handle_send_queue_update(SendQueueUpdate::Cancelled { transaction_id: txn_id })
+        .await;
+
+    assert_matches!(stream.next().await, Some(VectorDiff::Remove { index: 1 }));
+    // … along with its day divider.
+    assert_matches!(stream.next().await, Some(VectorDiff::Remove { index: 0 }));
+}
@@ -1,7 +1,7 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt::Debug,
-    sync::{Arc, Mutex, RwLock as StdRwLock},
+    sync::{atomic::AtomicU64, Arc, Mutex, RwLock as StdRwLock},
 };
 
 use futures_signals::signal::Mutable;
@@ -12,12 +12,13 @@ use ruma::{
     },
     assign, OwnedRoomId,
 };
-use tracing::trace;
+use tracing::{debug, trace, warn};
 use url::Url;
 
 use super::{
-    Error, FrozenSlidingSync, FrozenSlidingSyncView, SlidingSync, SlidingSyncRoom, SlidingSyncView,
-    SlidingSyncViewBuilder,
+    room_storage_key, room_subscription::MANUAL_SUBSCRIBER_ID, Error, FrozenSlidingSync,
+    FrozenSlidingSyncRoom, FrozenSlidingSyncRooms, FrozenSlidingSyncView, SlidingSync,
+    SlidingSyncRoom, SlidingSyncView, SlidingSyncViewBuilder, ROOMS_STORAGE_KEY_SUFFIX,
 };
 use crate::{Client, Result};
 
@@ -228,11 +229,76 @@ impl SlidingSyncBuilder {
         let client = self.client.ok_or(Error::BuildMissingField("client"))?;
 
         let mut delta_token_inner = None;
+        let mut pos_inner = None;
         let mut rooms_found: BTreeMap<OwnedRoomId, SlidingSyncRoom> = BTreeMap::new();
+        let mut frozen_room_ids = BTreeSet::new();
 
         if let Some(storage_key) = &self.storage_key {
             trace!(storage_key, "trying to load from cold");
 
+            if let Some(FrozenSlidingSync { to_device_since, delta_token, pos }) = client
+                .store()
+                .get_custom_value(storage_key.as_bytes())
+                .await?
+                .map(|v| serde_json::from_slice::<FrozenSlidingSync>(&v))
+                .transpose()?
+            {
+                trace!("frozen for generic found");
+                if let Some(since) = to_device_since {
+                    if let Some(to_device_ext) =
+                        self.extensions.get_or_insert_with(Default::default).to_device.as_mut()
+                    {
+                        to_device_ext.since = Some(since);
+                    }
+                }
+                delta_token_inner = delta_token;
+                pos_inner = pos;
+            }
+
+            // If we know the position of the session, we continue it: the
+            // server only sends us what changed since then, so everything we
+            // restore must be taken as is. Otherwise the restored data is only
+            // a preview until the server sent us the real state.
+            let resume = pos_inner.is_some();
+
+            if let Some(FrozenSlidingSyncRooms { room_ids, rooms }) = client
+                .store()
+                .get_custom_value(format!("{storage_key}{ROOMS_STORAGE_KEY_SUFFIX}").as_bytes())
+                .await?
+                .map(|v| serde_json::from_slice::<FrozenSlidingSyncRooms>(&v))
+                .transpose()?
+            {
+                trace!(len = room_ids.len() + rooms.len(), "frozen rooms found");
+
+                for room_id in room_ids {
+                    let Some(frozen_room) = client
+                        .store()
+                        .get_custom_value(room_storage_key(storage_key, &room_id).as_bytes())
+                        .await?
+                        .map(|v| serde_json::from_slice::<FrozenSlidingSyncRoom>(&v))
+                        .transpose()?
+                    else {
+                        warn!(?room_id, "frozen room is missing");
+                        continue;
+                    };
+
+                    rooms_found.insert(
+                        room_id.clone(),
+                        SlidingSyncRoom::from_frozen(frozen_room, client.clone(), !resume),
+                    );
+                    frozen_room_ids.insert(room_id);
+                }
+
+                // Older versions froze all the rooms together, they're frozen
+                // under their own key the next time we save to storage.
+                for (room_id, frozen_room) in rooms {
+                    rooms_found.insert(
+                        room_id,
+                        SlidingSyncRoom::from_frozen(frozen_room, client.clone(), !resume),
+                    );
+                }
+            }
+
             for (name, view) in &mut self.views {
                 if let Some(frozen_view) = client
                     .store()
@@ -241,37 +307,33 @@ impl SlidingSyncBuilder {
                     .map(|v| serde_json::from_slice::<FrozenSlidingSyncView>(&v))
                     .transpose()?
                 {
-                    trace!(name, "frozen for view found");
+                    trace!(name, resume, "frozen for view found");
+
+                    let FrozenSlidingSyncView { rooms_count, rooms_list, ranges, rooms } =
+                        frozen_view;
+
+                    if resume {
+                        view.set_from_resumed(rooms_count, rooms_list, ranges);
+                    } else {
+                        view.set_from_cold(rooms_count, rooms_list);
+                    }
 
-                    let FrozenSlidingSyncView { rooms_count, rooms_list, rooms } = frozen_view;
-                    view.set_from_cold(rooms_count, rooms_list);
+                    // Older versions froze the rooms together with the views.
                     for (key, frozen_room) in rooms.into_iter() {
                         rooms_found.entry(key).or_insert_with(|| {
-                            SlidingSyncRoom::from_frozen(frozen_room, client.clone())
+                            SlidingSyncRoom::from_frozen(frozen_room, client.clone(), true)
                         });
                     }
+                } else if resume {
+                    // We can't continue the session without knowing the state
+                    // of all the views.
+                    debug!(name, "no frozen state for view found, not resuming the session");
+                    pos_inner = None;
                 } else {
                     trace!(name, "no frozen state for view found");
                 }
             }
 
-            if let Some(FrozenSlidingSync { to_device_since, delta_token }) = client
-                .store()
-                .get_custom_value(storage_key.as_bytes())
-                .await?
-                .map(|v| serde_json::from_slice::<FrozenSlidingSync>(&v))
-                .transpose()?
-            {
-                trace!("frozen for generic found");
-                if let Some(since) = to_device_since {
-                    if let Some(to_device_ext) =
-                        self.extensions.get_or_insert_with(Default::default).to_device.as_mut()
-                    {
-                        to_device_ext.since = Some(since);
-                    }
-                }
-                delta_token_inner = delta_token;
-            }
             trace!("sync unfrozen done");
         };
 
@@ -279,6 +341,15 @@ impl SlidingSyncBuilder {
         let rooms = Arc::new(StdRwLock::new(rooms_found));
         let views = Arc::new(StdRwLock::new(self.views));
 
+        // The subscriptions of the builder aren't reference counted.
+        let room_subscribers = self
+            .subscriptions
+            .iter()
+            .map(|(room_id, settings)| {
+                (room_id.clone(), BTreeMap::from([(MANUAL_SUBSCRIBER_ID, settings.clone())]))
+            })
+            .collect();
+
         Ok(SlidingSync {
             homeserver: self.homeserver,
             client,
@@ -286,13 +357,16 @@ impl SlidingSyncBuilder {
 
             views,
             rooms,
+            frozen_room_ids: Arc::new(StdRwLock::new(frozen_room_ids)),
 
             extensions: Mutex::new(self.extensions).into(),
             sent_extensions: Mutex::new(None).into(),
             failure_count: Default::default(),
 
-            pos: Mutable::new(None),
+            pos: Mutable::new(pos_inner),
             delta_token: Mutable::new(delta_token_inner),
+            room_subscribers: Arc::new(StdRwLock::new(room_subscribers)),
+            next_subscriber_id: Arc::new(AtomicU64::new(MANUAL_SUBSCRIBER_ID + 1)),
             subscriptions: Arc::new(StdRwLock::new(self.subscriptions)),
             unsubscribe: Default::default(),
         })
@@ -16,7 +16,12 @@ impl Client {
         &self,
         response: v4::Response,
     ) -> Result<SyncResponse> {
-        let response = self.base_client().process_sliding_sync(response).await?;
+        let response = {
+            #[cfg(feature = "e2e-encryption")]
+            let _guard = self.encryption().spin_lock_store(None).await?;
+
+            self.base_client().process_sliding_sync(response).await?
+        };
         debug!("done processing on base_client");
         self.handle_sync_response(&response).await?;
         Ok(response)
@@ -0,0 +1,181 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! A sliding sync loop dedicated to end-to-end encryption.
+//!
+//! When the `e2ee` and `to_device` extensions are part of the main sliding
+//! sync loop, a slow room list response delays the delivery of the room keys
+//! too. The [`EncryptionSync`] runs a second [`SlidingSync`] instance which
+//! only carries these two extensions, with its own position and cold cache,
+//! so the keys keep arriving while the main loop is backing off or paused.
+//!
+//! The main loop should then be built without these extensions, otherwise the
+//! same to-device events are received twice:
+//!
+//! ```no_run
+//! # use futures::executor::block_on;
+//! # use futures::{pin_mut, StreamExt};
+//! # use matrix_sdk::{sliding_sync::EncryptionSync, Client};
+//! # use url::Url;
+//! # block_on(async {
+//! # let homeserver = Url::parse("http://example.com")?;
+//! # let client = Client::new(homeserver).await?;
+//! let proxy = Url::parse("http://sliding-sync.example.org")?;
+//!
+//! let encryption_sync = EncryptionSync::new(
+//!     client
+//!         .sliding_sync()
+//!         .await
+//!         .homeserver(proxy.clone())
+//!         .cold_cache("encryption"),
+//!     Some("main-app".to_owned()),
+//! )
+//! .await?;
+//!
+//! let main_sync = client
+//!     .sliding_sync()
+//!     .await
+//!     .homeserver(proxy)
+//!     .without_e2ee_extension()
+//!     .without_to_device_extension()
+//!     // any views you want are added here.
+//!     .build()
+//!     .await?;
+//!
+//! let sync = encryption_sync.sync();
+//! pin_mut!(sync);
+//! while let Some(result) = sync.next().await {
+//!     result?;
+//! }
+//! # anyhow::Ok(())
+//! # });
+//! ```
+
+use futures_core::stream::Stream;
+use futures_util::{pin_mut, StreamExt};
+use ruma::{
+    api::client::sync::sync_events::v4::{E2EEConfig, ToDeviceConfig},
+    assign,
+};
+use tracing::warn;
+
+use super::{SlidingSync, SlidingSyncBuilder};
+use crate::Result;
+
+/// A sliding sync loop which only receives the end-to-end encryption data,
+/// i.e. the to-device events and the device list changes.
+///
+/// See the [module documentation](self) for an example.
+#[derive(Clone, Debug)]
+pub struct EncryptionSync {
+    sliding_sync: SlidingSync,
+}
+
+impl EncryptionSync {
+    /// Create a new `EncryptionSync`.
+    ///
+    /// All the views of the given builder are removed, and only the `e2ee`
+    /// and `to_device` extensions are enabled. Use a
+    /// [cold cache](SlidingSyncBuilder::cold_cache) with a storage key that
+    /// differs from the one of the main loop, so both loops keep their own
+    /// position.
+    ///
+    /// # Arguments
+    ///
+    /// * `builder` - The builder of the sliding sync instance, with the
+    /// homeserver and cold cache already configured.
+    ///
+    /// * `lock_holder` - If set, the cross-process lock on the crypto store is
+    /// enabled with this process id, see
+    /// [`Encryption::enable_cross_process_store_lock`]. This is needed if
+    /// another process, for example a notification extension, shares the
+    /// same crypto store.
+    ///
+    /// [`Encryption::enable_cross_process_store_lock`]: crate::encryption::Encryption::enable_cross_process_store_lock
+    pub async fn new(builder: SlidingSyncBuilder, lock_holder: Option<String>) -> Result<Self> {
+        let sliding_sync = builder
+            .no_views()
+            .without_account_data_extension()
+            .without_receipt_extension()
+            .without_typing_extension()
+            .with_e2ee_extension(assign!(E2EEConfig::default(), { enabled: Some(true) }))
+            .with_to_device_extension(assign!(ToDeviceConfig::default(), { enabled: Some(true) }))
+            .build()
+            .await?;
+
+        if let Some(lock_holder) = lock_holder {
+            sliding_sync.client.encryption().enable_cross_process_store_lock(lock_holder).await?;
+        }
+
+        Ok(Self { sliding_sync })
+    }
+
+    /// The underlying [`SlidingSync`] instance.
+    pub fn sliding_sync(&self) -> &SlidingSync {
+        &self.sliding_sync
+    }
+
+    /// Run the sync loop.
+    ///
+    /// The returned stream yields once for every response, the received
+    /// to-device events and device list changes are handed over to the crypto
+    /// machine before. Errors don't end the stream, the next request is sent
+    /// when the stream is polled again.
+    pub fn sync(&self) -> impl Stream<Item = Result<()>> + '_ {
+        async_stream::stream! {
+            let sync = self.sliding_sync.stream();
+            pin_mut!(sync);
+
+            while let Some(update) = sync.next().await {
+                match update {
+                    Ok(_) => yield Ok(()),
+                    Err(e) => {
+                        warn!("Encryption sync failed: {e}");
+                        yield Err(e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use matrix_sdk_test::async_test;
+
+    use super::EncryptionSync;
+    use crate::{sliding_sync::SlidingSyncView, test_utils::logged_in_client};
+
+    #[async_test]
+    async fn test_only_encryption_extensions_are_enabled() {
+        let client = logged_in_client(None).await;
+        let view = SlidingSyncView::builder().name("view").build().unwrap();
+        let builder = client.sliding_sync().await.add_view(view).with_all_extensions();
+
+        let encryption_sync = EncryptionSync::new(builder, None).await.unwrap();
+        let sliding_sync = encryption_sync.sliding_sync();
+
+        assert!(sliding_sync.view("view").is_none());
+
+        let extensions = sliding_sync.extensions.lock().unwrap().clone().unwrap();
+        assert_eq!(extensions.e2ee.unwrap().enabled, Some(true));
+        assert_eq!(extensions.to_device.unwrap().enabled, Some(true));
+        assert!(extensions.account_data.is_none());
+        assert!(extensions.receipt.is_none());
+        assert!(extensions.typing.is_none());
+
+        // Even without any view, there is something to sync.
+        assert!(sliding_sync.has_extensions());
+    }
+}
@@ -456,9 +456,24 @@
 //! [`cold_cache(name)`][`SlidingSyncBuilder::cold_cache`] and for each view
 //! present at `.build()`[`SlidingSyncBuilder::build`] sliding sync will attempt
 //! to load their latest cached version from storage, as well as some overall
-//! information of Sliding Sync. If that succeeded the views `state` has been
-//! set to [`Preload`][SlidingSyncViewState::Preload]. Only room data of rooms
-//! present in one of the views is loaded from storage.
+//! information of Sliding Sync and the required state and timeline of all the
+//! known rooms. If that succeeded the views `state` has been set to
+//! [`Preload`][SlidingSyncViewState::Preload].
+//!
+//! The position of the session with the server is persisted too, so after a
+//! restart Sliding Sync continues the same session: the server only sends what
+//! changed in the meantime instead of a full resync, and the restored room
+//! lists are used as is. If the server doesn't know the session anymore and
+//! answers with `M_UNKNOWN_POS`, Sliding Sync starts a new session and treats
+//! all the restored data as a preview, like when no position was persisted.
+//!
+//! Every room is persisted under its own key, and only rewritten when it was
+//! part of a response. Besides the to-device `since` token, the extensions
+//! don't have a position of their own: the account data and read receipts they
+//! deliver are saved to the state store as they're received, and after a
+//! restart the server only sends what changed since the persisted position.
+//! Typing notifications aren't persisted, they're only relevant live. The
+//! extension settings are sent again with the first request after a restart.
 //!
 //! Once [#1441](https://github.com/matrix-org/matrix-rust-sdk/pull/1441) is merged
 //! one can disable caching on a per-view basis by setting
@@ -481,6 +496,29 @@
 //! Only the latest 10 timeline items of each room are cached and they are reset
 //! whenever a new set of timeline items is received by the server.
 //!
+//! ## Room list service
+//!
+//! Most user-facing clients combine a [`Selective`][SlidingSyncMode::Selective]
+//! view for the visible rooms with a background full-sync view, and turn the
+//! resulting entries into display data. The [`RoomListService`] does exactly
+//! that and exposes the room list as a single stream of
+//! [`VectorDiff`](eyeball_im::VectorDiff)s, with client-side filters on top.
+//!
+//! ## Encryption sync
+//!
+//! The e2ee and to-device extensions can be moved out of the main loop into
+//! a dedicated [`EncryptionSync`] instance, with its own position, so the
+//! room keys keep arriving while the main loop is slow, backing off or
+//! paused. A sliding sync instance without any view keeps running as long as
+//! it has extensions or room subscriptions configured.
+//!
+//! ## Notifications
+//!
+//! The [`NotificationClient`] builds a push notification out of the room ID
+//! and event ID of a push payload: it fetches and decrypts the event, waiting
+//! for the room key if needed, evaluates the push rules and resolves the
+//! sender and room names.
+//!
 //! ## Bot mode
 //!
 //! _Note_: This is not yet exposed via the API. See [#1475](https://github.com/matrix-org/matrix-rust-sdk/issues/1475)
@@ -614,15 +652,20 @@
 
 mod builder;
 mod client;
+#[cfg(feature = "e2e-encryption")]
+mod encryption_sync;
+mod notification_client;
 mod room;
+mod room_list;
+mod room_subscription;
 mod view;
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt::Debug,
     mem,
     sync::{
-        atomic::{AtomicU8, Ordering},
+        atomic::{AtomicU64, AtomicU8, Ordering},
         Arc, Mutex, RwLock as StdRwLock,
     },
     time::Duration,
@@ -630,9 +673,15 @@ use std::{
 
 pub use builder::*;
 pub use client::*;
+#[cfg(feature = "e2e-encryption")]
+pub use encryption_sync::*;
 use futures_core::stream::Stream;
 use futures_signals::signal::Mutable;
+pub use notification_client::*;
 pub use room::*;
+pub use room_list::*;
+pub use room_subscription::SlidingSyncRoomSubscription;
+use room_subscription::{merge_room_subscriptions, MANUAL_SUBSCRIBER_ID};
 use ruma::{
     api::client::{
         error::ErrorKind,
@@ -774,9 +823,21 @@ pub struct SlidingSync {
     /// The rooms details
     rooms: Arc<StdRwLock<BTreeMap<OwnedRoomId, SlidingSyncRoom>>>,
 
+    /// The rooms that are frozen to storage under their own key.
+    frozen_room_ids: Arc<StdRwLock<BTreeSet<OwnedRoomId>>>,
+
+    /// The room subscriptions sent to the server, i.e. the merged settings of
+    /// all the subscribers of each room.
     subscriptions: Arc<StdRwLock<BTreeMap<OwnedRoomId, v4::RoomSubscription>>>,
     unsubscribe: Arc<StdRwLock<Vec<OwnedRoomId>>>,
 
+    /// The settings asked for by every subscriber of each room, by subscriber
+    /// id.
+    room_subscribers: Arc<StdRwLock<BTreeMap<OwnedRoomId, BTreeMap<u64, v4::RoomSubscription>>>>,
+
+    /// The id of the next [`SlidingSyncRoomSubscription`].
+    next_subscriber_id: Arc<AtomicU64>,
+
     /// keeping track of retries and failure counts
     failure_count: Arc<AtomicU8>,
 
@@ -795,12 +856,16 @@ struct FrozenSlidingSync {
     to_device_since: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     delta_token: Option<String>,
+    /// The position of the session with the server, used to resume it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pos: Option<String>,
 }
 
 impl From<&SlidingSync> for FrozenSlidingSync {
     fn from(v: &SlidingSync) -> Self {
         FrozenSlidingSync {
             delta_token: v.delta_token.get_cloned(),
+            pos: v.pos.get_cloned(),
             to_device_since: v
                 .extensions
                 .lock()
@@ -811,23 +876,96 @@ impl From<&SlidingSync> for FrozenSlidingSync {
     }
 }
 
+/// The suffix of the storage key the rooms are frozen at.
+const ROOMS_STORAGE_KEY_SUFFIX: &str = "#rooms";
+
+/// The storage key the given room is frozen at.
+fn room_storage_key(storage_key: &str, room_id: &RoomId) -> String {
+    format!("{storage_key}{ROOMS_STORAGE_KEY_SUFFIX}::{room_id}")
+}
+
+/// The index of the rooms known to a [`SlidingSync`] that are frozen to
+/// storage, so their required state and timeline survive a restart.
+#[derive(Default, Serialize, Deserialize)]
+struct FrozenSlidingSyncRooms {
+    /// The rooms frozen under their own key, see [`room_storage_key`].
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    room_ids: BTreeSet<OwnedRoomId>,
+    /// Older versions froze all the rooms together.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    rooms: BTreeMap<OwnedRoomId, FrozenSlidingSyncRoom>,
+}
+
 impl SlidingSync {
-    async fn cache_to_storage(&self) -> Result<()> {
+    /// Freeze the state of this sliding sync to storage.
+    ///
+    /// Only the given rooms, which have seen updates, and the rooms that
+    /// haven't been frozen yet are written. The position of the session is
+    /// written last, so it never points past the frozen rooms and views.
+    async fn cache_to_storage(&self, updated_rooms: &[OwnedRoomId]) -> Result<()> {
         let Some(storage_key) = self.storage_key.as_ref() else { return Ok(()) };
         trace!(storage_key, "saving to storage for later use");
-        let v = serde_json::to_vec(&FrozenSlidingSync::from(self))?;
-        self.client.store().set_custom_value(storage_key.as_bytes(), v).await?;
-        let frozen_views = {
-            let rooms_lock = self.rooms.read().unwrap();
-            self.views
-                .read()
-                .unwrap()
+
+        let (frozen_rooms, room_ids) = {
+            let rooms = self.rooms.read().unwrap();
+            let frozen_room_ids = self.frozen_room_ids.read().unwrap();
+            let updated_rooms: BTreeSet<_> = updated_rooms.iter().collect();
+
+            let frozen_rooms = rooms
                 .iter()
-                .map(|(name, view)| {
-                    (name.clone(), FrozenSlidingSyncView::freeze(view, &rooms_lock))
+                .filter(|(room_id, _)| {
+                    updated_rooms.contains(room_id) || !frozen_room_ids.contains(*room_id)
                 })
-                .collect::<Vec<_>>()
+                .map(|(room_id, room)| (room_id.clone(), FrozenSlidingSyncRoom::from(room)))
+                .collect::<Vec<_>>();
+
+            (frozen_rooms, rooms.keys().cloned().collect::<BTreeSet<_>>())
         };
+        trace!(storage_key, len = frozen_rooms.len(), "saving rooms for later use");
+
+        for (room_id, frozen_room) in frozen_rooms {
+            self.client
+                .store()
+                .set_custom_value(
+                    room_storage_key(storage_key, &room_id).as_bytes(),
+                    serde_json::to_vec(&frozen_room)?,
+                )
+                .await?;
+        }
+
+        let previous_room_ids = self.frozen_room_ids.read().unwrap().clone();
+
+        if previous_room_ids != room_ids {
+            self.client
+                .store()
+                .set_custom_value(
+                    format!("{storage_key}{ROOMS_STORAGE_KEY_SUFFIX}").as_bytes(),
+                    serde_json::to_vec(&FrozenSlidingSyncRooms {
+                        room_ids: room_ids.clone(),
+                        ..Default::default()
+                    })?,
+                )
+                .await?;
+
+            // The index doesn't point to the rooms we don't know anymore, their
+            // keys can go away.
+            for room_id in previous_room_ids.difference(&room_ids) {
+                self.client
+                    .store()
+                    .remove_custom_value(room_storage_key(storage_key, room_id).as_bytes())
+                    .await?;
+            }
+
+            *self.frozen_room_ids.write().unwrap() = room_ids;
+        }
+
+        let frozen_views = self
+            .views
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, view)| (name.clone(), FrozenSlidingSyncView::freeze(view)))
+            .collect::<Vec<_>>();
         for (name, frozen) in frozen_views {
             trace!(storage_key, name, "saving to view for later use");
             self.client
@@ -838,6 +976,10 @@ impl SlidingSync {
                 )
                 .await?; // FIXME: parallelize?
         }
+
+        let v = serde_json::to_vec(&FrozenSlidingSync::from(self))?;
+        self.client.store().set_custom_value(storage_key.as_bytes(), v).await?;
+
         Ok(())
     }
 
@@ -871,24 +1013,95 @@ impl SlidingSync {
 
     /// Subscribe to a given room.
     ///
+    /// Calling this again for the same room replaces the settings given
+    /// previously. The subscription is kept until
+    /// [`unsubscribe`](Self::unsubscribe) is called, use
+    /// [`subscribe_with_handle`](Self::subscribe_with_handle) if multiple
+    /// parts of the app need to subscribe to the same room.
+    ///
     /// Note: this does not cancel any pending request, so make sure to only
     /// poll the stream after you've altered this. If you do that during, it
     /// might take one round trip to take effect.
     pub fn subscribe(&self, room_id: OwnedRoomId, settings: Option<v4::RoomSubscription>) {
-        self.subscriptions.write().unwrap().insert(room_id, settings.unwrap_or_default());
+        self.add_room_subscriber(room_id, MANUAL_SUBSCRIBER_ID, settings.unwrap_or_default());
+    }
+
+    /// Subscribe to a given room, until the returned handle is dropped.
+    ///
+    /// The subscriptions to a room are reference counted: the room stays
+    /// subscribed as long as one of its handles is alive, with the widest
+    /// settings of all the subscribers, i.e. the maximum timeline limit and
+    /// the union of the required state.
+    ///
+    /// Note: this does not cancel any pending request, so make sure to only
+    /// poll the stream after you've altered this. If you do that during, it
+    /// might take one round trip to take effect.
+    pub fn subscribe_with_handle(
+        &self,
+        room_id: OwnedRoomId,
+        settings: Option<v4::RoomSubscription>,
+    ) -> SlidingSyncRoomSubscription {
+        let subscriber_id = self.next_subscriber_id.fetch_add(1, Ordering::SeqCst);
+        self.add_room_subscriber(room_id.clone(), subscriber_id, settings.unwrap_or_default());
+
+        SlidingSyncRoomSubscription::new(self.clone(), room_id, subscriber_id)
     }
 
     /// Unsubscribe from a given room.
     ///
+    /// This removes the subscription to the room, even if some
+    /// [`SlidingSyncRoomSubscription`]s for it are still alive.
+    ///
     /// Note: this does not cancel any pending request, so make sure to only
     /// poll the stream after you've altered this. If you do that during, it
     /// might take one round trip to take effect.
     pub fn unsubscribe(&self, room_id: OwnedRoomId) {
+        self.room_subscribers.write().unwrap().remove(&room_id);
+
         if self.subscriptions.write().unwrap().remove(&room_id).is_some() {
             self.unsubscribe.write().unwrap().push(room_id);
         }
     }
 
+    fn add_room_subscriber(
+        &self,
+        room_id: OwnedRoomId,
+        subscriber_id: u64,
+        settings: v4::RoomSubscription,
+    ) {
+        let mut room_subscribers = self.room_subscribers.write().unwrap();
+        let subscribers = room_subscribers.entry(room_id.clone()).or_default();
+        subscribers.insert(subscriber_id, settings);
+        let merged = merge_room_subscriptions(subscribers.values());
+
+        // The room may have been unsubscribed since the last request.
+        self.unsubscribe.write().unwrap().retain(|id| *id != room_id);
+        self.subscriptions.write().unwrap().insert(room_id, merged);
+    }
+
+    fn remove_room_subscriber(&self, room_id: &RoomId, subscriber_id: u64) {
+        let mut room_subscribers = self.room_subscribers.write().unwrap();
+        let Some(subscribers) = room_subscribers.get_mut(room_id) else {
+            // The room has been unsubscribed in the meantime.
+            return;
+        };
+
+        if subscribers.remove(&subscriber_id).is_none() {
+            return;
+        }
+
+        if subscribers.is_empty() {
+            room_subscribers.remove(room_id);
+
+            if self.subscriptions.write().unwrap().remove(room_id).is_some() {
+                self.unsubscribe.write().unwrap().push(room_id.to_owned());
+            }
+        } else {
+            let merged = merge_room_subscriptions(subscribers.values());
+            self.subscriptions.write().unwrap().insert(room_id.to_owned(), merged);
+        }
+    }
+
     /// Add the common extensions if not already configured
     pub fn add_common_extensions(&self) {
         let mut lock = self.extensions.lock().unwrap();
@@ -1026,6 +1239,17 @@ impl SlidingSync {
                 }
             }
 
+            // Rooms whose account data changed, e.g. their tags, have seen an
+            // update too, even if they weren't part of the response.
+            rooms.extend(
+                processed
+                    .rooms
+                    .join
+                    .into_iter()
+                    .filter(|(_, room)| !room.account_data.is_empty())
+                    .map(|(id, _)| id),
+            );
+
             // Update the `to-device` next-batch if found.
             if let Some(to_device_since) = resp.extensions.to_device.map(|t| t.next_batch) {
                 self.update_to_device_since(to_device_since)
@@ -1040,11 +1264,27 @@ impl SlidingSync {
             UpdateSummary { views: updated_views, rooms }
         };
 
-        self.cache_to_storage().await?;
+        self.cache_to_storage(&update.rooms).await?;
 
         Ok(update)
     }
 
+    /// Whether there is at least one room subscription.
+    fn has_room_subscriptions(&self) -> bool {
+        !self.subscriptions.read().unwrap().is_empty()
+    }
+
+    /// Whether at least one extension is configured.
+    fn has_extensions(&self) -> bool {
+        self.extensions.lock().unwrap().as_ref().map_or(false, |ext| {
+            ext.to_device.is_some()
+                || ext.e2ee.is_some()
+                || ext.account_data.is_some()
+                || ext.receipt.is_some()
+                || ext.typing.is_some()
+        })
+    }
+
     async fn sync_once(
         &self,
         views: &mut BTreeMap<String, SlidingSyncViewRequestGenerator>,
@@ -1064,7 +1304,9 @@ impl SlidingSync {
             views.remove(&n);
         }
 
-        if views.is_empty() {
+        // Without any view, we still need to sync as long as the server has
+        // something to send us, e.g. when only running the e2ee extensions.
+        if views.is_empty() && !self.has_room_subscriptions() && !self.has_extensions() {
             return Ok(None);
         }
 
@@ -1169,6 +1411,17 @@ impl SlidingSync {
                             sync_span.in_scope(|| {
                                 warn!("Session expired. Restarting sliding sync.");
                                 *self.pos.lock_mut() = None;
+                                *self.delta_token.lock_mut() = None;
+
+                                // The server will send everything again from
+                                // scratch, don't mix it with what we
+                                // restored or received so far.
+                                for view in self.views.read().unwrap().values() {
+                                    view.mark_as_cold();
+                                }
+                                for room in self.rooms.read().unwrap().values() {
+                                    room.mark_as_cold();
+                                }
 
                                 // reset our extensions to the last known good ones.
                                 *self.extensions.lock().unwrap() = self.sent_extensions.lock().unwrap().take();
@@ -1189,10 +1442,11 @@ impl SlidingSync {
 
 #[cfg(test)]
 mod test {
-    use ruma::room_id;
+    use ruma::{room_id, uint};
     use serde_json::json;
 
     use super::*;
+    use crate::test_utils::logged_in_client;
 
     #[tokio::test]
     async fn check_find_room_in_view() -> Result<()> {
@@ -1251,4 +1505,150 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn frozen_sliding_sync_without_pos_deserializes() {
+        let frozen: FrozenSlidingSync =
+            serde_json::from_value(json!({ "delta_token": "delta", "to_device_since": "since" }))
+                .unwrap();
+
+        assert_eq!(frozen.delta_token.as_deref(), Some("delta"));
+        assert_eq!(frozen.to_device_since.as_deref(), Some("since"));
+        assert!(frozen.pos.is_none());
+    }
+
+    #[test]
+    fn frozen_view_is_restored_as_is_only_when_resuming() {
+        let a = room_id!("!a:matrix.example").to_owned();
+        let b = room_id!("!b:matrix.example").to_owned();
+
+        let view = SlidingSyncView::builder()
+            .name("testview")
+            .sync_mode(SlidingSyncMode::GrowingFullSync)
+            .build()
+            .unwrap();
+        view.rooms_list.lock_mut().replace_cloned(vec![
+            RoomListEntry::Filled(a.clone()),
+            RoomListEntry::Invalidated(b.clone()),
+        ]);
+        view.set_range(0, 1);
+
+        let frozen = FrozenSlidingSyncView::freeze(&view);
+        let frozen: FrozenSlidingSyncView =
+            serde_json::from_slice(&serde_json::to_vec(&frozen).unwrap()).unwrap();
+        assert_eq!(frozen.ranges, vec![(uint!(0), uint!(1))]);
+
+        let mut cold = view.new_builder().reset_ranges().build().unwrap();
+        cold.set_from_cold(frozen.rooms_count, frozen.rooms_list.clone());
+        assert!(cold
+            .rooms_list::<RoomListEntry>()
+            .iter()
+            .all(|entry| matches!(entry, RoomListEntry::Invalidated(_))));
+
+        let mut resumed = view.new_builder().reset_ranges().build().unwrap();
+        resumed.set_from_resumed(frozen.rooms_count, frozen.rooms_list, frozen.ranges);
+        let rooms_list = resumed.rooms_list::<RoomListEntry>();
+        assert!(matches!(&rooms_list[0], RoomListEntry::Filled(room_id) if *room_id == a));
+        assert!(matches!(&rooms_list[1], RoomListEntry::Invalidated(room_id) if *room_id == b));
+        assert_eq!(resumed.ranges.get_cloned(), vec![(uint!(0), uint!(1))]);
+    }
+
+    #[tokio::test]
+    async fn only_updated_rooms_are_frozen_again() -> Result<()> {
+        let client = logged_in_client(None).await;
+        let sliding_sync = client.sliding_sync().await.cold_cache("test").build().await?;
+        let a = room_id!("!a:matrix.example").to_owned();
+        let b = room_id!("!b:matrix.example").to_owned();
+
+        for room_id in [&a, &b] {
+            let room = serde_json::from_value(json!({ "name": room_id.as_str() })).unwrap();
+            sliding_sync.rooms.write().unwrap().insert(
+                room_id.clone(),
+                SlidingSyncRoom::from(client.clone(), room_id.clone(), room, Vec::new()),
+            );
+        }
+
+        // All the rooms are frozen the first time.
+        sliding_sync.cache_to_storage(&[]).await?;
+        let store = client.store();
+        let (a_key, b_key) = (room_storage_key("test", &a), room_storage_key("test", &b));
+        assert!(store.get_custom_value(a_key.as_bytes()).await?.is_some());
+        assert!(store.remove_custom_value(b_key.as_bytes()).await?.is_some());
+
+        // Afterwards, only the updated rooms are.
+        sliding_sync.cache_to_storage(&[a.clone()]).await?;
+        assert!(store.get_custom_value(b_key.as_bytes()).await?.is_none());
+
+        let restored = client.sliding_sync().await.cold_cache("test").build().await?;
+        assert!(restored.get_room(&a).is_some());
+        assert!(restored.get_room(&b).is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn forgotten_rooms_are_removed_from_storage() -> Result<()> {
+        let client = logged_in_client(None).await;
+        let sliding_sync = client.sliding_sync().await.cold_cache("test").build().await?;
+        let a = room_id!("!a:matrix.example").to_owned();
+        let b = room_id!("!b:matrix.example").to_owned();
+
+        for room_id in [&a, &b] {
+            let room = serde_json::from_value(json!({ "name": room_id.as_str() })).unwrap();
+            sliding_sync.rooms.write().unwrap().insert(
+                room_id.clone(),
+                SlidingSyncRoom::from(client.clone(), room_id.clone(), room, Vec::new()),
+            );
+        }
+
+        sliding_sync.cache_to_storage(&[]).await?;
+
+        sliding_sync.rooms.write().unwrap().remove(&b);
+        sliding_sync.cache_to_storage(&[]).await?;
+
+        let store = client.store();
+        assert!(store.get_custom_value(room_storage_key("test", &a).as_bytes()).await?.is_some());
+        assert!(store.get_custom_value(room_storage_key("test", &b).as_bytes()).await?.is_none());
+
+        let index: FrozenSlidingSyncRooms = serde_json::from_slice(
+            &store
+                .get_custom_value(format!("test{ROOMS_STORAGE_KEY_SUFFIX}").as_bytes())
+                .await?
+                .unwrap(),
+        )?;
+        assert_eq!(index.room_ids, BTreeSet::from([a]));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn room_subscription_handles_are_reference_counted() -> Result<()> {
+        let client = logged_in_client(None).await;
+        let sliding_sync = client.sliding_sync().await.build().await?;
+        let room_id = room_id!("!a:matrix.example").to_owned();
+
+        let first = sliding_sync.subscribe_with_handle(
+            room_id.clone(),
+            Some(assign!(v4::RoomSubscription::default(), { timeline_limit: Some(uint!(10)) })),
+        );
+        let second = sliding_sync.subscribe_with_handle(
+            room_id.clone(),
+            Some(assign!(v4::RoomSubscription::default(), { timeline_limit: Some(uint!(20)) })),
+        );
+
+        let timeline_limit =
+            || sliding_sync.subscriptions.read().unwrap().get(&room_id).map(|s| s.timeline_limit);
+        assert_eq!(timeline_limit(), Some(Some(uint!(20))));
+
+        // The other subscriber keeps the room subscribed.
+        drop(second);
+        assert_eq!(timeline_limit(), Some(Some(uint!(10))));
+        assert!(sliding_sync.unsubscribe.read().unwrap().is_empty());
+
+        drop(first);
+        assert_eq!(timeline_limit(), None);
+        assert_eq!(*sliding_sync.unsubscribe.read().unwrap(), vec![room_id.clone()]);
+
+        Ok(())
+    }
 }
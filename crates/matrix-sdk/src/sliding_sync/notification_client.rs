@@ -0,0 +1,630 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! Build push notifications out of a single event.
+//!
+//! Push payloads usually only contain a room ID and an event ID. The
+//! [`NotificationClient`] fetches the event, decrypts it, evaluates the push
+//! rules and resolves the sender's profile and the room name, so everything
+//! needed to display the notification is returned in one call.
+//!
+//! The event is fetched with the `/context` endpoint, with lazy-loaded
+//! members, so the profile of the sender is known even if the members of the
+//! room haven't been synced.
+//!
+//! ```no_run
+//! # use futures::executor::block_on;
+//! # use matrix_sdk::{
+//! #     sliding_sync::{NotificationClient, NotificationStatus},
+//! #     Client,
+//! # };
+//! # use ruma::{event_id, room_id};
+//! # use url::Url;
+//! # block_on(async {
+//! # let homeserver = Url::parse("http://example.com")?;
+//! # let client = Client::new(homeserver).await?;
+//! let builder = client
+//!     .sliding_sync()
+//!     .await
+//!     .homeserver(Url::parse("http://sliding-sync.example.org")?);
+//! let notification_client =
+//!     NotificationClient::new(client).with_sliding_sync(builder);
+//!
+//! match notification_client
+//!     .get_notification(
+//!         room_id!("!room:example.org"),
+//!         event_id!("$event:example.org"),
+//!     )
+//!     .await?
+//! {
+//!     NotificationStatus::Event(item) => {
+//!         // Display the notification.
+//!     }
+//!     NotificationStatus::UnableToDecrypt(item) => {
+//!         // Display a generic notification.
+//!     }
+//!     NotificationStatus::EventFilteredOut => {
+//!         // The push rules say this event must not notify.
+//!     }
+//! }
+//! # anyhow::Ok(())
+//! # });
+//! ```
+
+use std::time::Duration;
+
+use futures_util::{pin_mut, StreamExt};
+use matrix_sdk_base::{deserialized_responses::TimelineEvent, StateChanges};
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk_common::{sleep::sleep, timeout::timeout};
+use ruma::{
+    api::client::{
+        context::get_context,
+        filter::{LazyLoadOptions, RoomEventFilter},
+        sync::sync_events::v4::{self, E2EEConfig, ToDeviceConfig},
+    },
+    assign,
+    events::{
+        room::member::RoomMemberEventContent, AnyStateEvent, AnyTimelineEvent, StateEvent,
+        StateEventType,
+    },
+    push::{Action, Tweak},
+    serde::Raw,
+    uint, EventId, OwnedMxcUri, OwnedRoomId, OwnedUserId, RoomId, UserId,
+};
+use thiserror::Error;
+#[cfg(feature = "e2e-encryption")]
+use tracing::debug;
+use tracing::{instrument, warn};
+
+use super::{SlidingSync, SlidingSyncBuilder};
+use crate::{room, Client, Result};
+
+/// Error type for the [`NotificationClient`].
+#[derive(Debug, Error)]
+pub enum NotificationClientError {
+    /// An error happened while fetching or processing the event.
+    #[error(transparent)]
+    SdkError(#[from] crate::Error),
+
+    /// The room of the notification is unknown, even after syncing it.
+    #[error("the room {0} is unknown")]
+    UnknownRoom(OwnedRoomId),
+}
+
+/// The result of [`NotificationClient::get_notification`].
+#[derive(Clone, Debug)]
+pub enum NotificationStatus {
+    /// The event should notify, it has been decrypted if it was encrypted.
+    Event(NotificationItem),
+    /// The event is encrypted and the room key didn't arrive in time, the item
+    /// contains the encrypted event.
+    UnableToDecrypt(NotificationItem),
+    /// The push rules say this event must not notify.
+    EventFilteredOut,
+}
+
+/// The data needed to display a notification.
+#[derive(Clone, Debug)]
+pub struct NotificationItem {
+    /// The event of the notification.
+    pub event: TimelineEvent,
+    /// The sender of the event.
+    pub sender: OwnedUserId,
+    /// The display name of the sender, if known.
+    pub sender_display_name: Option<String>,
+    /// The avatar of the sender, if known.
+    pub sender_avatar_url: Option<OwnedMxcUri>,
+    /// The display name of the room.
+    pub room_display_name: String,
+    /// The avatar of the room, if any.
+    pub room_avatar_url: Option<OwnedMxcUri>,
+    /// Whether the room is a direct message.
+    pub is_direct: bool,
+    /// Whether the push rules ask for a sound to be played.
+    pub is_noisy: bool,
+    /// Whether the push rules ask for the event to be highlighted.
+    pub is_highlighted: bool,
+}
+
+/// A client to build push notifications out of a single event.
+///
+/// See the [module documentation](self) for an example.
+#[derive(Clone, Debug)]
+pub struct NotificationClient {
+    client: Client,
+    sliding_sync_builder: Option<SlidingSyncBuilder>,
+    decryption_timeout: Duration,
+}
+
+impl NotificationClient {
+    /// How long we wait for the room key of an encrypted event by default.
+    const DEFAULT_DECRYPTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// How long we wait between two decryption attempts, when there is no
+    /// sliding sync to fetch the room keys ourselves.
+    #[cfg(feature = "e2e-encryption")]
+    const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+    /// Create a new `NotificationClient`.
+    ///
+    /// Without [sliding sync](Self::with_sliding_sync), the room of the
+    /// notification must already be known by the client, and the room keys
+    /// must be received by another process sharing the same crypto store.
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            sliding_sync_builder: None,
+            decryption_timeout: Self::DEFAULT_DECRYPTION_TIMEOUT,
+        }
+    }
+
+    /// Use sliding sync to fetch the room of the notification if it's
+    /// unknown, and the room keys if they are missing.
+    ///
+    /// A short-lived [`SlidingSync`] instance, subscribed to the room of the
+    /// notification and running the `e2ee` and `to_device` extensions, is
+    /// built out of the given builder for every notification. It's reused by
+    /// all the decryption attempts, so no to-device event is missed between
+    /// two of them. The builder shouldn't use a cold cache shared with another
+    /// instance.
+    pub fn with_sliding_sync(mut self, builder: SlidingSyncBuilder) -> Self {
+        self.sliding_sync_builder = Some(builder);
+        self
+    }
+
+    /// Set for how long we wait for the room key of an encrypted event before
+    /// giving up with [`NotificationStatus::UnableToDecrypt`].
+    ///
+    /// Defaults to 10 seconds.
+    pub fn with_decryption_timeout(mut self, decryption_timeout: Duration) -> Self {
+        self.decryption_timeout = decryption_timeout;
+        self
+    }
+
+    /// Fetch the event with the given ID in the given room, and build the
+    /// notification for it.
+    #[instrument(skip(self))]
+    pub async fn get_notification(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<NotificationStatus, NotificationClientError> {
+        let sliding_sync = self.build_sliding_sync(room_id).await?;
+
+        if self.client.get_room(room_id).is_none() {
+            if let Some(sliding_sync) = &sliding_sync {
+                sync_once(sliding_sync).await?;
+            }
+        }
+
+        let room = self
+            .client
+            .get_room(room_id)
+            .ok_or_else(|| NotificationClientError::UnknownRoom(room_id.to_owned()))?;
+
+        Ok(self.build_notification(&room, event_id, sliding_sync.as_ref()).await?)
+    }
+
+    async fn build_notification(
+        &self,
+        room: &room::Room,
+        event_id: &EventId,
+        sliding_sync: Option<&SlidingSync>,
+    ) -> Result<NotificationStatus> {
+        let (event, state) = self.fetch_event(room, event_id).await?;
+        #[cfg(feature = "e2e-encryption")]
+        let event = self.retry_decryption(room, event, sliding_sync).await?;
+        #[cfg(not(feature = "e2e-encryption"))]
+        let _ = sliding_sync;
+
+        let is_decrypted = !is_encrypted(&event.event);
+
+        let base_client = self.client.base_client();
+        let changes = StateChanges::default();
+        let push_rules = base_client.get_push_rules(&changes).await?;
+        let actions =
+            match base_client.get_push_room_context(room, &room.clone_info(), &changes).await? {
+                Some(context) => push_rules.get_actions(&event.event, &context).to_owned(),
+                None => {
+                    // We can't evaluate the push rules without the context,
+                    // but the server already decided that this event
+                    // notifies.
+                    warn!("Couldn't build the push context of the room");
+                    vec![Action::Notify]
+                }
+            };
+
+        if is_decrypted && !actions.iter().any(|a| matches!(a, Action::Notify)) {
+            return Ok(NotificationStatus::EventFilteredOut);
+        }
+
+        let sender = event.event.deserialize()?.sender().to_owned();
+        let (sender_display_name, sender_avatar_url) =
+            match room.get_member_no_sync(&sender).await? {
+                Some(member) => (
+                    member.display_name().map(ToOwned::to_owned),
+                    member.avatar_url().map(ToOwned::to_owned),
+                ),
+                None => member_from_state(&state, &sender)
+                    .map(|content| (content.displayname, content.avatar_url))
+                    .unwrap_or_default(),
+            };
+
+        let item = NotificationItem {
+            sender_display_name,
+            sender_avatar_url,
+            sender,
+            room_display_name: room.display_name().await?.to_string(),
+            room_avatar_url: room.avatar_url(),
+            is_direct: room.is_direct(),
+            is_noisy: actions.iter().any(|a| matches!(a, Action::SetTweak(Tweak::Sound(_)))),
+            is_highlighted: actions
+                .iter()
+                .any(|a| matches!(a, Action::SetTweak(Tweak::Highlight(true)))),
+            event,
+        };
+
+        Ok(if is_decrypted {
+            NotificationStatus::Event(item)
+        } else {
+            NotificationStatus::UnableToDecrypt(item)
+        })
+    }
+
+    /// Fetch the event with the given ID with `/context`, along with the
+    /// lazy-loaded member events of the room.
+    ///
+    /// The event isn't decrypted yet.
+    async fn fetch_event(
+        &self,
+        room: &room::Common,
+        event_id: &EventId,
+    ) -> Result<(TimelineEvent, Vec<Raw<AnyStateEvent>>)> {
+        let request = assign!(
+            get_context::v3::Request::new(room.room_id().to_owned(), event_id.to_owned()),
+            {
+                limit: uint!(0),
+                filter: assign!(RoomEventFilter::default(), {
+                    lazy_load_options: LazyLoadOptions::Enabled {
+                        include_redundant_members: false,
+                    },
+                }),
+            }
+        );
+        let response = self.client.send(request, None).await?;
+
+        let event = match response.event {
+            Some(event) => TimelineEvent { event, encryption_info: None },
+            // Old servers may not return the event itself.
+            None => room.event(event_id).await?,
+        };
+
+        Ok((event, response.state))
+    }
+
+    /// Wait for the room key of the given event, if it's encrypted, for at
+    /// most the decryption timeout.
+    ///
+    /// Returns the event as is if it couldn't be decrypted in time.
+    #[cfg(feature = "e2e-encryption")]
+    async fn retry_decryption(
+        &self,
+        room: &room::Common,
+        event: TimelineEvent,
+        sliding_sync: Option<&SlidingSync>,
+    ) -> Result<TimelineEvent> {
+        if !is_encrypted(&event.event) {
+            return Ok(event);
+        }
+
+        let result = timeout(
+            Box::pin(self.wait_for_decryption(room, &event.event, sliding_sync)),
+            self.decryption_timeout,
+        )
+        .await;
+
+        match result {
+            Ok(decrypted) => decrypted,
+            Err(_) => {
+                debug!("Timed out waiting for the room key");
+                Ok(event)
+            }
+        }
+    }
+
+    #[cfg(feature = "e2e-encryption")]
+    async fn wait_for_decryption(
+        &self,
+        room: &room::Common,
+        event: &Raw<AnyTimelineEvent>,
+        sliding_sync: Option<&SlidingSync>,
+    ) -> Result<TimelineEvent> {
+        loop {
+            {
+                // Another process sharing the crypto store may have received
+                // the room key in the meantime, taking the lock reloads our
+                // caches if that's the case.
+                let _guard = self.client.encryption().spin_lock_store(None).await?;
+
+                match room.decrypt_event(event.cast_ref()).await {
+                    Ok(event) => return Ok(event),
+                    Err(e) => debug!("The event can't be decrypted yet: {e}"),
+                }
+            }
+
+            match sliding_sync {
+                Some(sliding_sync) => sync_once(sliding_sync).await?,
+                None => sleep(Self::RETRY_DELAY).await,
+            }
+        }
+    }
+
+    /// Build the sliding sync instance subscribed to the given room, with the
+    /// `e2ee` and `to_device` extensions.
+    ///
+    /// Returns `None` if no sliding sync has been configured.
+    async fn build_sliding_sync(&self, room_id: &RoomId) -> Result<Option<SlidingSync>> {
+        let Some(builder) = self.sliding_sync_builder.clone() else {
+            return Ok(None);
+        };
+
+        let sliding_sync = builder
+            .no_views()
+            .with_e2ee_extension(assign!(E2EEConfig::default(), { enabled: Some(true) }))
+            .with_to_device_extension(assign!(ToDeviceConfig::default(), { enabled: Some(true) }))
+            .build()
+            .await?;
+
+        sliding_sync.subscribe(
+            room_id.to_owned(),
+            Some(assign!(v4::RoomSubscription::default(), {
+                required_state: vec![
+                    (StateEventType::RoomAvatar, "".to_owned()),
+                    (StateEventType::RoomCanonicalAlias, "".to_owned()),
+                    (StateEventType::RoomEncryption, "".to_owned()),
+                    (StateEventType::RoomMember, "$LAZY".to_owned()),
+                    (StateEventType::RoomMember, "$ME".to_owned()),
+                    (StateEventType::RoomName, "".to_owned()),
+                    (StateEventType::RoomPowerLevels, "".to_owned()),
+                ],
+                timeline_limit: Some(uint!(1)),
+            })),
+        );
+
+        Ok(Some(sliding_sync))
+    }
+}
+
+/// Run a single request of the given sliding sync instance.
+async fn sync_once(sliding_sync: &SlidingSync) -> Result<()> {
+    let stream = sliding_sync.stream();
+    pin_mut!(stream);
+
+    if let Some(result) = stream.next().await {
+        result?;
+    }
+
+    Ok(())
+}
+
+/// Find the content of the member event of the given user in the given state.
+fn member_from_state(
+    state: &[Raw<AnyStateEvent>],
+    user_id: &UserId,
+) -> Option<RoomMemberEventContent> {
+    state.iter().find_map(|raw| match raw.deserialize().ok()? {
+        AnyStateEvent::RoomMember(StateEvent::Original(event)) if *event.state_key == *user_id => {
+            Some(event.content)
+        }
+        _ => None,
+    })
+}
+
+/// Whether the given event is still encrypted.
+fn is_encrypted(event: &Raw<AnyTimelineEvent>) -> bool {
+    event.get_field::<String>("type").ok().flatten().as_deref() == Some("m.room.encrypted")
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    #[cfg(feature = "e2e-encryption")]
+    use std::time::Duration;
+
+    use matrix_sdk_test::{async_test, test_json};
+    use ruma::{event_id, room_id, user_id};
+    use serde_json::json;
+    #[cfg(feature = "e2e-encryption")]
+    use wiremock::matchers::query_param;
+    use wiremock::{
+        matchers::{method, path, path_regex},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::{NotificationClient, NotificationClientError, NotificationStatus};
+    use crate::{config::SyncSettings, test_utils::logged_in_client};
+
+    #[async_test]
+    async fn test_notification_for_known_room() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/sync"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::SYNC))
+            .mount(&server)
+            .await;
+        client.sync_once(SyncSettings::default()).await.unwrap();
+
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/_matrix/client/r0/rooms/.*/context/.*"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "event": {
+                    "content": { "body": "Hello example", "msgtype": "m.text" },
+                    "event_id": "$notification:localhost",
+                    "origin_server_ts": 152037280,
+                    "room_id": *test_json::DEFAULT_SYNC_ROOM_ID,
+                    "sender": "@example:localhost",
+                    "type": "m.room.message",
+                },
+                "state": [],
+            })))
+            .mount(&server)
+            .await;
+
+        let notification_client = NotificationClient::new(client);
+        let status = notification_client
+            .get_notification(
+                *test_json::DEFAULT_SYNC_ROOM_ID,
+                event_id!("$notification:localhost"),
+            )
+            .await
+            .unwrap();
+
+        let NotificationStatus::Event(item) = status else {
+            panic!("Expected a notification, got {status:?}");
+        };
+        assert_eq!(item.sender, user_id!("@example:localhost"));
+        assert_eq!(item.sender_display_name.as_deref(), Some("example"));
+    }
+
+    #[async_test]
+    async fn test_notification_for_unknown_room() {
+        let client = logged_in_client(None).await;
+        let notification_client = NotificationClient::new(client);
+
+        let result = notification_client
+            .get_notification(room_id!("!unknown:localhost"), event_id!("$event:localhost"))
+            .await;
+
+        assert!(matches!(result, Err(NotificationClientError::UnknownRoom(_))));
+    }
+
+    /// Mock `/context` to return an encrypted event we don't have the room key
+    /// for, sent by a user whose member event isn't in the store.
+    #[cfg(feature = "e2e-encryption")]
+    async fn mock_undecryptable_context(server: &MockServer) {
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/_matrix/client/r0/rooms/.*/context/.*"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "event": {
+                    "content": {
+                        "algorithm": "m.megolm.v1.aes-sha2",
+                        "ciphertext": "AwgAEpABqOCAaP6NqXquQcEsrGCVInjRTLHmVH8exlDaJ",
+                        "device_id": "ALICEDEVICE",
+                        "sender_key": "r4R3JzHb2Dhqu2CmVDhWOtqLqvV/3Adt3UfeOz4MXOA",
+                        "session_id": "GZwSrUeWOBFrwsQuJoyzrd2ZJnuPs3SxSUtNxhCGhXU",
+                    },
+                    "event_id": "$notification:localhost",
+                    "origin_server_ts": 152037280,
+                    "room_id": *test_json::DEFAULT_SYNC_ROOM_ID,
+                    "sender": "@alice:localhost",
+                    "type": "m.room.encrypted",
+                },
+                "state": [{
+                    "content": { "displayname": "Alice", "membership": "join" },
+                    "event_id": "$alice_member:localhost",
+                    "origin_server_ts": 152037220,
+                    "room_id": *test_json::DEFAULT_SYNC_ROOM_ID,
+                    "sender": "@alice:localhost",
+                    "state_key": "@alice:localhost",
+                    "type": "m.room.member",
+                }],
+            })))
+            .mount(server)
+            .await;
+    }
+
+    #[async_test]
+    #[cfg(feature = "e2e-encryption")]
+    async fn test_notification_times_out_waiting_for_room_key() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/sync"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::SYNC))
+            .mount(&server)
+            .await;
+        client.sync_once(SyncSettings::default()).await.unwrap();
+
+        mock_undecryptable_context(&server).await;
+
+        let notification_client =
+            NotificationClient::new(client).with_decryption_timeout(Duration::from_millis(100));
+        let status = notification_client
+            .get_notification(
+                *test_json::DEFAULT_SYNC_ROOM_ID,
+                event_id!("$notification:localhost"),
+            )
+            .await
+            .unwrap();
+
+        let NotificationStatus::UnableToDecrypt(item) = status else {
+            panic!("Expected an undecryptable notification, got {status:?}");
+        };
+        assert_eq!(item.sender, user_id!("@alice:localhost"));
+        // The member of the sender comes from the state returned by `/context`.
+        assert_eq!(item.sender_display_name.as_deref(), Some("Alice"));
+    }
+
+    #[async_test]
+    #[cfg(feature = "e2e-encryption")]
+    async fn test_decryption_retries_reuse_the_sliding_sync() {
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        Mock::given(method("GET"))
+            .and(path("/_matrix/client/r0/sync"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::SYNC))
+            .mount(&server)
+            .await;
+        client.sync_once(SyncSettings::default()).await.unwrap();
+
+        mock_undecryptable_context(&server).await;
+
+        let sliding_sync_response = ResponseTemplate::new(200)
+            .set_body_json(json!({ "pos": "0", "lists": {}, "rooms": {} }))
+            .set_delay(Duration::from_millis(10));
+        // Every retry continues from the position of the previous one, only the
+        // first request starts from scratch.
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/unstable/org.matrix.msc3575/sync"))
+            .and(query_param("pos", "0"))
+            .respond_with(sliding_sync_response.clone())
+            .expect(1..)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/unstable/org.matrix.msc3575/sync"))
+            .respond_with(sliding_sync_response)
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let builder = client.sliding_sync().await.homeserver(server.uri().parse().unwrap());
+        let notification_client = NotificationClient::new(client)
+            .with_sliding_sync(builder)
+            .with_decryption_timeout(Duration::from_millis(200));
+        let status = notification_client
+            .get_notification(
+                *test_json::DEFAULT_SYNC_ROOM_ID,
+                event_id!("$notification:localhost"),
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(status, NotificationStatus::UnableToDecrypt(_)));
+    }
+}
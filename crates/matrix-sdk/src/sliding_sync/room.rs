@@ -107,18 +107,29 @@ impl From<&SlidingSyncRoom> for FrozenSlidingSyncRoom {
 }
 
 impl SlidingSyncRoom {
-    pub(super) fn from_frozen(val: FrozenSlidingSyncRoom, client: Client) -> Self {
+    /// Restore a room from storage.
+    ///
+    /// A cold room replaces its timeline with the first timeline updates it
+    /// receives, while a resumed room appends them to the restored timeline
+    /// since the server will only send us what's new.
+    pub(super) fn from_frozen(val: FrozenSlidingSyncRoom, client: Client, is_cold: bool) -> Self {
         let FrozenSlidingSyncRoom { room_id, inner, prev_batch, timeline_queue: timeline } = val;
         SlidingSyncRoom {
             client,
             room_id,
             inner,
             is_loading_more: Mutable::new(false),
-            is_cold: Arc::new(AtomicBool::new(true)),
+            is_cold: Arc::new(AtomicBool::new(is_cold)),
             prev_batch: Mutable::new(prev_batch),
             timeline_queue: Arc::new(MutableVec::new_with_values(timeline)),
         }
     }
+
+    /// Replace the timeline with the next timeline updates, because the server
+    /// forgot about our session and will send them again from scratch.
+    pub(super) fn mark_as_cold(&self) {
+        self.is_cold.store(true, Ordering::SeqCst);
+    }
 }
 
 impl SlidingSyncRoom {
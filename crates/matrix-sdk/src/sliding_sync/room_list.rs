@@ -0,0 +1,714 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! A high-level room list on top of [`SlidingSync`].
+//!
+//! Most apps display the room list the same way: the rooms currently visible
+//! on screen are synced with a [`Selective`](SlidingSyncMode::Selective) view,
+//! while all the other rooms are loaded in the background with a
+//! [`GrowingFullSync`](SlidingSyncMode::GrowingFullSync) view. The
+//! [`RoomListService`] owns both views, merges them into a single list of
+//! [`RoomListItem`]s holding the data needed to display each room, and exposes
+//! the changes to that list as a stream of [`VectorDiff`]s.
+//!
+//! ```no_run
+//! # use futures::executor::block_on;
+//! # use futures::{pin_mut, StreamExt};
+//! # use matrix_sdk::{sliding_sync::{RoomListFilter, RoomListService}, Client};
+//! # use url::Url;
+//! # block_on(async {
+//! # let homeserver = Url::parse("http://example.com")?;
+//! # let client = Client::new(homeserver).await?;
+//! let builder = client
+//!     .sliding_sync()
+//!     .await
+//!     .homeserver(Url::parse("http://sliding-sync.example.org")?);
+//! let room_list = RoomListService::new(builder).await?;
+//!
+//! let (rooms, diffs) = room_list.entries();
+//! // Render the `rooms`, then apply the `diffs` as they come in.
+//!
+//! // Only show the unread direct messages.
+//! room_list.set_filters(vec![RoomListFilter::Unread, RoomListFilter::DirectMessages]).await;
+//!
+//! let sync = room_list.sync();
+//! pin_mut!(sync);
+//! while let Some(result) = sync.next().await {
+//!     result?;
+//! }
+//! # anyhow::Ok(())
+//! # });
+//! ```
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex, RwLock as StdRwLock},
+};
+
+use eyeball_im::{ObservableVector, VectorDiff};
+use futures_core::stream::Stream;
+use futures_signals::signal::{Mutable, MutableSignalCloned, SignalExt, SignalStream};
+use futures_util::{pin_mut, StreamExt};
+use im::Vector;
+use ruma::{events::tag::TagName, OwnedRoomId};
+use tracing::{debug, instrument, warn};
+
+use super::{
+    RoomListEntry, SlidingSync, SlidingSyncBuilder, SlidingSyncMode, SlidingSyncState,
+    SlidingSyncView,
+};
+use crate::{room, Client, Result};
+
+/// The name of the view syncing the rooms currently visible to the user.
+pub const VISIBLE_ROOMS_VIEW_NAME: &str = "visible_rooms";
+
+/// The name of the view syncing all the rooms in the background.
+pub const ALL_ROOMS_VIEW_NAME: &str = "all_rooms";
+
+/// The state of the [`RoomListService`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RoomListState {
+    /// The service hasn't received any response yet.
+    #[default]
+    Init,
+    /// The visible rooms are known, the rest of the rooms are still being
+    /// loaded in the background.
+    Settling,
+    /// All the rooms are loaded, only live updates are received.
+    Running,
+    /// The last sync request failed, the service is waiting for the next one
+    /// to succeed.
+    Recovering,
+}
+
+/// A client-side filter on the rooms of the [`RoomListService`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RoomListFilter {
+    /// Only rooms with unread notifications.
+    Unread,
+    /// Only direct messages.
+    DirectMessages,
+    /// Only rooms tagged as favourite.
+    Favourites,
+    /// Only rooms we have been invited to.
+    Invites,
+    /// Only rooms whose name fuzzily matches the given pattern, i.e. contains
+    /// all the characters of the pattern in the same order, ignoring case and
+    /// whitespace.
+    FuzzyName(String),
+}
+
+impl RoomListFilter {
+    fn matches(&self, item: &RoomListItem) -> bool {
+        match self {
+            Self::Unread => item.notification_count > 0 || item.highlight_count > 0,
+            Self::DirectMessages => item.is_dm,
+            Self::Favourites => item.is_favourite,
+            Self::Invites => item.is_invite,
+            Self::FuzzyName(pattern) => {
+                item.name.as_deref().map_or(false, |name| fuzzy_match(pattern, name))
+            }
+        }
+    }
+}
+
+/// The data needed to display a room in the room list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoomListItem {
+    /// The ID of the room.
+    pub room_id: OwnedRoomId,
+    /// The name of the room, as calculated by the server or from the room
+    /// state.
+    pub name: Option<String>,
+    /// Whether this room is a direct message.
+    pub is_dm: bool,
+    /// Whether we have been invited to this room.
+    pub is_invite: bool,
+    /// Whether the room is tagged as favourite.
+    pub is_favourite: bool,
+    /// The number of unread notifications.
+    pub notification_count: u64,
+    /// The number of unread highlights.
+    pub highlight_count: u64,
+    /// Whether the position of the room in the list may be outdated, because
+    /// the server stopped sending us updates for it.
+    pub is_stale: bool,
+}
+
+/// A room list backed by a visible-range view and a background full-sync view.
+///
+/// See the [module documentation](self) for an example.
+#[derive(Clone)]
+pub struct RoomListService {
+    client: Client,
+    sliding_sync: SlidingSync,
+    state: Mutable<RoomListState>,
+    filters: Arc<StdRwLock<Vec<RoomListFilter>>>,
+    entries: Arc<Mutex<ObservableVector<RoomListItem>>>,
+    /// The items of all the rooms in the list, whether they match the filters
+    /// or not, so only the rooms that have seen updates need to be reloaded.
+    items: Arc<Mutex<BTreeMap<OwnedRoomId, RoomListItem>>>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for RoomListService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RoomListService")
+            .field("sliding_sync", &self.sliding_sync)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl RoomListService {
+    /// The number of rooms visible by default.
+    const DEFAULT_VISIBLE_ROOMS: u32 = 20;
+
+    /// How many rooms the background view grows by on each request.
+    const ALL_ROOMS_BATCH_SIZE: u32 = 100;
+
+    /// Create a new `RoomListService`.
+    ///
+    /// The visible-rooms and all-rooms views are added to the given builder,
+    /// replacing any view with the same name. Any other configuration of the
+    /// builder, like the homeserver or the cold cache, is kept.
+    pub async fn new(builder: SlidingSyncBuilder) -> Result<Self> {
+        let visible_rooms = SlidingSyncView::builder()
+            .name(VISIBLE_ROOMS_VIEW_NAME)
+            .sync_mode(SlidingSyncMode::Selective)
+            .set_range(0u32, Self::DEFAULT_VISIBLE_ROOMS - 1)
+            .timeline_limit(1u32)
+            .send_updates_for_items(true)
+            .build()?;
+
+        let all_rooms = SlidingSyncView::builder()
+            .name(ALL_ROOMS_VIEW_NAME)
+            .sync_mode(SlidingSyncMode::GrowingFullSync)
+            .batch_size(Self::ALL_ROOMS_BATCH_SIZE)
+            .timeline_limit(0u32)
+            .build()?;
+
+        let sliding_sync = builder
+            .add_view(visible_rooms)
+            .add_view(all_rooms)
+            .with_common_extensions()
+            .build()
+            .await?;
+
+        let this = Self {
+            client: sliding_sync.client.clone(),
+            sliding_sync,
+            state: Default::default(),
+            filters: Default::default(),
+            entries: Arc::new(Mutex::new(ObservableVector::new())),
+            items: Default::default(),
+        };
+
+        // Show the rooms restored from the cold cache right away, if any.
+        this.refresh_entries(&[]).await;
+
+        Ok(this)
+    }
+
+    /// The underlying [`SlidingSync`] instance.
+    pub fn sliding_sync(&self) -> &SlidingSync {
+        &self.sliding_sync
+    }
+
+    /// Get the current state.
+    pub fn state(&self) -> RoomListState {
+        self.state.get()
+    }
+
+    /// Get a stream of state.
+    pub fn state_stream(&self) -> SignalStream<MutableSignalCloned<RoomListState>> {
+        self.state.signal_cloned().to_stream()
+    }
+
+    /// Get the current rooms, and a stream of changes to them.
+    pub fn entries(&self) -> (Vector<RoomListItem>, impl Stream<Item = VectorDiff<RoomListItem>>) {
+        let entries = self.entries.lock().unwrap();
+        ((*entries).clone(), entries.subscribe())
+    }
+
+    /// Set the range of rooms currently visible to the user.
+    ///
+    /// The range is inclusive and applied on the next sync request.
+    pub fn set_visible_range(&self, start: u32, end: u32) {
+        if let Some(view) = self.sliding_sync.view(VISIBLE_ROOMS_VIEW_NAME) {
+            view.set_range(start, end);
+        }
+    }
+
+    /// Replace the client-side filters, only the rooms matching all of them
+    /// are part of the [`entries`](Self::entries).
+    pub async fn set_filters(&self, filters: Vec<RoomListFilter>) {
+        *self.filters.write().unwrap() = filters;
+        self.refresh_entries(&[]).await;
+    }
+
+    /// Run the sync loop.
+    ///
+    /// The returned stream yields once for every response, the changes to the
+    /// room list are published through [`entries`](Self::entries). Errors
+    /// don't end the stream, the service switches to
+    /// [`RoomListState::Recovering`] until the next successful response.
+    pub fn sync(&self) -> impl Stream<Item = Result<()>> + '_ {
+        async_stream::stream! {
+            let sync = self.sliding_sync.stream();
+            pin_mut!(sync);
+
+            while let Some(update) = sync.next().await {
+                match update {
+                    Ok(update) => {
+                        self.update_state();
+                        self.refresh_entries(&update.rooms).await;
+
+                        yield Ok(());
+                    }
+                    Err(e) => {
+                        warn!("Room list sync failed: {e}");
+                        self.state.set(RoomListState::Recovering);
+
+                        yield Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    fn update_state(&self) {
+        let all_rooms_loaded = self
+            .sliding_sync
+            .view(ALL_ROOMS_VIEW_NAME)
+            .map_or(false, |view| view.state() == SlidingSyncState::Live);
+
+        let next = if all_rooms_loaded { RoomListState::Running } else { RoomListState::Settling };
+        self.state.set_if(next, |before, now| before != now);
+    }
+
+    /// Merge the room lists of both views, in the order given by the server.
+    ///
+    /// The all-rooms view covers the whole list, but the visible-rooms view
+    /// may already know about positions the all-rooms view didn't load yet.
+    fn merged_room_list(&self) -> Vec<RoomListEntry> {
+        let all_rooms = self
+            .sliding_sync
+            .view(ALL_ROOMS_VIEW_NAME)
+            .map(|view| view.rooms_list::<RoomListEntry>())
+            .unwrap_or_default();
+        let visible_rooms = self
+            .sliding_sync
+            .view(VISIBLE_ROOMS_VIEW_NAME)
+            .map(|view| view.rooms_list::<RoomListEntry>())
+            .unwrap_or_default();
+
+        merge_room_lists(all_rooms, visible_rooms)
+    }
+
+    /// Rebuild the room list.
+    ///
+    /// Only the items of the given rooms, which have seen updates, and of the
+    /// rooms we didn't load yet are reloaded, the items of the other rooms
+    /// are reused.
+    #[instrument(skip_all)]
+    async fn refresh_entries(&self, updated_rooms: &[OwnedRoomId]) {
+        let updated_rooms: BTreeSet<_> = updated_rooms.iter().collect();
+        let mut all_items = BTreeMap::new();
+        let mut items = Vec::new();
+        let mut num_reloaded = 0;
+
+        for entry in self.merged_room_list() {
+            let (room_id, is_stale) = match entry {
+                RoomListEntry::Empty => continue,
+                RoomListEntry::Filled(room_id) => (room_id, false),
+                RoomListEntry::Invalidated(room_id) => (room_id, true),
+            };
+
+            let cached = if updated_rooms.contains(&room_id) {
+                None
+            } else {
+                self.items.lock().unwrap().get(&room_id).cloned()
+            };
+
+            let item = match cached {
+                Some(item) => RoomListItem { is_stale, ..item },
+                None => {
+                    num_reloaded += 1;
+                    self.room_list_item(room_id, is_stale).await
+                }
+            };
+
+            all_items.insert(item.room_id.clone(), item.clone());
+            items.push(item);
+        }
+
+        // Forget about the rooms that aren't part of the list anymore.
+        *self.items.lock().unwrap() = all_items;
+
+        let filters = self.filters.read().unwrap().clone();
+        items.retain(|item| filters.iter().all(|filter| filter.matches(item)));
+
+        debug!(count = items.len(), num_reloaded, "Refreshed the room list");
+        apply_entries(&mut self.entries.lock().unwrap(), items);
+    }
+
+    async fn room_list_item(&self, room_id: OwnedRoomId, is_stale: bool) -> RoomListItem {
+        let sliding_sync_room = self.sliding_sync.get_room(&room_id);
+        let room = self.client.get_room(&room_id);
+
+        let name = sliding_sync_room
+            .as_ref()
+            .and_then(|r| r.name().map(ToOwned::to_owned))
+            .or_else(|| room.as_ref()?.name());
+        let is_dm = sliding_sync_room.as_ref().and_then(|r| r.is_dm()).unwrap_or_default()
+            || room.as_ref().map_or(false, |r| r.is_direct());
+        let is_invite = matches!(room, Some(room::Room::Invited(_)));
+
+        let is_favourite = match &room {
+            Some(room) => self.is_favourite(room).await,
+            None => false,
+        };
+
+        let (notification_count, highlight_count) = sliding_sync_room
+            .as_ref()
+            .map(|r| r.unread_notifications().clone())
+            .or_else(|| Some(room.as_ref()?.unread_notification_counts()))
+            .map(|counts| {
+                (
+                    counts.notification_count.map_or(0, u64::from),
+                    counts.highlight_count.map_or(0, u64::from),
+                )
+            })
+            .unwrap_or_default();
+
+        RoomListItem {
+            room_id,
+            name,
+            is_dm,
+            is_invite,
+            is_favourite,
+            notification_count,
+            highlight_count,
+            is_stale,
+        }
+    }
+
+    async fn is_favourite(&self, room: &room::Room) -> bool {
+        match room.tags().await {
+            Ok(tags) => tags.map_or(false, |tags| tags.contains_key(&TagName::Favorite)),
+            Err(e) => {
+                warn!(room_id = ?room.room_id(), "Couldn't load the tags of the room: {e}");
+                false
+            }
+        }
+    }
+}
+
+/// Fill the gaps of the `all_rooms` list with the entries of the
+/// `visible_rooms` list at the same position, and drop the duplicated rooms.
+fn merge_room_lists(
+    all_rooms: Vec<RoomListEntry>,
+    visible_rooms: Vec<RoomListEntry>,
+) -> Vec<RoomListEntry> {
+    let len = all_rooms.len().max(visible_rooms.len());
+    let mut all_rooms = all_rooms.into_iter();
+    let mut visible_rooms = visible_rooms.into_iter();
+    let mut seen = BTreeSet::new();
+    let mut merged = Vec::with_capacity(len);
+
+    for _ in 0..len {
+        let from_all = all_rooms.next().unwrap_or_default();
+        let from_visible = visible_rooms.next().unwrap_or_default();
+
+        let entry = match (from_all, from_visible) {
+            (RoomListEntry::Filled(room_id), _) | (_, RoomListEntry::Filled(room_id)) => {
+                RoomListEntry::Filled(room_id)
+            }
+            (RoomListEntry::Invalidated(room_id), _) | (_, RoomListEntry::Invalidated(room_id)) => {
+                RoomListEntry::Invalidated(room_id)
+            }
+            (RoomListEntry::Empty, RoomListEntry::Empty) => RoomListEntry::Empty,
+        };
+
+        // Invalidated entries may point to a room that moved elsewhere in the
+        // list, only keep the first occurrence of every room.
+        match entry.as_room_id() {
+            Some(room_id) if !seen.insert(room_id.to_owned()) => {}
+            _ => merged.push(entry),
+        }
+    }
+
+    merged
+}
+
+/// Update `entries` to be equal to `items`, emitting as few diffs as easily
+/// possible.
+fn apply_entries(entries: &mut ObservableVector<RoomListItem>, items: Vec<RoomListItem>) {
+    let mut items = items.into_iter();
+
+    for index in 0..entries.len() {
+        match items.next() {
+            Some(item) => {
+                if entries[index] != item {
+                    entries.set(index, item);
+                }
+            }
+            None => {
+                for _ in index..entries.len() {
+                    entries.pop_back();
+                }
+                return;
+            }
+        }
+    }
+
+    for item in items {
+        entries.push_back(item);
+    }
+}
+
+/// Whether all the characters of `pattern` appear in `haystack`, in the same
+/// order, ignoring case and whitespace.
+fn fuzzy_match(pattern: &str, haystack: &str) -> bool {
+    let mut haystack = haystack.chars().flat_map(char::to_lowercase);
+
+    pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .all(|c| haystack.any(|h| h == c))
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use eyeball_im::{ObservableVector, VectorDiff};
+    use futures_util::{pin_mut, StreamExt};
+    use ruma::{room_id, OwnedRoomId};
+
+    use super::{
+        apply_entries, fuzzy_match, merge_room_lists, RoomListFilter, RoomListItem,
+        RoomListService, ALL_ROOMS_VIEW_NAME, VISIBLE_ROOMS_VIEW_NAME,
+    };
+    use crate::sliding_sync::RoomListEntry;
+
+    fn item(room_id: OwnedRoomId, name: &str) -> RoomListItem {
+        RoomListItem {
+            room_id,
+            name: Some(name.to_owned()),
+            is_dm: false,
+            is_invite: false,
+            is_favourite: false,
+            notification_count: 0,
+            highlight_count: 0,
+            is_stale: false,
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert!(fuzzy_match("mtx", "Matrix HQ"));
+        assert!(fuzzy_match("matrix hq", "MatrixHQ"));
+        assert!(fuzzy_match("", "Anything"));
+        assert!(!fuzzy_match("xm", "Matrix"));
+        assert!(!fuzzy_match("matrices", "Matrix"));
+    }
+
+    #[test]
+    fn test_merge_room_lists() {
+        let a = room_id!("!a:example.org").to_owned();
+        let b = room_id!("!b:example.org").to_owned();
+        let c = room_id!("!c:example.org").to_owned();
+
+        let all_rooms = vec![
+            RoomListEntry::Filled(a.clone()),
+            RoomListEntry::Empty,
+            RoomListEntry::Empty,
+            RoomListEntry::Invalidated(a.clone()),
+        ];
+        let visible_rooms = vec![
+            RoomListEntry::Invalidated(c.clone()),
+            RoomListEntry::Filled(b.clone()),
+            RoomListEntry::Invalidated(c.clone()),
+        ];
+
+        let merged = merge_room_lists(all_rooms, visible_rooms);
+        let merged: Vec<_> = merged.iter().map(|e| e.as_room_id().map(ToOwned::to_owned)).collect();
+
+        assert_eq!(merged, vec![Some(a), Some(b), Some(c)]);
+    }
+
+    #[tokio::test]
+    async fn test_apply_entries() {
+        let a = item(room_id!("!a:example.org").to_owned(), "A");
+        let b = item(room_id!("!b:example.org").to_owned(), "B");
+        let c = item(room_id!("!c:example.org").to_owned(), "C");
+
+        let mut entries = ObservableVector::new();
+        let diffs = entries.subscribe();
+        pin_mut!(diffs);
+
+        apply_entries(&mut entries, vec![a.clone(), b.clone()]);
+        assert_matches!(diffs.next().await, Some(VectorDiff::PushBack { value }) if value == a);
+        assert_matches!(diffs.next().await, Some(VectorDiff::PushBack { value }) if value == b);
+
+        apply_entries(&mut entries, vec![a.clone(), c.clone(), b.clone()]);
+        assert_matches!(
+            diffs.next().await,
+            Some(VectorDiff::Set { index: 1, value }) if value == c
+        );
+        assert_matches!(diffs.next().await, Some(VectorDiff::PushBack { value }) if value == b);
+
+        apply_entries(&mut entries, vec![a.clone()]);
+        assert_matches!(diffs.next().await, Some(VectorDiff::PopBack));
+        assert_matches!(diffs.next().await, Some(VectorDiff::PopBack));
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0], a);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_room_list_service_filters_and_order() {
+        use serde_json::json;
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        use crate::test_utils::logged_in_client;
+
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        // The server orders the rooms by recency, not by name.
+        let room_ids = ["!c:example.org", "!a:example.org", "!b:example.org"];
+        let list = json!({
+            "count": 3,
+            "ops": [{ "op": "SYNC", "range": [0, 2], "room_ids": room_ids }],
+        });
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/unstable/org.matrix.msc3575/sync"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "pos": "0",
+                "lists": { VISIBLE_ROOMS_VIEW_NAME: list, ALL_ROOMS_VIEW_NAME: list },
+                "rooms": {
+                    "!a:example.org": { "name": "Alpha", "initial": true },
+                    "!b:example.org": { "name": "Beta", "notification_count": 2, "initial": true },
+                    "!c:example.org": {
+                        "name": "Gamma",
+                        "highlight_count": 1,
+                        "notification_count": 1,
+                        "initial": true,
+                    },
+                },
+            })))
+            .mount(&server)
+            .await;
+
+        let builder = client.sliding_sync().await.homeserver(server.uri().parse().unwrap());
+        let room_list = RoomListService::new(builder).await.unwrap();
+
+        {
+            let sync = room_list.sync();
+            pin_mut!(sync);
+            sync.next().await.unwrap().unwrap();
+        }
+
+        let names = |room_list: &RoomListService| {
+            room_list.entries().0.into_iter().map(|item| item.name.unwrap()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(names(&room_list), ["Gamma", "Alpha", "Beta"]);
+
+        room_list.set_filters(vec![RoomListFilter::Unread]).await;
+        assert_eq!(names(&room_list), ["Gamma", "Beta"]);
+
+        room_list
+            .set_filters(vec![RoomListFilter::Unread, RoomListFilter::FuzzyName("bt".to_owned())])
+            .await;
+        assert_eq!(names(&room_list), ["Beta"]);
+
+        room_list.set_filters(Vec::new()).await;
+        assert_eq!(names(&room_list), ["Gamma", "Alpha", "Beta"]);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[tokio::test]
+    async fn test_room_list_service_only_reloads_updated_rooms() {
+        use serde_json::json;
+        use wiremock::{
+            matchers::{method, path},
+            Mock, MockServer, ResponseTemplate,
+        };
+
+        use crate::test_utils::logged_in_client;
+
+        let server = MockServer::start().await;
+        let client = logged_in_client(Some(server.uri())).await;
+
+        let room_ids = ["!a:example.org", "!b:example.org"];
+        let list = json!({
+            "count": 2,
+            "ops": [{ "op": "SYNC", "range": [0, 1], "room_ids": room_ids }],
+        });
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/unstable/org.matrix.msc3575/sync"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "pos": "0",
+                "lists": { VISIBLE_ROOMS_VIEW_NAME: list, ALL_ROOMS_VIEW_NAME: list },
+                "rooms": {
+                    "!a:example.org": { "name": "Alpha", "initial": true },
+                    "!b:example.org": { "name": "Beta", "initial": true },
+                },
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/_matrix/client/unstable/org.matrix.msc3575/sync"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "pos": "1",
+                "lists": {
+                    VISIBLE_ROOMS_VIEW_NAME: { "count": 2 },
+                    ALL_ROOMS_VIEW_NAME: { "count": 2 },
+                },
+                "rooms": {
+                    "!b:example.org": { "name": "Bravo" },
+                },
+            })))
+            .mount(&server)
+            .await;
+
+        let builder = client.sliding_sync().await.homeserver(server.uri().parse().unwrap());
+        let room_list = RoomListService::new(builder).await.unwrap();
+
+        let sync = room_list.sync();
+        pin_mut!(sync);
+        sync.next().await.unwrap().unwrap();
+
+        // Change the item of a room behind the back of the service, it should
+        // be reused as long as the room doesn't see any updates.
+        room_list.items.lock().unwrap().get_mut(room_id!("!a:example.org")).unwrap().name =
+            Some("Cached".to_owned());
+
+        sync.next().await.unwrap().unwrap();
+
+        let names: Vec<_> =
+            room_list.entries().0.into_iter().map(|item| item.name.unwrap()).collect();
+        assert_eq!(names, ["Cached", "Bravo"]);
+    }
+}
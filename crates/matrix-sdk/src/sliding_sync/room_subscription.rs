@@ -0,0 +1,108 @@
+// Copyright 2023 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+use ruma::{api::client::sync::sync_events::v4, OwnedRoomId, RoomId};
+
+use super::SlidingSync;
+
+/// The subscriber id used by [`SlidingSync::subscribe`], which isn't reference
+/// counted.
+pub(super) const MANUAL_SUBSCRIBER_ID: u64 = 0;
+
+/// A handle on a room subscription, created with
+/// [`SlidingSync::subscribe_with_handle`].
+///
+/// The room stays subscribed as long as at least one handle for it is alive.
+/// Dropping the last handle unsubscribes from the room.
+#[derive(Debug)]
+pub struct SlidingSyncRoomSubscription {
+    sliding_sync: SlidingSync,
+    room_id: OwnedRoomId,
+    subscriber_id: u64,
+}
+
+impl SlidingSyncRoomSubscription {
+    pub(super) fn new(sliding_sync: SlidingSync, room_id: OwnedRoomId, subscriber_id: u64) -> Self {
+        Self { sliding_sync, room_id, subscriber_id }
+    }
+
+    /// The room this subscription is for.
+    pub fn room_id(&self) -> &RoomId {
+        &self.room_id
+    }
+}
+
+impl Drop for SlidingSyncRoomSubscription {
+    fn drop(&mut self) {
+        self.sliding_sync.remove_room_subscriber(&self.room_id, self.subscriber_id);
+    }
+}
+
+/// Merge the settings of all the subscribers of a room into the widest one,
+/// i.e. the maximum timeline limit and the union of the required state.
+pub(super) fn merge_room_subscriptions<'a>(
+    subscriptions: impl IntoIterator<Item = &'a v4::RoomSubscription>,
+) -> v4::RoomSubscription {
+    let mut merged = v4::RoomSubscription::default();
+
+    for subscription in subscriptions {
+        for required_state in &subscription.required_state {
+            if !merged.required_state.contains(required_state) {
+                merged.required_state.push(required_state.clone());
+            }
+        }
+
+        merged.timeline_limit = merged.timeline_limit.max(subscription.timeline_limit);
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{api::client::sync::sync_events::v4, assign, events::StateEventType, uint};
+
+    use super::merge_room_subscriptions;
+
+    #[test]
+    fn merged_room_subscription_is_the_widest() {
+        let first = assign!(v4::RoomSubscription::default(), {
+            required_state: vec![
+                (StateEventType::RoomName, "".to_owned()),
+                (StateEventType::RoomTopic, "".to_owned()),
+            ],
+            timeline_limit: Some(uint!(10)),
+        });
+        let second = assign!(v4::RoomSubscription::default(), {
+            required_state: vec![
+                (StateEventType::RoomTopic, "".to_owned()),
+                (StateEventType::RoomMember, "$LAZY".to_owned()),
+            ],
+            timeline_limit: Some(uint!(20)),
+        });
+        let third = v4::RoomSubscription::default();
+
+        let merged = merge_room_subscriptions([&first, &second, &third]);
+
+        assert_eq!(merged.timeline_limit, Some(uint!(20)));
+        assert_eq!(
+            merged.required_state,
+            vec![
+                (StateEventType::RoomName, "".to_owned()),
+                (StateEventType::RoomTopic, "".to_owned()),
+                (StateEventType::RoomMember, "$LAZY".to_owned()),
+            ]
+        );
+    }
+}
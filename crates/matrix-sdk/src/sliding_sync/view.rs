@@ -99,33 +99,26 @@ pub struct SlidingSyncView {
 pub(super) struct FrozenSlidingSyncView {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub(super) rooms_count: Option<u32>,
+    /// The room list as it was when frozen, invalidated entries included.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub(super) rooms_list: Vec<RoomListEntry>,
+    /// The ranges of the view, only restored for the full-sync modes since
+    /// they manage their ranges themselves.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) ranges: Vec<(UInt, UInt)>,
+    /// The rooms of the view, only written by older versions. The rooms are
+    /// now frozen separately, see `FrozenSlidingSyncRooms`.
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub(super) rooms: BTreeMap<OwnedRoomId, FrozenSlidingSyncRoom>,
 }
 
 impl FrozenSlidingSyncView {
-    pub(super) fn freeze(
-        source_view: &SlidingSyncView,
-        rooms_map: &BTreeMap<OwnedRoomId, SlidingSyncRoom>,
-    ) -> Self {
-        let mut rooms = BTreeMap::new();
-        let mut rooms_list = Vec::new();
-        for entry in source_view.rooms_list.lock_ref().iter() {
-            match entry {
-                RoomListEntry::Filled(o) | RoomListEntry::Invalidated(o) => {
-                    rooms.insert(o.clone(), rooms_map.get(o).expect("rooms always exists").into());
-                }
-                _ => {}
-            };
-
-            rooms_list.push(entry.freeze());
-        }
+    pub(super) fn freeze(source_view: &SlidingSyncView) -> Self {
         FrozenSlidingSyncView {
             rooms_count: *source_view.rooms_count.lock_ref(),
-            rooms_list,
-            rooms,
+            rooms_list: source_view.rooms_list.lock_ref().to_vec(),
+            ranges: source_view.ranges.get_cloned(),
+            rooms: BTreeMap::new(),
         }
     }
 }
@@ -139,7 +132,36 @@ impl SlidingSyncView {
         self.state.set(SlidingSyncState::Preload);
         self.is_cold.store(true, Ordering::SeqCst);
         self.rooms_count.replace(rooms_count);
+        self.rooms_list
+            .lock_mut()
+            .replace_cloned(rooms_list.iter().map(RoomListEntry::freeze).collect());
+    }
+
+    /// Restore the view from storage to continue the same session with the
+    /// server, i.e. the server will only send us what changed since then.
+    ///
+    /// Unlike [`Self::set_from_cold`], the room list is taken as is, since the
+    /// server won't send it again.
+    pub(crate) fn set_from_resumed(
+        &mut self,
+        rooms_count: Option<u32>,
+        rooms_list: Vec<RoomListEntry>,
+        ranges: Vec<(UInt, UInt)>,
+    ) {
+        self.state.set(SlidingSyncState::Preload);
+        self.is_cold.store(false, Ordering::SeqCst);
+        self.rooms_count.replace(rooms_count);
         self.rooms_list.lock_mut().replace_cloned(rooms_list);
+
+        if self.sync_mode != SlidingSyncMode::Selective && !ranges.is_empty() {
+            self.ranges.set(ranges);
+        }
+    }
+
+    /// Forget about the room list on the next response, because the server
+    /// forgot about our session and will send it again from scratch.
+    pub(super) fn mark_as_cold(&self) {
+        self.is_cold.store(true, Ordering::SeqCst);
     }
 
     /// Create a new [`SlidingSyncViewBuilder`].
@@ -4,7 +4,8 @@ use std::{collections::BTreeMap, time::Duration};
 
 pub use matrix_sdk_base::sync::*;
 use matrix_sdk_base::{
-    deserialized_responses::AmbiguityChanges, instant::Instant,
+    deserialized_responses::{AmbiguityChanges, SyncToDeviceEvent},
+    instant::Instant,
     sync::SyncResponse as BaseSyncResponse,
 };
 use ruma::{
@@ -12,7 +13,7 @@ use ruma::{
         push::get_notifications::v3::Notification,
         sync::sync_events::{self, v3::Presence, DeviceLists},
     },
-    events::{AnyGlobalAccountDataEvent, AnyToDeviceEvent},
+    events::AnyGlobalAccountDataEvent,
     serde::Raw,
     DeviceKeyAlgorithm, OwnedRoomId,
 };
@@ -32,8 +33,9 @@ pub struct SyncResponse {
     pub presence: Presence,
     /// The global private data created by this user.
     pub account_data: Vec<Raw<AnyGlobalAccountDataEvent>>,
-    /// Messages sent directly between devices.
-    pub to_device_events: Vec<Raw<AnyToDeviceEvent>>,
+    /// Messages sent directly between devices, encrypted ones are replaced by
+    /// their decrypted version.
+    pub to_device_events: Vec<SyncToDeviceEvent>,
     /// Information on E2E device updates.
     ///
     /// Only present on an incremental sync.
@@ -81,7 +83,14 @@ impl Client {
         &self,
         response: sync_events::v3::Response,
     ) -> Result<BaseSyncResponse> {
-        let response = self.base_client().receive_sync_response(response).await?;
+        let response = {
+            // Decrypting the to-device events modifies the crypto store, make
+            // sure no other process is using it in the meantime.
+            #[cfg(feature = "e2e-encryption")]
+            let _guard = self.encryption().spin_lock_store(None).await?;
+
+            self.base_client().receive_sync_response(response).await?
+        };
         self.handle_sync_response(&response).await?;
         Ok(response)
     }
@@ -102,7 +111,7 @@ impl Client {
         let now = Instant::now();
         self.handle_sync_events(HandlerKind::GlobalAccountData, &None, account_data).await?;
         self.handle_sync_events(HandlerKind::Presence, &None, &presence.events).await?;
-        self.handle_sync_events(HandlerKind::ToDevice, &None, to_device_events).await?;
+        self.handle_sync_to_device_events(to_device_events).await?;
 
         for (room_id, room_info) in &rooms.join {
             let room = self.get_room(room_id);
@@ -1,12 +1,18 @@
-use std::time::Duration;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use assert_matches::assert_matches;
 use futures::{
     channel::{mpsc, oneshot},
-    StreamExt,
+    FutureExt, StreamExt,
 };
 use futures_signals::signal::SignalExt;
-use matrix_sdk::{config::RequestConfig, executor::spawn, HttpError, RefreshTokenError, Session};
+use matrix_sdk::{
+    config::RequestConfig, executor::spawn, HttpError, RefreshTokenError, Session, SessionChange,
+    SessionPersistenceError,
+};
 use matrix_sdk_test::{async_test, test_json};
 use ruma::{
     api::{
@@ -548,3 +554,293 @@ async fn refresh_token_handled_other_error() {
 
     client.whoami().await.unwrap_err();
 }
+
+#[async_test]
+async fn session_change_tokens_refreshed() {
+    let (builder, server) = test_client_builder().await;
+    let client = builder
+        .request_config(RequestConfig::new().disable_retry())
+        .server_versions([MatrixVersion::V1_3])
+        .handle_refresh_tokens()
+        .build()
+        .await
+        .unwrap();
+
+    let session = Session {
+        access_token: "1234".to_owned(),
+        refresh_token: Some("abcd".to_owned()),
+        user_id: user_id!("@example:localhost").to_owned(),
+        device_id: device_id!("DEVICEID").to_owned(),
+    };
+    client.restore_session(session).await.unwrap();
+    let mut session_changes = client.subscribe_to_session_changes();
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/refresh"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::REFRESH_TOKEN))
+        .expect(1)
+        .named("`POST /refresh`")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/account/whoami"))
+        .and(header(http::header::AUTHORIZATION, "Bearer 1234"))
+        .respond_with(
+            ResponseTemplate::new(401).set_body_json(&*test_json::UNKNOWN_TOKEN_SOFT_LOGOUT),
+        )
+        .expect(1)
+        .named("`GET /whoami` wrong token")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/account/whoami"))
+        .and(header(http::header::AUTHORIZATION, "Bearer 5678"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::WHOAMI))
+        .expect(1)
+        .named("`GET /whoami` good token")
+        .mount(&server)
+        .await;
+
+    client.whoami().await.unwrap();
+    assert_eq!(session_changes.next().await, Some(SessionChange::TokensRefreshed));
+}
+
+#[async_test]
+async fn session_change_unknown_token() {
+    let (client, server) = logged_in_client().await;
+    let mut session_changes = client.subscribe_to_session_changes();
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/account/whoami"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+            "errcode": "M_UNKNOWN_TOKEN",
+            "error": "Invalid access token passed.",
+        })))
+        .expect(1)
+        .named("`GET /whoami`")
+        .mount(&server)
+        .await;
+
+    let res = client.whoami().await.unwrap_err();
+    assert_matches!(
+        res.client_api_error_kind(),
+        Some(ErrorKind::UnknownToken { soft_logout: false })
+    );
+    assert_eq!(session_changes.next().await, Some(SessionChange::UnknownToken));
+}
+
+#[async_test]
+async fn session_change_not_broadcast_on_refresh_server_error() {
+    let (builder, server) = test_client_builder().await;
+    let client = builder
+        .request_config(RequestConfig::new().disable_retry())
+        .server_versions([MatrixVersion::V1_3])
+        .handle_refresh_tokens()
+        .build()
+        .await
+        .unwrap();
+
+    let session = Session {
+        access_token: "1234".to_owned(),
+        refresh_token: Some("abcd".to_owned()),
+        user_id: user_id!("@example:localhost").to_owned(),
+        device_id: device_id!("DEVICEID").to_owned(),
+    };
+    client.restore_session(session).await.unwrap();
+    let mut session_changes = client.subscribe_to_session_changes();
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/refresh"))
+        .respond_with(ResponseTemplate::new(500).set_body_json(json!({
+            "errcode": "M_UNKNOWN",
+            "error": "Internal server error",
+        })))
+        .expect(1)
+        .named("`POST /refresh`")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/account/whoami"))
+        .respond_with(
+            ResponseTemplate::new(401).set_body_json(&*test_json::UNKNOWN_TOKEN_SOFT_LOGOUT),
+        )
+        .expect(1)
+        .named("`GET /whoami`")
+        .mount(&server)
+        .await;
+
+    let res = client.whoami().await.unwrap_err();
+    assert_eq!(res.as_client_api_error().unwrap().status_code, 500);
+
+    // The refresh token might still be valid, the session is not considered
+    // logged out.
+    assert_eq!(session_changes.next().now_or_never(), None);
+    assert_eq!(client.refresh_token().as_deref(), Some("abcd"));
+}
+
+#[async_test]
+async fn restore_after_soft_logout() {
+    let (client, server) = logged_in_client().await;
+    let mut session_changes = client.subscribe_to_session_changes();
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/account/whoami"))
+        .and(header(http::header::AUTHORIZATION, "Bearer 1234"))
+        .respond_with(
+            ResponseTemplate::new(401).set_body_json(&*test_json::UNKNOWN_TOKEN_SOFT_LOGOUT),
+        )
+        .expect(1)
+        .named("`GET /whoami` soft logged out")
+        .mount(&server)
+        .await;
+
+    client.whoami().await.unwrap_err();
+    assert_eq!(session_changes.next().await, Some(SessionChange::SoftLoggedOut));
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/login"))
+        .and(body_partial_json(json!({
+            "device_id": "DEVICEID",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "5678",
+            "device_id": "DEVICEID",
+            "user_id": "@example:localhost",
+        })))
+        .expect(1)
+        .named("`POST /login`")
+        .mount(&server)
+        .await;
+
+    let login = client.login_username("example", "wordpass");
+    client.restore_after_soft_logout(login).await.unwrap();
+    assert_eq!(session_changes.next().await, Some(SessionChange::TokensRefreshed));
+
+    let session = client.session().unwrap();
+    assert_eq!(session.access_token, "5678");
+    assert_eq!(session.user_id, "@example:localhost");
+    assert_eq!(session.device_id, "DEVICEID");
+}
+
+#[async_test]
+async fn session_persistence_saves_refreshed_tokens() {
+    let saved_sessions = Arc::new(Mutex::new(Vec::new()));
+    let saved_sessions_clone = saved_sessions.clone();
+
+    let (builder, server) = test_client_builder().await;
+    let client = builder
+        .request_config(RequestConfig::new().disable_retry())
+        .server_versions([MatrixVersion::V1_3])
+        .session_persistence(move |session: Session| {
+            let saved_sessions = saved_sessions_clone.clone();
+            async move {
+                saved_sessions.lock().unwrap().push(session);
+                Ok::<_, SessionPersistenceError>(())
+            }
+        })
+        .build()
+        .await
+        .unwrap();
+
+    let session = Session {
+        access_token: "1234".to_owned(),
+        refresh_token: Some("abcd".to_owned()),
+        user_id: user_id!("@example:localhost").to_owned(),
+        device_id: device_id!("DEVICEID").to_owned(),
+    };
+    client.restore_session(session).await.unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/refresh"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(&*test_json::REFRESH_TOKEN_WITH_REFRESH_TOKEN),
+        )
+        .expect(1)
+        .named("`POST /refresh`")
+        .mount(&server)
+        .await;
+
+    client.refresh_access_token().await.unwrap().unwrap();
+
+    let saved_sessions = saved_sessions.lock().unwrap();
+    assert_eq!(saved_sessions.len(), 1);
+    assert_eq!(saved_sessions[0].access_token, "9012");
+    assert_eq!(saved_sessions[0].refresh_token.as_deref(), Some("wxyz"));
+    assert_eq!(saved_sessions[0].user_id, "@example:localhost");
+    assert_eq!(client.access_token().as_deref(), Some("9012"));
+}
+
+#[async_test]
+async fn session_persistence_failure() {
+    // The first save fails, the following ones succeed.
+    let saved_sessions = Arc::new(Mutex::new(None::<Vec<Session>>));
+    let saved_sessions_clone = saved_sessions.clone();
+
+    let (builder, server) = test_client_builder().await;
+    let client = builder
+        .request_config(RequestConfig::new().disable_retry())
+        .server_versions([MatrixVersion::V1_3])
+        .session_persistence(move |session: Session| {
+            let saved_sessions = saved_sessions_clone.clone();
+            async move {
+                let mut saved_sessions = saved_sessions.lock().unwrap();
+                let Some(saved_sessions) = saved_sessions.as_mut() else {
+                    *saved_sessions = Some(Vec::new());
+                    return Err::<(), SessionPersistenceError>("disk full".into());
+                };
+                saved_sessions.push(session);
+                Ok(())
+            }
+        })
+        .build()
+        .await
+        .unwrap();
+
+    let session = Session {
+        access_token: "1234".to_owned(),
+        refresh_token: Some("abcd".to_owned()),
+        user_id: user_id!("@example:localhost").to_owned(),
+        device_id: device_id!("DEVICEID").to_owned(),
+    };
+    client.restore_session(session).await.unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/v3/refresh"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(&*test_json::REFRESH_TOKEN_WITH_REFRESH_TOKEN),
+        )
+        .expect(1)
+        .named("`POST /refresh`")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/v3/account/whoami"))
+        .and(header(http::header::AUTHORIZATION, "Bearer 9012"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::WHOAMI))
+        .expect(1)
+        .named("`GET /whoami`")
+        .mount(&server)
+        .await;
+
+    let res = client.refresh_access_token().await;
+    assert_matches!(res, Err(HttpError::RefreshToken(RefreshTokenError::SessionPersistence(_))));
+
+    // The new tokens are used even if they couldn't be persisted, since the
+    // previous refresh token might not be valid anymore.
+    assert_eq!(client.access_token().as_deref(), Some("9012"));
+    assert_eq!(client.refresh_token().as_deref(), Some("wxyz"));
+    assert!(saved_sessions.lock().unwrap().as_ref().unwrap().is_empty());
+
+    // Saving them is retried before the next request.
+    client.whoami().await.unwrap();
+
+    let saved_sessions = saved_sessions.lock().unwrap();
+    let saved_sessions = saved_sessions.as_ref().unwrap();
+    assert_eq!(saved_sessions.len(), 1);
+    assert_eq!(saved_sessions[0].access_token, "9012");
+    assert_eq!(saved_sessions[0].refresh_token.as_deref(), Some("wxyz"));
+}
@@ -8,12 +8,15 @@ use matrix_sdk::{
     config::SyncSettings,
     room::Receipts,
 };
-use matrix_sdk_test::{async_test, test_json};
+use matrix_sdk_test::{
+    async_test, test_json, EphemeralTestEvent, EventBuilder, JoinedRoomBuilder,
+    RoomAccountDataTestEvent, TimelineTestEvent,
+};
 use ruma::{
     api::client::{membership::Invite3pidInit, receipt::create_receipt::v3::ReceiptType},
     assign, event_id,
     events::{receipt::ReceiptThread, room::message::RoomMessageEventContent},
-    mxc_uri, thirdparty, uint, user_id, TransactionId,
+    mxc_uri, room_id, thirdparty, uint, user_id, TransactionId,
 };
 use serde_json::json;
 use wiremock::{
@@ -521,3 +524,92 @@ async fn room_redact() {
 
     assert_eq!(event_id!("$h29iv0s8:example.com"), response.event_id)
 }
+
+fn message_event(event_id: &str) -> TimelineTestEvent {
+    TimelineTestEvent::Custom(json!({
+        "content": { "body": "Hello", "msgtype": "m.text" },
+        "event_id": event_id,
+        "origin_server_ts": 152037280,
+        "sender": "@other:localhost",
+        "type": "m.room.message",
+    }))
+}
+
+#[async_test]
+async fn unread_counts_follow_receipts_and_fully_read_marker() {
+    let (client, server) = logged_in_client().await;
+    let mut ev_builder = EventBuilder::new();
+    let room_id = room_id!("!unread:localhost");
+
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(message_event("$1:localhost"))
+            .add_timeline_event(message_event("$2:localhost"))
+            .add_timeline_event(message_event("$3:localhost")),
+    );
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let sync_token = client.sync_once(SyncSettings::new()).await.unwrap().next_batch;
+
+    let room = client.get_joined_room(room_id).unwrap();
+    assert_eq!(room.unread_counts().messages, 3);
+    assert_eq!(room.latest_event_id().as_deref(), Some(event_id!("$3:localhost")));
+
+    // A read receipt from another device, for an event of the previous sync.
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_ephemeral_event(
+        EphemeralTestEvent::Custom(json!({
+            "content": {
+                "$2:localhost": { "m.read": { "@example:localhost": { "ts": 1 } } },
+            },
+            "type": "m.receipt",
+        })),
+    ));
+    mock_sync(&server, ev_builder.build_json_sync_response(), Some(sync_token.clone())).await;
+    let sync_token =
+        client.sync_once(SyncSettings::new().token(sync_token)).await.unwrap().next_batch;
+
+    assert_eq!(room.unread_counts().messages, 1);
+
+    // The fully read marker moves to the latest event.
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_account_data(
+        RoomAccountDataTestEvent::Custom(json!({
+            "content": { "event_id": "$3:localhost" },
+            "type": "m.fully_read",
+        })),
+    ));
+    mock_sync(&server, ev_builder.build_json_sync_response(), Some(sync_token.clone())).await;
+    client.sync_once(SyncSettings::new().token(sync_token)).await.unwrap();
+
+    assert_eq!(room.unread_counts().messages, 0);
+}
+
+#[async_test]
+async fn mark_as_read_sends_receipts_for_the_latest_event() {
+    let (client, server) = logged_in_client().await;
+    let mut ev_builder = EventBuilder::new();
+    let room_id = room_id!("!unread:localhost");
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/read_markers$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({
+            "m.fully_read": "$2:localhost",
+            "m.read": "$2:localhost",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EMPTY))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(message_event("$1:localhost"))
+            .add_timeline_event(message_event("$2:localhost")),
+    );
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::new()).await.unwrap();
+
+    let room = client.get_joined_room(room_id).unwrap();
+    assert_eq!(room.unread_counts().messages, 2);
+
+    room.mark_as_read().await.unwrap();
+}
@@ -70,6 +70,9 @@ enum FeatureSet {
     Markdown,
     Socks,
     SsoLogin,
+    ExperimentalOidc,
+    ExperimentalQrLogin,
+    BackupsV1,
 }
 
 #[derive(Subcommand, PartialEq, Eq, PartialOrd, Ord)]
@@ -203,6 +206,9 @@ fn run_feature_tests(cmd: Option<FeatureSet>) -> Result<()> {
         (FeatureSet::Markdown, "--features markdown"),
         (FeatureSet::Socks, "--features socks"),
         (FeatureSet::SsoLogin, "--features sso-login"),
+        (FeatureSet::ExperimentalOidc, "--features experimental-oidc"),
+        (FeatureSet::ExperimentalQrLogin, "--features experimental-qr-login"),
+        (FeatureSet::BackupsV1, "--features backups-v1"),
     ]);
 
     let run = |arg_set: &str| {